@@ -0,0 +1,4 @@
+// Migrations are embedded with `sqlx::migrate!()`, so adding one must trigger a rebuild.
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
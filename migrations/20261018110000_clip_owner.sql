@@ -0,0 +1,3 @@
+-- The API key (by fingerprint) that created a clip; NULL for clips posted through the web form
+ALTER TABLE clips ADD COLUMN owner TEXT;
+CREATE INDEX IF NOT EXISTS clips_owner ON clips (owner);
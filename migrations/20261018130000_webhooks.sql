@@ -0,0 +1,29 @@
+-- Webhooks registered by an API key (by fingerprint) for events on the clips it owns,
+-- and the outbox of their deliveries; events is a comma separated list such as clip.created,clip.updated
+CREATE TABLE IF NOT EXISTS webhooks
+(
+    webhook_id TEXT PRIMARY KEY NOT NULL,
+    owner      TEXT NOT NULL,
+    url        TEXT NOT NULL,
+    secret     TEXT NOT NULL,
+    events     TEXT NOT NULL,
+    created    DATETIME NOT NULL
+);
+CREATE INDEX IF NOT EXISTS webhooks_owner ON webhooks (owner);
+
+-- next_attempt is NULL once the delivery succeeded or was given up
+CREATE TABLE IF NOT EXISTS webhook_deliveries
+(
+    webhook_id      TEXT NOT NULL REFERENCES webhooks (webhook_id) ON DELETE CASCADE,
+    event_id        TEXT NOT NULL,
+    event           TEXT NOT NULL,
+    payload         TEXT NOT NULL,
+    created         DATETIME NOT NULL,
+    attempts        INTEGER NOT NULL DEFAULT 0,
+    next_attempt    DATETIME,
+    delivered       DATETIME,
+    response_status INTEGER,
+    last_error      TEXT,
+    PRIMARY KEY (webhook_id, event_id)
+);
+CREATE INDEX IF NOT EXISTS webhook_deliveries_due ON webhook_deliveries (next_attempt);
@@ -0,0 +1,3 @@
+-- The clip a clip was forked from; forks outlive their parent
+ALTER TABLE clips ADD COLUMN parent_clip_id TEXT REFERENCES clips (clip_id) ON DELETE SET NULL;
+CREATE INDEX IF NOT EXISTS clips_parent ON clips (parent_clip_id);
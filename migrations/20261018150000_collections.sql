@@ -0,0 +1,19 @@
+-- Clips shared together under one shortcode; members are ordered by position
+CREATE TABLE IF NOT EXISTS collections
+(
+    collection_id TEXT PRIMARY KEY NOT NULL,
+    shortcode     TEXT UNIQUE NOT NULL,
+    title         TEXT,
+    posted        DATETIME NOT NULL,
+    expires       DATETIME,
+    owner         TEXT
+);
+
+CREATE TABLE IF NOT EXISTS collection_clips
+(
+    collection_id TEXT NOT NULL REFERENCES collections (collection_id) ON DELETE CASCADE,
+    position      INTEGER NOT NULL,
+    clip_id       TEXT NOT NULL REFERENCES clips (clip_id) ON DELETE CASCADE,
+    PRIMARY KEY (collection_id, position)
+);
+CREATE INDEX IF NOT EXISTS collection_clips_clip ON collection_clips (clip_id);
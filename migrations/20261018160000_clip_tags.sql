@@ -0,0 +1,8 @@
+-- Tags of clips, normalized to lowercase by the service
+CREATE TABLE IF NOT EXISTS clip_tags
+(
+    clip_id TEXT NOT NULL REFERENCES clips (clip_id) ON DELETE CASCADE,
+    tag     TEXT NOT NULL,
+    PRIMARY KEY (clip_id, tag)
+);
+CREATE INDEX IF NOT EXISTS clip_tags_tag ON clip_tags (tag);
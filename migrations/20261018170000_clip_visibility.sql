@@ -0,0 +1,3 @@
+-- Who may find and read a clip: 'public', 'unlisted' or 'private'
+ALTER TABLE clips ADD COLUMN visibility TEXT NOT NULL DEFAULT 'unlisted';
+CREATE INDEX IF NOT EXISTS clips_visibility ON clips (visibility, posted);
@@ -0,0 +1,5 @@
+-- Add migration script here
+CREATE TABLE IF NOT EXISTS api_keys
+(
+    api_key BYTEA PRIMARY KEY
+);
@@ -0,0 +1,3 @@
+-- Content of password protected clips is encrypted with a key derived from the password.
+ALTER TABLE clips ADD COLUMN content_nonce BYTEA;
+ALTER TABLE clips ADD COLUMN content_salt BYTEA;
@@ -0,0 +1,2 @@
+-- Content of end-to-end encrypted clips is ciphertext; the key never reaches the server.
+ALTER TABLE clips ADD COLUMN encrypted BOOLEAN NOT NULL DEFAULT FALSE;
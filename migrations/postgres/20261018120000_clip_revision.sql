@@ -0,0 +1,3 @@
+-- Bumped by every update, for ETags and optimistic concurrency; updated is NULL until the first update
+ALTER TABLE clips ADD COLUMN revision BIGINT NOT NULL DEFAULT 1;
+ALTER TABLE clips ADD COLUMN updated TIMESTAMP;
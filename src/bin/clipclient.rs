@@ -1,118 +1,341 @@
+use serde::Deserialize;
 use std::error::Error;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use structopt::StructOpt;
-use clipstash::{Clip, ShortCode};
-use clipstash::domain::clip::field::{Content, Expires, Password, Title};
+use clipstash::{Clip, ClipError, ShortCode};
+use clipstash::client::blocking::ClipstashClient;
+use clipstash::client::{ClientError, Event, DEFAULT_ADDR};
+use clipstash::domain::clip::field::{Content, Encrypted, Expires, Password, Tags, Title, Visibility};
+use clipstash::domain::crypto::e2e;
 use clipstash::service::ask::{GetClip, NewClip, UpdateClip};
 use clipstash::web::api::{ApiKey, API_KEY_HEADER};
 
+/// A shortcode, or a full clip link such as `http://host/clip/<shortcode>#<key>`.
+#[derive(Debug, Clone)]
+struct ClipRef {
+    addr: Option<String>,
+    shortcode: ShortCode,
+    key: Option<String>,
+}
+
+impl FromStr for ClipRef {
+    type Err = String;
+
+    fn from_str(raw: &str) -> Result<Self, Self::Err> {
+        if !raw.contains("://") {
+            return Ok(Self { addr: None, shortcode: ShortCode::from(raw), key: None });
+        }
+        let url = reqwest::Url::parse(raw).map_err(|e| e.to_string())?;
+        let shortcode = url
+            .path_segments()
+            .and_then(|segments| segments.rev().find(|s| !s.is_empty()))
+            .ok_or_else(|| format!("no shortcode in '{}'", raw))?;
+        let addr = format!("{}://{}", url.scheme(), url.host_str().unwrap_or_default());
+        let addr = match url.port() {
+            Some(port) => format!("{}:{}", addr, port),
+            None => addr,
+        };
+        Ok(Self {
+            addr: Some(addr),
+            shortcode: ShortCode::from(shortcode),
+            key: url.fragment().filter(|f| !f.is_empty()).map(str::to_owned),
+        })
+    }
+}
+
+/// How results are printed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::EnumString, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+enum Output {
+    /// Only the clip content.
+    Raw,
+    Json,
+    /// The link to the clip, including the #key of encrypted clips.
+    Url,
+}
+
+/// `~/.config/clipstash/config.toml`; command line options and environment variables take precedence.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ClientConfig {
+    addr: Option<String>,
+    api_key: Option<String>,
+}
+
+impl ClientConfig {
+    fn default_path() -> Option<PathBuf> {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .map(|dir| dir.join("clipstash").join("config.toml"))
+    }
+
+    /// A missing file is an empty config, unless it was asked for explicitly.
+    fn load(path: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        let (path, required) = match path {
+            Some(path) => (path.to_owned(), true),
+            None => match Self::default_path() {
+                Some(path) => (path, false),
+                None => return Ok(Self::default()),
+            },
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(raw) => toml::from_str(&raw).map_err(|e| format!("{}: {}", path.display(), e).into()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => Ok(Self::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e).into()),
+        }
+    }
+}
+
 #[derive(StructOpt, Debug)]
 enum Command {
     Get{
-        shortcode: ShortCode,
+        #[structopt(help = "shortcode or clip link; a key in the #fragment decrypts the clip locally")]
+        clip: ClipRef,
         #[structopt(long, short, help = "password")]
-        password: Option<String>
+        password: Option<String>,
+        #[structopt(long, help = "write only the content; same as --output raw")]
+        raw: bool,
     },
     New{
-        #[structopt(help = "content")]
-        clip: String,
+        #[structopt(help = "content, or - to read stdin [default: stdin]")]
+        clip: Option<String>,
+        #[structopt(long, short, parse(from_os_str), conflicts_with = "clip", help = "read the content from a file")]
+        file: Option<PathBuf>,
         #[structopt(long, short, help = "password")]
         password: Option<Password>,
         #[structopt(long, short, help = "expires")]
         expires: Option<Expires>,
         #[structopt(long, short, help = "title")]
         title: Option<Title>,
+        #[structopt(long, help = "encrypt locally; the key is only part of the printed link")]
+        encrypt: bool,
+        #[structopt(long, help = "tags, separated by commas")]
+        tags: Option<Tags>,
+        #[structopt(long, help = "public, unlisted (the default) or private")]
+        visibility: Option<Visibility>,
     },
     Update{
-        shortcode: ShortCode,
-        #[structopt(help = "content")]
-        clip: String,
+        #[structopt(help = "shortcode or clip link; a key in the #fragment re-encrypts the content")]
+        target: ClipRef,
+        #[structopt(help = "content, or - to read stdin [default: stdin]")]
+        clip: Option<String>,
+        #[structopt(long, short, parse(from_os_str), conflicts_with = "clip", help = "read the content from a file")]
+        file: Option<PathBuf>,
         #[structopt(long, short, help = "password")]
         password: Option<Password>,
         #[structopt(long, short, help = "expires")]
         expires: Option<Expires>,
         #[structopt(long, short, help = "title")]
         title: Option<Title>,
+        #[structopt(long, help = "tags replacing the clip's tags, separated by commas")]
+        tags: Option<Tags>,
+        #[structopt(long, help = "public, unlisted or private")]
+        visibility: Option<Visibility>,
     },
 }
 
 #[derive(StructOpt, Debug)]
-#[structopt(name = "clipclient", about = "A client for the clip service")]
+#[structopt(name = "clipclient", about = "A client for the clip service", after_help = EXIT_CODES)]
 struct Opt{
     #[structopt(subcommand)]
     command: Command,
-    #[structopt(short, long, default_value = "http://127.0.0.1:8000", env = "CLIPSTASH_ADDR")]
-    addr: String,
-    #[structopt(long)]
-    api_key: ApiKey,
-}
-
-fn get_clip(addr: &str, ask_svc: GetClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
-    let client = reqwest::blocking::Client::builder().build()?;
-    let addr = format!("{}/api/clip/{}", addr, ask_svc.shortcode.into_inner());
-    let mut request = client.get(&addr);
-    request = match ask_svc.password.into_inner() {
-        Some(password) => request.header(reqwest::header::COOKIE, format!("password={}", password)),
-        None => request
-    };
-    request = request.header(API_KEY_HEADER, api_key.to_base64());
-    Ok(request.send()?.json()?)
+    #[structopt(short, long, env = "CLIPSTASH_ADDR", help = "server address [default: addr from the config file, or http://127.0.0.1:8000]")]
+    addr: Option<String>,
+    #[structopt(long, env = "CLIPSTASH_API_KEY", hide_env_values = true, help = "[default: api_key from the config file]")]
+    api_key: Option<ApiKey>,
+    #[structopt(short, long, parse(from_os_str), env = "CLIPSTASH_CLIENT_CONFIG", help = "config file [default: ~/.config/clipstash/config.toml]")]
+    config: Option<PathBuf>,
+    #[structopt(short, long, global = true, possible_values = &["raw", "json", "url"], help = "output format [default: json for get, url for new and update]")]
+    output: Option<Output>,
+    #[structopt(short, long, global = true, help = "print requests and responses to stderr")]
+    verbose: bool,
+    #[structopt(long, global = true, default_value = "3", help = "retries after connection errors, rate limiting and server errors; new clips are only retried when the server was unreachable")]
+    retries: u32,
+}
+
+const EXIT_CODES: &str = "EXIT CODES:
+    1    invalid arguments or local error
+    2    request rejected by the server, or the clip changed meanwhile (400, 409, 412, 413, 422)
+    3    missing or invalid API key, or wrong password (401, 403)
+    4    clip not found (404)
+    5    server error or rate limited (5xx, 429)
+    6    server unreachable";
+
+fn exit_code(e: &ClientError) -> i32 {
+    match e {
+        ClientError::BadRequest(..) | ClientError::Conflict(_) | ClientError::Unexpected(..) | ClientError::Decode(_) => 2,
+        ClientError::Unauthorized(..) | ClientError::MissingApiKey => 3,
+        ClientError::NotFound(_) => 4,
+        ClientError::Server(..) | ClientError::TooManyRequests(_) => 5,
+        ClientError::Connection(_) => 6,
+        ClientError::Http(_) => 1,
+    }
+}
+
+fn decrypt(mut clip: Clip, key: Option<&str>) -> Result<Clip, ClipError> {
+    match key {
+        Some(key) if clip.encrypted.is_encrypted() => {
+            clip.content = Content::new(e2e::decrypt(key, clip.content.as_str())?.as_str())?;
+            clip.encrypted = Encrypted::new(false);
+        }
+        None if clip.encrypted.is_encrypted() => {
+            eprintln!("Warning: clip is end-to-end encrypted; pass the full link including #key to decrypt it");
+        }
+        _ => (),
+    }
+    Ok(clip)
+}
+
+fn link(addr: &str, clip: &Clip, key: Option<&str>) -> String {
+    match key {
+        Some(key) => format!("{}/clip/{}#{}", addr, clip.shortcode.as_str(), key),
+        None => format!("{}/clip/{}", addr, clip.shortcode.as_str()),
+    }
+}
+
+/// Content given on the command line, read from `file`, or read from `stdin` when absent or `-`.
+fn read_content<R: Read>(clip: Option<String>, file: Option<PathBuf>, mut stdin: R) -> Result<String, Box<dyn Error>> {
+    match (clip, file) {
+        (Some(clip), _) if clip != "-" => Ok(clip),
+        (_, Some(file)) => std::fs::read_to_string(&file).map_err(|e| format!("{}: {}", file.display(), e).into()),
+        _ => {
+            let mut content = String::new();
+            stdin.read_to_string(&mut content)?;
+            Ok(content)
+        }
+    }
+}
+
+fn write<W: Write>(out: &mut W, output: Output, addr: &str, clip: &Clip, key: Option<&str>) -> Result<(), Box<dyn Error>> {
+    match output {
+        Output::Raw => out.write_all(clip.content.as_str().as_bytes())?,
+        Output::Json => writeln!(out, "{}", serde_json::to_string_pretty(clip)?)?,
+        Output::Url => writeln!(out, "{}", link(addr, clip, key))?,
+    }
+    Ok(out.flush()?)
 }
 
-fn new_clip(addr: &str, ask_svc: NewClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
-    let client = reqwest::blocking::Client::builder().build()?;
-    let addr = format!("{}/api/clip", addr);
-    let mut request = client.post(&addr);
-    request = request.header(API_KEY_HEADER, api_key.to_base64());
-    Ok(request.json(&ask_svc).send()?.json()?)
+fn print(output: Output, addr: &str, clip: &Clip, key: Option<&str>) -> Result<(), Box<dyn Error>> {
+    write(&mut std::io::stdout().lock(), output, addr, clip, key)
 }
-fn update_clip(addr: &str, ask_svc: UpdateClip, api_key: ApiKey) -> Result<Clip, Box<dyn Error>> {
-    let client = reqwest::blocking::Client::builder().build()?;
-    let addr = format!("{}/api/clip", addr);
-    let mut request = client.put(&addr);
-    request = request.header(API_KEY_HEADER, api_key.to_base64());
-    Ok(request.json(&ask_svc).send()?.json()?)
+
+/// The server address and API key: command line options and environment variables first, then the config file.
+fn settings(addr: Option<String>, api_key: Option<ApiKey>, config: ClientConfig) -> Result<(String, ApiKey), Box<dyn Error>> {
+    let addr = addr
+        .or(config.addr)
+        .unwrap_or_else(|| DEFAULT_ADDR.to_owned());
+    let addr = addr.trim_end_matches('/').to_owned();
+    let api_key = match (api_key, config.api_key) {
+        (Some(api_key), _) => api_key,
+        (None, Some(api_key)) => ApiKey::from_str(&api_key).map_err(|e| format!("api_key in config file: {}", e))?,
+        (None, None) => return Err("no API key; pass --api-key or set api_key in ~/.config/clipstash/config.toml".into()),
+    };
+    Ok((addr, api_key))
 }
 
+/// Prints requests and responses for `--verbose`, with credentials hidden.
+fn log(event: &Event<'_>) {
+    match event {
+        Event::Request(request) => {
+            eprintln!("> {} {}", request.method(), request.url());
+            for (name, value) in request.headers() {
+                let value = match name.as_str() {
+                    API_KEY_HEADER | "cookie" => "<hidden>",
+                    _ => value.to_str().unwrap_or("<binary>"),
+                };
+                eprintln!("> {}: {}", name, value);
+            }
+            if let Some(body) = request.body().and_then(|body| body.as_bytes()) {
+                eprintln!(">\n{}", String::from_utf8_lossy(body));
+            }
+        }
+        Event::Response { status, headers, body } => {
+            eprintln!("< {}", status);
+            for (name, value) in headers.iter() {
+                eprintln!("< {}: {}", name, value.to_str().unwrap_or("<binary>"));
+            }
+            eprintln!("<\n{}", body);
+        }
+        Event::Retry { error, delay } => eprintln!("* {}; retrying in {:?}", error, delay),
+    }
+}
 
 fn run(opt: Opt) -> Result<(), Box<dyn Error>> {
+    let config = ClientConfig::load(opt.config.as_deref())?;
+    let (addr, api_key) = settings(opt.addr, opt.api_key, config)?;
+    let (retries, verbose) = (opt.retries, opt.verbose);
+    let connect = |addr: &str| -> Result<ClipstashClient, ClientError> {
+        let client = ClipstashClient::new(addr)?
+            .with_api_key(api_key.clone())
+            .with_retries(retries);
+        Ok(if verbose { client.with_observer(log) } else { client })
+    };
     match opt.command {
-        Command::Get {shortcode, password} => {
+        Command::Get {clip, password, raw} => {
+            let output = if raw { Output::Raw } else { opt.output.unwrap_or(Output::Json) };
+            let addr = clip.addr.unwrap_or(addr);
             let req = GetClip {
-                shortcode,
-                password: Password::new(password.unwrap_or_default())?
+                shortcode: clip.shortcode,
+                password: Password::new(password.unwrap_or_default())?,
+                viewer: Default::default(),
             };
-            let clip = get_clip(opt.addr.as_str(), req, opt.api_key)?;
-            println!("{:#?}", clip);
-            Ok(())
+            let fetched = connect(&addr)?.get_clip(req)?;
+            print(output, &addr, &decrypt(fetched, clip.key.as_deref())?, clip.key.as_deref())
         },
-        Command::New {clip, password, expires, title} => {
+        Command::New {clip, file, password, expires, title, encrypt, tags, visibility} => {
+            let clip = read_content(clip, file, std::io::stdin())?;
+            let key = encrypt.then(e2e::generate_key);
+            let content = match &key {
+                Some(key) => e2e::encrypt(key, clip.as_str())?,
+                None => clip,
+            };
             let req = NewClip {
-                content: Content::new(clip.as_str())?,
+                content: Content::new(content.as_str())?,
                 password: password.unwrap_or_default(),
                 expires: expires.unwrap_or_default(),
                 title: title.unwrap_or_default(),
+                encrypted: Encrypted::new(key.is_some()),
+                owner: Default::default(),
+                parent: Default::default(),
+                visibility: visibility.unwrap_or_default(),
+                tags: tags.unwrap_or_default(),
             };
-            let clip = new_clip(opt.addr.as_str(), req, opt.api_key)?;
-            println!("{:#?}", clip);
-            Ok(())
+            let clip = connect(&addr)?.new_clip(req)?;
+            print(opt.output.unwrap_or(Output::Url), &addr, &decrypt(clip, key.as_deref())?, key.as_deref())
         },
-        Command::Update {shortcode, clip, password, expires, title} => {
+        Command::Update {target: clip_ref, clip, file, password, expires, title, tags, visibility} => {
+            let clip = read_content(clip, file, std::io::stdin())?;
+            let addr = clip_ref.addr.unwrap_or(addr);
             let password = password.unwrap_or_default();
             let svc_req = GetClip {
-                shortcode: shortcode.clone(),
+                shortcode: clip_ref.shortcode.clone(),
                 password: password.clone(),
+                viewer: Default::default(),
+            };
+            let client = connect(&addr)?;
+            let original_clip = client.get_clip(svc_req)?;
+            let content = match &clip_ref.key {
+                Some(key) => e2e::encrypt(key, clip.as_str())?,
+                None => clip,
             };
-            let original_clip = get_clip(opt.addr.as_str(), svc_req, opt.api_key.clone())?;
             let svc_req = UpdateClip {
-                shortcode,
-                content: Content::new(clip.as_str())?,
+                shortcode: clip_ref.shortcode,
+                content: Content::new(content.as_str())?,
                 password,
                 expires: expires.unwrap_or(original_clip.expires),
                 title: title.unwrap_or(original_clip.title),
+                encrypted: Encrypted::new(clip_ref.key.is_some()),
+                // fails if someone else updated the clip since it was read above
+                revision: Some(original_clip.revision),
+                visibility,
+                tags,
             };
-            let clip = update_clip(opt.addr.as_str(), svc_req, opt.api_key)?;
-            println!("{:#?}", clip);
-            Ok(())
+            let clip = client.update_clip(svc_req)?;
+            print(opt.output.unwrap_or(Output::Url), &addr, &decrypt(clip, clip_ref.key.as_deref())?, clip_ref.key.as_deref())
         },
     }
 }
@@ -120,6 +343,117 @@ fn main() {
     let opt = Opt::from_args();
     if let Err(e) = run(opt) {
         eprintln!("Error: {}", e);
-        std::process::exit(1);
+        std::process::exit(e.downcast_ref::<ClientError>().map_or(1, exit_code));
+    }
+}
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn temp_file(contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("clipclient-{}", uuid::Uuid::new_v4()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn clip(encrypted: bool) -> Clip {
+        let clip = serde_json::json!({
+            "shortcode": "abc123",
+            "content": "some content",
+            "title": null,
+            "posted": "2026-01-01T00:00:00Z",
+            "expires": null,
+            "hits": 0,
+            "encrypted": encrypted,
+            "revision": 1,
+            "updated": "2026-01-01T00:00:00Z",
+        });
+        serde_json::from_value(clip).unwrap()
+    }
+
+    fn render(output: Output, clip: &Clip, key: Option<&str>) -> String {
+        let mut out = Vec::new();
+        write(&mut out, output, "http://host", clip, key).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn reads_content_from_arguments_files_and_stdin() {
+        let stdin = "from stdin".as_bytes();
+        assert_eq!(read_content(Some("given".to_owned()), None, stdin).unwrap(), "given");
+        assert_eq!(read_content(None, None, stdin).unwrap(), "from stdin");
+        assert_eq!(read_content(Some("-".to_owned()), None, stdin).unwrap(), "from stdin");
+
+        let file = temp_file("from a file");
+        assert_eq!(read_content(None, Some(file.clone()), stdin).unwrap(), "from a file");
+        std::fs::remove_file(&file).unwrap();
+        let err = read_content(None, Some(file.clone()), stdin).unwrap_err();
+        assert!(err.to_string().starts_with(&file.display().to_string()));
+    }
+
+    #[test]
+    fn renders_each_output_format() {
+        let plain = clip(false);
+        assert_eq!(render(Output::Raw, &plain, None), "some content");
+        assert_eq!(render(Output::Url, &plain, None), "http://host/clip/abc123\n");
+        let json: serde_json::Value = serde_json::from_str(&render(Output::Json, &plain, None)).unwrap();
+        assert_eq!(json["shortcode"], "abc123");
+        assert_eq!(json["content"], "some content");
+        assert!(json.get("password").is_none());
+
+        assert_eq!(render(Output::Url, &clip(true), Some("k3y")), "http://host/clip/abc123#k3y\n");
+        assert_eq!("json".parse::<Output>().unwrap(), Output::Json);
+    }
+
+    #[test]
+    fn maps_errors_to_exit_codes() {
+        use reqwest::StatusCode;
+
+        let code = |status: StatusCode| exit_code(&ClientError::from_response(status, ""));
+        assert_eq!(code(StatusCode::BAD_REQUEST), 2);
+        assert_eq!(code(StatusCode::PRECONDITION_FAILED), 2);
+        assert_eq!(code(StatusCode::UNPROCESSABLE_ENTITY), 2);
+        assert_eq!(code(StatusCode::UNAUTHORIZED), 3);
+        assert_eq!(code(StatusCode::FORBIDDEN), 3);
+        assert_eq!(code(StatusCode::NOT_FOUND), 4);
+        assert_eq!(code(StatusCode::TOO_MANY_REQUESTS), 5);
+        assert_eq!(code(StatusCode::BAD_GATEWAY), 5);
+        assert_eq!(exit_code(&ClientError::MissingApiKey), 3);
+        assert_eq!(exit_code(&ClientError::Decode("eof".to_owned())), 2);
+    }
+
+    #[test]
+    fn loads_config_files() {
+        let file = temp_file("addr = \"http://configured\"\napi_key = \"AAAA\"\n");
+        let config = ClientConfig::load(Some(&file)).unwrap();
+        assert_eq!(config.addr.as_deref(), Some("http://configured"));
+        assert_eq!(config.api_key.as_deref(), Some("AAAA"));
+
+        std::fs::write(&file, "adr = \"typo\"\n").unwrap();
+        assert!(ClientConfig::load(Some(&file)).is_err());
+        std::fs::remove_file(&file).unwrap();
+        // a config file asked for explicitly must exist
+        assert!(ClientConfig::load(Some(&file)).is_err());
+    }
+
+    #[test]
+    fn options_take_precedence_over_the_config_file() {
+        let from_file = ApiKey::default();
+        let config = || ClientConfig { addr: Some("http://configured/".to_owned()), api_key: Some(from_file.to_base64()) };
+
+        let (addr, api_key) = settings(None, None, config()).unwrap();
+        assert_eq!(addr, "http://configured");
+        assert_eq!(api_key.to_base64(), from_file.to_base64());
+
+        let given = ApiKey::default();
+        let (addr, api_key) = settings(Some("http://given".to_owned()), Some(given.clone()), config()).unwrap();
+        assert_eq!(addr, "http://given");
+        assert_eq!(api_key.to_base64(), given.to_base64());
+
+        let (addr, _) = settings(None, Some(given), ClientConfig::default()).unwrap();
+        assert_eq!(addr, DEFAULT_ADDR);
+        assert!(settings(None, None, ClientConfig::default()).is_err());
+        let bad_key = ClientConfig { addr: None, api_key: Some("not base64!".to_owned()) };
+        assert!(settings(None, None, bad_key).unwrap_err().to_string().contains("api_key in config file"));
     }
-}
\ No newline at end of file
+}
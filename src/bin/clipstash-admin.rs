@@ -0,0 +1,252 @@
+use clipstash::config::{Config, DEFAULT_CONFIG_FILE};
+use clipstash::data::{backup, envelope};
+use clipstash::data::repository::RevocationStatus;
+use clipstash::data::AppDatabase;
+use clipstash::service::action;
+use clipstash::service::live::Changes;
+use clipstash::service::archive::{ConflictMode, ExportFilter};
+use clipstash::web::api::ApiKey;
+use clipstash::{ShortCode, Time};
+use dotenv::dotenv;
+use std::error::Error;
+use std::path::PathBuf;
+use rocket::figment::providers::Serialized;
+use structopt::StructOpt;
+use tokio::io::{AsyncBufRead, AsyncWrite, BufReader};
+
+#[derive(Debug, StructOpt)]
+enum ApiKeyCommand {
+    /// Generate a new API key and print it
+    Create,
+    /// Print every API key
+    List,
+    /// Revoke an API key
+    Revoke {
+        api_key: ApiKey,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+enum Command {
+    /// Manage API keys
+    ApiKey(ApiKeyCommand),
+    /// Delete every clip and collection past its expiry date
+    PurgeExpired,
+    /// Delete a single clip
+    Delete {
+        shortcode: ShortCode,
+    },
+    /// Show clip counts, storage used and the most viewed clips
+    Stats {
+        #[structopt(long, default_value = "10", help = "number of top clips to show")]
+        top: u32,
+    },
+    /// Write clips to a JSON Lines archive
+    Export {
+        #[structopt(long, help = "only clips created with the API key of this fingerprint")]
+        owner: Option<String>,
+        #[structopt(long, help = "only clips posted on or after this date (YYYY-MM-DD)")]
+        since: Option<Time>,
+        #[structopt(long, help = "only clips posted before this date (YYYY-MM-DD)")]
+        until: Option<Time>,
+        #[structopt(short, long, parse(from_os_str), help = "archive to write [default: stdout]")]
+        output: Option<PathBuf>,
+    },
+    /// Read clips from a JSON Lines archive, keeping their shortcodes, dates, hits and passwords
+    Import {
+        #[structopt(parse(from_os_str), help = "archive to read, or - for stdin")]
+        input: PathBuf,
+        #[structopt(long, default_value = "skip", possible_values = &["skip", "overwrite", "new-shortcode"])]
+        on_conflict: ConflictMode,
+    },
+    /// Encrypt password protected clips stored before content encryption; run once after upgrading
+    SealProtected,
+    /// Snapshot the SQLite database while it is in use
+    Backup {
+        #[structopt(long, parse(from_os_str), help = "snapshot directory [default: backup.dir]")]
+        dir: Option<PathBuf>,
+        #[structopt(long, help = "snapshots to keep [default: backup.keep]")]
+        keep: Option<usize>,
+    },
+    /// Check a snapshot's integrity and schema version
+    VerifyBackup {
+        #[structopt(parse(from_os_str))]
+        snapshot: PathBuf,
+    },
+    /// Replace the SQLite database with a snapshot; stop httpd first
+    Restore {
+        #[structopt(parse(from_os_str))]
+        snapshot: PathBuf,
+    },
+    /// Apply pending migrations
+    Migrate {
+        #[structopt(long, help = "only report migration status, do not apply")]
+        status: bool,
+    },
+    /// Add a new master key to the keyfile and re-encrypt all clips under it
+    RotateKey {
+        #[structopt(long, parse(from_os_str), help = "keyfile to rotate [default: encryption.keyfile]")]
+        keyfile: Option<PathBuf>,
+        #[structopt(long, default_value = "500", help = "clips re-encrypted per batch")]
+        batch_size: u32,
+        #[structopt(long, help = "remove retired keys no clip refers to any more, except the key active until now")]
+        prune: bool,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "clipstash-admin", about = "Administration of a clipstash database")]
+struct Opt {
+    #[structopt(short, long, parse(from_os_str), default_value = DEFAULT_CONFIG_FILE, env = "CLIPSTASH_CONFIG")]
+    config: PathBuf,
+    #[structopt(short, long, help = "database connection string [default: database.url]")]
+    database: Option<String>,
+    #[structopt(subcommand)]
+    command: Command,
+}
+
+fn load_config(opt: &Opt) -> Result<Config, Box<dyn Error>> {
+    let mut figment = Config::figment(&opt.config);
+    if let Some(url) = &opt.database {
+        figment = figment.merge(Serialized::default("database.url", url));
+    }
+    Ok(Config::from_figment(&figment)?)
+}
+
+fn print_stats(stats: &clipstash::service::Stats) {
+    println!("clips:        {}", stats.clips);
+    println!("  protected:  {}", stats.protected);
+    println!("  expired:    {}", stats.expired);
+    println!("content size: {} bytes", stats.total_bytes);
+    println!("api keys:     {}", stats.api_keys);
+    if !stats.top_clips.is_empty() {
+        println!("top clips:");
+        for clip in &stats.top_clips {
+            let title = clip.title.clone().into_inner().unwrap_or_default();
+            println!("  {:>8}  {}  {}", clip.hits.clone().into_inner(), clip.shortcode.as_str(), title);
+        }
+    }
+}
+
+async fn run(command: Command, config: Config) -> Result<(), Box<dyn Error>> {
+    // these work on snapshot files, and must not hold the database open
+    match &command {
+        Command::VerifyBackup { snapshot } => {
+            print!("{}", action::verify_backup(snapshot).await?);
+            println!("{} is a valid snapshot", snapshot.display());
+            return Ok(());
+        }
+        Command::Restore { snapshot } => {
+            let target = backup::sqlite_path(&config.database.url)
+                .ok_or("restore needs a file-backed SQLite database")?;
+            print!("{}", action::restore_backup(snapshot, &target).await?);
+            println!("restored {} from {}", target.display(), snapshot.display());
+            return Ok(());
+        }
+        _ => (),
+    }
+
+    let database = AppDatabase::connect(&config.database.url, config.database.pool_size).await?;
+    // rotate-key works on the raw, encrypted rows; everything else sees plaintext
+    let database = match (&command, &config.encryption.keyfile) {
+        (Command::RotateKey { .. }, _) | (_, None) => database,
+        (_, Some(keyfile)) => database.with_envelope(keyfile)?,
+    };
+    let repo = database.repository();
+    // nobody watches clips from this process; a running server learns of changes when clips are read
+    let changes = Changes::default();
+    match command {
+        Command::ApiKey(ApiKeyCommand::Create) => {
+            println!("{}", action::generate_api_key(repo).await?.to_base64());
+        }
+        Command::ApiKey(ApiKeyCommand::List) => {
+            for api_key in action::list_api_keys(repo).await? {
+                println!("{}", api_key.to_base64());
+            }
+        }
+        Command::ApiKey(ApiKeyCommand::Revoke { api_key }) => {
+            match action::revoke_api_key(api_key, repo).await? {
+                RevocationStatus::Revoked => println!("API key revoked"),
+                RevocationStatus::NotFound => return Err("API key not found".into()),
+            }
+        }
+        Command::PurgeExpired => {
+            println!("deleted {} expired clips", action::delete_expires(&changes, repo).await?);
+            println!("deleted {} expired collections", action::delete_expired_collections(repo).await?);
+        }
+        Command::Delete { shortcode } => {
+            action::delete_clip(&shortcode, &changes, repo).await?;
+            println!("deleted clip {}", shortcode.as_str());
+        }
+        Command::Stats { top } => {
+            print_stats(&action::stats(top, repo).await?);
+        }
+        Command::Export { owner, since, until, output } => {
+            let filter = ExportFilter { owner, since, until };
+            let out: Box<dyn AsyncWrite + Unpin + Send> = match output {
+                Some(path) => Box::new(tokio::fs::File::create(path).await?),
+                None => Box::new(tokio::io::stdout()),
+            };
+            let exported = action::export_clips(&filter, out, repo).await?;
+            eprintln!("exported {} clips", exported);
+        }
+        Command::Import { input, on_conflict } => {
+            let input: Box<dyn AsyncBufRead + Unpin + Send> = match input.to_str() {
+                Some("-") => Box::new(BufReader::new(tokio::io::stdin())),
+                _ => Box::new(BufReader::new(tokio::fs::File::open(input).await?)),
+            };
+            print!("{}", action::import_clips(input, on_conflict, &config.shortcode, repo).await?);
+        }
+        Command::SealProtected => {
+            println!("sealed {} protected clips", action::seal_protected_clips(repo).await?);
+        }
+        Command::Backup { dir, keep } => {
+            let mut policy = config.backup.policy();
+            policy.dir = dir.unwrap_or(policy.dir);
+            policy.keep = keep.unwrap_or(policy.keep);
+            let snapshot = action::backup(&policy, repo).await?;
+            println!("wrote {} ({} bytes)", snapshot.path.display(), snapshot.bytes);
+            for old in snapshot.removed {
+                println!("removed {}", old.display());
+            }
+        }
+        Command::VerifyBackup { .. } | Command::Restore { .. } => unreachable!("handled before connecting"),
+        Command::Migrate { status } => {
+            let report = match status {
+                true => action::migration_status(repo).await?,
+                false => action::migrate(repo).await?,
+            };
+            print!("{}", report);
+            if !report.is_up_to_date() {
+                return Err(format!("pending migrations {:?}", report.pending()).into());
+            }
+        }
+        Command::RotateKey { keyfile, batch_size, prune } => {
+            let keyfile = keyfile
+                .or(config.encryption.keyfile)
+                .ok_or("no keyfile; pass --keyfile or set encryption.keyfile")?;
+            let report = envelope::rotate(repo, &keyfile, batch_size, prune).await?;
+            print!("{}", report);
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    dotenv().ok();
+
+    let opt = Opt::from_args();
+    let config = match load_config(&opt) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
+    if let Err(e) = rt.block_on(run(opt.command, config)) {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
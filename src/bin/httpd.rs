@@ -1,17 +1,68 @@
-use clipstash::data::AppDatabase;
+use clipstash::config::{Config, DEFAULT_CONFIG_FILE};
+use clipstash::data::{AppDatabase, MEMORY_URL};
 use clipstash::web::{renderer::Renderer, hitcounter::HitCounter};
 use dotenv::dotenv;
+use std::net::IpAddr;
 use std::path::PathBuf;
+use rocket::figment::providers::Serialized;
+use rocket::figment::Figment;
 use structopt::StructOpt;
 use clipstash::domain::maintenance::Maintenance;
+use clipstash::service::live::Changes;
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "httpd")]
 struct Opt {
-    #[structopt(default_value = "sqlite:data.db")]
-    connection_string: String,
-    #[structopt(short, long, parse(from_os_str), default_value = "templates/")]
-    template_directory: PathBuf,
+    #[structopt(help = "database connection string [default: sqlite:data.db]")]
+    connection_string: Option<String>,
+    #[structopt(short, long, parse(from_os_str), default_value = DEFAULT_CONFIG_FILE, env = "CLIPSTASH_CONFIG")]
+    config: PathBuf,
+    #[structopt(short, long, parse(from_os_str))]
+    template_directory: Option<PathBuf>,
+    #[structopt(short, long, parse(from_os_str))]
+    static_directory: Option<PathBuf>,
+    #[structopt(short, long)]
+    address: Option<IpAddr>,
+    #[structopt(short, long)]
+    port: Option<u16>,
+    #[structopt(long)]
+    pool_size: Option<u32>,
+    #[structopt(long, conflicts_with = "no-migrate", help = "apply pending migrations before serving (the default)")]
+    migrate: bool,
+    #[structopt(long, help = "do not apply pending migrations; refuse to start if any are pending")]
+    no_migrate: bool,
+    #[structopt(long, help = "keep all data in memory; nothing survives a restart")]
+    ephemeral: bool,
+    #[structopt(long, help = "print the effective configuration and exit")]
+    print_config: bool,
+}
+
+impl Opt {
+    /// Command line flags take precedence over the config file and environment.
+    fn merge_into(&self, mut figment: Figment) -> Figment {
+        if let Some(url) = &self.connection_string {
+            figment = figment.merge(Serialized::default("database.url", url));
+        }
+        if let Some(dir) = &self.template_directory {
+            figment = figment.merge(Serialized::default("paths.template_dir", dir));
+        }
+        if let Some(dir) = &self.static_directory {
+            figment = figment.merge(Serialized::default("paths.static_dir", dir));
+        }
+        if let Some(address) = &self.address {
+            figment = figment.merge(Serialized::default("server.address", address));
+        }
+        if let Some(port) = self.port {
+            figment = figment.merge(Serialized::default("server.port", port));
+        }
+        if let Some(pool_size) = self.pool_size {
+            figment = figment.merge(Serialized::default("database.pool_size", pool_size));
+        }
+        if self.ephemeral {
+            figment = figment.merge(Serialized::default("database.url", MEMORY_URL));
+        }
+        figment
+    }
 }
 
 fn main() {
@@ -19,26 +70,100 @@ fn main() {
 
     let opt = Opt::from_args();
 
+    let config = match Config::from_figment(&opt.merge_into(Config::figment(&opt.config))) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if opt.print_config {
+        print!("{}", config.to_toml());
+        return;
+    }
+
+    if let Err(e) = config.require_secret_key() {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+
     let rt = tokio::runtime::Runtime::new().expect("Failed to create runtime");
 
     let handle = rt.handle().clone();
-    let renderer = Renderer::new(opt.template_directory.clone());
+    let renderer = Renderer::new(config.paths.template_dir.clone());
 
-    let database = rt.block_on(async move {
-        AppDatabase::new(&opt.connection_string).await
+    let database = rt.block_on(async {
+        let database = match AppDatabase::connect(&config.database.url, config.database.pool_size).await {
+            Ok(database) => database,
+            Err(e) => {
+                eprintln!("Failed to open database '{}': {}", config.database.url, e);
+                std::process::exit(1);
+            }
+        };
+
+        let report = if opt.migrate || !opt.no_migrate {
+            database.migrate().await
+        } else {
+            database.migration_status().await
+        };
+        match report {
+            Ok(report) => {
+                println!("Migration status:\n{}", report);
+                if !report.is_up_to_date() {
+                    eprintln!("Pending migrations {:?}; run without --no-migrate to apply them", report.pending());
+                    std::process::exit(1);
+                }
+            }
+            Err(e) => {
+                eprintln!("Migration failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        match &config.encryption.keyfile {
+            Some(keyfile) => database.with_envelope(keyfile).unwrap_or_else(|e| {
+                eprintln!("Failed to enable encryption at rest: {}", e);
+                std::process::exit(1);
+            }),
+            None => database,
+        }
     });
 
-    let hit_counter = HitCounter::new(database.get_pool().clone(), handle.clone());
-    let maintenance = Maintenance::spawn(database.get_pool().clone(), handle.clone());
+    let changes = Changes::default();
+    let hit_counter = HitCounter::new(database.clone(), changes.clone(), handle.clone(), config.workers.hit_flush_interval());
+    let mut maintenance = Maintenance::spawn(database.clone(), changes.clone(), handle.clone(), config.workers.maintenance_interval())
+        .with_webhooks(database.clone(), handle.clone(), config.webhooks, config.workers.webhook_interval());
+    if let Some(period) = config.backup.interval() {
+        maintenance = maintenance.with_backups(database.clone(), handle.clone(), config.backup.policy(), period);
+    }
 
     let config = clipstash::RocketConfig {
+        figment: config.rocket_figment(),
+        static_dir: config.paths.static_dir,
         renderer,
         database,
         hit_counter,
-        maintenance
+        maintenance,
+        webhooks: config.webhooks,
+        shortcode: config.shortcode,
+        changes,
     };
 
     rt.block_on(async move{
         clipstash::rocket(config).launch().await.expect("Failed to launch Rocket");
     });
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn migrate_flags_conflict() {
+        let opt = |args: &[&str]| Opt::from_iter_safe(std::iter::once("httpd").chain(args.iter().copied()));
+        assert!(opt(&[]).is_ok_and(|opt| !opt.migrate && !opt.no_migrate));
+        assert!(opt(&["--migrate"]).is_ok_and(|opt| opt.migrate));
+        assert!(opt(&["--no-migrate"]).is_ok_and(|opt| opt.no_migrate));
+        assert!(opt(&["--migrate", "--no-migrate"]).is_err());
+    }
+}
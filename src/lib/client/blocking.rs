@@ -0,0 +1,72 @@
+//! A blocking wrapper around [`super::ClipstashClient`] for code without an async runtime.
+//!
+//! Like `reqwest::blocking`, it must not be used from within an async runtime.
+
+use tokio::runtime::Runtime;
+use crate::service::archive::ArchivedClip;
+use crate::service::ask::{GetClip, NewClip, UpdateClip};
+use crate::web::api::ApiKey;
+use crate::{Clip, Time};
+use super::ClientError;
+
+type Result<T> = std::result::Result<T, ClientError>;
+
+#[derive(Debug)]
+pub struct ClipstashClient {
+    inner: super::ClipstashClient,
+    runtime: Runtime,
+}
+
+impl ClipstashClient {
+    pub fn new<A: Into<String>>(addr: A) -> Result<Self> {
+        Ok(Self::from_async(super::ClipstashClient::new(addr)?))
+    }
+
+    pub fn from_async(inner: super::ClipstashClient) -> Self {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Failed to create runtime");
+        Self { inner, runtime }
+    }
+
+    pub fn with_api_key(self, api_key: ApiKey) -> Self {
+        Self { inner: self.inner.with_api_key(api_key), ..self }
+    }
+
+    pub fn with_retries(self, retries: u32) -> Self {
+        Self { inner: self.inner.with_retries(retries), ..self }
+    }
+
+    pub fn with_observer<F: Fn(&super::Event<'_>) + Send + Sync + 'static>(self, observer: F) -> Self {
+        Self { inner: self.inner.with_observer(observer), ..self }
+    }
+
+    pub fn addr(&self) -> &str {
+        self.inner.addr()
+    }
+
+    pub fn clip_url(&self, clip: &Clip) -> String {
+        self.inner.clip_url(clip)
+    }
+
+    pub fn get_clip(&self, req: GetClip) -> Result<Clip> {
+        self.runtime.block_on(self.inner.get_clip(req))
+    }
+
+    pub fn new_clip(&self, req: NewClip) -> Result<Clip> {
+        self.runtime.block_on(self.inner.new_clip(req))
+    }
+
+    pub fn update_clip(&self, req: UpdateClip) -> Result<Clip> {
+        self.runtime.block_on(self.inner.update_clip(req))
+    }
+
+    pub fn new_api_key(&self) -> Result<String> {
+        self.runtime.block_on(self.inner.new_api_key())
+    }
+
+    pub fn export_clips(&self, since: Option<Time>, until: Option<Time>) -> Result<Vec<ArchivedClip>> {
+        self.runtime.block_on(self.inner.export_clips(since, until))
+    }
+}
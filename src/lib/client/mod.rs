@@ -0,0 +1,342 @@
+//! An HTTP client for the `/api` routes, sharing request and response types with the server.
+
+pub mod blocking;
+
+use std::sync::Arc;
+use std::time::Duration;
+use reqwest::header::HeaderMap;
+use reqwest::{Method, Request, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use crate::service::archive::ArchivedClip;
+use crate::service::ask::{GetClip, NewClip, UpdateClip};
+use crate::web::api::{ApiKey, ErrorEnvelope, API_KEY_HEADER};
+use crate::web::conditional::etag;
+use crate::web::PASSWORD_COOKIE;
+use crate::{Clip, Time};
+
+pub const DEFAULT_ADDR: &str = "http://127.0.0.1:8000";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error("cannot reach the server: {0}")]
+    Connection(reqwest::Error),
+    #[error("request rejected ({0}): {1}")]
+    BadRequest(StatusCode, String),
+    #[error("not authorized ({0}): {1}")]
+    Unauthorized(StatusCode, String),
+    #[error("not found: {0}")]
+    NotFound(String),
+    #[error("conflict: {0}")]
+    Conflict(String),
+    #[error("too many requests: {0}")]
+    TooManyRequests(String),
+    #[error("server error ({0}): {1}")]
+    Server(StatusCode, String),
+    #[error("unexpected response ({0}): {1}")]
+    Unexpected(StatusCode, String),
+    #[error("invalid response: {0}")]
+    Decode(String),
+    #[error("no API key configured")]
+    MissingApiKey,
+    #[error("http error: {0}")]
+    Http(#[from] reqwest::Error),
+}
+
+impl ClientError {
+    /// Builds the error from a failed response, using the message of the server's error envelope.
+    pub fn from_response(status: StatusCode, body: &str) -> Self {
+        let message = match serde_json::from_str::<ErrorEnvelope>(body) {
+            Ok(envelope) => format!("{} ({})", envelope.error.message, envelope.error.code),
+            // servers before /api/v1 answered with a bare JSON string
+            Err(_) => serde_json::from_str::<String>(body).unwrap_or_else(|_| body.to_owned()),
+        };
+        match status {
+            StatusCode::BAD_REQUEST | StatusCode::PAYLOAD_TOO_LARGE | StatusCode::UNPROCESSABLE_ENTITY => {
+                Self::BadRequest(status, message)
+            }
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Self::Unauthorized(status, message),
+            StatusCode::NOT_FOUND => Self::NotFound(message),
+            StatusCode::CONFLICT | StatusCode::PRECONDITION_FAILED | StatusCode::PRECONDITION_REQUIRED => {
+                Self::Conflict(message)
+            }
+            StatusCode::TOO_MANY_REQUESTS => Self::TooManyRequests(message),
+            status if status.is_server_error() => Self::Server(status, message),
+            status => Self::Unexpected(status, message),
+        }
+    }
+
+    /// Connection failures, rate limiting and server errors may succeed when tried again.
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Connection(_) | Self::TooManyRequests(_) | Self::Server(..))
+    }
+}
+
+type Result<T> = std::result::Result<T, ClientError>;
+
+/// What a client reports to the observer set with [`ClipstashClient::with_observer`].
+#[derive(Debug)]
+pub enum Event<'a> {
+    /// About to be sent; headers include the API key and password cookie.
+    Request(&'a Request),
+    Response { status: StatusCode, headers: &'a HeaderMap, body: &'a str },
+    Retry { error: &'a ClientError, delay: Duration },
+}
+
+#[derive(Clone)]
+struct Observer(Arc<dyn Fn(&Event<'_>) + Send + Sync>);
+
+impl std::fmt::Debug for Observer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Observer")
+    }
+}
+
+/// Async client for a clipstash server. Cheap to clone; clones share a connection pool.
+#[derive(Debug, Clone)]
+pub struct ClipstashClient {
+    http: reqwest::Client,
+    addr: String,
+    api_key: Option<ApiKey>,
+    retries: u32,
+    observer: Option<Observer>,
+}
+
+impl ClipstashClient {
+    const BACKOFF: Duration = Duration::from_millis(250);
+
+    /// A client for the server at `addr`, such as `http://127.0.0.1:8000`.
+    pub fn new<A: Into<String>>(addr: A) -> Result<Self> {
+        Ok(Self {
+            http: reqwest::Client::builder().build()?,
+            addr: addr.into().trim_end_matches('/').to_owned(),
+            api_key: None,
+            retries: 3,
+            observer: None,
+        })
+    }
+
+    pub fn with_api_key(mut self, api_key: ApiKey) -> Self {
+        self.api_key = Some(api_key);
+        self
+    }
+
+    /// How often a request is retried after a connection error, 429 or 5xx response, with exponential backoff.
+    /// Requests that are not idempotent, such as creating a clip, are only retried when the connection
+    /// failed before anything was sent.
+    pub fn with_retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Calls `observer` with every request, response and retry, such as to log them.
+    pub fn with_observer<F: Fn(&Event<'_>) + Send + Sync + 'static>(mut self, observer: F) -> Self {
+        self.observer = Some(Observer(Arc::new(observer)));
+        self
+    }
+
+    fn observe(&self, event: Event<'_>) {
+        if let Some(Observer(observer)) = &self.observer {
+            observer(&event);
+        }
+    }
+
+    pub fn addr(&self) -> &str {
+        &self.addr
+    }
+
+    /// The link to a clip's page.
+    pub fn clip_url(&self, clip: &Clip) -> String {
+        format!("{}/clip/{}", self.addr, clip.shortcode.as_str())
+    }
+
+    pub async fn get_clip(&self, req: GetClip) -> Result<Clip> {
+        let url = format!("{}/api/v1/clip/{}", self.addr, req.shortcode.into_inner());
+        let mut request = self.http.get(url);
+        if let Some(password) = req.password.into_inner() {
+            request = request.header(reqwest::header::COOKIE, format!("{}={}", PASSWORD_COOKIE, password));
+        }
+        self.json(self.authorized(request)?).await
+    }
+
+    pub async fn new_clip(&self, req: NewClip) -> Result<Clip> {
+        let request = self.http.post(format!("{}/api/v1/clip", self.addr)).json(&req);
+        self.json(self.authorized(request)?).await
+    }
+
+    /// Updates a clip if it is still at `req.revision`, or unconditionally when that is `None`.
+    pub async fn update_clip(&self, req: UpdateClip) -> Result<Clip> {
+        let if_match = req.revision.map_or_else(|| "*".to_owned(), etag);
+        let request = self.http
+            .put(format!("{}/api/v1/clip", self.addr))
+            .header(reqwest::header::IF_MATCH, if_match)
+            .json(&req);
+        self.json(self.authorized(request)?).await
+    }
+
+    /// Asks the server to generate an API key; the key is printed in the server log, not returned.
+    pub async fn new_api_key(&self) -> Result<String> {
+        self.json(self.http.get(format!("{}/api/v1/clip/key", self.addr))).await
+    }
+
+    /// The clips created with this client's API key, optionally limited to a posted date range.
+    pub async fn export_clips(&self, since: Option<Time>, until: Option<Time>) -> Result<Vec<ArchivedClip>> {
+        let date = |time: Time| time.into_inner().format("%Y-%m-%d").to_string();
+        let query: Vec<(&str, String)> = [("since", since.map(date)), ("until", until.map(date))]
+            .into_iter()
+            .filter_map(|(name, value)| value.map(|value| (name, value)))
+            .collect();
+        let request = self.http.get(format!("{}/api/v1/export", self.addr)).query(&query);
+        let body = self.send(self.authorized(request)?).await?;
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).map_err(|e| ClientError::Decode(e.to_string())))
+            .collect()
+    }
+
+    fn authorized(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        let api_key = self.api_key.as_ref().ok_or(ClientError::MissingApiKey)?;
+        Ok(request.header(API_KEY_HEADER, api_key.to_base64()))
+    }
+
+    async fn json<T: DeserializeOwned>(&self, request: RequestBuilder) -> Result<T> {
+        let body = self.send(request).await?;
+        serde_json::from_str(&body).map_err(|e| ClientError::Decode(e.to_string()))
+    }
+
+    /// Sends a request and returns the body of a successful response.
+    async fn send(&self, request: RequestBuilder) -> Result<String> {
+        let request = request.build()?;
+        let mut attempt = 0;
+        loop {
+            let retry = request.try_clone().expect("request bodies are buffered");
+            self.observe(Event::Request(&retry));
+            let error = match self.http.execute(retry).await {
+                Ok(response) => {
+                    let status = response.status();
+                    let headers = response.headers().clone();
+                    let body = response.text().await.map_err(ClientError::Connection)?;
+                    self.observe(Event::Response { status, headers: &headers, body: &body });
+                    if status.is_success() {
+                        return Ok(body);
+                    }
+                    ClientError::from_response(status, &body)
+                }
+                Err(e) if e.is_connect() || e.is_timeout() => ClientError::Connection(e),
+                Err(e) => return Err(e.into()),
+            };
+            if !may_retry(request.method(), &error) || attempt >= self.retries {
+                return Err(error);
+            }
+            let delay = backoff(attempt);
+            self.observe(Event::Retry { error: &error, delay });
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        }
+    }
+}
+
+/// A request that reached the server may have taken effect, so only idempotent ones are sent again.
+fn may_retry(method: &Method, error: &ClientError) -> bool {
+    match error {
+        ClientError::Connection(e) if e.is_connect() => true,
+        error => method.is_idempotent() && error.is_transient(),
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    ClipstashClient::BACKOFF * 2u32.pow(attempt.min(5))
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::test::async_runtime;
+
+    #[test]
+    fn reads_api_error_bodies() {
+        let body = r#"{"error":{"code":"invalid_password","message":"Invalid password","details":null}}"#;
+        let error = ClientError::from_response(StatusCode::FORBIDDEN, body);
+        assert!(matches!(error, ClientError::Unauthorized(_, ref message) if message == "Invalid password (invalid_password)"));
+        let error = ClientError::from_response(StatusCode::NOT_FOUND, r#""entity not found""#);
+        assert!(matches!(error, ClientError::NotFound(ref message) if message == "entity not found"));
+        assert!(ClientError::from_response(StatusCode::BAD_GATEWAY, "<html>").is_transient());
+    }
+
+    #[test]
+    fn requires_api_key_and_reports_connection_errors() {
+        let rt = async_runtime();
+        // nothing listens on port 1
+        let client = ClipstashClient::new("http://127.0.0.1:1/").unwrap().with_retries(0);
+        assert_eq!(client.addr(), "http://127.0.0.1:1");
+        let missing = rt.block_on(client.get_clip("aaa".into()));
+        assert!(matches!(missing, Err(ClientError::MissingApiKey)));
+        let client = client.with_api_key(ApiKey::default());
+        let unreachable = rt.block_on(client.get_clip("aaa".into()));
+        assert!(matches!(unreachable, Err(ClientError::Connection(_))));
+    }
+
+    /// Answers every request with `status` and counts them.
+    fn serve(status: &'static str) -> (String, std::sync::Arc<std::sync::atomic::AtomicUsize>) {
+        use std::io::{Read, Write};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = format!("http://{}", listener.local_addr().unwrap());
+        let requests = std::sync::Arc::new(AtomicUsize::new(0));
+        let counted = requests.clone();
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                let mut buf = [0; 4096];
+                let _ = stream.read(&mut buf);
+                counted.fetch_add(1, Ordering::SeqCst);
+                let response = format!("HTTP/1.1 {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n", status);
+                let _ = stream.write_all(response.as_bytes());
+            }
+        });
+        (addr, requests)
+    }
+
+    #[test]
+    fn retries_only_idempotent_requests_after_a_response() {
+        use std::sync::atomic::Ordering;
+
+        let rt = async_runtime();
+        let new_clip = || serde_json::from_str::<NewClip>(r#"{"content":"x","title":null,"expires":null,"password":null}"#).unwrap();
+        for status in ["503 Service Unavailable", "429 Too Many Requests"] {
+            let (addr, requests) = serve(status);
+            let client = ClipstashClient::new(addr).unwrap().with_api_key(ApiKey::default()).with_retries(1);
+            assert!(rt.block_on(client.new_clip(new_clip())).unwrap_err().is_transient());
+            assert_eq!(requests.load(Ordering::SeqCst), 1);
+            assert!(rt.block_on(client.get_clip("aaa".into())).unwrap_err().is_transient());
+            assert_eq!(requests.load(Ordering::SeqCst), 3);
+        }
+
+        let refused = rt.block_on(ClipstashClient::new("http://127.0.0.1:1").unwrap().with_retries(0).new_api_key()).unwrap_err();
+        assert!(may_retry(&Method::POST, &refused));
+        let not_found = ClientError::from_response(StatusCode::NOT_FOUND, "");
+        assert!(!may_retry(&Method::GET, &not_found));
+    }
+
+    #[test]
+    fn reports_requests_responses_and_retries() {
+        let rt = async_runtime();
+        let (addr, _) = serve("503 Service Unavailable");
+        let events = Arc::new(parking_lot::Mutex::new(vec![]));
+        let seen = Arc::clone(&events);
+        let client = ClipstashClient::new(addr).unwrap().with_api_key(ApiKey::default()).with_retries(1).with_observer(move |event| {
+            seen.lock().push(match event {
+                Event::Request(request) => request.method().to_string(),
+                Event::Response { status, .. } => status.as_u16().to_string(),
+                Event::Retry { .. } => "retry".to_owned(),
+            });
+        });
+        assert!(rt.block_on(client.get_clip("aaa".into())).is_err());
+        assert_eq!(*events.lock(), ["GET", "503", "retry", "GET", "503"]);
+    }
+
+    #[test]
+    fn backs_off_exponentially_up_to_a_limit() {
+        let delays: Vec<_> = (0..8).map(backoff).map(|delay| delay.as_millis()).collect();
+        assert_eq!(delays, [250, 500, 1000, 2000, 4000, 8000, 8000, 8000]);
+    }
+}
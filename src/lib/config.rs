@@ -0,0 +1,329 @@
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use rocket::data::{ByteUnit, Limits};
+use rocket::figment::providers::{Env, Format, Serialized, Toml};
+use rocket::figment::Figment;
+use serde::{Deserialize, Serialize};
+use crate::data::backup::BackupPolicy;
+use crate::domain::clip::field::ShortCodePolicy;
+use crate::domain::webhook::ReceiverPolicy;
+
+pub const DEFAULT_CONFIG_FILE: &str = "clipstash.toml";
+pub const ENV_PREFIX: &str = "CLIPSTASH_";
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("configuration error: {0}")]
+    Figment(Box<rocket::figment::Error>),
+    #[error("invalid configuration: {0}")]
+    Invalid(String),
+}
+
+impl From<rocket::figment::Error> for ConfigError {
+    fn from(err: rocket::figment::Error) -> Self {
+        Self::Figment(Box::new(err))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ServerConfig {
+    pub address: IpAddr,
+    pub port: u16,
+    pub secret_key: Option<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            address: IpAddr::from([127, 0, 0, 1]),
+            port: 8000,
+            secret_key: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub url: String,
+    pub pool_size: u32,
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        Self {
+            url: "sqlite:data.db".to_owned(),
+            pool_size: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PathsConfig {
+    pub static_dir: PathBuf,
+    pub template_dir: PathBuf,
+}
+
+impl Default for PathsConfig {
+    fn default() -> Self {
+        Self {
+            static_dir: "static/".into(),
+            template_dir: "templates/".into(),
+        }
+    }
+}
+
+/// Intervals of the background workers, in seconds.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WorkersConfig {
+    pub hit_flush_interval: u64,
+    pub maintenance_interval: u64,
+    pub webhook_interval: u64,
+}
+
+impl WorkersConfig {
+    pub fn hit_flush_interval(&self) -> Duration {
+        Duration::from_secs(self.hit_flush_interval)
+    }
+
+    pub fn maintenance_interval(&self) -> Duration {
+        Duration::from_secs(self.maintenance_interval)
+    }
+
+    pub fn webhook_interval(&self) -> Duration {
+        Duration::from_secs(self.webhook_interval)
+    }
+}
+
+impl Default for WorkersConfig {
+    fn default() -> Self {
+        Self {
+            hit_flush_interval: 5,
+            maintenance_interval: 10,
+            webhook_interval: 5,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LimitsConfig {
+    pub json: ByteUnit,
+    pub form: ByteUnit,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            json: Limits::JSON,
+            form: Limits::FORM,
+        }
+    }
+}
+
+/// SQLite snapshots; `interval` is in seconds, and 0 leaves backups to `clipstash-admin backup`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackupConfig {
+    pub dir: PathBuf,
+    pub keep: usize,
+    pub interval: u64,
+}
+
+impl BackupConfig {
+    pub fn policy(&self) -> BackupPolicy {
+        BackupPolicy {
+            dir: self.dir.clone(),
+            keep: self.keep,
+        }
+    }
+
+    /// How often the maintenance worker takes a snapshot, if at all.
+    pub fn interval(&self) -> Option<Duration> {
+        (self.interval > 0).then(|| Duration::from_secs(self.interval))
+    }
+}
+
+impl Default for BackupConfig {
+    fn default() -> Self {
+        Self {
+            dir: "backups/".into(),
+            keep: 7,
+            interval: 0,
+        }
+    }
+}
+
+/// Encryption at rest of clip content and titles; disabled unless a keyfile is set.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EncryptionConfig {
+    pub keyfile: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub database: DatabaseConfig,
+    pub paths: PathsConfig,
+    pub workers: WorkersConfig,
+    pub limits: LimitsConfig,
+    pub shortcode: ShortCodePolicy,
+    pub encryption: EncryptionConfig,
+    pub backup: BackupConfig,
+    pub webhooks: ReceiverPolicy,
+}
+
+impl Config {
+    /// Defaults, then the TOML file (if present), then `CLIPSTASH_SECTION__KEY` variables.
+    pub fn figment<P: AsRef<Path>>(path: P) -> Figment {
+        Figment::from(Serialized::defaults(Config::default()))
+            .merge(Toml::file(path))
+            .merge(Env::prefixed(ENV_PREFIX).split("__"))
+    }
+
+    pub fn from_figment(figment: &Figment) -> Result<Self, ConfigError> {
+        let config: Config = figment.extract()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        Self::from_figment(&Self::figment(path))
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.database.pool_size == 0 {
+            return Err(ConfigError::Invalid("database.pool_size must be at least 1".to_owned()));
+        }
+        if [self.workers.hit_flush_interval, self.workers.maintenance_interval, self.workers.webhook_interval].contains(&0) {
+            return Err(ConfigError::Invalid("worker intervals must be at least 1 second".to_owned()));
+        }
+        if self.backup.keep == 0 {
+            return Err(ConfigError::Invalid("backup.keep must be at least 1".to_owned()));
+        }
+        self.shortcode.validate().map_err(|e| ConfigError::Invalid(e.to_string()))
+    }
+
+    /// The Rocket figment for the server section and request limits.
+    pub fn rocket_figment(&self) -> Figment {
+        let limits = Limits::default()
+            .limit("json", self.limits.json)
+            .limit("form", self.limits.form);
+        let figment = rocket::Config::figment()
+            .merge(("address", self.server.address))
+            .merge(("port", self.server.port))
+            .merge(("limits", limits));
+        match &self.server.secret_key {
+            Some(key) => figment.merge(("secret_key", key)),
+            None => figment,
+        }
+    }
+
+    /// Release builds need `server.secret_key` for the private cookies; Rocket would refuse to
+    /// launch without one anyway, but without naming the setting to fix.
+    pub fn require_secret_key(&self) -> Result<(), ConfigError> {
+        Self::check_secret_key(&self.rocket_figment())
+    }
+
+    fn check_secret_key(figment: &Figment) -> Result<(), ConfigError> {
+        let rocket = rocket::Config::try_from(figment)?;
+        if rocket.profile == rocket::Config::DEBUG_PROFILE || rocket.secret_key.is_provided() {
+            return Ok(());
+        }
+        Err(ConfigError::Invalid(format!(
+            "server.secret_key is required in the '{}' profile; generate one with `openssl rand -base64 32` \
+             and set it in the config file or CLIPSTASH_SERVER__SECRET_KEY",
+            rocket.profile
+        )))
+    }
+
+    /// The effective configuration as TOML, with the secret key redacted.
+    pub fn to_toml(&self) -> String {
+        let mut config = self.clone();
+        if config.server.secret_key.is_some() {
+            config.server.secret_key = Some("<redacted>".to_owned());
+        }
+        toml::to_string_pretty(&config).expect("config is serializable")
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn figment(toml: &str, env_prefix: &str) -> Figment {
+        Figment::from(Serialized::defaults(Config::default()))
+            .merge(Toml::string(toml))
+            .merge(Env::prefixed(env_prefix).split("__"))
+    }
+
+    #[test]
+    fn defaults_are_valid() {
+        let config = Config::from_figment(&figment("", "CLIPSTASH_TEST_DEFAULTS_")).unwrap();
+        assert_eq!(config.server.port, 8000);
+        assert_eq!(config.database.url, "sqlite:data.db");
+        assert_eq!(config.shortcode.length, 10);
+    }
+
+    #[test]
+    fn layers_file_then_env_then_flags() {
+        std::env::set_var("CLIPSTASH_TEST_LAYERS_DATABASE__POOL_SIZE", "3");
+        std::env::set_var("CLIPSTASH_TEST_LAYERS_SERVER__PORT", "9000");
+        let toml = r#"
+            [server]
+            port = 8080
+
+            [database]
+            url = "sqlite:other.db"
+            pool_size = 20
+
+            [workers]
+            maintenance_interval = 60
+        "#;
+        let figment = figment(toml, "CLIPSTASH_TEST_LAYERS_")
+            .merge(Serialized::default("server.port", 9100));
+        let config = Config::from_figment(&figment).unwrap();
+
+        assert_eq!(config.database.url, "sqlite:other.db");
+        assert_eq!(config.database.pool_size, 3);
+        assert_eq!(config.workers.maintenance_interval, 60);
+        assert_eq!(config.workers.hit_flush_interval, 5);
+        assert_eq!(config.server.port, 9100);
+    }
+
+    #[test]
+    fn rejects_invalid_shortcode_policy() {
+        let toml = r#"
+            [shortcode]
+            alphabet = "a"
+        "#;
+        assert!(Config::from_figment(&figment(toml, "CLIPSTASH_TEST_INVALID_")).is_err());
+    }
+
+    #[test]
+    fn redacts_secret_key() {
+        let mut config = Config::default();
+        config.server.secret_key = Some("hunter2".to_owned());
+        let printed = config.to_toml();
+        assert!(!printed.contains("hunter2"));
+        assert!(printed.contains("[database]"));
+    }
+
+    #[test]
+    fn release_profile_requires_secret_key() {
+        let mut config = Config::default();
+        let release = |config: &Config| config.rocket_figment().select(rocket::Config::RELEASE_PROFILE);
+        let err = Config::check_secret_key(&release(&config)).unwrap_err();
+        assert!(err.to_string().contains("server.secret_key"));
+
+        config.server.secret_key = Some(base64::encode([7u8; 32]));
+        assert!(Config::check_secret_key(&release(&config)).is_ok());
+        assert!(Config::check_secret_key(&Config::default().rocket_figment().select(rocket::Config::DEBUG_PROFILE)).is_ok());
+    }
+}
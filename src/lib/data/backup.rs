@@ -0,0 +1,205 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use sqlx::Row;
+use crate::data::migrate::{self, MigrationReport, MIGRATOR};
+use crate::data::repository::Repository;
+use crate::data::{DataError, DatabasePool};
+
+type Result<T> = std::result::Result<T, DataError>;
+
+const PREFIX: &str = "clipstash-";
+const EXTENSION: &str = "db";
+
+/// Where snapshots go and how many of them to keep.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupPolicy {
+    pub dir: PathBuf,
+    pub keep: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub path: PathBuf,
+    pub bytes: u64,
+    /// Older snapshots deleted to stay within [`BackupPolicy::keep`].
+    pub removed: Vec<PathBuf>,
+}
+
+fn backup_error<E: std::fmt::Display>(path: &Path, e: E) -> DataError {
+    DataError::Backup(format!("{}: {}", path.display(), e))
+}
+
+/// The file behind a `sqlite:` connection string, or `None` for in-memory databases.
+pub fn sqlite_path(connection_str: &str) -> Option<PathBuf> {
+    let path = connection_str
+        .strip_prefix("sqlite://")
+        .or_else(|| connection_str.strip_prefix("sqlite:"))
+        .unwrap_or(connection_str);
+    let path = path.split('?').next().unwrap_or_default();
+    match path {
+        "" | ":memory:" => None,
+        path => Some(PathBuf::from(path)),
+    }
+}
+
+/// Snapshots, oldest first.
+pub fn snapshots(dir: &Path) -> Result<Vec<PathBuf>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(backup_error(dir, e)),
+    };
+    let mut snapshots: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.extension().is_some_and(|ext| ext == EXTENSION)
+                && path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with(PREFIX))
+        })
+        .collect();
+    // timestamped names sort chronologically
+    snapshots.sort();
+    Ok(snapshots)
+}
+
+/// Writes a consistent copy of the live database into `policy.dir`, checks it, and removes
+/// the oldest snapshots beyond `policy.keep`.
+pub async fn snapshot<R: Repository + ?Sized>(repo: &R, policy: &BackupPolicy) -> Result<Snapshot> {
+    fs::create_dir_all(&policy.dir).map_err(|e| backup_error(&policy.dir, e))?;
+    let name = format!("{}{}", PREFIX, Utc::now().format("%Y%m%dT%H%M%S%3fZ"));
+    let path = policy.dir.join(&name).with_extension(EXTENSION);
+    let partial = path.with_extension("partial");
+
+    repo.backup_into(&partial).await?;
+    if let Err(e) = integrity_check(&partial).await {
+        let _ = fs::remove_file(&partial);
+        return Err(e);
+    }
+    fs::rename(&partial, &path).map_err(|e| backup_error(&path, e))?;
+    let bytes = fs::metadata(&path).map_err(|e| backup_error(&path, e))?.len();
+
+    let mut removed = vec![];
+    let existing = snapshots(&policy.dir)?;
+    for old in existing.iter().take(existing.len().saturating_sub(policy.keep.max(1))) {
+        fs::remove_file(old).map_err(|e| backup_error(old, e))?;
+        removed.push(old.clone());
+    }
+    Ok(Snapshot { path, bytes, removed })
+}
+
+async fn open_read_only(path: &Path) -> Result<DatabasePool> {
+    if !path.is_file() {
+        return Err(backup_error(path, "no such file"));
+    }
+    let options = SqliteConnectOptions::from_str("sqlite:")?
+        .filename(path)
+        .read_only(true);
+    Ok(SqlitePoolOptions::new().max_connections(1).connect_with(options).await?)
+}
+
+async fn integrity_check(path: &Path) -> Result<()> {
+    let pool = open_read_only(path).await?;
+    let problems: Vec<String> = sqlx::query("PRAGMA integrity_check")
+        .fetch_all(&pool)
+        .await?
+        .iter()
+        .map(|row| row.get(0))
+        .filter(|result: &String| result != "ok")
+        .collect();
+    pool.close().await;
+    match problems.is_empty() {
+        true => Ok(()),
+        false => Err(backup_error(path, format!("integrity check failed: {}", problems.join("; ")))),
+    }
+}
+
+/// Checks a snapshot's integrity and that its schema was written by migrations this build knows.
+///
+/// Older snapshots pass; their pending migrations are applied when `httpd` next starts.
+pub async fn verify(path: &Path) -> Result<MigrationReport> {
+    integrity_check(path).await?;
+    let pool = open_read_only(path).await?;
+    let applied = sqlx::query("SELECT version, checksum FROM _sqlx_migrations WHERE success = 1 ORDER BY version")
+        .fetch_all(&pool)
+        .await
+        .map_err(|e| backup_error(path, format!("not a clipstash database: {}", e)))?;
+    pool.close().await;
+
+    let mut versions = vec![];
+    for row in &applied {
+        let version: i64 = row.get(0);
+        let checksum: Vec<u8> = row.get(1);
+        match MIGRATOR.iter().find(|m| m.version == version) {
+            None => return Err(backup_error(path, format!("migration {} is newer than this build", version))),
+            Some(m) if *m.checksum != checksum[..] => {
+                return Err(backup_error(path, format!("migration {} differs from this build", version)))
+            }
+            Some(_) => versions.push(version),
+        }
+    }
+    Ok(migrate::report(&MIGRATOR, &versions, &versions))
+}
+
+/// Replaces the database at `target` with a verified snapshot. `httpd` must not be running.
+pub async fn restore(snapshot: &Path, target: &Path) -> Result<MigrationReport> {
+    let report = verify(snapshot).await?;
+    let restoring = target.with_extension("restoring");
+    fs::copy(snapshot, &restoring).map_err(|e| backup_error(&restoring, e))?;
+    for suffix in ["-wal", "-shm"] {
+        let mut journal = target.as_os_str().to_owned();
+        journal.push(suffix);
+        match fs::remove_file(&journal) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(backup_error(Path::new(&journal), e)),
+            _ => (),
+        }
+    }
+    fs::rename(&restoring, target).map_err(|e| backup_error(target, e))?;
+    Ok(report)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::data::sqlite::SqliteRepository;
+    use crate::data::DbId;
+    use crate::test::async_runtime;
+
+    #[test]
+    fn finds_sqlite_path() {
+        assert_eq!(sqlite_path("sqlite:data.db"), Some(PathBuf::from("data.db")));
+        assert_eq!(sqlite_path("sqlite:///tmp/x.db?mode=rwc"), Some(PathBuf::from("/tmp/x.db")));
+        assert_eq!(sqlite_path("sqlite::memory:"), None);
+    }
+
+    #[test]
+    fn snapshots_rotate_verify_and_restore() {
+        let dir = std::env::temp_dir().join(format!("clipstash-backup-test-{}", DbId::new()));
+        let policy = BackupPolicy { dir: dir.clone(), keep: 2 };
+        let rt = async_runtime();
+        rt.block_on(async {
+            let live = dir.join("live.db");
+            fs::create_dir_all(&dir).unwrap();
+            let repo = SqliteRepository::connect(&format!("sqlite:{}", live.display()), 1).await.unwrap();
+            repo.migrate().await.unwrap();
+
+            let mut taken = vec![];
+            for _ in 0..3 {
+                taken.push(snapshot(&repo, &policy).await.unwrap());
+            }
+            assert_eq!(taken[2].removed, vec![taken[0].path.clone()]);
+            assert_eq!(snapshots(&dir).unwrap(), vec![taken[1].path.clone(), taken[2].path.clone()]);
+
+            assert!(verify(&taken[2].path).await.unwrap().is_up_to_date());
+            fs::write(dir.join("clipstash-bogus.db"), b"not a database").unwrap();
+            assert!(verify(&dir.join("clipstash-bogus.db")).await.is_err());
+
+            let restored = dir.join("restored.db");
+            assert!(restore(&taken[2].path, &restored).await.unwrap().is_up_to_date());
+            assert!(verify(&restored).await.is_ok());
+        });
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,665 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use parking_lot::RwLock;
+use rand::RngCore;
+use crate::data::migrate::MigrationReport;
+use crate::data::repository::{ApiKeyRepository, ClipRepository, CollectionRepository, Repository, RevocationStatus, WebhookRepository};
+use crate::data::{model, DataError};
+use crate::web::api::ApiKey;
+use crate::ShortCode;
+
+type Result<T> = std::result::Result<T, DataError>;
+
+/// Marks an encrypted value: `$enc1$<master key id>$<wrapped data key>$<ciphertext>`.
+const PREFIX: &str = "$enc1$";
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 24;
+
+fn encryption_error<E: fmt::Display>(e: E) -> DataError {
+    DataError::Encryption(e.to_string())
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+/// Encrypts with a fresh nonce, returning `nonce || ciphertext`.
+fn encrypt(key: &Key, plaintext: &[u8]) -> Vec<u8> {
+    let nonce = random_bytes::<NONCE_LEN>();
+    let ciphertext = XChaCha20Poly1305::new(key)
+        .encrypt(XNonce::from_slice(&nonce), plaintext)
+        .expect("encryption does not fail for in-memory buffers");
+    [nonce.as_slice(), ciphertext.as_slice()].concat()
+}
+
+fn decrypt(key: &Key, sealed: &[u8]) -> Result<Vec<u8>> {
+    if sealed.len() <= NONCE_LEN {
+        return Err(encryption_error("ciphertext is too short"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+    XChaCha20Poly1305::new(key)
+        .decrypt(XNonce::from_slice(nonce), ciphertext)
+        .map_err(|_| encryption_error("unable to decrypt value"))
+}
+
+#[derive(Clone)]
+struct MasterKey {
+    id: String,
+    key: Key,
+}
+
+impl fmt::Debug for MasterKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MasterKey").field("id", &self.id).finish_non_exhaustive()
+    }
+}
+
+impl MasterKey {
+    fn generate() -> Self {
+        let id = random_bytes::<4>().iter().map(|b| format!("{:02x}", b)).collect();
+        Self { id, key: random_bytes::<KEY_LEN>().into() }
+    }
+}
+
+/// The master keys of a keyfile. The first key encrypts new values; the rest only decrypt.
+///
+/// A keyfile has one `<id> <base64 key>` pair per line; blank lines and `#` comments are ignored.
+#[derive(Debug, Clone, Default)]
+pub struct Keyring {
+    keys: Vec<MasterKey>,
+}
+
+impl Keyring {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut keys = vec![];
+        for line in text.lines().map(str::trim).filter(|l| !l.is_empty() && !l.starts_with('#')) {
+            let (id, key) = line
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| encryption_error("keyfile lines must be '<id> <base64 key>'"))?;
+            let key = base64::decode(key.trim()).map_err(encryption_error)?;
+            if key.len() != KEY_LEN || id.contains('$') {
+                return Err(encryption_error(format!("invalid master key '{}'", id)));
+            }
+            keys.push(MasterKey { id: id.to_owned(), key: *Key::from_slice(&key) });
+        }
+        Ok(Self { keys })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(|e| encryption_error(format!("unable to read keyfile '{}': {}", path.display(), e)))?;
+        let keyring = Self::parse(&text)?;
+        match keyring.keys.is_empty() {
+            true => Err(encryption_error(format!("keyfile '{}' has no keys", path.display()))),
+            false => Ok(keyring),
+        }
+    }
+
+    /// Writes the keyfile atomically, readable only by its owner.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let write = |options: &fs::OpenOptions| -> std::io::Result<()> {
+            use std::io::Write;
+            options.open(&tmp)?.write_all(self.to_string().as_bytes())?;
+            fs::rename(&tmp, path)
+        };
+        write(&options).map_err(|e| encryption_error(format!("unable to write keyfile '{}': {}", path.display(), e)))
+    }
+
+    /// Adds a new active key and returns its id.
+    pub fn rotate(&mut self) -> &str {
+        self.keys.insert(0, MasterKey::generate());
+        &self.keys[0].id
+    }
+
+    pub fn active_id(&self) -> Option<&str> {
+        self.keys.first().map(|key| key.id.as_str())
+    }
+
+    pub fn ids(&self) -> impl Iterator<Item = &str> {
+        self.keys.iter().map(|key| key.id.as_str())
+    }
+
+    pub fn contains(&self, id: &str) -> bool {
+        self.keys.iter().any(|key| key.id == id)
+    }
+
+    /// Drops every key for which `keep` returns false, except the active one.
+    pub fn retain<F: Fn(&str) -> bool>(&mut self, keep: F) {
+        let active = self.active_id().map(str::to_owned);
+        self.keys.retain(|key| Some(&key.id) == active.as_ref() || keep(&key.id));
+    }
+
+    /// The id of the master key that `value` was encrypted under, if it is encrypted at all.
+    pub fn key_id(value: &str) -> Option<&str> {
+        value.strip_prefix(PREFIX)?.split('$').next()
+    }
+
+    /// Encrypts `plaintext` under a fresh data key, wrapped by the active master key.
+    pub fn seal(&self, plaintext: &str) -> Result<String> {
+        let master = self.keys.first().ok_or_else(|| encryption_error("keyring is empty"))?;
+        let data_key: Key = random_bytes::<KEY_LEN>().into();
+        Ok(format!(
+            "{}{}${}${}",
+            PREFIX,
+            master.id,
+            base64::encode(encrypt(&master.key, &data_key)),
+            base64::encode(encrypt(&data_key, plaintext.as_bytes())),
+        ))
+    }
+
+    /// Decrypts a sealed value; values stored before encryption was enabled are returned as-is.
+    pub fn open(&self, value: &str) -> Result<String> {
+        let rest = match value.strip_prefix(PREFIX) {
+            Some(rest) => rest,
+            None => return Ok(value.to_owned()),
+        };
+        let mut parts = rest.splitn(3, '$');
+        let (id, wrapped, ciphertext) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(id), Some(wrapped), Some(ciphertext)) => (id, wrapped, ciphertext),
+            _ => return Err(encryption_error("malformed encrypted value")),
+        };
+        let master = self.keys
+            .iter()
+            .find(|key| key.id == id)
+            .ok_or_else(|| encryption_error(format!("unknown master key '{}'", id)))?;
+        let data_key = decrypt(&master.key, &base64::decode(wrapped).map_err(encryption_error)?)?;
+        if data_key.len() != KEY_LEN {
+            return Err(encryption_error("invalid data key"));
+        }
+        let plaintext = decrypt(Key::from_slice(&data_key), &base64::decode(ciphertext).map_err(encryption_error)?)?;
+        String::from_utf8(plaintext).map_err(encryption_error)
+    }
+
+    fn open_clip(&self, mut clip: model::Clip) -> Result<model::Clip> {
+        clip.content = self.open(&clip.content)?;
+        clip.title = clip.title.map(|title| self.open(&title)).transpose()?;
+        Ok(clip)
+    }
+}
+
+impl fmt::Display for Keyring {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "# clipstash master keys; the first one encrypts new data")?;
+        for key in &self.keys {
+            writeln!(f, "{} {}", key.id, base64::encode(key.key))?;
+        }
+        Ok(())
+    }
+}
+
+/// The keyring as last read, with the keyfile's modification time at that point.
+struct LoadedKeyring {
+    keyring: Arc<Keyring>,
+    modified: Option<SystemTime>,
+}
+
+/// Encrypts clip content and titles at rest on top of any backend.
+///
+/// The keyfile is read again when a value references an unknown key, and before sealing when it
+/// has changed, so a running server follows `clipstash-admin rotate-key` without a restart.
+pub struct EnvelopeRepository {
+    inner: Arc<dyn Repository>,
+    keyfile: PathBuf,
+    loaded: RwLock<LoadedKeyring>,
+}
+
+impl EnvelopeRepository {
+    pub fn new<P: Into<PathBuf>>(inner: Arc<dyn Repository>, keyfile: P) -> Result<Self> {
+        let keyfile = keyfile.into();
+        let modified = modified(&keyfile);
+        let keyring = Arc::new(Keyring::load(&keyfile)?);
+        Ok(Self { inner, keyfile, loaded: RwLock::new(LoadedKeyring { keyring, modified }) })
+    }
+
+    fn keyring(&self) -> Arc<Keyring> {
+        self.loaded.read().keyring.clone()
+    }
+
+    fn reload(&self) -> Result<Arc<Keyring>> {
+        let modified = modified(&self.keyfile);
+        let keyring = Arc::new(Keyring::load(&self.keyfile)?);
+        *self.loaded.write() = LoadedKeyring { keyring: keyring.clone(), modified };
+        Ok(keyring)
+    }
+
+    /// The keyring to seal new values with, read again if the keyfile changed since, so new rows
+    /// are never sealed under a key that a rotation has retired.
+    fn sealing_keyring(&self) -> Result<Arc<Keyring>> {
+        let modified = modified(&self.keyfile);
+        match modified.is_some() && modified != self.loaded.read().modified {
+            true => self.reload(),
+            false => Ok(self.keyring()),
+        }
+    }
+
+    /// The keyring able to open `clip`, reloading the keyfile if it names a key we do not know yet.
+    fn keyring_for(&self, clip: &model::Clip) -> Result<Arc<Keyring>> {
+        let keyring = self.keyring();
+        let unknown = [Some(clip.content.as_str()), clip.title.as_deref()]
+            .into_iter()
+            .flatten()
+            .filter_map(Keyring::key_id)
+            .any(|id| !keyring.contains(id));
+        match unknown {
+            true => self.reload(),
+            false => Ok(keyring),
+        }
+    }
+
+    fn open_clip(&self, clip: model::Clip) -> Result<model::Clip> {
+        self.keyring_for(&clip)?.open_clip(clip)
+    }
+}
+
+#[rocket::async_trait]
+impl ClipRepository for EnvelopeRepository {
+    async fn get_clip(&self, model: model::GetClip) -> Result<model::Clip> {
+        self.open_clip(self.inner.get_clip(model).await?)
+    }
+
+    async fn get_clip_by_id(&self, clip_id: &str) -> Result<model::Clip> {
+        self.open_clip(self.inner.get_clip_by_id(clip_id).await?)
+    }
+
+    async fn new_clip(&self, mut model: model::NewClip) -> Result<model::Clip> {
+        let keyring = self.sealing_keyring()?;
+        model.content = keyring.seal(&model.content)?;
+        model.title = model.title.map(|title| keyring.seal(&title)).transpose()?;
+        self.open_clip(self.inner.new_clip(model).await?)
+    }
+
+    async fn replace_clip(&self, mut model: model::NewClip) -> Result<model::Clip> {
+        let keyring = self.sealing_keyring()?;
+        model.content = keyring.seal(&model.content)?;
+        model.title = model.title.map(|title| keyring.seal(&title)).transpose()?;
+        self.open_clip(self.inner.replace_clip(model).await?)
+    }
+
+    async fn update_clip(&self, mut model: model::UpdateClip) -> Result<model::Clip> {
+        let keyring = self.sealing_keyring()?;
+        model.content = keyring.seal(&model.content)?;
+        model.title = model.title.map(|title| keyring.seal(&title)).transpose()?;
+        self.open_clip(self.inner.update_clip(model).await?)
+    }
+
+    async fn list_clips(&self, after: Option<String>, limit: u32) -> Result<Vec<model::Clip>> {
+        self.inner
+            .list_clips(after, limit)
+            .await?
+            .into_iter()
+            .map(|clip| self.open_clip(clip))
+            .collect()
+    }
+
+    /// Passed through untouched: callers rewrite raw, already encrypted values.
+    async fn rewrite_clip_text(&self, model: model::RewriteClipText) -> Result<bool> {
+        self.inner.rewrite_clip_text(model).await
+    }
+
+    async fn seal_clip_content(&self, mut model: model::SealClipContent) -> Result<bool> {
+        model.content = self.sealing_keyring()?.seal(&model.content)?;
+        self.inner.seal_clip_content(model).await
+    }
+
+    async fn increase_hit_count(&self, shortcode: &ShortCode, hits: u32) -> Result<()> {
+        self.inner.increase_hit_count(shortcode, hits).await
+    }
+
+    async fn delete_expired(&self) -> Result<Vec<model::Clip>> {
+        self.inner
+            .delete_expired()
+            .await?
+            .into_iter()
+            .map(|clip| self.open_clip(clip))
+            .collect()
+    }
+
+    async fn delete_clip(&self, shortcode: &ShortCode) -> Result<()> {
+        self.inner.delete_clip(shortcode).await
+    }
+
+    async fn clip_stats(&self) -> Result<model::ClipStats> {
+        self.inner.clip_stats().await
+    }
+
+    async fn top_clips(&self, limit: u32) -> Result<Vec<model::Clip>> {
+        self.inner
+            .top_clips(limit)
+            .await?
+            .into_iter()
+            .map(|clip| self.open_clip(clip))
+            .collect()
+    }
+
+    async fn list_forks(&self, clip_id: &str) -> Result<Vec<model::Clip>> {
+        self.inner
+            .list_forks(clip_id)
+            .await?
+            .into_iter()
+            .map(|clip| self.open_clip(clip))
+            .collect()
+    }
+
+    async fn clip_tags(&self, clip_id: &str) -> Result<Vec<String>> {
+        self.inner.clip_tags(clip_id).await
+    }
+
+    async fn find_clips(&self, filter: model::ClipFilter) -> Result<Vec<model::Clip>> {
+        self.inner
+            .find_clips(filter)
+            .await?
+            .into_iter()
+            .map(|clip| self.open_clip(clip))
+            .collect()
+    }
+
+    async fn recent_clips(&self, limit: u32) -> Result<Vec<model::Clip>> {
+        self.inner
+            .recent_clips(limit)
+            .await?
+            .into_iter()
+            .map(|clip| self.open_clip(clip))
+            .collect()
+    }
+}
+
+#[rocket::async_trait]
+impl ApiKeyRepository for EnvelopeRepository {
+    async fn save_api_key(&self, api_key: ApiKey) -> Result<ApiKey> {
+        self.inner.save_api_key(api_key).await
+    }
+
+    async fn revoke_api_key(&self, api_key: ApiKey) -> Result<RevocationStatus> {
+        self.inner.revoke_api_key(api_key).await
+    }
+
+    async fn api_key_is_valid(&self, api_key: ApiKey) -> Result<bool> {
+        self.inner.api_key_is_valid(api_key).await
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        self.inner.list_api_keys().await
+    }
+}
+
+/// Webhooks and event payloads hold no clip text, so they are stored as they are.
+#[rocket::async_trait]
+impl WebhookRepository for EnvelopeRepository {
+    async fn new_webhook(&self, model: model::NewWebhook) -> Result<model::Webhook> {
+        self.inner.new_webhook(model).await
+    }
+
+    async fn list_webhooks(&self, owner: &str) -> Result<Vec<model::Webhook>> {
+        self.inner.list_webhooks(owner).await
+    }
+
+    async fn delete_webhook(&self, owner: &str, webhook_id: &str) -> Result<()> {
+        self.inner.delete_webhook(owner, webhook_id).await
+    }
+
+    async fn enqueue_event(&self, model: model::NewEvent) -> Result<u64> {
+        self.inner.enqueue_event(model).await
+    }
+
+    async fn due_deliveries(&self, now: i64, limit: u32) -> Result<Vec<model::PendingDelivery>> {
+        self.inner.due_deliveries(now, limit).await
+    }
+
+    async fn record_attempt(&self, model: model::DeliveryAttempt) -> Result<()> {
+        self.inner.record_attempt(model).await
+    }
+
+    async fn list_deliveries(&self, owner: &str, webhook_id: &str, limit: u32) -> Result<Vec<model::Delivery>> {
+        self.inner.list_deliveries(owner, webhook_id, limit).await
+    }
+}
+
+/// Collection titles are stored as they are, since key rotation only rewrites clips; the clips in
+/// a collection are opened like any other.
+#[rocket::async_trait]
+impl CollectionRepository for EnvelopeRepository {
+    async fn new_collection(&self, model: model::NewCollection) -> Result<model::Collection> {
+        self.inner.new_collection(model).await
+    }
+
+    async fn get_collection(&self, shortcode: &str) -> Result<model::Collection> {
+        self.inner.get_collection(shortcode).await
+    }
+
+    async fn collection_clips(&self, collection_id: &str) -> Result<Vec<model::Clip>> {
+        self.inner
+            .collection_clips(collection_id)
+            .await?
+            .into_iter()
+            .map(|clip| self.open_clip(clip))
+            .collect()
+    }
+
+    async fn delete_expired_collections(&self) -> Result<u64> {
+        self.inner.delete_expired_collections().await
+    }
+}
+
+#[rocket::async_trait]
+impl Repository for EnvelopeRepository {
+    async fn ping(&self) -> Result<()> {
+        self.inner.ping().await
+    }
+
+    async fn migrate(&self) -> Result<MigrationReport> {
+        self.inner.migrate().await
+    }
+
+    async fn migration_status(&self) -> Result<MigrationReport> {
+        self.inner.migration_status().await
+    }
+
+    /// Snapshots keep clips encrypted; the keyfile must be backed up separately.
+    async fn backup_into(&self, path: &Path) -> Result<()> {
+        self.inner.backup_into(path).await
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct RotationReport {
+    pub key_id: String,
+    pub batches: u64,
+    pub rewritten: u64,
+    /// Rows changed by someone else while being re-encrypted; they were left as written.
+    pub skipped: u64,
+    pub pruned: Vec<String>,
+}
+
+impl fmt::Display for RotationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "active key: {}", self.key_id)?;
+        writeln!(f, "re-encrypted {} clips in {} batches", self.rewritten, self.batches)?;
+        if self.skipped > 0 {
+            writeln!(f, "skipped {} clips modified during rotation", self.skipped)?;
+        }
+        if !self.pruned.is_empty() {
+            writeln!(f, "pruned retired keys: {}", self.pruned.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Adds a new master key to `keyfile` (creating it if needed) and re-encrypts every clip under it,
+/// `batch_size` rows at a time. `repo` must be the raw backend, not an [`EnvelopeRepository`].
+///
+/// With `prune`, retired keys that no row refers to any more are removed from the keyfile. The key
+/// that was active until now is always kept: a server may still be sealing a row with it.
+pub async fn rotate(repo: &dyn Repository, keyfile: &Path, batch_size: u32, prune: bool) -> Result<RotationReport> {
+    let mut keyring = match keyfile.exists() {
+        true => Keyring::load(keyfile)?,
+        false => Keyring::default(),
+    };
+    let previous = keyring.active_id().map(str::to_owned);
+    let mut report = RotationReport {
+        key_id: keyring.rotate().to_owned(),
+        ..Default::default()
+    };
+    keyring.save(keyfile)?;
+
+    let is_current = |value: &str| Keyring::key_id(value) == Some(report.key_id.as_str());
+    let mut after = None;
+    loop {
+        let batch = repo.list_clips(after.take(), batch_size.max(1)).await?;
+        let last = match batch.last() {
+            Some(clip) => clip.clip_id.clone(),
+            None => break,
+        };
+        report.batches += 1;
+        for clip in batch {
+            if is_current(&clip.content) && clip.title.as_deref().is_none_or(is_current) {
+                continue;
+            }
+            let expected_content = clip.content.clone();
+            let shortcode = clip.shortcode.clone();
+            let clip = keyring.open_clip(clip)?;
+            let rewrite = model::RewriteClipText {
+                shortcode,
+                expected_content,
+                content: keyring.seal(&clip.content)?,
+                title: clip.title.map(|title| keyring.seal(&title)).transpose()?,
+            };
+            match repo.rewrite_clip_text(rewrite).await? {
+                true => report.rewritten += 1,
+                false => report.skipped += 1,
+            }
+        }
+        after = Some(last);
+    }
+
+    if prune {
+        let in_use = keys_in_use(repo, batch_size).await?;
+        let before: Vec<String> = keyring.ids().map(str::to_owned).collect();
+        keyring.retain(|id| Some(id) == previous.as_deref() || in_use.iter().any(|used| used == id));
+        report.pruned = before.into_iter().filter(|id| !keyring.contains(id)).collect();
+        keyring.save(keyfile)?;
+    }
+    Ok(report)
+}
+
+async fn keys_in_use(repo: &dyn Repository, batch_size: u32) -> Result<Vec<String>> {
+    let mut in_use: Vec<String> = vec![];
+    let mut after = None;
+    loop {
+        let batch = repo.list_clips(after.take(), batch_size.max(1)).await?;
+        let last = match batch.last() {
+            Some(clip) => clip.clip_id.clone(),
+            None => return Ok(in_use),
+        };
+        for clip in &batch {
+            for id in [Some(clip.content.as_str()), clip.title.as_deref()].into_iter().flatten().filter_map(Keyring::key_id) {
+                if !in_use.iter().any(|used| used == id) {
+                    in_use.push(id.to_owned());
+                }
+            }
+        }
+        after = Some(last);
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::data::memory::MemoryRepository;
+    use crate::data::DbId;
+    use futures::executor::block_on;
+
+    fn keyfile() -> PathBuf {
+        std::env::temp_dir().join(format!("clipstash-test-{}.keys", DbId::new()))
+    }
+
+    fn new_clip(content: &str) -> model::NewClip {
+        model::NewClip {
+            clip_id: DbId::new().into(),
+            shortcode: ShortCode::new().into_inner(),
+            content: content.to_owned(),
+            title: Some("title".to_owned()),
+            posted: chrono::Utc::now().timestamp(),
+            expires: None,
+            password: None,
+            content_nonce: None,
+            content_salt: None,
+            encrypted: false,
+            hits: 0,
+            owner: None,
+            parent_clip_id: None,
+            visibility: "unlisted".to_owned(),
+            tags: vec![],
+        }
+    }
+
+    #[test]
+    fn seals_and_opens_values() {
+        let mut keyring = Keyring::default();
+        assert!(keyring.seal("secret").is_err());
+        keyring.rotate();
+
+        let sealed = keyring.seal("secret").unwrap();
+        assert!(!sealed.contains("secret"));
+        assert_eq!(Keyring::key_id(&sealed), keyring.active_id());
+        assert_eq!(keyring.open(&sealed).unwrap(), "secret");
+        assert_eq!(keyring.open("stored before encryption").unwrap(), "stored before encryption");
+
+        let reloaded = Keyring::parse(&keyring.to_string()).unwrap();
+        assert_eq!(reloaded.open(&sealed).unwrap(), "secret");
+        assert!(Keyring::parse(&format!("{}\n", MasterKey::generate().id)).is_err());
+    }
+
+    #[test]
+    fn encrypts_at_rest_and_rotates() {
+        let path = keyfile();
+        let raw = Arc::new(MemoryRepository::new());
+        block_on(async {
+            raw.new_clip(new_clip("plaintext from before")).await.unwrap();
+            let first = rotate(raw.as_ref(), &path, 1, false).await.unwrap();
+            assert_eq!(first.rewritten, 1);
+
+            let repo = EnvelopeRepository::new(raw.clone(), &path).unwrap();
+            let clip = repo.new_clip(new_clip("top secret")).await.unwrap();
+            assert_eq!(clip.content, "top secret");
+            let stored = raw.get_clip(clip.shortcode.clone().into()).await.unwrap();
+            assert!(!stored.content.contains("top secret"));
+            assert!(!stored.title.unwrap().contains("title"));
+
+            let second = rotate(raw.as_ref(), &path, 1, true).await.unwrap();
+            assert_eq!((second.rewritten, second.batches), (2, 2));
+            // the key active until now is kept for servers still sealing with it
+            assert!(second.pruned.is_empty());
+
+            // the repository reloads the keyfile to read rows under the new key
+            let clip = repo.get_clip(clip.shortcode.into()).await.unwrap();
+            assert_eq!(clip.content, "top secret");
+            // and seals new rows under it
+            let newer = repo.new_clip(new_clip("newer")).await.unwrap();
+            let stored = raw.get_clip(newer.shortcode.into()).await.unwrap();
+            assert_eq!(Keyring::key_id(&stored.content), Some(second.key_id.as_str()));
+
+            let third = rotate(raw.as_ref(), &path, 1, true).await.unwrap();
+            assert_eq!(third.pruned, vec![first.key_id]);
+            assert_eq!(Keyring::load(&path).unwrap().ids().count(), 2);
+        });
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,474 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use parking_lot::RwLock;
+use crate::data::migrate::MigrationReport;
+use crate::data::repository::{ApiKeyRepository, ClipRepository, CollectionRepository, Repository, RevocationStatus, WebhookRepository};
+use crate::data::{model, DataError};
+use crate::web::api::ApiKey;
+use crate::ShortCode;
+
+type Result<T> = std::result::Result<T, DataError>;
+
+/// Process-local storage for tests, demos and `httpd --ephemeral`; everything is lost on exit.
+#[derive(Default)]
+pub struct MemoryRepository {
+    clips: RwLock<HashMap<String, model::Clip>>,
+    /// Tags of each clip id.
+    tags: RwLock<HashMap<String, Vec<String>>>,
+    api_keys: RwLock<HashSet<Vec<u8>>>,
+    webhooks: RwLock<Vec<model::Webhook>>,
+    deliveries: RwLock<Vec<model::Delivery>>,
+    collections: RwLock<HashMap<String, model::Collection>>,
+    /// Clip ids of each collection, in order.
+    collection_clips: RwLock<HashMap<String, Vec<String>>>,
+}
+
+fn timestamp(secs: i64) -> NaiveDateTime {
+    DateTime::from_timestamp(secs, 0).unwrap_or_default().naive_utc()
+}
+
+impl MemoryRepository {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Removes a clip and its tags, unlinking its forks as ON DELETE SET NULL does.
+    fn remove_clip(&self, clips: &mut HashMap<String, model::Clip>, shortcode: &str) -> Option<model::Clip> {
+        let removed = clips.remove(shortcode)?;
+        self.tags.write().remove(&removed.clip_id);
+        for clip in clips.values_mut().filter(|clip| clip.parent_clip_id.as_ref() == Some(&removed.clip_id)) {
+            clip.parent_clip_id = None;
+        }
+        Some(removed)
+    }
+
+    fn insert_clip(&self, model: model::NewClip, replace: bool) -> Result<model::Clip> {
+        let clip = model::Clip {
+            clip_id: model.clip_id,
+            shortcode: model.shortcode,
+            content: model.content,
+            title: model.title,
+            posted: timestamp(model.posted),
+            expires: model.expires.map(timestamp),
+            password: model.password,
+            hits: model.hits,
+            content_nonce: model.content_nonce,
+            content_salt: model.content_salt,
+            encrypted: model.encrypted,
+            owner: model.owner,
+            revision: 1,
+            updated: None,
+            parent_clip_id: model.parent_clip_id,
+            visibility: model.visibility,
+        };
+        let mut clips = self.clips.write();
+        if replace {
+            self.remove_clip(&mut clips, &clip.shortcode);
+        } else if clips.contains_key(&clip.shortcode) {
+            return Err(DataError::Conflict(format!("shortcode '{}' already exists", clip.shortcode)));
+        }
+        clips.insert(clip.shortcode.clone(), clip.clone());
+        if !model.tags.is_empty() {
+            self.tags.write().insert(clip.clip_id.clone(), model.tags);
+        }
+        Ok(clip)
+    }
+}
+
+#[rocket::async_trait]
+impl ClipRepository for MemoryRepository {
+    async fn get_clip(&self, model: model::GetClip) -> Result<model::Clip> {
+        self.clips
+            .read()
+            .get(&model.shortcode)
+            .cloned()
+            .ok_or(DataError::Database(sqlx::Error::RowNotFound))
+    }
+
+    async fn get_clip_by_id(&self, clip_id: &str) -> Result<model::Clip> {
+        self.clips
+            .read()
+            .values()
+            .find(|clip| clip.clip_id == clip_id)
+            .cloned()
+            .ok_or(DataError::Database(sqlx::Error::RowNotFound))
+    }
+
+    async fn new_clip(&self, model: model::NewClip) -> Result<model::Clip> {
+        self.insert_clip(model, false)
+    }
+
+    async fn replace_clip(&self, model: model::NewClip) -> Result<model::Clip> {
+        self.insert_clip(model, true)
+    }
+
+    async fn update_clip(&self, model: model::UpdateClip) -> Result<model::Clip> {
+        let mut clips = self.clips.write();
+        let clip = clips
+            .get_mut(&model.shortcode)
+            .ok_or(DataError::Database(sqlx::Error::RowNotFound))?;
+        match model.expected_revision {
+            Some(expected) if expected != clip.revision => {
+                return Err(DataError::RevisionMismatch { expected, actual: clip.revision })
+            }
+            _ => (),
+        }
+        clip.content = model.content;
+        clip.title = model.title;
+        clip.expires = model.expires.map(timestamp);
+        clip.password = model.password;
+        clip.content_nonce = model.content_nonce;
+        clip.content_salt = model.content_salt;
+        clip.encrypted = model.encrypted;
+        if let Some(visibility) = model.visibility {
+            clip.visibility = visibility;
+        }
+        clip.revision += 1;
+        clip.updated = Some(timestamp(model.updated));
+        if let Some(tags) = model.tags {
+            self.tags.write().insert(clip.clip_id.clone(), tags);
+        }
+        Ok(clip.clone())
+    }
+
+    async fn list_clips(&self, after: Option<String>, limit: u32) -> Result<Vec<model::Clip>> {
+        let after = after.unwrap_or_default();
+        let mut clips: Vec<_> = self.clips
+            .read()
+            .values()
+            .filter(|clip| clip.clip_id > after)
+            .cloned()
+            .collect();
+        clips.sort_by(|a, b| a.clip_id.cmp(&b.clip_id));
+        clips.truncate(limit as usize);
+        Ok(clips)
+    }
+
+    async fn rewrite_clip_text(&self, model: model::RewriteClipText) -> Result<bool> {
+        match self.clips.write().get_mut(&model.shortcode) {
+            Some(clip) if clip.content == model.expected_content => {
+                clip.content = model.content;
+                clip.title = model.title;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn seal_clip_content(&self, model: model::SealClipContent) -> Result<bool> {
+        match self.clips.write().get_mut(&model.shortcode) {
+            Some(clip) if clip.password.as_ref() == Some(&model.expected_password) && clip.content_nonce.is_none() => {
+                clip.content = model.content;
+                clip.password = Some(model.password);
+                clip.content_nonce = Some(model.content_nonce);
+                clip.content_salt = Some(model.content_salt);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn increase_hit_count(&self, shortcode: &ShortCode, hits: u32) -> Result<()> {
+        if let Some(clip) = self.clips.write().get_mut(shortcode.as_str()) {
+            clip.hits += i64::from(hits);
+        }
+        Ok(())
+    }
+
+    /// Same rule as the SQL backends: a clip is gone once the current second is past `expires`.
+    async fn delete_expired(&self) -> Result<Vec<model::Clip>> {
+        let now = Utc::now().timestamp();
+        let mut clips = self.clips.write();
+        let expired: Vec<String> = clips
+            .values()
+            .filter(|clip| clip.expires.is_some_and(|expires| now > expires.and_utc().timestamp()))
+            .map(|clip| clip.shortcode.clone())
+            .collect();
+        let expired: Vec<_> = expired.iter().filter_map(|shortcode| clips.remove(shortcode)).collect();
+        let mut tags = self.tags.write();
+        for clip in &expired {
+            tags.remove(&clip.clip_id);
+        }
+        Ok(expired)
+    }
+
+    async fn delete_clip(&self, shortcode: &ShortCode) -> Result<()> {
+        let mut clips = self.clips.write();
+        match self.remove_clip(&mut clips, shortcode.as_str()) {
+            Some(_) => Ok(()),
+            None => Err(DataError::Database(sqlx::Error::RowNotFound)),
+        }
+    }
+
+    async fn clip_stats(&self) -> Result<model::ClipStats> {
+        let now = Utc::now().timestamp();
+        let clips = self.clips.read();
+        Ok(model::ClipStats {
+            clips: clips.len() as i64,
+            protected: clips.values().filter(|clip| clip.password.is_some()).count() as i64,
+            expired: clips.values().filter(|clip| clip.expires.is_some_and(|e| now > e.and_utc().timestamp())).count() as i64,
+            total_bytes: clips.values().map(|clip| clip.content.len() as i64).sum(),
+        })
+    }
+
+    async fn top_clips(&self, limit: u32) -> Result<Vec<model::Clip>> {
+        let mut clips: Vec<_> = self.clips.read().values().cloned().collect();
+        clips.sort_by(|a, b| b.hits.cmp(&a.hits).then(b.posted.cmp(&a.posted)));
+        clips.truncate(limit as usize);
+        Ok(clips)
+    }
+
+    async fn list_forks(&self, clip_id: &str) -> Result<Vec<model::Clip>> {
+        let mut forks: Vec<_> = self.clips.read().values().filter(|clip| clip.parent_clip_id.as_deref() == Some(clip_id)).cloned().collect();
+        forks.sort_by(|a, b| a.posted.cmp(&b.posted).then(a.clip_id.cmp(&b.clip_id)));
+        Ok(forks)
+    }
+
+    async fn clip_tags(&self, clip_id: &str) -> Result<Vec<String>> {
+        let mut tags = self.tags.read().get(clip_id).cloned().unwrap_or_default();
+        tags.sort();
+        Ok(tags)
+    }
+
+    async fn find_clips(&self, filter: model::ClipFilter) -> Result<Vec<model::Clip>> {
+        let now = Utc::now().timestamp();
+        let tags = self.tags.read();
+        let mut clips: Vec<_> = self.clips
+            .read()
+            .values()
+            .filter(|clip| clip.expires.is_none_or(|expires| now <= expires.and_utc().timestamp()))
+            .filter(|clip| filter.owner.is_none() || clip.owner == filter.owner)
+            .filter(|clip| filter.tag.as_ref().is_none_or(|tag| tags.get(&clip.clip_id).is_some_and(|tags| tags.contains(tag))))
+            .filter(|clip| !filter.public || (clip.visibility == "public" && clip.password.is_none() && !clip.encrypted))
+            .cloned()
+            .collect();
+        clips.sort_by(|a, b| b.posted.cmp(&a.posted).then(b.clip_id.cmp(&a.clip_id)));
+        clips.truncate(usize::try_from(filter.limit).unwrap_or_default());
+        Ok(clips)
+    }
+
+    async fn recent_clips(&self, limit: u32) -> Result<Vec<model::Clip>> {
+        self.find_clips(model::ClipFilter::public(None, limit)).await
+    }
+}
+
+#[rocket::async_trait]
+impl ApiKeyRepository for MemoryRepository {
+    async fn save_api_key(&self, api_key: ApiKey) -> Result<ApiKey> {
+        self.api_keys.write().insert(api_key.clone().into_inner());
+        Ok(api_key)
+    }
+
+    async fn revoke_api_key(&self, api_key: ApiKey) -> Result<RevocationStatus> {
+        Ok(match self.api_keys.write().remove(&api_key.into_inner()) {
+            true => RevocationStatus::Revoked,
+            false => RevocationStatus::NotFound,
+        })
+    }
+
+    async fn api_key_is_valid(&self, api_key: ApiKey) -> Result<bool> {
+        Ok(self.api_keys.read().contains(&api_key.into_inner()))
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        Ok(self.api_keys.read().iter().cloned().map(ApiKey::from).collect())
+    }
+}
+
+#[rocket::async_trait]
+impl WebhookRepository for MemoryRepository {
+    async fn new_webhook(&self, model: model::NewWebhook) -> Result<model::Webhook> {
+        let webhook = model::Webhook {
+            webhook_id: model.webhook_id,
+            owner: model.owner,
+            url: model.url,
+            secret: model.secret,
+            events: model.events,
+            created: timestamp(model.created),
+        };
+        self.webhooks.write().push(webhook.clone());
+        Ok(webhook)
+    }
+
+    async fn list_webhooks(&self, owner: &str) -> Result<Vec<model::Webhook>> {
+        Ok(self.webhooks.read().iter().filter(|webhook| webhook.owner == owner).cloned().collect())
+    }
+
+    async fn delete_webhook(&self, owner: &str, webhook_id: &str) -> Result<()> {
+        let mut webhooks = self.webhooks.write();
+        let before = webhooks.len();
+        webhooks.retain(|webhook| !(webhook.owner == owner && webhook.webhook_id == webhook_id));
+        if webhooks.len() == before {
+            return Err(DataError::Database(sqlx::Error::RowNotFound));
+        }
+        self.deliveries.write().retain(|delivery| delivery.webhook_id != webhook_id);
+        Ok(())
+    }
+
+    async fn enqueue_event(&self, model: model::NewEvent) -> Result<u64> {
+        let webhooks = self.webhooks.read();
+        let mut deliveries = self.deliveries.write();
+        let before = deliveries.len();
+        for webhook in webhooks.iter().filter(|webhook| webhook.owner == model.owner) {
+            if webhook.events.split(',').any(|event| event == model.event) {
+                deliveries.push(model::Delivery {
+                    webhook_id: webhook.webhook_id.clone(),
+                    event_id: model.event_id.clone(),
+                    event: model.event.clone(),
+                    payload: model.payload.clone(),
+                    created: timestamp(model.created),
+                    attempts: 0,
+                    next_attempt: Some(timestamp(model.created)),
+                    delivered: None,
+                    response_status: None,
+                    last_error: None,
+                });
+            }
+        }
+        Ok((deliveries.len() - before) as u64)
+    }
+
+    async fn due_deliveries(&self, now: i64, limit: u32) -> Result<Vec<model::PendingDelivery>> {
+        let webhooks = self.webhooks.read();
+        let mut due: Vec<_> = self.deliveries
+            .read()
+            .iter()
+            .filter(|delivery| delivery.next_attempt.is_some_and(|next| next <= timestamp(now)))
+            .cloned()
+            .collect();
+        due.sort_by_key(|delivery| delivery.next_attempt);
+        Ok(due
+            .into_iter()
+            .filter_map(|delivery| {
+                let webhook = webhooks.iter().find(|webhook| webhook.webhook_id == delivery.webhook_id)?;
+                Some(model::PendingDelivery {
+                    webhook_id: delivery.webhook_id,
+                    event_id: delivery.event_id,
+                    event: delivery.event,
+                    payload: delivery.payload,
+                    attempts: delivery.attempts,
+                    url: webhook.url.clone(),
+                    secret: webhook.secret.clone(),
+                })
+            })
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn record_attempt(&self, model: model::DeliveryAttempt) -> Result<()> {
+        let mut deliveries = self.deliveries.write();
+        let delivery = deliveries
+            .iter_mut()
+            .find(|delivery| delivery.webhook_id == model.webhook_id && delivery.event_id == model.event_id);
+        if let Some(delivery) = delivery {
+            delivery.attempts += 1;
+            delivery.delivered = model.delivered.map(timestamp);
+            delivery.next_attempt = model.next_attempt.map(timestamp);
+            delivery.response_status = model.response_status;
+            delivery.last_error = model.last_error;
+        }
+        Ok(())
+    }
+
+    async fn list_deliveries(&self, owner: &str, webhook_id: &str, limit: u32) -> Result<Vec<model::Delivery>> {
+        if !self.webhooks.read().iter().any(|webhook| webhook.owner == owner && webhook.webhook_id == webhook_id) {
+            return Ok(vec![]);
+        }
+        let mut deliveries: Vec<_> = self.deliveries
+            .read()
+            .iter()
+            .filter(|delivery| delivery.webhook_id == webhook_id)
+            .cloned()
+            .collect();
+        deliveries.sort_by(|a, b| b.created.cmp(&a.created).then(a.event_id.cmp(&b.event_id)));
+        deliveries.truncate(limit as usize);
+        Ok(deliveries)
+    }
+}
+
+#[rocket::async_trait]
+impl CollectionRepository for MemoryRepository {
+    async fn new_collection(&self, model: model::NewCollection) -> Result<model::Collection> {
+        let collection = model::Collection {
+            collection_id: model.collection_id,
+            shortcode: model.shortcode,
+            title: model.title,
+            posted: timestamp(model.posted),
+            expires: model.expires.map(timestamp),
+            owner: model.owner,
+        };
+        let mut collections = self.collections.write();
+        if collections.contains_key(&collection.shortcode) {
+            return Err(DataError::Conflict(format!("shortcode '{}' already exists", collection.shortcode)));
+        }
+        collections.insert(collection.shortcode.clone(), collection.clone());
+        self.collection_clips.write().insert(collection.collection_id.clone(), model.clip_ids);
+        Ok(collection)
+    }
+
+    async fn get_collection(&self, shortcode: &str) -> Result<model::Collection> {
+        self.collections
+            .read()
+            .get(shortcode)
+            .cloned()
+            .ok_or(DataError::Database(sqlx::Error::RowNotFound))
+    }
+
+    async fn collection_clips(&self, collection_id: &str) -> Result<Vec<model::Clip>> {
+        let clips = self.clips.read();
+        let clip_ids = self.collection_clips.read().get(collection_id).cloned().unwrap_or_default();
+        // deleted clips are skipped, as ON DELETE CASCADE drops them
+        Ok(clip_ids
+            .iter()
+            .filter_map(|clip_id| clips.values().find(|clip| &clip.clip_id == clip_id).cloned())
+            .collect())
+    }
+
+    async fn delete_expired_collections(&self) -> Result<u64> {
+        let now = Utc::now().timestamp();
+        let mut collections = self.collections.write();
+        let expired: Vec<String> = collections
+            .values()
+            .filter(|collection| collection.expires.is_some_and(|expires| now > expires.and_utc().timestamp()))
+            .map(|collection| collection.shortcode.clone())
+            .collect();
+        let mut collection_clips = self.collection_clips.write();
+        for shortcode in &expired {
+            if let Some(collection) = collections.remove(shortcode) {
+                collection_clips.remove(&collection.collection_id);
+            }
+        }
+        Ok(expired.len() as u64)
+    }
+}
+
+#[rocket::async_trait]
+impl Repository for MemoryRepository {
+    async fn ping(&self) -> Result<()> {
+        Ok(())
+    }
+
+    async fn migrate(&self) -> Result<MigrationReport> {
+        self.migration_status().await
+    }
+
+    async fn migration_status(&self) -> Result<MigrationReport> {
+        Ok(MigrationReport { migrations: vec![] })
+    }
+
+    async fn backup_into(&self, _path: &Path) -> Result<()> {
+        Err(DataError::Unsupported("in-memory databases cannot be backed up".to_owned()))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::MemoryRepository;
+    use crate::data::repository::conformance;
+
+    #[test]
+    fn conforms_to_repository() {
+        futures::executor::block_on(conformance::run(&MemoryRepository::new()));
+    }
+}
@@ -0,0 +1,78 @@
+use std::fmt;
+use serde::Serialize;
+use sqlx::migrate::Migrator;
+
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+#[cfg(feature = "postgres")]
+pub static POSTGRES_MIGRATOR: Migrator = sqlx::migrate!("./migrations/postgres");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationState {
+    AlreadyApplied,
+    Applied,
+    Pending,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub state: MigrationState,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MigrationReport {
+    pub migrations: Vec<MigrationStatus>,
+}
+
+impl MigrationReport {
+    pub fn pending(&self) -> Vec<i64> {
+        self.migrations
+            .iter()
+            .filter(|m| m.state == MigrationState::Pending)
+            .map(|m| m.version)
+            .collect()
+    }
+
+    pub fn is_up_to_date(&self) -> bool {
+        self.pending().is_empty()
+    }
+}
+
+impl fmt::Display for MigrationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for m in &self.migrations {
+            let state = match m.state {
+                MigrationState::AlreadyApplied => "up to date",
+                MigrationState::Applied => "applied",
+                MigrationState::Pending => "PENDING",
+            };
+            writeln!(f, "{:>14} {:<24} {}", m.version, m.description, state)?;
+        }
+        Ok(())
+    }
+}
+
+/// Compares the migrations known to `migrator` with the versions applied before and after a run.
+pub fn report(migrator: &Migrator, applied_before: &[i64], applied_after: &[i64]) -> MigrationReport {
+    let migrations = migrator
+        .iter()
+        .map(|migration| {
+            let state = if applied_before.contains(&migration.version) {
+                MigrationState::AlreadyApplied
+            } else if applied_after.contains(&migration.version) {
+                MigrationState::Applied
+            } else {
+                MigrationState::Pending
+            };
+            MigrationStatus {
+                version: migration.version,
+                description: migration.description.to_string(),
+                state,
+            }
+        })
+        .collect();
+    MigrationReport { migrations }
+}
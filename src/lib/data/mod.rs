@@ -1,46 +1,108 @@
+pub mod backup;
+pub mod envelope;
+pub mod memory;
+pub mod migrate;
 pub mod model;
+#[cfg(feature = "postgres")]
+pub mod postgres;
 pub mod query;
+pub mod repository;
+pub mod sqlite;
 
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::Arc;
 use derive_more::{Display, From};
 use serde::{Deserialize, Serialize};
-use sqlx::Sqlite;
 use uuid::Uuid;
+use repository::Repository;
 
 #[derive(Debug, thiserror::Error)]
 pub enum DataError {
     #[error("database error: {0}")]
     Database(#[from] sqlx::Error),
+    #[error("migration error: {0}")]
+    Migrate(#[from] sqlx::migrate::MigrateError),
+    #[error("unsupported database: {0}")]
+    Unsupported(String),
+    #[error("conflict: {0}")]
+    Conflict(String),
+    #[error("clip is at revision {actual}, not {expected}")]
+    RevisionMismatch { expected: i64, actual: i64 },
+    #[error("encryption error: {0}")]
+    Encryption(String),
+    #[error("backup error: {0}")]
+    Backup(String),
 }
 
-pub type AppDatabase = Database<Sqlite>;
+pub type AppDatabase = Database;
 pub type DatabasePool = sqlx::sqlite::SqlitePool;
-pub type Transaction<'t> = sqlx::Transaction<'t, Sqlite>;
 pub type AppDatabaseRow = sqlx::sqlite::SqliteRow;
 pub type AppDatabaseQueryResult = sqlx::sqlite::SqliteQueryResult;
 
-pub struct Database<D: sqlx::Database>(sqlx::Pool<D>);
-
-impl Database<Sqlite> {
-    pub async fn new(connection_str: &str) -> Self {
-        let pool = sqlx::sqlite::SqlitePoolOptions::new()
-            .connect(connection_str)
-            .await;
-
-        match pool {
-            Ok(pool) => Self(pool),
-            Err(e) => {
-                eprintln!("{}\n", e);
-                eprintln!("If the database has not yet been created, run: \n $ sqlx database setup \n");
-                panic!("Database connection error");
-            }
+pub const DEFAULT_POOL_SIZE: u32 = 10;
+pub const MEMORY_URL: &str = "memory:";
+
+/// Handle to the configured storage backend; cheap to clone.
+#[derive(Clone)]
+pub struct Database(Arc<dyn Repository>);
+
+impl Database {
+    pub async fn new(connection_str: &str) -> Result<Self, DataError> {
+        Self::connect(connection_str, DEFAULT_POOL_SIZE).await
+    }
+
+    /// Picks the backend from the URL scheme: `memory:`, `postgres://`, and SQLite for anything else.
+    pub async fn connect(connection_str: &str, pool_size: u32) -> Result<Self, DataError> {
+        if connection_str == MEMORY_URL {
+            Ok(Self::memory())
+        } else if is_postgres_url(connection_str) {
+            Self::connect_postgres(connection_str, pool_size).await
+        } else {
+            let repo = sqlite::SqliteRepository::connect(connection_str, pool_size).await?;
+            Ok(Self::from_repository(repo))
         }
     }
 
-    pub fn get_pool(&self) -> &DatabasePool {
-        &self.0
+    #[cfg(feature = "postgres")]
+    async fn connect_postgres(connection_str: &str, pool_size: u32) -> Result<Self, DataError> {
+        let repo = postgres::PostgresRepository::connect(connection_str, pool_size).await?;
+        Ok(Self::from_repository(repo))
+    }
+
+    #[cfg(not(feature = "postgres"))]
+    async fn connect_postgres(_connection_str: &str, _pool_size: u32) -> Result<Self, DataError> {
+        Err(DataError::Unsupported("PostgreSQL support requires the `postgres` feature".to_owned()))
     }
 
+    pub fn memory() -> Self {
+        Self::from_repository(memory::MemoryRepository::new())
+    }
+
+    pub fn from_repository<R: Repository + 'static>(repo: R) -> Self {
+        Self(Arc::new(repo))
+    }
+
+    /// Encrypts clip content and titles at rest with the master keys in `keyfile`.
+    pub fn with_envelope<P: AsRef<Path>>(self, keyfile: P) -> Result<Self, DataError> {
+        Ok(Self::from_repository(envelope::EnvelopeRepository::new(self.0, keyfile.as_ref())?))
+    }
+
+    pub fn repository(&self) -> &dyn Repository {
+        self.0.as_ref()
+    }
+
+    pub async fn migrate(&self) -> Result<migrate::MigrationReport, DataError> {
+        self.0.migrate().await
+    }
+
+    pub async fn migration_status(&self) -> Result<migrate::MigrationReport, DataError> {
+        self.0.migration_status().await
+    }
+}
+
+fn is_postgres_url(connection_str: &str) -> bool {
+    connection_str.starts_with("postgres://") || connection_str.starts_with("postgresql://")
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, From, Display)]
@@ -77,20 +139,20 @@ impl FromStr for DbId {
 
 #[cfg(test)]
 pub mod test {
-    use rocket::http::uri::fmt::Kind::Path;
     use crate::data::*;
     use tokio::runtime::Handle;
 
     pub fn new_db(handle: &Handle) -> AppDatabase {
-        use sqlx::migrate::Migrator;
-        use std::path::Path;
+        Database::from_repository(new_sqlite(handle))
+    }
+
+    pub fn new_sqlite(handle: &Handle) -> sqlite::SqliteRepository {
+        use crate::data::repository::Repository;
 
         handle.block_on(async move {
-            let db = Database::new(":memory:").await;
-            let migrator = Migrator::new(Path::new("./migrations")).await.unwrap();
-            let pool = db.get_pool();
-            migrator.run(pool).await.unwrap();
-            db
+            let repo = sqlite::SqliteRepository::connect(":memory:", DEFAULT_POOL_SIZE).await.unwrap();
+            repo.migrate().await.unwrap();
+            repo
         })
     }
 }
\ No newline at end of file
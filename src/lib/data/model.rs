@@ -1,8 +1,13 @@
 use chrono::{NaiveDateTime, Utc};
 use crate::{ClipError, ShortCode, Time};
 use crate::data::DbId;
+use crate::domain::clip::field::{Password, Tags, Visibility};
+use crate::domain::crypto;
+use crate::domain::webhook::{ClipEvent, EventPayload};
+use crate::service::archive::ArchivedClip;
+use subtle::ConstantTimeEq;
 
-#[derive(Debug, sqlx::FromRow)]
+#[derive(Debug, Clone, sqlx::FromRow)]
 pub struct Clip {
     pub(in crate::data) clip_id: String,
     pub(in crate::data) shortcode: String,
@@ -12,6 +17,111 @@ pub struct Clip {
     pub(in crate::data) expires: Option<NaiveDateTime>,
     pub(in crate::data) password: Option<String>,
     pub(in crate::data) hits: i64,
+    pub(in crate::data) content_nonce: Option<Vec<u8>>,
+    pub(in crate::data) content_salt: Option<Vec<u8>>,
+    pub(in crate::data) encrypted: bool,
+    pub(in crate::data) owner: Option<String>,
+    pub(in crate::data) revision: i64,
+    pub(in crate::data) updated: Option<NaiveDateTime>,
+    pub(in crate::data) parent_clip_id: Option<String>,
+    pub(in crate::data) visibility: String,
+}
+
+impl Clip {
+    pub fn clip_id(&self) -> &str {
+        &self.clip_id
+    }
+
+    pub fn shortcode(&self) -> &str {
+        &self.shortcode
+    }
+
+    pub fn parent_clip_id(&self) -> Option<&str> {
+        self.parent_clip_id.as_deref()
+    }
+
+    /// Whether `viewer` may read the clip: a private clip only by the API key or web session that created it.
+    pub fn visible_to(&self, viewer: Option<&str>) -> bool {
+        self.visibility != Visibility::Private.to_string() || (self.owner.is_some() && self.owner.as_deref() == viewer)
+    }
+
+    /// The sealed content and hashed password of a protected clip stored before content encryption,
+    /// or `None` if the clip is unprotected or already sealed.
+    pub fn seal_content(&self) -> Option<SealClipContent> {
+        let password = self.password.as_deref().filter(|stored| !crypto::is_password_hash(stored))?;
+        if self.content_nonce.is_some() {
+            return None;
+        }
+        let protected = Protected::new(self.content.clone(), Some(password.to_owned()));
+        Some(SealClipContent {
+            shortcode: self.shortcode.clone(),
+            expected_password: password.to_owned(),
+            content: protected.content,
+            password: protected.password?,
+            content_nonce: protected.content_nonce?,
+            content_salt: protected.content_salt?,
+        })
+    }
+
+    /// Checks `password` and decrypts the content of a protected clip, or `None` if the password is wrong.
+    /// Clips stored before content encryption are compared with their plaintext password.
+    ///
+    /// Runs Argon2 for protected clips, so keep it off the async workers.
+    pub fn unlock(mut self, password: &Password) -> Result<Option<Self>, ClipError> {
+        let (stored, password) = match (self.password.clone(), password.as_str()) {
+            (None, _) => return Ok(Some(self)),
+            (Some(_), None) => return Ok(None),
+            (Some(stored), Some(password)) => (stored, password),
+        };
+        let (nonce, salt) = match (self.content_nonce.take(), self.content_salt.take()) {
+            (Some(nonce), Some(salt)) => (nonce, salt),
+            _ if crypto::is_password_hash(&stored) => return Ok(crypto::verify_password(password, &stored).then_some(self)),
+            _ => return Ok(bool::from(stored.as_bytes().ct_eq(password.as_bytes())).then_some(self)),
+        };
+        let sealed = crypto::Sealed {
+            ciphertext: base64::decode(&self.content).map_err(|e| ClipError::Crypto(e.to_string()))?,
+            nonce,
+            salt,
+        };
+        match crypto::open(password, &stored, &sealed)? {
+            Some(plaintext) => {
+                self.content = String::from_utf8(plaintext).map_err(|e| ClipError::Crypto(e.to_string()))?;
+                Ok(Some(self))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Content and password as they are stored: encrypted, with a verifier for the password, when the
+/// clip has a password. Runs Argon2 for protected clips, so keep it off the async workers.
+struct Protected {
+    content: String,
+    password: Option<String>,
+    content_nonce: Option<Vec<u8>>,
+    content_salt: Option<Vec<u8>>,
+}
+
+impl Protected {
+    fn new(content: String, password: Option<String>) -> Self {
+        match password {
+            Some(password) => {
+                let (sealed, verifier) = crypto::seal(&password, content.as_bytes());
+                Self {
+                    content: base64::encode(&sealed.ciphertext),
+                    password: Some(verifier),
+                    content_nonce: Some(sealed.nonce),
+                    content_salt: Some(sealed.salt),
+                }
+            }
+            None => Self {
+                content,
+                password: None,
+                content_nonce: None,
+                content_salt: None,
+            },
+        }
+    }
 }
 
 impl TryFrom<Clip> for crate::domain::Clip {
@@ -31,6 +141,14 @@ impl TryFrom<Clip> for crate::domain::Clip {
                 expires: field::Expires::new(clip.expires.map(Time::from_naive_utc)),
                 password: field::Password::new(clip.password.unwrap_or_default())?,
                 hits: field::Hits::new(u64::try_from(clip.hits)?),
+                encrypted: field::Encrypted::new(clip.encrypted),
+                owner: field::Owner::new(clip.owner),
+                revision: field::Revision::new(u64::try_from(clip.revision)?),
+                updated: field::Updated::new(Time::from_naive_utc(clip.updated.unwrap_or(clip.posted))),
+                parent: field::Parent::new(clip.parent_clip_id.as_deref().map(DbId::from_str).transpose()?.map(field::ClipId::new)),
+                visibility: field::Visibility::from_str(&clip.visibility).map_err(|_| ClipError::InvalidVisibility(clip.visibility))?,
+                // stored apart from the clip, and read by the service
+                tags: Default::default(),
             }
         )
 
@@ -73,18 +191,36 @@ pub struct NewClip {
     pub(in crate::data) posted: i64,
     pub(in crate::data) expires: Option<i64>,
     pub(in crate::data) password: Option<String>,
+    pub(in crate::data) content_nonce: Option<Vec<u8>>,
+    pub(in crate::data) content_salt: Option<Vec<u8>>,
+    pub(in crate::data) encrypted: bool,
+    pub(in crate::data) hits: i64,
+    pub(in crate::data) owner: Option<String>,
+    pub(in crate::data) parent_clip_id: Option<String>,
+    pub(in crate::data) visibility: String,
+    /// Stored in `clip_tags`, with the clip.
+    pub(in crate::data) tags: Vec<String>,
 }
 
-impl From<crate::service::ask::NewClip> for NewClip {
-    fn from(req: crate::service::ask::NewClip) -> Self {
+impl NewClip {
+    pub fn new(req: crate::service::ask::NewClip, shortcode: ShortCode) -> Self {
+        let protected = Protected::new(req.content.into_inner(), req.password.into_inner());
         Self {
             clip_id: DbId::new().into() ,
-            shortcode: ShortCode::default().into(),
-            content: req.content.into_inner(),
+            shortcode: shortcode.into(),
+            content: protected.content,
             title: req.title.into_inner(),
             posted: Utc::now().timestamp(),
             expires: req.expires.into_inner().map(|time| time.timestamp()),
-            password: req.password.into_inner(),
+            password: protected.password,
+            content_nonce: protected.content_nonce,
+            content_salt: protected.content_salt,
+            encrypted: req.encrypted.into_inner(),
+            hits: 0,
+            owner: req.owner.into_inner(),
+            parent_clip_id: req.parent.into_inner().map(|id| id.into_inner().to_string()),
+            visibility: req.visibility.to_string(),
+            tags: req.tags.into_inner(),
         }
     }
 
@@ -94,19 +230,448 @@ pub struct UpdateClip {
     pub(in crate::data) shortcode: String,
     pub(in crate::data) content: String,
     pub(in crate::data) title: Option<String>,
-    pub(in crate::data) expires: i64,
+    pub(in crate::data) expires: Option<i64>,
     pub(in crate::data) password: Option<String>,
+    pub(in crate::data) content_nonce: Option<Vec<u8>>,
+    pub(in crate::data) content_salt: Option<Vec<u8>>,
+    pub(in crate::data) encrypted: bool,
+    pub(in crate::data) updated: i64,
+    /// Compare-and-set: the update only applies while the clip is at this revision.
+    pub(in crate::data) expected_revision: Option<i64>,
+    /// Replaces the clip's visibility when given.
+    pub(in crate::data) visibility: Option<String>,
+    /// Replaces the clip's tags when given.
+    pub(in crate::data) tags: Option<Vec<String>>,
 }
 
 impl From<crate::service::ask::UpdateClip> for UpdateClip {
     fn from(req: crate::service::ask::UpdateClip) -> Self {
+        let protected = Protected::new(req.content.into_inner(), req.password.into_inner());
         Self {
-            shortcode: ShortCode::default().into(),
-            content: req.content.into_inner(),
+            shortcode: req.shortcode.into_inner(),
+            content: protected.content,
             title: req.title.into_inner(),
-            expires: req.expires.into_inner().map(|time| time.timestamp()).expect("expires is required"),
-            password: req.password.into_inner(),
+            expires: req.expires.into_inner().map(|time| time.timestamp()),
+            password: protected.password,
+            content_nonce: protected.content_nonce,
+            content_salt: protected.content_salt,
+            encrypted: req.encrypted.into_inner(),
+            updated: Utc::now().timestamp(),
+            // a revision beyond i64 matches no clip
+            expected_revision: req.revision.map(|revision| i64::try_from(revision.into_inner()).unwrap_or(-1)),
+            visibility: req.visibility.map(|visibility| visibility.to_string()),
+            tags: req.tags.map(crate::domain::clip::field::Tags::into_inner),
+        }
+    }
+
+}
+impl From<Clip> for ArchivedClip {
+    fn from(clip: Clip) -> Self {
+        Self {
+            shortcode: clip.shortcode,
+            content: clip.content,
+            title: clip.title,
+            posted: clip.posted.and_utc(),
+            expires: clip.expires.map(|expires| expires.and_utc()),
+            password: clip.password,
+            hits: u64::try_from(clip.hits).unwrap_or_default(),
+            content_nonce: clip.content_nonce.map(base64::encode),
+            content_salt: clip.content_salt.map(base64::encode),
+            encrypted: clip.encrypted,
+            owner: clip.owner,
+            visibility: clip.visibility.parse().unwrap_or_default(),
+            // stored apart from the clip
+            tags: vec![],
+            parent: None,
+        }
+    }
+}
+
+/// An archived clip as inserted on import; the clip id is always new, and the parent is linked
+/// with [`NewClip::with_parent`].
+impl TryFrom<ArchivedClip> for NewClip {
+    type Error = ClipError;
+
+    fn try_from(clip: ArchivedClip) -> Result<Self, Self::Error> {
+        let decode = |value: Option<String>| {
+            value
+                .map(|value| base64::decode(value).map_err(|e| ClipError::Crypto(e.to_string())))
+                .transpose()
+        };
+        if clip.shortcode.trim().is_empty() {
+            return Err(ClipError::InvalidShortCode("empty shortcode".to_owned()));
+        }
+        if clip.content.is_empty() {
+            return Err(ClipError::EmptyContent);
+        }
+        let content_nonce = decode(clip.content_nonce)?;
+        let content_salt = decode(clip.content_salt)?;
+        if content_nonce.is_some() != content_salt.is_some() {
+            return Err(ClipError::Crypto("nonce and salt must be given together".to_owned()));
+        }
+        let tags = Tags::new(clip.tags)?;
+        Ok(Self {
+            clip_id: DbId::new().into(),
+            shortcode: clip.shortcode,
+            content: clip.content,
+            title: clip.title,
+            posted: clip.posted.timestamp(),
+            expires: clip.expires.map(|expires| expires.timestamp()),
+            password: clip.password,
+            content_nonce,
+            content_salt,
+            encrypted: clip.encrypted,
+            hits: i64::try_from(clip.hits)?,
+            owner: clip.owner,
+            parent_clip_id: None,
+            visibility: clip.visibility.to_string(),
+            tags: tags.into_inner(),
+        })
+    }
+}
+
+impl NewClip {
+    pub fn with_parent(self, parent_clip_id: Option<String>) -> Self {
+        Self { parent_clip_id, ..self }
+    }
+}
+
+/// Which unexpired clips to find, newest first.
+pub struct ClipFilter {
+    pub(in crate::data) owner: Option<String>,
+    pub(in crate::data) tag: Option<String>,
+    /// Only public clips anyone may read: without a password and not end-to-end encrypted.
+    pub(in crate::data) public: bool,
+    pub(in crate::data) limit: i64,
+}
+
+impl ClipFilter {
+    pub fn owned_by(owner: String, tag: Option<String>, limit: u32) -> Self {
+        Self { owner: Some(owner), tag, public: false, limit: i64::from(limit) }
+    }
+
+    pub fn public(tag: Option<String>, limit: u32) -> Self {
+        Self { owner: None, tag, public: true, limit: i64::from(limit) }
+    }
+}
+
+/// Totals over all stored clips, including expired clips that have not been purged yet.
+#[derive(Debug, Clone, Default, sqlx::FromRow)]
+pub struct ClipStats {
+    pub clips: i64,
+    pub protected: i64,
+    pub expired: i64,
+    /// Bytes of stored content, which for encrypted clips is the ciphertext.
+    pub total_bytes: i64,
+}
+
+/// Content and title rewritten in place, e.g. when re-encrypting under a new key.
+pub struct RewriteClipText {
+    pub(in crate::data) shortcode: String,
+    pub(in crate::data) expected_content: String,
+    pub(in crate::data) content: String,
+    pub(in crate::data) title: Option<String>,
+}
+
+/// Sealed content replacing the plaintext of a protected clip, applied only while the clip still
+/// has its plaintext password and no nonce.
+pub struct SealClipContent {
+    pub(in crate::data) shortcode: String,
+    pub(in crate::data) expected_password: String,
+    pub(in crate::data) content: String,
+    pub(in crate::data) password: String,
+    pub(in crate::data) content_nonce: Vec<u8>,
+    pub(in crate::data) content_salt: Vec<u8>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Webhook {
+    pub(in crate::data) webhook_id: String,
+    pub(in crate::data) owner: String,
+    pub(in crate::data) url: String,
+    pub(in crate::data) secret: String,
+    /// Comma separated event names.
+    pub(in crate::data) events: String,
+    pub(in crate::data) created: NaiveDateTime,
+}
+
+impl Webhook {
+    pub fn webhook_id(&self) -> &str {
+        &self.webhook_id
+    }
+}
+
+/// The webhook as shown to its owner; the secret is only revealed on registration.
+impl From<Webhook> for crate::domain::webhook::Webhook {
+    fn from(webhook: Webhook) -> Self {
+        use std::str::FromStr;
+
+        Self {
+            id: webhook.webhook_id,
+            url: webhook.url,
+            events: webhook.events.split(',').filter_map(|event| ClipEvent::from_str(event).ok()).collect(),
+            created: webhook.created.and_utc(),
+            secret: None,
         }
     }
+}
+
+pub struct NewWebhook {
+    pub(in crate::data) webhook_id: String,
+    pub(in crate::data) owner: String,
+    pub(in crate::data) url: String,
+    pub(in crate::data) secret: String,
+    pub(in crate::data) events: String,
+    pub(in crate::data) created: i64,
+}
+
+impl NewWebhook {
+    pub fn new(owner: String, url: String, events: &[ClipEvent], secret: String) -> Self {
+        Self {
+            webhook_id: DbId::new().into(),
+            owner,
+            url,
+            secret,
+            events: events.iter().map(ClipEvent::to_string).collect::<Vec<_>>().join(","),
+            created: Utc::now().timestamp(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Collection {
+    pub(in crate::data) collection_id: String,
+    pub(in crate::data) shortcode: String,
+    pub(in crate::data) title: Option<String>,
+    pub(in crate::data) posted: NaiveDateTime,
+    pub(in crate::data) expires: Option<NaiveDateTime>,
+    pub(in crate::data) owner: Option<String>,
+}
+
+impl Collection {
+    pub fn collection_id(&self) -> &str {
+        &self.collection_id
+    }
+}
+
+/// The collection without its clips, which the service adds.
+impl From<Collection> for crate::domain::collection::Collection {
+    fn from(collection: Collection) -> Self {
+        Self {
+            id: collection.collection_id,
+            shortcode: ShortCode::from(collection.shortcode),
+            title: collection.title,
+            posted: collection.posted.and_utc(),
+            expires: collection.expires.map(|expires| expires.and_utc()),
+            owner: collection.owner,
+            clips: vec![],
+        }
+    }
+}
+
+pub struct NewCollection {
+    pub(in crate::data) collection_id: String,
+    pub(in crate::data) shortcode: String,
+    pub(in crate::data) title: Option<String>,
+    pub(in crate::data) posted: i64,
+    pub(in crate::data) expires: Option<i64>,
+    pub(in crate::data) owner: Option<String>,
+    /// In collection order.
+    pub(in crate::data) clip_ids: Vec<String>,
+}
+
+impl NewCollection {
+    pub fn new(shortcode: ShortCode, title: Option<String>, expires: Option<Time>, owner: Option<String>, clips: &[crate::Clip]) -> Self {
+        Self {
+            collection_id: DbId::new().into(),
+            shortcode: shortcode.into(),
+            title,
+            posted: Utc::now().timestamp(),
+            expires: expires.map(|time| time.timestamp()),
+            owner,
+            clip_ids: clips.iter().map(|clip| clip.clip_id.clone().into_inner().to_string()).collect(),
+        }
+    }
+}
+
+/// An event on a clip of `owner`, queued for each of the owner's webhooks subscribed to it.
+pub struct NewEvent {
+    pub(in crate::data) event_id: String,
+    pub(in crate::data) owner: String,
+    pub(in crate::data) event: String,
+    pub(in crate::data) payload: String,
+    pub(in crate::data) created: i64,
+}
 
-}
\ No newline at end of file
+impl NewEvent {
+    pub fn new(owner: String, payload: &EventPayload) -> Result<Self, serde_json::Error> {
+        Ok(Self {
+            event_id: payload.id.clone(),
+            owner,
+            event: payload.event.to_string(),
+            payload: serde_json::to_string(payload)?,
+            created: payload.created.timestamp(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct Delivery {
+    pub(in crate::data) webhook_id: String,
+    pub(in crate::data) event_id: String,
+    pub(in crate::data) event: String,
+    pub(in crate::data) payload: String,
+    pub(in crate::data) created: NaiveDateTime,
+    pub(in crate::data) attempts: i64,
+    pub(in crate::data) next_attempt: Option<NaiveDateTime>,
+    pub(in crate::data) delivered: Option<NaiveDateTime>,
+    pub(in crate::data) response_status: Option<i64>,
+    pub(in crate::data) last_error: Option<String>,
+}
+
+impl TryFrom<Delivery> for crate::domain::webhook::Delivery {
+    type Error = strum::ParseError;
+
+    fn try_from(delivery: Delivery) -> Result<Self, Self::Error> {
+        use crate::domain::webhook::DeliveryStatus;
+        use std::str::FromStr;
+
+        let status = match (&delivery.delivered, &delivery.next_attempt) {
+            (Some(_), _) => DeliveryStatus::Delivered,
+            (None, Some(_)) => DeliveryStatus::Pending,
+            (None, None) => DeliveryStatus::Failed,
+        };
+        Ok(Self {
+            id: delivery.event_id,
+            event: ClipEvent::from_str(&delivery.event)?,
+            status,
+            attempts: u32::try_from(delivery.attempts).unwrap_or_default(),
+            created: delivery.created.and_utc(),
+            next_attempt: delivery.next_attempt.map(|time| time.and_utc()),
+            delivered: delivery.delivered.map(|time| time.and_utc()),
+            response_status: delivery.response_status.and_then(|status| u16::try_from(status).ok()),
+            last_error: delivery.last_error,
+        })
+    }
+}
+
+/// A delivery due for another attempt, with where to send it and how to sign it.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct PendingDelivery {
+    pub webhook_id: String,
+    pub event_id: String,
+    pub event: String,
+    pub payload: String,
+    pub attempts: i64,
+    pub url: String,
+    pub secret: String,
+}
+
+/// The outcome of one attempt: delivered, retried at `next_attempt`, or given up when neither is set.
+pub struct DeliveryAttempt {
+    pub(in crate::data) webhook_id: String,
+    pub(in crate::data) event_id: String,
+    pub(in crate::data) delivered: Option<i64>,
+    pub(in crate::data) next_attempt: Option<i64>,
+    pub(in crate::data) response_status: Option<i64>,
+    pub(in crate::data) last_error: Option<String>,
+}
+
+impl DeliveryAttempt {
+    pub fn delivered(delivery: &PendingDelivery, response_status: u16) -> Self {
+        Self {
+            webhook_id: delivery.webhook_id.clone(),
+            event_id: delivery.event_id.clone(),
+            delivered: Some(Utc::now().timestamp()),
+            next_attempt: None,
+            response_status: Some(i64::from(response_status)),
+            last_error: None,
+        }
+    }
+
+    /// Schedules a retry with backoff, unless this was the last allowed attempt.
+    pub fn failed(delivery: &PendingDelivery, response_status: Option<u16>, error: String) -> Self {
+        use crate::domain::webhook::{retry_delay, MAX_ATTEMPTS};
+
+        let attempts = u32::try_from(delivery.attempts + 1).unwrap_or(u32::MAX);
+        let next_attempt = match attempts < MAX_ATTEMPTS {
+            true => Some(Utc::now().timestamp() + retry_delay(attempts).as_secs() as i64),
+            false => None,
+        };
+        Self {
+            webhook_id: delivery.webhook_id.clone(),
+            event_id: delivery.event_id.clone(),
+            delivered: None,
+            next_attempt,
+            response_status: response_status.map(i64::from),
+            last_error: Some(error),
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::data::model;
+    use crate::domain::clip::field::{Content, Expires, Password, Title};
+    use crate::service::ask;
+    use crate::ShortCode;
+
+    fn new_clip(password: &str) -> model::NewClip {
+        let req = ask::NewClip {
+            content: Content::new("launch codes").unwrap(),
+            title: Title::default(),
+            expires: Expires::default(),
+            password: Password::new(password.to_owned()).unwrap(),
+            encrypted: Default::default(),
+            owner: Default::default(),
+            parent: Default::default(),
+            visibility: Default::default(),
+            tags: Default::default(),
+        };
+        model::NewClip::new(req, ShortCode::new())
+    }
+
+    fn stored(new: model::NewClip) -> model::Clip {
+        model::Clip {
+            clip_id: new.clip_id,
+            shortcode: new.shortcode,
+            content: new.content,
+            title: new.title,
+            posted: chrono::Utc::now().naive_utc(),
+            expires: None,
+            password: new.password,
+            hits: 0,
+            content_nonce: new.content_nonce,
+            content_salt: new.content_salt,
+            encrypted: new.encrypted,
+            owner: new.owner,
+            revision: 1,
+            updated: None,
+            parent_clip_id: new.parent_clip_id,
+            visibility: new.visibility,
+        }
+    }
+
+    #[test]
+    fn protected_clip_is_stored_encrypted() {
+        let new = new_clip("hunter2");
+        assert!(!new.content.contains("launch codes"));
+        assert!(!new.password.as_deref().unwrap().contains("hunter2"));
+        assert!(new.content_nonce.is_some() && new.content_salt.is_some());
+
+        let clip = stored(new);
+        let wrong = Password::new("hunter3".to_owned()).unwrap();
+        let right = Password::new("hunter2".to_owned()).unwrap();
+        assert!(clip.clone().unlock(&wrong).unwrap().is_none());
+        assert!(clip.clone().unlock(&Password::default()).unwrap().is_none());
+        assert_eq!(clip.unlock(&right).unwrap().unwrap().content, "launch codes");
+    }
+
+    #[test]
+    fn unprotected_clip_is_stored_as_is() {
+        let new = new_clip("");
+        assert_eq!(new.content, "launch codes");
+        assert!(new.password.is_none() && new.content_nonce.is_none());
+        assert_eq!(stored(new).unlock(&Password::default()).unwrap().unwrap().content, "launch codes");
+    }
+}
@@ -0,0 +1,562 @@
+use std::path::Path;
+use chrono::{DateTime, NaiveDateTime};
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use sqlx::Row;
+use crate::data::migrate::{self, MigrationReport, POSTGRES_MIGRATOR};
+use crate::data::repository::{ApiKeyRepository, ClipRepository, CollectionRepository, Repository, RevocationStatus, WebhookRepository};
+use crate::data::{model, DataError};
+use crate::web::api::ApiKey;
+use crate::ShortCode;
+
+type Result<T> = std::result::Result<T, DataError>;
+
+/// PostgreSQL storage. Queries are checked at runtime, since `query!` is bound to the SQLite schema.
+pub struct PostgresRepository(PgPool);
+
+fn timestamp(secs: i64) -> NaiveDateTime {
+    DateTime::from_timestamp(secs, 0).unwrap_or_default().naive_utc()
+}
+
+impl PostgresRepository {
+    pub async fn connect(connection_str: &str, pool_size: u32) -> Result<Self> {
+        let pool = PgPoolOptions::new()
+            .max_connections(pool_size)
+            .connect(connection_str)
+            .await?;
+        Ok(Self(pool))
+    }
+
+    pub fn get_pool(&self) -> &PgPool {
+        &self.0
+    }
+
+    async fn applied_versions(&self) -> Result<Vec<i64>> {
+        let rows = sqlx::query("SELECT version FROM _sqlx_migrations WHERE success ORDER BY version")
+            .fetch_all(&self.0)
+            .await;
+        match rows {
+            Ok(rows) => Ok(rows.iter().map(|row| row.get(0)).collect()),
+            // undefined_table: the database has never been migrated
+            Err(sqlx::Error::Database(e)) if e.code().as_deref() == Some("42P01") => Ok(vec![]),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+async fn insert_clip(model: model::NewClip, transaction: &mut sqlx::Transaction<'_, sqlx::Postgres>) -> Result<String> {
+    sqlx::query(
+        r#"INSERT INTO clips (
+            clip_id,
+            shortcode,
+            content,
+            title,
+            posted,
+            expires,
+            password,
+            hits,
+            content_nonce,
+            content_salt,
+            encrypted,
+            owner,
+            parent_clip_id,
+            visibility
+        ) VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)"#)
+        .bind(&model.clip_id)
+        .bind(&model.shortcode)
+        .bind(model.content)
+        .bind(model.title)
+        .bind(timestamp(model.posted))
+        .bind(model.expires.map(timestamp))
+        .bind(model.password)
+        .bind(model.hits)
+        .bind(model.content_nonce)
+        .bind(model.content_salt)
+        .bind(model.encrypted)
+        .bind(model.owner)
+        .bind(model.parent_clip_id)
+        .bind(model.visibility)
+        .execute(&mut *transaction)
+        .await?;
+    for tag in &model.tags {
+        sqlx::query("INSERT INTO clip_tags (clip_id, tag) VALUES ($1, $2)")
+            .bind(&model.clip_id)
+            .bind(tag)
+            .execute(&mut *transaction)
+            .await?;
+    }
+    Ok(model.shortcode)
+}
+
+#[rocket::async_trait]
+impl ClipRepository for PostgresRepository {
+    async fn get_clip(&self, model: model::GetClip) -> Result<model::Clip> {
+        Ok(
+            sqlx::query_as::<_, model::Clip>("SELECT * FROM clips WHERE shortcode = $1")
+                .bind(model.shortcode)
+                .fetch_one(&self.0)
+                .await?
+        )
+    }
+
+    async fn get_clip_by_id(&self, clip_id: &str) -> Result<model::Clip> {
+        Ok(
+            sqlx::query_as::<_, model::Clip>("SELECT * FROM clips WHERE clip_id = $1")
+                .bind(clip_id)
+                .fetch_one(&self.0)
+                .await?
+        )
+    }
+
+    async fn new_clip(&self, model: model::NewClip) -> Result<model::Clip> {
+        let mut transaction = self.0.begin().await?;
+        let shortcode = insert_clip(model, &mut transaction).await?;
+        transaction.commit().await?;
+        self.get_clip(shortcode.into()).await
+    }
+
+    async fn replace_clip(&self, model: model::NewClip) -> Result<model::Clip> {
+        let mut transaction = self.0.begin().await?;
+        sqlx::query("DELETE FROM clips WHERE shortcode = $1")
+            .bind(&model.shortcode)
+            .execute(&mut transaction)
+            .await?;
+        let shortcode = insert_clip(model, &mut transaction).await?;
+        transaction.commit().await?;
+        self.get_clip(shortcode.into()).await
+    }
+
+    async fn update_clip(&self, model: model::UpdateClip) -> Result<model::Clip> {
+        let mut transaction = self.0.begin().await?;
+        let updated = sqlx::query(
+            r#"UPDATE clips SET
+                content = $1,
+                title = $2,
+                expires = $3,
+                password = $4,
+                content_nonce = $5,
+                content_salt = $6,
+                encrypted = $7,
+                visibility = COALESCE($8, visibility),
+                revision = revision + 1,
+                updated = $9
+                WHERE shortcode = $10 AND ($11::BIGINT IS NULL OR revision = $11)"#)
+            .bind(model.content)
+            .bind(model.title)
+            .bind(model.expires.map(timestamp))
+            .bind(model.password)
+            .bind(model.content_nonce)
+            .bind(model.content_salt)
+            .bind(model.encrypted)
+            .bind(model.visibility)
+            .bind(timestamp(model.updated))
+            .bind(&model.shortcode)
+            .bind(model.expected_revision)
+            .execute(&mut transaction)
+            .await?
+            .rows_affected();
+        if let (1.., Some(tags)) = (updated, &model.tags) {
+            sqlx::query("DELETE FROM clip_tags WHERE clip_id = (SELECT clip_id FROM clips WHERE shortcode = $1)")
+                .bind(&model.shortcode)
+                .execute(&mut transaction)
+                .await?;
+            for tag in tags {
+                sqlx::query("INSERT INTO clip_tags (clip_id, tag) SELECT clip_id, $1 FROM clips WHERE shortcode = $2")
+                    .bind(tag)
+                    .bind(&model.shortcode)
+                    .execute(&mut transaction)
+                    .await?;
+            }
+        }
+        transaction.commit().await?;
+        let clip = self.get_clip(model.shortcode.into()).await?;
+        match (updated, model.expected_revision) {
+            (0, Some(expected)) => Err(DataError::RevisionMismatch { expected, actual: clip.revision }),
+            _ => Ok(clip),
+        }
+    }
+
+    async fn list_clips(&self, after: Option<String>, limit: u32) -> Result<Vec<model::Clip>> {
+        Ok(
+            sqlx::query_as::<_, model::Clip>(r#"SELECT * FROM clips WHERE clip_id COLLATE "C" > $1 ORDER BY clip_id COLLATE "C" LIMIT $2"#)
+                .bind(after.unwrap_or_default())
+                .bind(i64::from(limit))
+                .fetch_all(&self.0)
+                .await?
+        )
+    }
+
+    async fn rewrite_clip_text(&self, model: model::RewriteClipText) -> Result<bool> {
+        let result = sqlx::query("UPDATE clips SET content = $1, title = $2 WHERE shortcode = $3 AND content = $4")
+            .bind(model.content)
+            .bind(model.title)
+            .bind(model.shortcode)
+            .bind(model.expected_content)
+            .execute(&self.0)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn seal_clip_content(&self, model: model::SealClipContent) -> Result<bool> {
+        let result = sqlx::query(
+            r#"UPDATE clips SET content = $1, password = $2, content_nonce = $3, content_salt = $4
+               WHERE shortcode = $5 AND password = $6 AND content_nonce IS NULL"#,
+        )
+            .bind(model.content)
+            .bind(model.password)
+            .bind(model.content_nonce)
+            .bind(model.content_salt)
+            .bind(model.shortcode)
+            .bind(model.expected_password)
+            .execute(&self.0)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn increase_hit_count(&self, shortcode: &ShortCode, hits: u32) -> Result<()> {
+        sqlx::query("UPDATE clips SET hits = hits + $1 WHERE shortcode = $2")
+            .bind(i64::from(hits))
+            .bind(shortcode.as_str())
+            .execute(&self.0)
+            .await?;
+        Ok(())
+    }
+
+    async fn delete_expired(&self) -> Result<Vec<model::Clip>> {
+        Ok(
+            sqlx::query_as::<_, model::Clip>("DELETE FROM clips WHERE expires < (now() AT TIME ZONE 'utc') RETURNING *")
+                .fetch_all(&self.0)
+                .await?
+        )
+    }
+
+    async fn delete_clip(&self, shortcode: &ShortCode) -> Result<()> {
+        let result = sqlx::query("DELETE FROM clips WHERE shortcode = $1")
+            .bind(shortcode.as_str())
+            .execute(&self.0)
+            .await?;
+        match result.rows_affected() {
+            0 => Err(sqlx::Error::RowNotFound.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn clip_stats(&self) -> Result<model::ClipStats> {
+        Ok(
+            sqlx::query_as::<_, model::ClipStats>(
+                r#"SELECT
+                    COUNT(*) AS clips,
+                    COUNT(password) AS protected,
+                    COUNT(CASE WHEN expires < (now() AT TIME ZONE 'utc') THEN 1 END) AS expired,
+                    COALESCE(SUM(octet_length(content)), 0)::BIGINT AS total_bytes
+                FROM clips"#)
+                .fetch_one(&self.0)
+                .await?
+        )
+    }
+
+    async fn top_clips(&self, limit: u32) -> Result<Vec<model::Clip>> {
+        Ok(
+            sqlx::query_as::<_, model::Clip>("SELECT * FROM clips ORDER BY hits DESC, posted DESC LIMIT $1")
+                .bind(i64::from(limit))
+                .fetch_all(&self.0)
+                .await?
+        )
+    }
+
+    async fn list_forks(&self, clip_id: &str) -> Result<Vec<model::Clip>> {
+        Ok(
+            sqlx::query_as::<_, model::Clip>("SELECT * FROM clips WHERE parent_clip_id = $1 ORDER BY posted, clip_id")
+                .bind(clip_id)
+                .fetch_all(&self.0)
+                .await?
+        )
+    }
+
+    async fn clip_tags(&self, clip_id: &str) -> Result<Vec<String>> {
+        Ok(
+            sqlx::query_scalar("SELECT tag FROM clip_tags WHERE clip_id = $1 ORDER BY tag")
+                .bind(clip_id)
+                .fetch_all(&self.0)
+                .await?
+        )
+    }
+
+    async fn find_clips(&self, filter: model::ClipFilter) -> Result<Vec<model::Clip>> {
+        Ok(
+            sqlx::query_as::<_, model::Clip>(
+                r#"SELECT * FROM clips
+                WHERE (expires IS NULL OR expires >= (now() AT TIME ZONE 'utc'))
+                    AND ($1::TEXT IS NULL OR owner = $1)
+                    AND ($2::TEXT IS NULL OR clip_id IN (SELECT clip_id FROM clip_tags WHERE tag = $2))
+                    AND (NOT $3 OR (visibility = 'public' AND password IS NULL AND NOT encrypted))
+                ORDER BY posted DESC, clip_id DESC LIMIT $4"#)
+                .bind(filter.owner)
+                .bind(filter.tag)
+                .bind(filter.public)
+                .bind(filter.limit)
+                .fetch_all(&self.0)
+                .await?
+        )
+    }
+
+    async fn recent_clips(&self, limit: u32) -> Result<Vec<model::Clip>> {
+        Ok(
+            sqlx::query_as::<_, model::Clip>(
+                r#"SELECT * FROM clips
+                WHERE visibility = 'public' AND password IS NULL AND NOT encrypted
+                    AND (expires IS NULL OR expires >= (now() AT TIME ZONE 'utc'))
+                ORDER BY posted DESC, clip_id DESC LIMIT $1"#)
+                .bind(i64::from(limit))
+                .fetch_all(&self.0)
+                .await?
+        )
+    }
+}
+
+#[rocket::async_trait]
+impl ApiKeyRepository for PostgresRepository {
+    async fn save_api_key(&self, api_key: ApiKey) -> Result<ApiKey> {
+        sqlx::query("INSERT INTO api_keys (api_key) VALUES ($1)")
+            .bind(api_key.clone().into_inner())
+            .execute(&self.0)
+            .await?;
+        Ok(api_key)
+    }
+
+    async fn revoke_api_key(&self, api_key: ApiKey) -> Result<RevocationStatus> {
+        let result = sqlx::query("DELETE FROM api_keys WHERE api_key = $1")
+            .bind(api_key.into_inner())
+            .execute(&self.0)
+            .await?;
+        Ok(match result.rows_affected() {
+            0 => RevocationStatus::NotFound,
+            _ => RevocationStatus::Revoked
+        })
+    }
+
+    async fn api_key_is_valid(&self, api_key: ApiKey) -> Result<bool> {
+        let row = sqlx::query("SELECT COUNT(api_key) FROM api_keys WHERE api_key = $1")
+            .bind(api_key.into_inner())
+            .fetch_one(&self.0)
+            .await?;
+        let count: i64 = row.get(0);
+        Ok(count > 0)
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        let rows = sqlx::query("SELECT api_key FROM api_keys")
+            .fetch_all(&self.0)
+            .await?;
+        Ok(rows.iter().map(|row| ApiKey::from(row.get::<Vec<u8>, _>(0))).collect())
+    }
+}
+
+#[rocket::async_trait]
+impl WebhookRepository for PostgresRepository {
+    async fn new_webhook(&self, model: model::NewWebhook) -> Result<model::Webhook> {
+        Ok(
+            sqlx::query_as::<_, model::Webhook>(
+                r#"INSERT INTO webhooks (webhook_id, owner, url, secret, events, created)
+                VALUES ($1, $2, $3, $4, $5, $6) RETURNING *"#)
+                .bind(model.webhook_id)
+                .bind(model.owner)
+                .bind(model.url)
+                .bind(model.secret)
+                .bind(model.events)
+                .bind(timestamp(model.created))
+                .fetch_one(&self.0)
+                .await?
+        )
+    }
+
+    async fn list_webhooks(&self, owner: &str) -> Result<Vec<model::Webhook>> {
+        Ok(
+            sqlx::query_as::<_, model::Webhook>("SELECT * FROM webhooks WHERE owner = $1 ORDER BY created, webhook_id")
+                .bind(owner)
+                .fetch_all(&self.0)
+                .await?
+        )
+    }
+
+    async fn delete_webhook(&self, owner: &str, webhook_id: &str) -> Result<()> {
+        let result = sqlx::query("DELETE FROM webhooks WHERE owner = $1 AND webhook_id = $2")
+            .bind(owner)
+            .bind(webhook_id)
+            .execute(&self.0)
+            .await?;
+        match result.rows_affected() {
+            0 => Err(sqlx::Error::RowNotFound.into()),
+            _ => Ok(()),
+        }
+    }
+
+    async fn enqueue_event(&self, model: model::NewEvent) -> Result<u64> {
+        let created = timestamp(model.created);
+        Ok(
+            sqlx::query(
+                r#"INSERT INTO webhook_deliveries (webhook_id, event_id, event, payload, created, next_attempt)
+                SELECT webhook_id, $1, $2, $3, $4, $4 FROM webhooks
+                WHERE owner = $5 AND (',' || events || ',') LIKE ('%,' || $2 || ',%')"#)
+                .bind(model.event_id)
+                .bind(model.event)
+                .bind(model.payload)
+                .bind(created)
+                .bind(model.owner)
+                .execute(&self.0)
+                .await?
+                .rows_affected()
+        )
+    }
+
+    async fn due_deliveries(&self, now: i64, limit: u32) -> Result<Vec<model::PendingDelivery>> {
+        Ok(
+            sqlx::query_as::<_, model::PendingDelivery>(
+                r#"SELECT d.webhook_id, d.event_id, d.event, d.payload, d.attempts, w.url, w.secret
+                FROM webhook_deliveries d JOIN webhooks w ON w.webhook_id = d.webhook_id
+                WHERE d.next_attempt <= $1 ORDER BY d.next_attempt LIMIT $2"#)
+                .bind(timestamp(now))
+                .bind(i64::from(limit))
+                .fetch_all(&self.0)
+                .await?
+        )
+    }
+
+    async fn record_attempt(&self, model: model::DeliveryAttempt) -> Result<()> {
+        sqlx::query(
+            r#"UPDATE webhook_deliveries SET
+                attempts = attempts + 1,
+                delivered = $1,
+                next_attempt = $2,
+                response_status = $3,
+                last_error = $4
+                WHERE webhook_id = $5 AND event_id = $6"#)
+            .bind(model.delivered.map(timestamp))
+            .bind(model.next_attempt.map(timestamp))
+            .bind(model.response_status)
+            .bind(model.last_error)
+            .bind(model.webhook_id)
+            .bind(model.event_id)
+            .execute(&self.0)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_deliveries(&self, owner: &str, webhook_id: &str, limit: u32) -> Result<Vec<model::Delivery>> {
+        Ok(
+            sqlx::query_as::<_, model::Delivery>(
+                r#"SELECT d.* FROM webhook_deliveries d JOIN webhooks w ON w.webhook_id = d.webhook_id
+                WHERE w.owner = $1 AND d.webhook_id = $2 ORDER BY d.created DESC, d.event_id LIMIT $3"#)
+                .bind(owner)
+                .bind(webhook_id)
+                .bind(i64::from(limit))
+                .fetch_all(&self.0)
+                .await?
+        )
+    }
+}
+
+#[rocket::async_trait]
+impl CollectionRepository for PostgresRepository {
+    async fn new_collection(&self, model: model::NewCollection) -> Result<model::Collection> {
+        let mut transaction = self.0.begin().await?;
+        sqlx::query(
+            r#"INSERT INTO collections (collection_id, shortcode, title, posted, expires, owner)
+            VALUES ($1, $2, $3, $4, $5, $6)"#)
+            .bind(&model.collection_id)
+            .bind(&model.shortcode)
+            .bind(model.title)
+            .bind(timestamp(model.posted))
+            .bind(model.expires.map(timestamp))
+            .bind(model.owner)
+            .execute(&mut transaction)
+            .await?;
+        for (position, clip_id) in model.clip_ids.iter().enumerate() {
+            sqlx::query("INSERT INTO collection_clips (collection_id, position, clip_id) VALUES ($1, $2, $3)")
+                .bind(&model.collection_id)
+                .bind(position as i32)
+                .bind(clip_id)
+                .execute(&mut transaction)
+                .await?;
+        }
+        transaction.commit().await?;
+        self.get_collection(&model.shortcode).await
+    }
+
+    async fn get_collection(&self, shortcode: &str) -> Result<model::Collection> {
+        Ok(
+            sqlx::query_as::<_, model::Collection>("SELECT * FROM collections WHERE shortcode = $1")
+                .bind(shortcode)
+                .fetch_one(&self.0)
+                .await?
+        )
+    }
+
+    async fn collection_clips(&self, collection_id: &str) -> Result<Vec<model::Clip>> {
+        Ok(
+            sqlx::query_as::<_, model::Clip>(
+                r#"SELECT clips.* FROM collection_clips JOIN clips ON clips.clip_id = collection_clips.clip_id
+                WHERE collection_id = $1 ORDER BY position"#)
+                .bind(collection_id)
+                .fetch_all(&self.0)
+                .await?
+        )
+    }
+
+    async fn delete_expired_collections(&self) -> Result<u64> {
+        Ok(
+            sqlx::query("DELETE FROM collections WHERE expires < (now() AT TIME ZONE 'utc')")
+                .execute(&self.0)
+                .await?
+                .rows_affected()
+        )
+    }
+}
+
+#[rocket::async_trait]
+impl Repository for PostgresRepository {
+    async fn ping(&self) -> Result<()> {
+        sqlx::query("SELECT 1").execute(&self.0).await?;
+        Ok(())
+    }
+
+    async fn migrate(&self) -> Result<MigrationReport> {
+        let before = self.applied_versions().await?;
+        POSTGRES_MIGRATOR.run(&self.0).await?;
+        let after = self.applied_versions().await?;
+        Ok(migrate::report(&POSTGRES_MIGRATOR, &before, &after))
+    }
+
+    async fn migration_status(&self) -> Result<MigrationReport> {
+        let applied = self.applied_versions().await?;
+        Ok(migrate::report(&POSTGRES_MIGRATOR, &applied, &applied))
+    }
+
+    async fn backup_into(&self, _path: &Path) -> Result<()> {
+        Err(DataError::Unsupported("use pg_dump to back up PostgreSQL".to_owned()))
+    }
+}
+
+/// Runs against the database in `CLIPSTASH_TEST_POSTGRES_URL`, and is skipped when it is unset.
+#[cfg(test)]
+pub mod test {
+    use super::PostgresRepository;
+    use crate::data::repository::{conformance, Repository};
+    use crate::test::async_runtime;
+
+    #[test]
+    fn conforms_to_repository() {
+        let url = match std::env::var("CLIPSTASH_TEST_POSTGRES_URL") {
+            Ok(url) => url,
+            Err(_) => {
+                eprintln!("CLIPSTASH_TEST_POSTGRES_URL not set, skipping");
+                return;
+            }
+        };
+        let rt = async_runtime();
+        rt.block_on(async move {
+            let repo = PostgresRepository::connect(&url, 2).await.unwrap();
+            repo.migrate().await.unwrap();
+            conformance::run(&repo).await;
+        });
+    }
+}
@@ -1,4 +1,5 @@
 use super::model;
+use crate::data::repository::RevocationStatus;
 use crate::data::{DataError, DatabasePool};
 use crate::ShortCode;
 use crate::web::api::ApiKey;
@@ -34,12 +35,24 @@ pub async fn get_clip<M: Into<model::GetClip>>(model: M, pool:&DatabasePool) ->
     )
 }
 
-pub async fn new_clip<M: Into<model::NewClip>>(
-    model: M,
-    pool:&DatabasePool
-) -> Result<model::Clip>{
-    let model = model.into();
-    let _ = sqlx::query!(
+pub async fn get_clip_by_id(clip_id: &str, pool: &DatabasePool) -> Result<model::Clip> {
+    Ok(
+        sqlx::query_as!(model::Clip, "SELECT * FROM clips WHERE clip_id = ?", clip_id)
+            .fetch_one(pool)
+            .await?
+    )
+}
+
+pub async fn list_forks(clip_id: &str, pool: &DatabasePool) -> Result<Vec<model::Clip>> {
+    Ok(
+        sqlx::query_as!(model::Clip, "SELECT * FROM clips WHERE parent_clip_id = ? ORDER BY posted, clip_id", clip_id)
+            .fetch_all(pool)
+            .await?
+    )
+}
+
+async fn insert_clip(model: &model::NewClip, transaction: &mut sqlx::Transaction<'_, sqlx::Sqlite>) -> Result<()> {
+    sqlx::query!(
         r#"INSERT INTO clips (
             clip_id,
             shortcode,
@@ -48,8 +61,14 @@ pub async fn new_clip<M: Into<model::NewClip>>(
             posted,
             expires,
             password,
-            hits
-        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?)"#,
+            hits,
+            content_nonce,
+            content_salt,
+            encrypted,
+            owner,
+            parent_clip_id,
+            visibility
+        ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"#,
         model.clip_id,
         model.shortcode,
         model.content,
@@ -57,10 +76,42 @@ pub async fn new_clip<M: Into<model::NewClip>>(
         model.posted,
         model.expires,
         model.password,
-        0
+        model.hits,
+        model.content_nonce,
+        model.content_salt,
+        model.encrypted,
+        model.owner,
+        model.parent_clip_id,
+        model.visibility
     )
-        .execute(pool)
+        .execute(&mut *transaction)
     .await?;
+    for tag in &model.tags {
+        sqlx::query!("INSERT INTO clip_tags (clip_id, tag) VALUES (?, ?)", model.clip_id, tag)
+            .execute(&mut *transaction)
+            .await?;
+    }
+    Ok(())
+}
+
+pub async fn new_clip<M: Into<model::NewClip>>(
+    model: M,
+    pool:&DatabasePool
+) -> Result<model::Clip>{
+    let model = model.into();
+    let mut transaction = pool.begin().await?;
+    insert_clip(&model, &mut transaction).await?;
+    transaction.commit().await?;
+    get_clip(model.shortcode, pool).await
+}
+
+pub async fn replace_clip(model: model::NewClip, pool: &DatabasePool) -> Result<model::Clip> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(r#"DELETE FROM clips WHERE shortcode = ?"#, model.shortcode)
+        .execute(&mut transaction)
+        .await?;
+    insert_clip(&model, &mut transaction).await?;
+    transaction.commit().await?;
     get_clip(model.shortcode, pool).await
 }
 
@@ -69,22 +120,178 @@ pub async fn update_clip<M: Into<model::UpdateClip >>(
     pool:&DatabasePool
 ) -> Result<model::Clip>{
     let model = model.into();
-    let _ = sqlx::query!(
+    let mut transaction = pool.begin().await?;
+    let updated = sqlx::query!(
         r#"UPDATE clips SET
             content = ?,
             title = ?,
             expires = ?,
-            password = ?
-            WHERE shortcode = ?"#,
+            password = ?,
+            content_nonce = ?,
+            content_salt = ?,
+            encrypted = ?,
+            visibility = COALESCE(?, visibility),
+            revision = revision + 1,
+            updated = ?
+            WHERE shortcode = ? AND (? IS NULL OR revision = ?)"#,
         model.content,
         model.title,
         model.expires,
         model.password,
-        model.shortcode
+        model.content_nonce,
+        model.content_salt,
+        model.encrypted,
+        model.visibility,
+        model.updated,
+        model.shortcode,
+        model.expected_revision,
+        model.expected_revision
     )
+        .execute(&mut transaction)
+        .await?
+        .rows_affected();
+    if let (1.., Some(tags)) = (updated, &model.tags) {
+        sqlx::query!("DELETE FROM clip_tags WHERE clip_id = (SELECT clip_id FROM clips WHERE shortcode = ?)", model.shortcode)
+            .execute(&mut transaction)
+            .await?;
+        for tag in tags {
+            sqlx::query!(
+                "INSERT INTO clip_tags (clip_id, tag) SELECT clip_id, ? FROM clips WHERE shortcode = ?",
+                tag,
+                model.shortcode
+            )
+                .execute(&mut transaction)
+                .await?;
+        }
+    }
+    transaction.commit().await?;
+    let clip = get_clip(model.shortcode, pool).await?;
+    match (updated, model.expected_revision) {
+        (0, Some(expected)) => Err(DataError::RevisionMismatch { expected, actual: clip.revision }),
+        _ => Ok(clip),
+    }
+}
+
+pub async fn list_clips(after: Option<String>, limit: u32, pool: &DatabasePool) -> Result<Vec<model::Clip>> {
+    let after = after.unwrap_or_default();
+    Ok(
+        sqlx::query_as!(
+            model::Clip,
+            "SELECT * FROM clips WHERE clip_id > ? ORDER BY clip_id LIMIT ?",
+            after,
+            limit
+        )
+        .fetch_all(pool)
+        .await?
+    )
+}
+
+pub async fn clip_tags(clip_id: &str, pool: &DatabasePool) -> Result<Vec<String>> {
+    Ok(
+        sqlx::query_scalar!("SELECT tag FROM clip_tags WHERE clip_id = ? ORDER BY tag", clip_id)
+            .fetch_all(pool)
+            .await?
+    )
+}
+
+pub async fn find_clips(filter: model::ClipFilter, pool: &DatabasePool) -> Result<Vec<model::Clip>> {
+    Ok(
+        sqlx::query_as::<_, model::Clip>(
+            r#"SELECT * FROM clips
+            WHERE (expires IS NULL OR strftime('%s', 'now') <= expires)
+                AND (?1 IS NULL OR owner = ?1)
+                AND (?2 IS NULL OR clip_id IN (SELECT clip_id FROM clip_tags WHERE tag = ?2))
+                AND (NOT ?3 OR (visibility = 'public' AND password IS NULL AND NOT encrypted))
+            ORDER BY posted DESC, clip_id DESC LIMIT ?4"#)
+        .bind(filter.owner)
+        .bind(filter.tag)
+        .bind(filter.public)
+        .bind(filter.limit)
+        .fetch_all(pool)
+        .await?
+    )
+}
+
+pub async fn recent_clips(limit: u32, pool: &DatabasePool) -> Result<Vec<model::Clip>> {
+    Ok(
+        sqlx::query_as::<_, model::Clip>(
+            r#"SELECT * FROM clips
+            WHERE visibility = 'public' AND password IS NULL AND NOT encrypted
+                AND (expires IS NULL OR strftime('%s', 'now') <= expires)
+            ORDER BY posted DESC, clip_id DESC LIMIT ?"#)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?
+    )
+}
+
+pub async fn rewrite_clip_text(model: model::RewriteClipText, pool: &DatabasePool) -> Result<bool> {
+    Ok(
+        sqlx::query!(
+            r#"UPDATE clips SET content = ?, title = ? WHERE shortcode = ? AND content = ?"#,
+            model.content,
+            model.title,
+            model.shortcode,
+            model.expected_content
+        )
+        .execute(pool)
+        .await?
+        .rows_affected() > 0
+    )
+}
+
+pub async fn seal_clip_content(model: model::SealClipContent, pool: &DatabasePool) -> Result<bool> {
+    Ok(
+        sqlx::query!(
+            r#"UPDATE clips SET content = ?, password = ?, content_nonce = ?, content_salt = ?
+               WHERE shortcode = ? AND password = ? AND content_nonce IS NULL"#,
+            model.content,
+            model.password,
+            model.content_nonce,
+            model.content_salt,
+            model.shortcode,
+            model.expected_password
+        )
+        .execute(pool)
+        .await?
+        .rows_affected() > 0
+    )
+}
+
+pub async fn delete_clip(shortcode: &ShortCode, pool: &DatabasePool) -> Result<()> {
+    let shortcode = shortcode.as_str();
+    let result = sqlx::query!(r#"DELETE FROM clips WHERE shortcode = ?"#, shortcode)
         .execute(pool)
         .await?;
-    get_clip(model.shortcode, pool).await
+    match result.rows_affected() {
+        0 => Err(sqlx::Error::RowNotFound.into()),
+        _ => Ok(()),
+    }
+}
+
+pub async fn clip_stats(pool: &DatabasePool) -> Result<model::ClipStats> {
+    Ok(
+        sqlx::query_as!(
+            model::ClipStats,
+            r#"SELECT
+                COUNT(*) AS "clips!: i64",
+                COUNT(password) AS "protected!: i64",
+                COUNT(CASE WHEN strftime('%s', 'now') > expires THEN 1 END) AS "expired!: i64",
+                COALESCE(SUM(LENGTH(CAST(content AS BLOB))), 0) AS "total_bytes!: i64"
+            FROM clips"#
+        )
+        .fetch_one(pool)
+        .await?
+    )
+}
+
+pub async fn top_clips(limit: u32, pool: &DatabasePool) -> Result<Vec<model::Clip>> {
+    Ok(
+        sqlx::query_as::<_, model::Clip>("SELECT * FROM clips ORDER BY hits DESC, posted DESC LIMIT ?")
+        .bind(limit)
+        .fetch_all(pool)
+        .await?
+    )
 }
 
 pub async fn save_api_key(
@@ -92,20 +299,15 @@ pub async fn save_api_key(
     pool: &DatabasePool
 ) -> Result<ApiKey> {
     let bytes = api_key.clone().into_inner();
-    let _ = sqlx::query!(
+    sqlx::query!(
         r#"INSERT INTO api_keys (api_key) VALUES (?)"#,
         bytes
     )
         .execute(pool)
-        .await
-        .map(|_| ())?;
+        .await?;
     Ok(api_key)
 }
 
-pub enum RevocationStatus {
-    Revoked,
-    NotFound
-}
 pub async fn revoke_api_key(
     api_key: ApiKey,
     pool: &DatabasePool
@@ -142,27 +344,230 @@ pub async fn api_key_is_valid(
     )
 }
 
-pub async fn delete_expired(pool: &DatabasePool) -> Result<u64> {
+pub async fn list_api_keys(pool: &DatabasePool) -> Result<Vec<ApiKey>> {
+    Ok(
+        sqlx::query!(r#"SELECT api_key AS "api_key!: Vec<u8>" FROM api_keys"#)
+            .fetch_all(pool)
+            .await?
+            .into_iter()
+            .map(|row| ApiKey::from(row.api_key))
+            .collect()
+    )
+}
+
+pub async fn ping(pool: &DatabasePool) -> Result<()> {
+    sqlx::query("SELECT 1")
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Whether sqlx has created its migrations table, which a database that was never migrated lacks.
+pub async fn has_migrations_table(pool: &DatabasePool) -> Result<bool> {
+    let tables: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = '_sqlx_migrations'")
+        .fetch_one(pool)
+        .await?;
+    Ok(tables > 0)
+}
+
+pub async fn applied_migrations(pool: &DatabasePool) -> Result<Vec<i64>> {
+    Ok(
+        sqlx::query("SELECT version FROM _sqlx_migrations WHERE success = 1 ORDER BY version")
+            .fetch_all(pool)
+            .await?
+            .iter()
+            .map(|row| row.get(0))
+            .collect()
+    )
+}
+
+/// `VACUUM INTO` copies the database in one read transaction, so writers are not blocked.
+pub async fn vacuum_into(path: &str, pool: &DatabasePool) -> Result<()> {
+    sqlx::query("VACUUM INTO ?")
+        .bind(path)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn delete_expired(pool: &DatabasePool) -> Result<Vec<model::Clip>> {
+    Ok(
+        sqlx::query_as::<_, model::Clip>("DELETE FROM clips WHERE strftime('%s', 'now') > expires RETURNING *")
+        .fetch_all(pool)
+        .await?
+    )
+}
+
+/// The collection and its clips are inserted together, or not at all.
+pub async fn new_collection(model: model::NewCollection, pool: &DatabasePool) -> Result<model::Collection> {
+    let mut transaction = pool.begin().await?;
+    sqlx::query!(
+        r#"INSERT INTO collections (collection_id, shortcode, title, posted, expires, owner) VALUES (?, ?, ?, ?, ?, ?)"#,
+        model.collection_id,
+        model.shortcode,
+        model.title,
+        model.posted,
+        model.expires,
+        model.owner
+    )
+        .execute(&mut transaction)
+        .await?;
+    for (position, clip_id) in model.clip_ids.iter().enumerate() {
+        let position = position as i64;
+        sqlx::query!(
+            r#"INSERT INTO collection_clips (collection_id, position, clip_id) VALUES (?, ?, ?)"#,
+            model.collection_id,
+            position,
+            clip_id
+        )
+            .execute(&mut transaction)
+            .await?;
+    }
+    transaction.commit().await?;
+    get_collection(&model.shortcode, pool).await
+}
+
+pub async fn get_collection(shortcode: &str, pool: &DatabasePool) -> Result<model::Collection> {
+    Ok(
+        sqlx::query_as!(model::Collection, "SELECT * FROM collections WHERE shortcode = ?", shortcode)
+            .fetch_one(pool)
+            .await?
+    )
+}
+
+pub async fn collection_clips(collection_id: &str, pool: &DatabasePool) -> Result<Vec<model::Clip>> {
+    Ok(
+        sqlx::query_as!(
+            model::Clip,
+            r#"SELECT clips.* FROM collection_clips JOIN clips ON clips.clip_id = collection_clips.clip_id
+            WHERE collection_id = ? ORDER BY position"#,
+            collection_id
+        )
+            .fetch_all(pool)
+            .await?
+    )
+}
+
+pub async fn delete_expired_collections(pool: &DatabasePool) -> Result<u64> {
     Ok(
-        sqlx::query!(r#"DELETE FROM clips WHERE strftime('%s', 'now') > expires"#)
+        sqlx::query!("DELETE FROM collections WHERE strftime('%s', 'now') > expires")
+            .execute(pool)
+            .await?
+            .rows_affected()
+    )
+}
+
+pub async fn new_webhook(model: model::NewWebhook, pool: &DatabasePool) -> Result<model::Webhook> {
+    sqlx::query!(
+        r#"INSERT INTO webhooks (webhook_id, owner, url, secret, events, created) VALUES (?, ?, ?, ?, ?, ?)"#,
+        model.webhook_id,
+        model.owner,
+        model.url,
+        model.secret,
+        model.events,
+        model.created
+    )
+        .execute(pool)
+        .await?;
+    Ok(
+        sqlx::query_as!(model::Webhook, "SELECT * FROM webhooks WHERE webhook_id = ?", model.webhook_id)
+            .fetch_one(pool)
+            .await?
+    )
+}
+
+pub async fn list_webhooks(owner: &str, pool: &DatabasePool) -> Result<Vec<model::Webhook>> {
+    Ok(
+        sqlx::query_as!(model::Webhook, "SELECT * FROM webhooks WHERE owner = ? ORDER BY created, webhook_id", owner)
+            .fetch_all(pool)
+            .await?
+    )
+}
+
+/// Deliveries go with the webhook through `ON DELETE CASCADE`.
+pub async fn delete_webhook(owner: &str, webhook_id: &str, pool: &DatabasePool) -> Result<()> {
+    let result = sqlx::query!(r#"DELETE FROM webhooks WHERE owner = ? AND webhook_id = ?"#, owner, webhook_id)
+        .execute(pool)
+        .await?;
+    match result.rows_affected() {
+        0 => Err(sqlx::Error::RowNotFound.into()),
+        _ => Ok(()),
+    }
+}
+
+pub async fn enqueue_event(model: model::NewEvent, pool: &DatabasePool) -> Result<u64> {
+    Ok(
+        sqlx::query!(
+            r#"INSERT INTO webhook_deliveries (webhook_id, event_id, event, payload, created, next_attempt)
+            SELECT webhook_id, ?, ?, ?, ?, ? FROM webhooks
+            WHERE owner = ? AND (',' || events || ',') LIKE ('%,' || ? || ',%')"#,
+            model.event_id,
+            model.event,
+            model.payload,
+            model.created,
+            model.created,
+            model.owner,
+            model.event
+        )
         .execute(pool)
         .await?
         .rows_affected()
     )
 }
 
+pub async fn due_deliveries(now: i64, limit: u32, pool: &DatabasePool) -> Result<Vec<model::PendingDelivery>> {
+    Ok(
+        sqlx::query_as::<_, model::PendingDelivery>(
+            r#"SELECT d.webhook_id, d.event_id, d.event, d.payload, d.attempts, w.url, w.secret
+            FROM webhook_deliveries d JOIN webhooks w ON w.webhook_id = d.webhook_id
+            WHERE d.next_attempt <= ? ORDER BY d.next_attempt LIMIT ?"#)
+        .bind(now)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?
+    )
+}
+
+pub async fn record_attempt(model: model::DeliveryAttempt, pool: &DatabasePool) -> Result<()> {
+    sqlx::query!(
+        r#"UPDATE webhook_deliveries SET
+            attempts = attempts + 1,
+            delivered = ?,
+            next_attempt = ?,
+            response_status = ?,
+            last_error = ?
+            WHERE webhook_id = ? AND event_id = ?"#,
+        model.delivered,
+        model.next_attempt,
+        model.response_status,
+        model.last_error,
+        model.webhook_id,
+        model.event_id
+    )
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn list_deliveries(owner: &str, webhook_id: &str, limit: u32, pool: &DatabasePool) -> Result<Vec<model::Delivery>> {
+    Ok(
+        sqlx::query_as::<_, model::Delivery>(
+            r#"SELECT d.* FROM webhook_deliveries d JOIN webhooks w ON w.webhook_id = d.webhook_id
+            WHERE w.owner = ? AND d.webhook_id = ? ORDER BY d.created DESC, d.event_id LIMIT ?"#)
+        .bind(owner)
+        .bind(webhook_id)
+        .bind(limit)
+        .fetch_all(pool)
+        .await?
+    )
+}
+
 #[cfg(test)]
 pub mod test {
     use crate::data::test::*;
     use crate::data::*;
     use crate::test::async_runtime;
 
-    fn model_get_clip(shortcode: &str) -> model::GetClip {
-        model::GetClip {
-            shortcode: shortcode.into()
-        }
-    }
-
     fn model_new_clip(shortcode: &str) -> model::NewClip {
         use chrono::Utc;
 
@@ -173,14 +578,22 @@ pub mod test {
             shortcode: shortcode.into(),
             posted: Utc::now().timestamp(),
             expires: None,
-            password: None
+            password: None,
+            content_nonce: None,
+            content_salt: None,
+            encrypted: false,
+            hits: 0,
+            owner: None,
+            parent_clip_id: None,
+            visibility: "unlisted".to_owned(),
+            tags: vec![],
         }
     }
 
     #[test]
     fn clip_new_and_get(){
         let rt = async_runtime();
-        let db = new_db(rt.handle());
+        let db = new_sqlite(rt.handle());
         let pool = db.get_pool();
         let clip = rt.block_on(async move {
             super::new_clip(model_new_clip("1"), &pool.clone()).await
@@ -0,0 +1,580 @@
+use std::path::Path;
+use crate::data::migrate::MigrationReport;
+use crate::data::{model, DataError};
+use crate::web::api::ApiKey;
+use crate::ShortCode;
+
+type Result<T> = std::result::Result<T, DataError>;
+
+pub enum RevocationStatus {
+    Revoked,
+    NotFound
+}
+
+/// Storage of clips, implemented once per database backend.
+#[rocket::async_trait]
+pub trait ClipRepository: Send + Sync {
+    async fn get_clip(&self, model: model::GetClip) -> Result<model::Clip>;
+    /// Fails with `RowNotFound` when there is no such clip.
+    async fn get_clip_by_id(&self, clip_id: &str) -> Result<model::Clip>;
+    async fn new_clip(&self, model: model::NewClip) -> Result<model::Clip>;
+    /// Inserts the clip in place of any clip with its shortcode, in one transaction.
+    async fn replace_clip(&self, model: model::NewClip) -> Result<model::Clip>;
+    async fn update_clip(&self, model: model::UpdateClip) -> Result<model::Clip>;
+    /// Up to `limit` clips ordered by `clip_id`, starting after `after`.
+    async fn list_clips(&self, after: Option<String>, limit: u32) -> Result<Vec<model::Clip>>;
+    /// Replaces content and title only if the content is still `expected_content`; false otherwise.
+    async fn rewrite_clip_text(&self, model: model::RewriteClipText) -> Result<bool>;
+    /// Stores sealed content while the clip is still a plaintext protected clip; false otherwise.
+    async fn seal_clip_content(&self, model: model::SealClipContent) -> Result<bool>;
+    async fn increase_hit_count(&self, shortcode: &ShortCode, hits: u32) -> Result<()>;
+    /// Deletes the clips whose expiry has passed and returns them.
+    async fn delete_expired(&self) -> Result<Vec<model::Clip>>;
+    /// Fails with `RowNotFound` when there is no such clip.
+    async fn delete_clip(&self, shortcode: &ShortCode) -> Result<()>;
+    async fn clip_stats(&self) -> Result<model::ClipStats>;
+    /// The `limit` most viewed clips, most hits first.
+    async fn top_clips(&self, limit: u32) -> Result<Vec<model::Clip>>;
+    /// The clips forked from `clip_id`, oldest first.
+    async fn list_forks(&self, clip_id: &str) -> Result<Vec<model::Clip>>;
+    /// The tags of a clip, sorted.
+    async fn clip_tags(&self, clip_id: &str) -> Result<Vec<String>>;
+    /// Unexpired clips matching `filter`, newest first.
+    async fn find_clips(&self, filter: model::ClipFilter) -> Result<Vec<model::Clip>>;
+    /// The `limit` newest public clips that are unexpired, unprotected and not end-to-end encrypted.
+    async fn recent_clips(&self, limit: u32) -> Result<Vec<model::Clip>>;
+}
+
+/// Storage of API keys, implemented once per database backend.
+#[rocket::async_trait]
+pub trait ApiKeyRepository: Send + Sync {
+    async fn save_api_key(&self, api_key: ApiKey) -> Result<ApiKey>;
+    async fn revoke_api_key(&self, api_key: ApiKey) -> Result<RevocationStatus>;
+    async fn api_key_is_valid(&self, api_key: ApiKey) -> Result<bool>;
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>>;
+}
+
+/// Webhooks of API keys, and the outbox of their deliveries.
+#[rocket::async_trait]
+pub trait WebhookRepository: Send + Sync {
+    async fn new_webhook(&self, model: model::NewWebhook) -> Result<model::Webhook>;
+    async fn list_webhooks(&self, owner: &str) -> Result<Vec<model::Webhook>>;
+    /// Also drops its deliveries. Fails with `RowNotFound` when `owner` has no such webhook.
+    async fn delete_webhook(&self, owner: &str, webhook_id: &str) -> Result<()>;
+    /// Queues the event for every webhook of its owner subscribed to it, returning how many deliveries were queued.
+    async fn enqueue_event(&self, model: model::NewEvent) -> Result<u64>;
+    /// Up to `limit` deliveries whose next attempt is due at `now`, longest waiting first.
+    async fn due_deliveries(&self, now: i64, limit: u32) -> Result<Vec<model::PendingDelivery>>;
+    async fn record_attempt(&self, model: model::DeliveryAttempt) -> Result<()>;
+    /// The `limit` latest deliveries of a webhook of `owner`, newest first.
+    async fn list_deliveries(&self, owner: &str, webhook_id: &str, limit: u32) -> Result<Vec<model::Delivery>>;
+}
+
+/// Collections of clips shared under one shortcode.
+#[rocket::async_trait]
+pub trait CollectionRepository: Send + Sync {
+    async fn new_collection(&self, model: model::NewCollection) -> Result<model::Collection>;
+    /// Fails with `RowNotFound` when there is no such collection.
+    async fn get_collection(&self, shortcode: &str) -> Result<model::Collection>;
+    /// The clips of a collection in order; deleted clips drop out of it.
+    async fn collection_clips(&self, collection_id: &str) -> Result<Vec<model::Clip>>;
+    /// Deletes the collections whose expiry has passed, but not their clips, returning how many.
+    async fn delete_expired_collections(&self) -> Result<u64>;
+}
+
+/// A complete storage backend, as held by [`crate::data::Database`].
+#[rocket::async_trait]
+pub trait Repository: ClipRepository + ApiKeyRepository + WebhookRepository + CollectionRepository {
+    async fn ping(&self) -> Result<()>;
+    async fn migrate(&self) -> Result<MigrationReport>;
+    async fn migration_status(&self) -> Result<MigrationReport>;
+    /// Writes a consistent copy of the database to `path`, which must not exist yet.
+    async fn backup_into(&self, path: &Path) -> Result<()>;
+}
+
+/// Behaviour every [`Repository`] implementation must share; run from each backend's tests.
+#[cfg(test)]
+pub mod conformance {
+    use super::*;
+    use crate::data::DbId;
+    use chrono::{Duration, Utc};
+
+    /// An unlisted clip without a password, to store directly.
+    pub fn new_clip(shortcode: &ShortCode, expires: Option<i64>) -> model::NewClip {
+        model::NewClip {
+            clip_id: DbId::new().into(),
+            shortcode: shortcode.clone().into_inner(),
+            content: format!("content for clip '{}'", shortcode.as_str()),
+            title: Some("title".to_owned()),
+            posted: Utc::now().timestamp(),
+            expires,
+            password: None,
+            content_nonce: None,
+            content_salt: None,
+            encrypted: false,
+            hits: 0,
+            owner: Some("owner".to_owned()),
+            parent_clip_id: None,
+            visibility: "unlisted".to_owned(),
+            tags: vec![],
+        }
+    }
+
+    /// A password protected clip as stored before content encryption: plaintext content and password.
+    pub fn legacy_protected_clip(shortcode: &ShortCode, password: &str) -> model::NewClip {
+        model::NewClip { password: Some(password.to_owned()), ..new_clip(shortcode, None) }
+    }
+
+    async fn clip_new_and_get(repo: &dyn Repository) {
+        let shortcode = ShortCode::new();
+        let clip = repo.new_clip(new_clip(&shortcode, None)).await.unwrap();
+        assert_eq!(clip.shortcode, shortcode.as_str());
+        assert_eq!(clip.hits, 0);
+
+        let clip = repo.get_clip(shortcode.clone().into()).await.unwrap();
+        assert_eq!(clip.content, format!("content for clip '{}'", shortcode.as_str()));
+        assert_eq!(clip.title.as_deref(), Some("title"));
+        assert_eq!(clip.owner.as_deref(), Some("owner"));
+    }
+
+    async fn duplicate_shortcode_is_rejected(repo: &dyn Repository) {
+        let shortcode = ShortCode::new();
+        repo.new_clip(new_clip(&shortcode, None)).await.unwrap();
+        assert!(repo.new_clip(new_clip(&shortcode, None)).await.is_err());
+    }
+
+    async fn replace_clip(repo: &dyn Repository) {
+        let shortcode = ShortCode::new();
+        let mut original = new_clip(&shortcode, None);
+        original.tags = vec!["old".to_owned()];
+        let original = repo.new_clip(original).await.unwrap();
+
+        let mut replacement = new_clip(&shortcode, None);
+        replacement.content = "replacement".to_owned();
+        let clip = repo.replace_clip(replacement).await.unwrap();
+        assert_ne!(clip.clip_id, original.clip_id);
+        let clip = repo.get_clip(shortcode.into()).await.unwrap();
+        assert_eq!(clip.content, "replacement");
+        assert!(repo.clip_tags(&clip.clip_id).await.unwrap().is_empty());
+
+        // without a clip to replace it is a plain insert
+        let fresh = ShortCode::new();
+        repo.replace_clip(new_clip(&fresh, None)).await.unwrap();
+        assert!(repo.get_clip(fresh.into()).await.is_ok());
+    }
+
+    async fn missing_clip_is_row_not_found(repo: &dyn Repository) {
+        let err = repo.get_clip(ShortCode::new().into()).await.unwrap_err();
+        assert!(matches!(err, DataError::Database(sqlx::Error::RowNotFound)));
+    }
+
+    async fn clip_update(repo: &dyn Repository) {
+        let shortcode = ShortCode::new();
+        repo.new_clip(new_clip(&shortcode, None)).await.unwrap();
+        let expires = (Utc::now() + Duration::days(1)).timestamp();
+        let clip = repo.update_clip(model::UpdateClip {
+            shortcode: shortcode.clone().into_inner(),
+            content: "updated".to_owned(),
+            title: None,
+            expires: Some(expires),
+            password: Some("hash".to_owned()),
+            content_nonce: Some(vec![1; 24]),
+            content_salt: Some(vec![2; 16]),
+            encrypted: true,
+            updated: Utc::now().timestamp(),
+            expected_revision: Some(1),
+            visibility: None,
+            tags: None,
+        }).await.unwrap();
+        assert_eq!(clip.content, "updated");
+        assert_eq!(clip.revision, 2);
+        assert!(clip.updated.is_some());
+        assert_eq!(clip.title, None);
+        assert_eq!(clip.expires.map(|e| e.and_utc().timestamp()), Some(expires));
+        assert_eq!(clip.password.as_deref(), Some("hash"));
+        assert_eq!(clip.content_nonce, Some(vec![1; 24]));
+        assert_eq!(clip.content_salt, Some(vec![2; 16]));
+        assert!(clip.encrypted);
+
+        let stale = |content: &str, expected_revision| model::UpdateClip {
+            shortcode: shortcode.clone().into_inner(),
+            content: content.to_owned(),
+            title: None,
+            expires: None,
+            password: None,
+            content_nonce: None,
+            content_salt: None,
+            encrypted: false,
+            updated: Utc::now().timestamp(),
+            expected_revision,
+            visibility: None,
+            tags: None,
+        };
+        let err = repo.update_clip(stale("lost", Some(1))).await.unwrap_err();
+        assert!(matches!(err, DataError::RevisionMismatch { expected: 1, actual: 2 }));
+        assert_eq!(repo.get_clip(shortcode.clone().into()).await.unwrap().content, "updated");
+        // without an expected revision the update always applies
+        assert_eq!(repo.update_clip(stale("forced", None)).await.unwrap().revision, 3);
+    }
+
+    async fn list_clips(repo: &dyn Repository) {
+        for _ in 0..3 {
+            repo.new_clip(new_clip(&ShortCode::new(), None)).await.unwrap();
+        }
+        let first = repo.list_clips(None, 2).await.unwrap();
+        assert_eq!(first.len(), 2);
+        assert!(first[0].clip_id < first[1].clip_id);
+        let rest = repo.list_clips(Some(first[1].clip_id.clone()), 1000).await.unwrap();
+        assert!(!rest.is_empty());
+        assert!(rest.iter().all(|clip| clip.clip_id > first[1].clip_id));
+    }
+
+    async fn rewrite_clip_text(repo: &dyn Repository) {
+        let shortcode = ShortCode::new();
+        let clip = repo.new_clip(new_clip(&shortcode, None)).await.unwrap();
+        let rewrite = |expected_content: &str| model::RewriteClipText {
+            shortcode: shortcode.clone().into_inner(),
+            expected_content: expected_content.to_owned(),
+            content: "rewritten".to_owned(),
+            title: Some("rewritten title".to_owned()),
+        };
+        assert!(!repo.rewrite_clip_text(rewrite("stale")).await.unwrap());
+        assert!(repo.rewrite_clip_text(rewrite(&clip.content)).await.unwrap());
+        let clip = repo.get_clip(shortcode.into()).await.unwrap();
+        assert_eq!(clip.content, "rewritten");
+        assert_eq!(clip.title.as_deref(), Some("rewritten title"));
+    }
+
+    async fn seal_clip_content(repo: &dyn Repository) {
+        use crate::domain::clip::field::Password;
+
+        let shortcode = ShortCode::new();
+        let clip = repo.new_clip(legacy_protected_clip(&shortcode, "hunter2")).await.unwrap();
+        let seal = clip.seal_content().unwrap();
+        assert!(repo.seal_clip_content(seal).await.unwrap());
+
+        let sealed = repo.get_clip(shortcode.clone().into()).await.unwrap();
+        assert!(sealed.content_nonce.is_some() && sealed.seal_content().is_none());
+        assert_ne!(sealed.content, clip.content);
+        let password = Password::new("hunter2".to_owned()).unwrap();
+        assert_eq!(sealed.unlock(&password).unwrap().unwrap().content, clip.content);
+        // a second run finds nothing left to seal
+        assert!(!repo.seal_clip_content(clip.seal_content().unwrap()).await.unwrap());
+    }
+
+    async fn hit_count(repo: &dyn Repository) {
+        let shortcode = ShortCode::new();
+        repo.new_clip(new_clip(&shortcode, None)).await.unwrap();
+        repo.increase_hit_count(&shortcode, 3).await.unwrap();
+        repo.increase_hit_count(&shortcode, 2).await.unwrap();
+        assert_eq!(repo.get_clip(shortcode.into()).await.unwrap().hits, 5);
+    }
+
+    async fn delete_expired(repo: &dyn Repository) {
+        let expired = ShortCode::new();
+        let current = ShortCode::new();
+        let past = (Utc::now() - Duration::minutes(1)).timestamp();
+        let future = (Utc::now() + Duration::days(1)).timestamp();
+        repo.new_clip(new_clip(&expired, Some(past))).await.unwrap();
+        repo.new_clip(new_clip(&current, Some(future))).await.unwrap();
+
+        let deleted = repo.delete_expired().await.unwrap();
+        assert!(deleted.iter().any(|clip| clip.shortcode == expired.as_str()));
+        assert!(deleted.iter().all(|clip| clip.shortcode != current.as_str()));
+        assert!(repo.get_clip(expired.into()).await.is_err());
+        assert!(repo.get_clip(current.into()).await.is_ok());
+    }
+
+    async fn delete_clip(repo: &dyn Repository) {
+        let shortcode = ShortCode::new();
+        repo.new_clip(new_clip(&shortcode, None)).await.unwrap();
+        repo.delete_clip(&shortcode).await.unwrap();
+        assert!(repo.get_clip(shortcode.clone().into()).await.is_err());
+        let err = repo.delete_clip(&shortcode).await.unwrap_err();
+        assert!(matches!(err, DataError::Database(sqlx::Error::RowNotFound)));
+    }
+
+    async fn forks(repo: &dyn Repository) {
+        let parent = repo.new_clip(new_clip(&ShortCode::new(), None)).await.unwrap();
+        assert_eq!(repo.get_clip_by_id(&parent.clip_id).await.unwrap().shortcode, parent.shortcode);
+        let mut forks = vec![];
+        for later in 0..2 {
+            let mut fork = new_clip(&ShortCode::new(), None);
+            fork.posted += later;
+            fork.parent_clip_id = Some(parent.clip_id.clone());
+            forks.push(repo.new_clip(fork).await.unwrap());
+        }
+        assert_eq!(forks[0].parent_clip_id.as_ref(), Some(&parent.clip_id));
+        let listed: Vec<_> = repo.list_forks(&parent.clip_id).await.unwrap().into_iter().map(|clip| clip.clip_id).collect();
+        assert_eq!(listed, [forks[0].clip_id.clone(), forks[1].clip_id.clone()]);
+
+        // forks outlive the clip they came from
+        repo.delete_clip(&ShortCode::from(parent.shortcode.as_str())).await.unwrap();
+        assert_eq!(repo.get_clip(forks[0].shortcode.clone().into()).await.unwrap().parent_clip_id, None);
+        let err = repo.get_clip_by_id(&parent.clip_id).await.unwrap_err();
+        assert!(matches!(err, DataError::Database(sqlx::Error::RowNotFound)));
+    }
+
+    async fn tags(repo: &dyn Repository) {
+        let tag = format!("tag-{}", ShortCode::new().as_str().to_lowercase());
+        let owner = DbId::new().to_string();
+        let tagged = |password: Option<&str>, tags: &[&str]| {
+            let mut clip = new_clip(&ShortCode::new(), None);
+            clip.password = password.map(str::to_owned);
+            clip.owner = Some(owner.clone());
+            clip.visibility = "public".to_owned();
+            clip.tags = tags.iter().map(|tag| tag.to_string()).collect();
+            clip
+        };
+        let mut older = tagged(None, &[&tag, "ops"]);
+        older.posted -= 10;
+        let older = repo.new_clip(older).await.unwrap();
+        let protected = repo.new_clip(tagged(Some("hash"), &[&tag])).await.unwrap();
+        let untagged = repo.new_clip(tagged(None, &[])).await.unwrap();
+        assert_eq!(repo.clip_tags(&older.clip_id).await.unwrap(), ["ops".to_owned(), tag.clone()]);
+        assert!(repo.clip_tags(&untagged.clip_id).await.unwrap().is_empty());
+
+        let ids = |clips: Vec<model::Clip>| clips.into_iter().map(|clip| clip.clip_id).collect::<Vec<_>>();
+        let owned = repo.find_clips(model::ClipFilter::owned_by(owner.clone(), None, 10)).await.unwrap();
+        assert_eq!(owned.len(), 3);
+        let owned = repo.find_clips(model::ClipFilter::owned_by(owner.clone(), Some(tag.clone()), 10)).await.unwrap();
+        assert_eq!(ids(owned), [protected.clip_id.clone(), older.clip_id.clone()]);
+        let public = repo.find_clips(model::ClipFilter::public(Some(tag.clone()), 10)).await.unwrap();
+        assert_eq!(ids(public), [older.clip_id.as_str()]);
+
+        // an update replaces the tags only when it has some
+        let update = |tags: Option<Vec<String>>| model::UpdateClip {
+            shortcode: older.shortcode.clone(),
+            content: "updated".to_owned(),
+            title: None,
+            expires: None,
+            password: None,
+            content_nonce: None,
+            content_salt: None,
+            encrypted: false,
+            updated: Utc::now().timestamp(),
+            expected_revision: None,
+            visibility: None,
+            tags,
+        };
+        repo.update_clip(update(None)).await.unwrap();
+        assert_eq!(repo.clip_tags(&older.clip_id).await.unwrap().len(), 2);
+        repo.update_clip(update(Some(vec!["db".to_owned()]))).await.unwrap();
+        assert_eq!(repo.clip_tags(&older.clip_id).await.unwrap(), ["db"]);
+
+        repo.delete_clip(&ShortCode::from(protected.shortcode.as_str())).await.unwrap();
+        assert!(repo.clip_tags(&protected.clip_id).await.unwrap().is_empty());
+        assert!(repo.find_clips(model::ClipFilter::owned_by(owner, Some(tag), 10)).await.unwrap().is_empty());
+    }
+
+    async fn visibility(repo: &dyn Repository) {
+        let tag = format!("tag-{}", ShortCode::new().as_str().to_lowercase());
+        let mut clip = new_clip(&ShortCode::new(), None);
+        clip.tags = vec![tag.clone()];
+        let clip = repo.new_clip(clip).await.unwrap();
+        assert_eq!(clip.visibility, "unlisted");
+        let public = || async { repo.find_clips(model::ClipFilter::public(Some(tag.clone()), 10)).await.unwrap().len() };
+        assert_eq!(public().await, 0);
+
+        let update = |visibility: Option<&str>| model::UpdateClip {
+            shortcode: clip.shortcode.clone(),
+            content: clip.content.clone(),
+            title: None,
+            expires: None,
+            password: None,
+            content_nonce: None,
+            content_salt: None,
+            encrypted: false,
+            updated: Utc::now().timestamp(),
+            expected_revision: None,
+            visibility: visibility.map(str::to_owned),
+            tags: None,
+        };
+        assert_eq!(repo.update_clip(update(Some("public"))).await.unwrap().visibility, "public");
+        assert_eq!(public().await, 1);
+        // left out, the visibility is kept
+        assert_eq!(repo.update_clip(update(None)).await.unwrap().visibility, "public");
+        repo.update_clip(update(Some("private"))).await.unwrap();
+        assert_eq!(public().await, 0);
+    }
+
+    async fn recent_clips(repo: &dyn Repository) {
+        let past = (Utc::now() - Duration::minutes(1)).timestamp();
+        let public = |password: Option<&str>, encrypted: bool, expires: Option<i64>| {
+            let mut clip = new_clip(&ShortCode::new(), expires);
+            clip.visibility = "public".to_owned();
+            clip.password = password.map(str::to_owned);
+            clip.encrypted = encrypted;
+            clip
+        };
+        let mut older = public(None, false, None);
+        older.posted -= 10;
+        let older = repo.new_clip(older).await.unwrap();
+        let newer = repo.new_clip(public(None, false, None)).await.unwrap();
+        let hidden = [
+            repo.new_clip(public(Some("hash"), false, None)).await.unwrap(),
+            repo.new_clip(public(None, true, None)).await.unwrap(),
+            repo.new_clip(public(None, false, Some(past))).await.unwrap(),
+            repo.new_clip(new_clip(&ShortCode::new(), None)).await.unwrap(),
+        ];
+
+        let recent: Vec<_> = repo.recent_clips(1000).await.unwrap().into_iter().map(|clip| clip.clip_id).collect();
+        let position = |clip: &model::Clip| recent.iter().position(|id| *id == clip.clip_id);
+        assert!(position(&newer).unwrap() < position(&older).unwrap());
+        assert!(hidden.iter().all(|clip| position(clip).is_none()));
+        assert_eq!(repo.recent_clips(1).await.unwrap().len(), 1);
+    }
+
+    async fn collections(repo: &dyn Repository) {
+        let mut clips = vec![];
+        for _ in 0..3 {
+            clips.push(repo.new_clip(new_clip(&ShortCode::new(), None)).await.unwrap());
+        }
+        let domain_clips: Vec<crate::Clip> = clips.iter().rev().map(|clip| clip.clone().try_into().unwrap()).collect();
+        let past = (Utc::now() - Duration::minutes(1)).timestamp();
+        let new_collection = |expires: Option<i64>| {
+            let expires = expires.map(|secs| crate::Time::from(chrono::DateTime::from_timestamp(secs, 0).unwrap()));
+            model::NewCollection::new(ShortCode::new(), Some("bundle".to_owned()), expires, Some("owner".to_owned()), &domain_clips)
+        };
+        let collection = repo.new_collection(new_collection(None)).await.unwrap();
+        let expired = repo.new_collection(new_collection(Some(past))).await.unwrap();
+        assert_eq!(collection.title.as_deref(), Some("bundle"));
+        let fetched = repo.get_collection(&collection.shortcode).await.unwrap();
+        assert_eq!(fetched.collection_id, collection.collection_id);
+
+        let members: Vec<_> = repo.collection_clips(&collection.collection_id).await.unwrap().into_iter().map(|clip| clip.clip_id).collect();
+        assert_eq!(members, [clips[2].clip_id.clone(), clips[1].clip_id.clone(), clips[0].clip_id.clone()]);
+        // deleted clips drop out of the collection
+        repo.delete_clip(&ShortCode::from(clips[1].shortcode.as_str())).await.unwrap();
+        assert_eq!(repo.collection_clips(&collection.collection_id).await.unwrap().len(), 2);
+
+        assert!(repo.delete_expired_collections().await.unwrap() >= 1);
+        let err = repo.get_collection(&expired.shortcode).await.unwrap_err();
+        assert!(matches!(err, DataError::Database(sqlx::Error::RowNotFound)));
+        assert!(repo.get_collection(&collection.shortcode).await.is_ok());
+        // expiring a collection keeps its clips
+        assert!(repo.get_clip(clips[0].shortcode.clone().into()).await.is_ok());
+    }
+
+    async fn stats(repo: &dyn Repository) {
+        let before = repo.clip_stats().await.unwrap();
+        let popular = ShortCode::new();
+        let mut protected = new_clip(&ShortCode::new(), Some((Utc::now() - Duration::minutes(1)).timestamp()));
+        protected.password = Some("hash".to_owned());
+        repo.new_clip(protected).await.unwrap();
+        repo.new_clip(new_clip(&popular, None)).await.unwrap();
+        repo.increase_hit_count(&popular, u32::MAX).await.unwrap();
+
+        let after = repo.clip_stats().await.unwrap();
+        assert_eq!(after.clips, before.clips + 2);
+        assert_eq!(after.protected, before.protected + 1);
+        assert_eq!(after.expired, before.expired + 1);
+        assert!(after.total_bytes > before.total_bytes);
+
+        let top = repo.top_clips(2).await.unwrap();
+        assert_eq!(top.len(), 2);
+        assert!(top[0].hits >= top[1].hits);
+        assert!(top[0].hits >= i64::from(u32::MAX));
+    }
+
+    async fn api_keys(repo: &dyn Repository) {
+        let key = repo.save_api_key(ApiKey::default()).await.unwrap();
+        assert!(repo.api_key_is_valid(key.clone()).await.unwrap());
+        assert!(repo.list_api_keys().await.unwrap().iter().any(|k| k.to_base64() == key.to_base64()));
+        assert!(!repo.api_key_is_valid(ApiKey::default()).await.unwrap());
+
+        assert!(matches!(repo.revoke_api_key(key.clone()).await.unwrap(), RevocationStatus::Revoked));
+        assert!(matches!(repo.revoke_api_key(key.clone()).await.unwrap(), RevocationStatus::NotFound));
+        assert!(!repo.api_key_is_valid(key).await.unwrap());
+    }
+
+    async fn webhooks(repo: &dyn Repository) {
+        use crate::domain::webhook::{ClipEvent, ClipSummary, EventPayload};
+
+        let (owner, stranger) = (DbId::new().to_string(), DbId::new().to_string());
+        let new_webhook = |owner: &str, events: &[ClipEvent]| {
+            model::NewWebhook::new(owner.to_owned(), "http://127.0.0.1:9/hook".to_owned(), events, "secret".to_owned())
+        };
+        let webhook = repo.new_webhook(new_webhook(&owner, &[ClipEvent::Created, ClipEvent::Updated])).await.unwrap();
+        repo.new_webhook(new_webhook(&stranger, &[ClipEvent::Updated])).await.unwrap();
+        let listed = repo.list_webhooks(&owner).await.unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].events, "clip.created,clip.updated");
+
+        let event = |event: ClipEvent| {
+            let payload = EventPayload {
+                id: DbId::new().to_string(),
+                event,
+                created: Utc::now() - Duration::seconds(1),
+                clip: ClipSummary {
+                    shortcode: "shortcode".to_owned(),
+                    revision: 1,
+                    hits: 0,
+                    posted: Utc::now(),
+                    updated: Utc::now(),
+                    expires: None,
+                    protected: false,
+                },
+            };
+            model::NewEvent::new(owner.clone(), &payload).unwrap()
+        };
+        assert_eq!(repo.enqueue_event(event(ClipEvent::Viewed)).await.unwrap(), 0);
+        let updated = event(ClipEvent::Updated);
+        let event_id = updated.event_id.clone();
+        assert_eq!(repo.enqueue_event(updated).await.unwrap(), 1);
+
+        let now = Utc::now().timestamp();
+        let due = repo.due_deliveries(now, 1000).await.unwrap();
+        let pending = due.iter().find(|d| d.event_id == event_id).expect("queued delivery is due");
+        assert_eq!((pending.attempts, pending.secret.as_str()), (0, "secret"));
+        assert!(pending.payload.contains("\"clip.updated\""));
+
+        // a failed attempt is retried later, not right away
+        repo.record_attempt(model::DeliveryAttempt::failed(pending, Some(500), "server error".to_owned())).await.unwrap();
+        assert!(repo.due_deliveries(now, 1000).await.unwrap().iter().all(|d| d.event_id != event_id));
+        let log = repo.list_deliveries(&owner, &webhook.webhook_id, 10).await.unwrap();
+        assert_eq!(log.len(), 1);
+        assert_eq!((log[0].attempts, log[0].response_status), (1, Some(500)));
+        assert!(log[0].next_attempt.is_some() && log[0].delivered.is_none());
+
+        repo.record_attempt(model::DeliveryAttempt::delivered(pending, 204)).await.unwrap();
+        let log = repo.list_deliveries(&owner, &webhook.webhook_id, 10).await.unwrap();
+        assert_eq!((log[0].attempts, log[0].response_status), (2, Some(204)));
+        assert!(log[0].next_attempt.is_none() && log[0].delivered.is_some());
+        assert!(repo.list_deliveries(&stranger, &webhook.webhook_id, 10).await.unwrap().is_empty());
+
+        let err = repo.delete_webhook(&stranger, &webhook.webhook_id).await.unwrap_err();
+        assert!(matches!(err, DataError::Database(sqlx::Error::RowNotFound)));
+        repo.delete_webhook(&owner, &webhook.webhook_id).await.unwrap();
+        assert!(repo.list_webhooks(&owner).await.unwrap().is_empty());
+        assert!(repo.list_deliveries(&owner, &webhook.webhook_id, 10).await.unwrap().is_empty());
+    }
+
+    async fn migrations(repo: &dyn Repository) {
+        repo.ping().await.unwrap();
+        assert!(repo.migration_status().await.unwrap().is_up_to_date());
+    }
+
+    pub async fn run(repo: &dyn Repository) {
+        clip_new_and_get(repo).await;
+        duplicate_shortcode_is_rejected(repo).await;
+        missing_clip_is_row_not_found(repo).await;
+        replace_clip(repo).await;
+        clip_update(repo).await;
+        list_clips(repo).await;
+        rewrite_clip_text(repo).await;
+        seal_clip_content(repo).await;
+        hit_count(repo).await;
+        delete_expired(repo).await;
+        delete_clip(repo).await;
+        forks(repo).await;
+        tags(repo).await;
+        visibility(repo).await;
+        recent_clips(repo).await;
+        collections(repo).await;
+        stats(repo).await;
+        api_keys(repo).await;
+        webhooks(repo).await;
+        migrations(repo).await;
+    }
+}
@@ -0,0 +1,230 @@
+use std::path::Path;
+use std::str::FromStr;
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+use crate::data::migrate::{self, MigrationReport, MIGRATOR};
+use crate::data::repository::{ApiKeyRepository, ClipRepository, CollectionRepository, Repository, RevocationStatus, WebhookRepository};
+use crate::data::{model, query, DataError, DatabasePool};
+use crate::web::api::ApiKey;
+use crate::ShortCode;
+
+type Result<T> = std::result::Result<T, DataError>;
+
+pub struct SqliteRepository(DatabasePool);
+
+impl SqliteRepository {
+    /// Connects to the database, creating the database file if it does not exist yet.
+    pub async fn connect(connection_str: &str, pool_size: u32) -> Result<Self> {
+        let options = SqliteConnectOptions::from_str(connection_str)?
+            .create_if_missing(true);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(pool_size)
+            .connect_with(options)
+            .await?;
+        Ok(Self(pool))
+    }
+
+    pub fn get_pool(&self) -> &DatabasePool {
+        &self.0
+    }
+
+    /// Versions recorded by sqlx; a database that has never been migrated has no table yet.
+    async fn applied_versions(&self) -> Result<Vec<i64>> {
+        match query::has_migrations_table(&self.0).await? {
+            true => query::applied_migrations(&self.0).await,
+            false => Ok(vec![]),
+        }
+    }
+}
+
+#[rocket::async_trait]
+impl ClipRepository for SqliteRepository {
+    async fn get_clip(&self, model: model::GetClip) -> Result<model::Clip> {
+        query::get_clip(model, &self.0).await
+    }
+
+    async fn get_clip_by_id(&self, clip_id: &str) -> Result<model::Clip> {
+        query::get_clip_by_id(clip_id, &self.0).await
+    }
+
+    async fn new_clip(&self, model: model::NewClip) -> Result<model::Clip> {
+        query::new_clip(model, &self.0).await
+    }
+
+    async fn replace_clip(&self, model: model::NewClip) -> Result<model::Clip> {
+        query::replace_clip(model, &self.0).await
+    }
+
+    async fn update_clip(&self, model: model::UpdateClip) -> Result<model::Clip> {
+        query::update_clip(model, &self.0).await
+    }
+
+    async fn list_clips(&self, after: Option<String>, limit: u32) -> Result<Vec<model::Clip>> {
+        query::list_clips(after, limit, &self.0).await
+    }
+
+    async fn rewrite_clip_text(&self, model: model::RewriteClipText) -> Result<bool> {
+        query::rewrite_clip_text(model, &self.0).await
+    }
+
+    async fn seal_clip_content(&self, model: model::SealClipContent) -> Result<bool> {
+        query::seal_clip_content(model, &self.0).await
+    }
+
+    async fn increase_hit_count(&self, shortcode: &ShortCode, hits: u32) -> Result<()> {
+        query::increase_hit_count(shortcode, hits, &self.0).await
+    }
+
+    async fn delete_expired(&self) -> Result<Vec<model::Clip>> {
+        query::delete_expired(&self.0).await
+    }
+
+    async fn delete_clip(&self, shortcode: &ShortCode) -> Result<()> {
+        query::delete_clip(shortcode, &self.0).await
+    }
+
+    async fn clip_stats(&self) -> Result<model::ClipStats> {
+        query::clip_stats(&self.0).await
+    }
+
+    async fn top_clips(&self, limit: u32) -> Result<Vec<model::Clip>> {
+        query::top_clips(limit, &self.0).await
+    }
+
+    async fn list_forks(&self, clip_id: &str) -> Result<Vec<model::Clip>> {
+        query::list_forks(clip_id, &self.0).await
+    }
+
+    async fn clip_tags(&self, clip_id: &str) -> Result<Vec<String>> {
+        query::clip_tags(clip_id, &self.0).await
+    }
+
+    async fn find_clips(&self, filter: model::ClipFilter) -> Result<Vec<model::Clip>> {
+        query::find_clips(filter, &self.0).await
+    }
+
+    async fn recent_clips(&self, limit: u32) -> Result<Vec<model::Clip>> {
+        query::recent_clips(limit, &self.0).await
+    }
+}
+
+#[rocket::async_trait]
+impl ApiKeyRepository for SqliteRepository {
+    async fn save_api_key(&self, api_key: ApiKey) -> Result<ApiKey> {
+        query::save_api_key(api_key, &self.0).await
+    }
+
+    async fn revoke_api_key(&self, api_key: ApiKey) -> Result<RevocationStatus> {
+        query::revoke_api_key(api_key, &self.0).await
+    }
+
+    async fn api_key_is_valid(&self, api_key: ApiKey) -> Result<bool> {
+        query::api_key_is_valid(api_key, &self.0).await
+    }
+
+    async fn list_api_keys(&self) -> Result<Vec<ApiKey>> {
+        query::list_api_keys(&self.0).await
+    }
+}
+
+#[rocket::async_trait]
+impl WebhookRepository for SqliteRepository {
+    async fn new_webhook(&self, model: model::NewWebhook) -> Result<model::Webhook> {
+        query::new_webhook(model, &self.0).await
+    }
+
+    async fn list_webhooks(&self, owner: &str) -> Result<Vec<model::Webhook>> {
+        query::list_webhooks(owner, &self.0).await
+    }
+
+    async fn delete_webhook(&self, owner: &str, webhook_id: &str) -> Result<()> {
+        query::delete_webhook(owner, webhook_id, &self.0).await
+    }
+
+    async fn enqueue_event(&self, model: model::NewEvent) -> Result<u64> {
+        query::enqueue_event(model, &self.0).await
+    }
+
+    async fn due_deliveries(&self, now: i64, limit: u32) -> Result<Vec<model::PendingDelivery>> {
+        query::due_deliveries(now, limit, &self.0).await
+    }
+
+    async fn record_attempt(&self, model: model::DeliveryAttempt) -> Result<()> {
+        query::record_attempt(model, &self.0).await
+    }
+
+    async fn list_deliveries(&self, owner: &str, webhook_id: &str, limit: u32) -> Result<Vec<model::Delivery>> {
+        query::list_deliveries(owner, webhook_id, limit, &self.0).await
+    }
+}
+
+#[rocket::async_trait]
+impl CollectionRepository for SqliteRepository {
+    async fn new_collection(&self, model: model::NewCollection) -> Result<model::Collection> {
+        query::new_collection(model, &self.0).await
+    }
+
+    async fn get_collection(&self, shortcode: &str) -> Result<model::Collection> {
+        query::get_collection(shortcode, &self.0).await
+    }
+
+    async fn collection_clips(&self, collection_id: &str) -> Result<Vec<model::Clip>> {
+        query::collection_clips(collection_id, &self.0).await
+    }
+
+    async fn delete_expired_collections(&self) -> Result<u64> {
+        query::delete_expired_collections(&self.0).await
+    }
+}
+
+#[rocket::async_trait]
+impl Repository for SqliteRepository {
+    async fn ping(&self) -> Result<()> {
+        query::ping(&self.0).await
+    }
+
+    async fn migrate(&self) -> Result<MigrationReport> {
+        let before = self.applied_versions().await?;
+        MIGRATOR.run(&self.0).await?;
+        let after = self.applied_versions().await?;
+        Ok(migrate::report(&MIGRATOR, &before, &after))
+    }
+
+    async fn migration_status(&self) -> Result<MigrationReport> {
+        let applied = self.applied_versions().await?;
+        Ok(migrate::report(&MIGRATOR, &applied, &applied))
+    }
+
+    async fn backup_into(&self, path: &Path) -> Result<()> {
+        let path = path.to_str().ok_or_else(|| DataError::Backup(format!("unsupported path {:?}", path)))?;
+        query::vacuum_into(path, &self.0).await
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::data::repository::conformance;
+    use crate::data::test::new_sqlite;
+    use crate::test::async_runtime;
+
+    #[test]
+    fn conforms_to_repository() {
+        let rt = async_runtime();
+        let repo = new_sqlite(rt.handle());
+        rt.block_on(conformance::run(&repo));
+    }
+
+    #[test]
+    fn never_migrated_database_has_every_migration_pending() {
+        use crate::data::repository::Repository;
+        use super::SqliteRepository;
+
+        let rt = async_runtime();
+        rt.block_on(async {
+            let repo = SqliteRepository::connect(":memory:", 1).await.unwrap();
+            let report = repo.migration_status().await.unwrap();
+            assert!(!report.pending().is_empty());
+            assert_eq!(report.pending().len(), report.migrations.len());
+            assert!(repo.migrate().await.unwrap().is_up_to_date());
+        });
+    }
+}
@@ -0,0 +1,31 @@
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+
+/// Whether the content was encrypted by the client, with the key kept in the link fragment.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct Encrypted(bool);
+
+impl Encrypted {
+    pub fn new(encrypted: bool) -> Self {
+        Self(encrypted)
+    }
+
+    pub fn into_inner(self) -> bool {
+        self.0
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.0
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for Encrypted {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Ok(Self(bool::from_value(field)?))
+    }
+
+    fn default() -> Option<Self> {
+        Some(Self(false))
+    }
+}
@@ -1,7 +1,7 @@
 mod clip_id;
 pub use clip_id::ClipId;
 mod shortcode;
-pub use shortcode::ShortCode;
+pub use shortcode::{ShortCode, ShortCodePolicy};
 
 mod content;
 pub use content::Content;
@@ -19,4 +19,20 @@ mod password;
 pub use password::Password;
 
 mod hits;
-pub use hits::Hits;
\ No newline at end of file
+pub use hits::Hits;
+
+mod encrypted;
+pub use encrypted::Encrypted;
+mod owner;
+pub use owner::Owner;
+mod parent;
+pub use parent::Parent;
+mod tags;
+pub use tags::{normalize_tag, Tags, MAX_TAGS};
+mod visibility;
+pub use visibility::Visibility;
+
+mod revision;
+pub use revision::Revision;
+mod updated;
+pub use updated::Updated;
@@ -0,0 +1,19 @@
+use serde::{Deserialize, Serialize};
+
+/// Fingerprint of the API key that created a clip; see [`crate::web::api::ApiKey::id`].
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+pub struct Owner(Option<String>);
+
+impl Owner {
+    pub fn new<T: Into<Option<String>>>(owner: T) -> Self {
+        Self(owner.into())
+    }
+
+    pub fn into_inner(self) -> Option<String> {
+        self.0
+    }
+
+    pub fn as_deref(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
+}
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+use super::ClipId;
+
+/// The clip this one was forked from, if any.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct Parent(Option<ClipId>);
+
+impl Parent {
+    pub fn new<T: Into<Option<ClipId>>>(parent: T) -> Self {
+        Self(parent.into())
+    }
+
+    pub fn into_inner(self) -> Option<ClipId> {
+        self.0
+    }
+
+    pub fn as_ref(&self) -> Option<&ClipId> {
+        self.0.as_ref()
+    }
+}
@@ -3,7 +3,7 @@ use rocket::form::{self,FromFormField, ValueField};
 use serde::{Deserialize, Serialize};
 use crate::domain::clip::ClipError;
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, PartialOrd, Default)]
 pub struct Password(Option<String>);
 
 impl Password {
@@ -24,14 +24,12 @@ impl Password {
         self.0
     }
 
-    pub fn has_password(&self) -> bool {
-        self.0.is_some()
+    pub fn as_str(&self) -> Option<&str> {
+        self.0.as_deref()
     }
-}
 
-impl Default for Password {
-    fn default() -> Self {
-        Self(None)
+    pub fn has_password(&self) -> bool {
+        self.0.is_some()
     }
 }
 
@@ -0,0 +1,18 @@
+use derive_more::Constructor;
+use serde::{Deserialize, Serialize};
+
+/// Starts at 1 and grows with every update of the clip.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, Constructor, PartialEq, Eq)]
+pub struct Revision(u64);
+
+impl Revision {
+    pub fn into_inner(self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for Revision {
+    fn default() -> Self {
+        Self(1)
+    }
+}
@@ -4,21 +4,59 @@ use rocket::{UriDisplayPath, UriDisplayQuery};
 use serde::{Deserialize, Serialize};
 use crate::domain::clip::ClipError;
 
+/// How new shortcodes are generated.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(default)]
+pub struct ShortCodePolicy {
+    pub length: usize,
+    pub alphabet: String,
+}
+
+impl ShortCodePolicy {
+    pub fn validate(&self) -> Result<(), ClipError> {
+        let mut chars: Vec<char> = self.alphabet.chars().collect();
+        chars.sort_unstable();
+        chars.dedup();
+        if chars.len() < 2 || chars.len() != self.alphabet.chars().count() {
+            return Err(ClipError::InvalidShortCodePolicy("alphabet needs at least 2 distinct characters".to_owned()));
+        }
+        if chars.iter().any(|c| !c.is_ascii_alphanumeric()) {
+            return Err(ClipError::InvalidShortCodePolicy("alphabet must be ASCII alphanumeric".to_owned()));
+        }
+        if self.length < 4 {
+            return Err(ClipError::InvalidShortCodePolicy("length must be at least 4".to_owned()));
+        }
+        Ok(())
+    }
+}
+
+impl Default for ShortCodePolicy {
+    fn default() -> Self {
+        Self {
+            length: 10,
+            alphabet: "abcd1234".to_owned(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, From, UriDisplayPath, UriDisplayQuery, Hash, Eq, PartialEq)]
 pub struct ShortCode(String);
 
 impl ShortCode {
+    /// Generates a shortcode with the default policy.
     pub fn new() -> Self {
+        Self::with_policy(&ShortCodePolicy::default())
+    }
+
+    pub fn with_policy(policy: &ShortCodePolicy) -> Self {
         use rand::prelude::*;
 
-        let allowed_chars = [
-            'a', 'b', 'c', 'd', '1', '2', '3', '4',
-        ];
+        let allowed_chars: Vec<char> = policy.alphabet.chars().collect();
 
         let mut rng = thread_rng();
-        let mut shortcode  = String::with_capacity(10);
+        let mut shortcode  = String::with_capacity(policy.length);
 
-        for _ in 0..10 {
+        for _ in 0..policy.length {
             shortcode.push(
                 *allowed_chars.choose(&mut rng).expect("sampling array should have values")
             );
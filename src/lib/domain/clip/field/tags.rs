@@ -0,0 +1,87 @@
+use std::str::FromStr;
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use crate::domain::clip::ClipError;
+
+pub const MAX_TAGS: usize = 10;
+const MAX_TAG_CHARS: usize = 32;
+
+/// A tag, lowercased: letters, digits, `-`, `_` and `.`.
+pub fn normalize_tag(tag: &str) -> Result<String, ClipError> {
+    let tag = tag.trim().to_lowercase();
+    let valid = |c: char| c.is_alphanumeric() || matches!(c, '-' | '_' | '.');
+    match tag.chars().count() {
+        0 => Err(ClipError::InvalidTag("tags may not be empty".to_owned())),
+        n if n > MAX_TAG_CHARS => Err(ClipError::InvalidTag(format!("'{}' is longer than {} characters", tag, MAX_TAG_CHARS))),
+        _ if !tag.chars().all(valid) => Err(ClipError::InvalidTag(format!("'{}' may only have letters, digits, '-', '_' and '.'", tag))),
+        _ => Ok(tag),
+    }
+}
+
+/// The tags of a clip, sorted and without duplicates.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
+#[serde(try_from = "Vec<String>")]
+pub struct Tags(Vec<String>);
+
+impl Tags {
+    pub fn new(tags: Vec<String>) -> Result<Self, ClipError> {
+        let mut tags = tags.iter().map(|tag| normalize_tag(tag)).collect::<Result<Vec<_>, _>>()?;
+        tags.sort();
+        tags.dedup();
+        if tags.len() > MAX_TAGS {
+            return Err(ClipError::InvalidTag(format!("a clip may have at most {} tags", MAX_TAGS)));
+        }
+        Ok(Self(tags))
+    }
+
+    pub fn into_inner(self) -> Vec<String> {
+        self.0
+    }
+
+    pub fn as_slice(&self) -> &[String] {
+        &self.0
+    }
+}
+
+impl TryFrom<Vec<String>> for Tags {
+    type Error = ClipError;
+
+    fn try_from(tags: Vec<String>) -> Result<Self, Self::Error> {
+        Self::new(tags)
+    }
+}
+
+/// Tags separated by commas or spaces, as typed into a form.
+impl FromStr for Tags {
+    type Err = ClipError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::new(s.split([',', ' ']).filter(|tag| !tag.trim().is_empty()).map(str::to_owned).collect())
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for Tags {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Ok(Self::from_str(field.value).map_err(|e| form::Error::validation(format!("{}", e)))?)
+    }
+
+    fn default() -> Option<Self> {
+        Some(<Tags as Default>::default())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn tags_are_normalized() {
+        let tags: Tags = " Postgres, ops  ops,k8s".parse().unwrap();
+        assert_eq!(tags.as_slice(), ["k8s", "ops", "postgres"]);
+        assert!("deploy/prod".parse::<Tags>().is_err());
+        assert!(serde_json::from_str::<Tags>(r#"["a", ""]"#).is_err());
+        let many: Vec<String> = (0..=MAX_TAGS).map(|n| n.to_string()).collect();
+        assert!(Tags::new(many).is_err());
+    }
+}
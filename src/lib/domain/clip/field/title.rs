@@ -24,6 +24,10 @@ impl Title {
     pub fn into_inner(self) -> Option<String> {
         self.0
     }
+
+    pub fn as_deref(&self) -> Option<&str> {
+        self.0.as_deref()
+    }
 }
 
 impl Default for Title {
@@ -0,0 +1,13 @@
+use derive_more::Constructor;
+use serde::{Deserialize, Serialize};
+use crate::domain::time::Time;
+
+/// When the clip last changed; its posted time until the first update.
+#[derive(Debug, Clone, Serialize, Deserialize, Constructor)]
+pub struct Updated(Time);
+
+impl Updated {
+    pub fn into_inner(self) -> Time {
+        self.0
+    }
+}
@@ -0,0 +1,47 @@
+use rocket::form::{self, FromFormField, ValueField};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// Who may find and read a clip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, strum::Display, strum::EnumString, utoipa::ToSchema)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase")]
+pub enum Visibility {
+    /// Listed on the recent and tag pages.
+    Public,
+    /// Readable by anyone with the shortcode, but never listed.
+    #[default]
+    Unlisted,
+    /// Only readable with the API key or web session that created the clip.
+    Private,
+}
+
+impl Visibility {
+    pub fn is_private(self) -> bool {
+        self == Self::Private
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromFormField<'r> for Visibility {
+    fn from_value(field: ValueField<'r>) -> form::Result<'r, Self> {
+        Self::from_str(field.value.trim()).map_err(|_| form::Error::validation("Visibility must be public, unlisted or private").into())
+    }
+
+    fn default() -> Option<Self> {
+        Some(<Visibility as Default>::default())
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn parses_lowercase_names() {
+        assert_eq!(Visibility::from_str("private").unwrap(), Visibility::Private);
+        assert_eq!(Visibility::Public.to_string(), "public");
+        assert_eq!(serde_json::to_string(&Visibility::Unlisted).unwrap(), r#""unlisted""#);
+        assert!(Visibility::from_str("secret").is_err());
+    }
+}
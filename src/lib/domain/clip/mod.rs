@@ -19,17 +19,52 @@ pub enum ClipError {
     Id(#[from] uuid::Error),
     #[error("hits parse error: {0}")]
     Hits(#[from] std::num::TryFromIntError),
+    #[error("invalid shortcode: {0}")]
+    InvalidShortCode(String),
+    #[error("invalid shortcode policy: {0}")]
+    InvalidShortCodePolicy(String),
+    #[error("invalid tag: {0}")]
+    InvalidTag(String),
+    #[error("invalid visibility: {0}")]
+    InvalidVisibility(String),
+    #[error("encryption error: {0}")]
+    Crypto(String),
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, utoipa::ToSchema)]
 pub struct Clip {
     #[serde(skip)]
     pub clip_id: field::ClipId,
+    #[schema(value_type = String)]
     pub shortcode: field::ShortCode,
+    /// Base64 ciphertext when `encrypted` is set.
+    #[schema(value_type = String)]
     pub content: field::Content,
+    #[schema(value_type = Option<String>)]
     pub title: field::Title,
+    #[schema(value_type = DateTime<Utc>)]
     pub posted: field::Posted,
+    #[schema(value_type = Option<DateTime<Utc>>)]
     pub expires: field::Expires,
+    #[serde(skip_serializing, default)]
+    #[schema(value_type = Option<String>, write_only)]
     pub password: field::Password,
+    #[schema(value_type = u64)]
     pub hits: field::Hits,
+    #[serde(default)]
+    #[schema(value_type = bool)]
+    pub encrypted: field::Encrypted,
+    #[serde(skip)]
+    pub owner: field::Owner,
+    #[schema(value_type = u64)]
+    pub revision: field::Revision,
+    #[schema(value_type = DateTime<Utc>)]
+    pub updated: field::Updated,
+    #[serde(skip)]
+    pub parent: field::Parent,
+    #[serde(default)]
+    pub visibility: field::Visibility,
+    #[serde(default)]
+    #[schema(value_type = Vec<String>)]
+    pub tags: field::Tags,
 }
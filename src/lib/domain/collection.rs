@@ -0,0 +1,184 @@
+//! Clips shared together under one shortcode, and downloaded together as an archive.
+
+use std::collections::HashSet;
+use std::io::{self, Cursor, Write};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use crate::{Clip, ShortCode};
+
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct Collection {
+    #[serde(skip)]
+    pub id: String,
+    #[schema(value_type = String)]
+    pub shortcode: ShortCode,
+    pub title: Option<String>,
+    pub posted: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    pub owner: Option<String>,
+    /// In order. Clips deleted, expired or protected with a password since the collection was made are left out.
+    pub clips: Vec<Clip>,
+}
+
+/// How the clips of a collection are downloaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, strum::Display, strum::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum ArchiveFormat {
+    #[default]
+    Tar,
+    Zip,
+}
+
+impl ArchiveFormat {
+    pub fn media_type(self) -> &'static str {
+        match self {
+            Self::Tar => "application/x-tar",
+            Self::Zip => "application/zip",
+        }
+    }
+}
+
+/// A file name from a clip title: path separators and control characters are replaced, and the
+/// name may not be hidden or climb out of the archive.
+fn file_name(clip: &Clip) -> String {
+    let title = clip.title.clone().into_inner().unwrap_or_default();
+    let name: String = title
+        .trim()
+        .chars()
+        .map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c })
+        .collect();
+    let name = name.trim_start_matches('.');
+    match name.is_empty() {
+        true => format!("{}.txt", clip.shortcode.as_str()),
+        false => name.to_owned(),
+    }
+}
+
+impl Collection {
+    /// The name of each clip in an archive of the collection: its title, numbered when taken.
+    pub fn file_names(&self) -> Vec<String> {
+        let mut taken = HashSet::new();
+        self.clips
+            .iter()
+            .map(|clip| {
+                let name = file_name(clip);
+                let (stem, extension) = match name.rsplit_once('.') {
+                    Some((stem, extension)) if !stem.is_empty() => (stem.to_owned(), format!(".{}", extension)),
+                    _ => (name.clone(), String::new()),
+                };
+                let mut candidate = name;
+                let mut number = 1;
+                while !taken.insert(candidate.clone()) {
+                    number += 1;
+                    candidate = format!("{}-{}{}", stem, number, extension);
+                }
+                candidate
+            })
+            .collect()
+    }
+
+    /// The content of every clip, one file each.
+    pub fn archive(&self, format: ArchiveFormat) -> io::Result<Vec<u8>> {
+        let files = self.file_names().into_iter().zip(&self.clips);
+        match format {
+            ArchiveFormat::Tar => {
+                let mut tar = tar::Builder::new(vec![]);
+                for (name, clip) in files {
+                    let content = clip.content.as_str().as_bytes();
+                    let mut header = tar::Header::new_gnu();
+                    header.set_size(content.len() as u64);
+                    header.set_mode(0o644);
+                    header.set_mtime(clip.updated.clone().into_inner().timestamp().max(0) as u64);
+                    tar.append_data(&mut header, name, content)?;
+                }
+                tar.into_inner()
+            }
+            ArchiveFormat::Zip => {
+                let mut zip = zip::ZipWriter::new(Cursor::new(vec![]));
+                let options = zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+                for (name, clip) in files {
+                    zip.start_file(name, options).map_err(io::Error::other)?;
+                    zip.write_all(clip.content.as_str().as_bytes())?;
+                }
+                Ok(zip.finish().map_err(io::Error::other)?.into_inner())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::domain::clip::field;
+    use std::io::Read;
+
+    fn clip(title: Option<&str>, content: &str) -> Clip {
+        Clip {
+            clip_id: Default::default(),
+            shortcode: ShortCode::from("abc"),
+            content: field::Content::new(content).unwrap(),
+            title: field::Title::new(title.map(str::to_owned)),
+            posted: field::Posted::new(Utc::now().into()),
+            expires: Default::default(),
+            password: Default::default(),
+            hits: field::Hits::new(0),
+            encrypted: Default::default(),
+            owner: Default::default(),
+            revision: field::Revision::new(1),
+            updated: field::Updated::new(Utc::now().into()),
+            parent: Default::default(),
+            visibility: Default::default(),
+            tags: Default::default(),
+        }
+    }
+
+    fn collection(clips: Vec<Clip>) -> Collection {
+        Collection {
+            id: String::new(),
+            shortcode: ShortCode::from("bundle"),
+            title: None,
+            posted: Utc::now(),
+            expires: None,
+            owner: None,
+            clips,
+        }
+    }
+
+    #[test]
+    fn names_files_safely_and_uniquely() {
+        let clips = vec![
+            clip(Some("app.toml"), "a"),
+            clip(Some("app.toml"), "b"),
+            clip(Some("../../etc/passwd"), "c"),
+            clip(None, "d"),
+            clip(Some("Makefile"), "e"),
+            clip(Some("Makefile"), "f"),
+        ];
+        assert_eq!(
+            collection(clips).file_names(),
+            ["app.toml", "app-2.toml", "_.._etc_passwd", "abc.txt", "Makefile", "Makefile-2"]
+        );
+    }
+
+    #[test]
+    fn archives_every_clip() {
+        let collection = collection(vec![clip(Some("deploy.sh"), "#!/bin/sh\n"), clip(Some("deploy.log"), "ok\n")]);
+
+        let tar = collection.archive(ArchiveFormat::Tar).unwrap();
+        let mut files = vec![];
+        for entry in tar::Archive::new(tar.as_slice()).entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut content = String::new();
+            entry.read_to_string(&mut content).unwrap();
+            files.push((entry.path().unwrap().display().to_string(), content));
+        }
+        assert_eq!(files, [("deploy.sh".to_owned(), "#!/bin/sh\n".to_owned()), ("deploy.log".to_owned(), "ok\n".to_owned())]);
+
+        let zip = collection.archive(ArchiveFormat::Zip).unwrap();
+        let mut zip = zip::ZipArchive::new(Cursor::new(zip)).unwrap();
+        let mut content = String::new();
+        zip.by_name("deploy.log").unwrap().read_to_string(&mut content).unwrap();
+        assert_eq!((zip.len(), content.as_str()), (2, "ok\n"));
+    }
+}
@@ -0,0 +1,199 @@
+use argon2::password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use rand::RngCore;
+use subtle::ConstantTimeEq;
+use crate::domain::clip::ClipError;
+
+pub const SALT_LEN: usize = 16;
+pub const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+/// Marks a password verifier derived together with the content key, see [`seal`].
+const VERIFIER_PREFIX: &str = "$argon2id-kv$";
+
+/// Clip content encrypted with a key derived from the clip password.
+#[derive(Debug, Clone)]
+pub struct Sealed {
+    pub ciphertext: Vec<u8>,
+    pub nonce: Vec<u8>,
+    pub salt: Vec<u8>,
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+    let mut bytes = [0u8; N];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+/// An Argon2id PHC string, as stored for protected clips before the verifier of [`seal`].
+pub fn hash_password(password: &str) -> String {
+    let salt = SaltString::encode_b64(&random_bytes::<SALT_LEN>()).expect("salt length is valid");
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("default argon2 parameters are valid")
+        .to_string()
+}
+
+/// Whether a stored password is a verifier or hash rather than the plaintext of an old clip.
+pub fn is_password_hash(stored: &str) -> bool {
+    stored.starts_with("$argon2")
+}
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    match PasswordHash::new(hash) {
+        Ok(hash) => Argon2::default().verify_password(password.as_bytes(), &hash).is_ok(),
+        Err(_) => false,
+    }
+}
+
+/// The content key and the password verifier, from a single Argon2id run.
+fn derive(password: &str, salt: &[u8]) -> Result<(Key, String), ClipError> {
+    let mut output = [0u8; KEY_LEN * 2];
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut output)
+        .map_err(|e| ClipError::Crypto(e.to_string()))?;
+    let (key, verifier) = output.split_at(KEY_LEN);
+    let verifier = format!("{}{}", VERIFIER_PREFIX, base64::encode_config(verifier, base64::STANDARD_NO_PAD));
+    Ok((*Key::from_slice(key), verifier))
+}
+
+/// The key of content sealed before verifiers, derived on its own.
+fn derive_key(password: &str, salt: &[u8]) -> Result<Key, ClipError> {
+    let mut key = Key::default();
+    Argon2::default()
+        .hash_password_into(password.as_bytes(), salt, &mut key)
+        .map_err(|e| ClipError::Crypto(e.to_string()))?;
+    Ok(key)
+}
+
+fn decrypt(key: &Key, sealed: &Sealed) -> Result<Vec<u8>, ClipError> {
+    if sealed.nonce.len() != NONCE_LEN {
+        return Err(ClipError::Crypto("invalid nonce length".to_owned()));
+    }
+    XChaCha20Poly1305::new(key)
+        .decrypt(XNonce::from_slice(&sealed.nonce), sealed.ciphertext.as_slice())
+        .map_err(|_| ClipError::Crypto("unable to decrypt content".to_owned()))
+}
+
+/// Encrypts `plaintext`, returning it with the verifier to store in place of the password.
+pub fn seal(password: &str, plaintext: &[u8]) -> (Sealed, String) {
+    let salt = random_bytes::<SALT_LEN>();
+    let nonce = random_bytes::<NONCE_LEN>();
+    let (key, verifier) = derive(password, &salt).expect("salt length is valid");
+    let ciphertext = XChaCha20Poly1305::new(&key)
+        .encrypt(XNonce::from_slice(&nonce), plaintext)
+        .expect("encryption does not fail for in-memory buffers");
+    let sealed = Sealed {
+        ciphertext,
+        nonce: nonce.to_vec(),
+        salt: salt.to_vec(),
+    };
+    (sealed, verifier)
+}
+
+/// Checks `password` against the `stored` verifier or hash and decrypts the content, or `None`
+/// if the password is wrong.
+pub fn open(password: &str, stored: &str, sealed: &Sealed) -> Result<Option<Vec<u8>>, ClipError> {
+    if stored.starts_with(VERIFIER_PREFIX) {
+        let (key, verifier) = derive(password, &sealed.salt)?;
+        match bool::from(verifier.as_bytes().ct_eq(stored.as_bytes())) {
+            true => decrypt(&key, sealed).map(Some),
+            false => Ok(None),
+        }
+    } else if verify_password(password, stored) {
+        decrypt(&derive_key(password, &sealed.salt)?, sealed).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// End-to-end encryption done by the client, matching `static/e2e.js`: AES-256-GCM, with the
+/// base64url key carried in the link fragment and content stored as base64(iv || ciphertext).
+pub mod e2e {
+    use aes_gcm::aead::{Aead, KeyInit};
+    use aes_gcm::{Aes256Gcm, Key, Nonce};
+    use crate::domain::clip::ClipError;
+    use super::random_bytes;
+
+    const KEY_LEN: usize = 32;
+    const IV_LEN: usize = 12;
+
+    fn decode_key(key: &str) -> Result<Key<Aes256Gcm>, ClipError> {
+        let key = base64::decode_config(key, base64::URL_SAFE_NO_PAD)
+            .map_err(|e| ClipError::Crypto(format!("invalid key: {}", e)))?;
+        if key.len() != KEY_LEN {
+            return Err(ClipError::Crypto("invalid key length".to_owned()));
+        }
+        Ok(*Key::<Aes256Gcm>::from_slice(&key))
+    }
+
+    pub fn generate_key() -> String {
+        base64::encode_config(random_bytes::<KEY_LEN>(), base64::URL_SAFE_NO_PAD)
+    }
+
+    pub fn encrypt(key: &str, plaintext: &str) -> Result<String, ClipError> {
+        let iv = random_bytes::<IV_LEN>();
+        let ciphertext = Aes256Gcm::new(&decode_key(key)?)
+            .encrypt(Nonce::from_slice(&iv), plaintext.as_bytes())
+            .map_err(|_| ClipError::Crypto("unable to encrypt content".to_owned()))?;
+        Ok(base64::encode([iv.as_slice(), ciphertext.as_slice()].concat()))
+    }
+
+    pub fn decrypt(key: &str, content: &str) -> Result<String, ClipError> {
+        let bytes = base64::decode(content.trim()).map_err(|e| ClipError::Crypto(e.to_string()))?;
+        if bytes.len() <= IV_LEN {
+            return Err(ClipError::Crypto("content is too short".to_owned()));
+        }
+        let (iv, ciphertext) = bytes.split_at(IV_LEN);
+        let plaintext = Aes256Gcm::new(&decode_key(key)?)
+            .decrypt(Nonce::from_slice(iv), ciphertext)
+            .map_err(|_| ClipError::Crypto("unable to decrypt content; is the key correct?".to_owned()))?;
+        String::from_utf8(plaintext).map_err(|e| ClipError::Crypto(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn hashes_and_verifies_password() {
+        let hash = hash_password("hunter2");
+        assert!(is_password_hash(&hash));
+        assert!(!hash.contains("hunter2"));
+        assert!(verify_password("hunter2", &hash));
+        assert!(!verify_password("hunter3", &hash));
+    }
+
+    #[test]
+    fn seals_and_opens_content() {
+        let (sealed, verifier) = seal("hunter2", b"top secret");
+        assert_ne!(sealed.ciphertext, b"top secret");
+        assert!(is_password_hash(&verifier) && !verifier.contains("hunter2"));
+        assert_eq!(open("hunter2", &verifier, &sealed).unwrap().unwrap(), b"top secret");
+        assert_eq!(open("hunter3", &verifier, &sealed).unwrap(), None);
+    }
+
+    #[test]
+    fn opens_content_sealed_before_verifiers() {
+        let salt = random_bytes::<SALT_LEN>();
+        let nonce = random_bytes::<NONCE_LEN>();
+        let ciphertext = XChaCha20Poly1305::new(&derive_key("hunter2", &salt).unwrap())
+            .encrypt(XNonce::from_slice(&nonce), b"top secret".as_slice())
+            .unwrap();
+        let sealed = Sealed { ciphertext, nonce: nonce.to_vec(), salt: salt.to_vec() };
+        let hash = hash_password("hunter2");
+        assert_eq!(open("hunter2", &hash, &sealed).unwrap().unwrap(), b"top secret");
+        assert_eq!(open("hunter3", &hash, &sealed).unwrap(), None);
+    }
+
+    #[test]
+    fn e2e_round_trip() {
+        let key = e2e::generate_key();
+        let content = e2e::encrypt(&key, "zero knowledge").unwrap();
+        assert!(!content.contains("zero knowledge"));
+        assert_eq!(e2e::decrypt(&key, &content).unwrap(), "zero knowledge");
+        assert!(e2e::decrypt(&e2e::generate_key(), &content).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! Edits of clip text that concurrent editors can merge with operational transformation.
+//!
+//! Positions and lengths count Unicode scalar values, so browsers and the server agree on them.
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum EditError {
+    #[error("splice at {at} deleting {delete} is outside a text of {len} characters")]
+    OutOfRange { at: usize, delete: usize, len: usize },
+}
+
+/// Replaces `delete` characters starting at `at` with `insert`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Splice {
+    pub at: usize,
+    #[serde(default)]
+    pub delete: usize,
+    #[serde(default)]
+    pub insert: String,
+}
+
+impl Splice {
+    /// The single splice turning `old` into `new`: everything between their common prefix and suffix.
+    pub fn between(old: &str, new: &str) -> Self {
+        let (old, new): (Vec<char>, Vec<char>) = (old.chars().collect(), new.chars().collect());
+        let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+        let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+        Self {
+            at: prefix,
+            delete: old.len() - prefix - suffix,
+            insert: new[prefix..new.len() - suffix].iter().collect(),
+        }
+    }
+
+    pub fn is_noop(&self) -> bool {
+        self.delete == 0 && self.insert.is_empty()
+    }
+
+    fn end(&self) -> usize {
+        self.at.saturating_add(self.delete)
+    }
+
+    fn inserted(&self) -> usize {
+        self.insert.chars().count()
+    }
+
+    pub fn apply(&self, text: &str) -> Result<String, EditError> {
+        let out_of_range = || EditError::OutOfRange { at: self.at, delete: self.delete, len: text.chars().count() };
+        let byte_at = |chars: usize| text.char_indices().map(|(i, _)| i).chain(Some(text.len())).nth(chars);
+        let start = byte_at(self.at).ok_or_else(out_of_range)?;
+        let end = self.at.checked_add(self.delete).and_then(byte_at).ok_or_else(out_of_range)?;
+        Ok([&text[..start], self.insert.as_str(), &text[end..]].concat())
+    }
+
+    /// This splice rewritten to apply after `other`, when both were made against the same text.
+    ///
+    /// `first` breaks ties between inserts at the same position. Where the splices overlap, both
+    /// replaced ranges are removed and both insertions are kept, `first`'s before the other's.
+    pub fn transform(&self, other: &Splice, first: bool) -> Splice {
+        let both_inserts = self.delete == 0 && other.delete == 0;
+        let before = self.end() < other.at || (self.end() == other.at && (!both_inserts || first));
+        let after = self.at > other.end() || (self.at == other.end() && (!both_inserts || !first));
+        if before {
+            self.clone()
+        } else if after {
+            Splice { at: self.at - other.delete + other.inserted(), ..self.clone() }
+        } else {
+            let start = self.at.min(other.at);
+            let end = self.end().max(other.end());
+            let insert = match first {
+                true => [self.insert.as_str(), other.insert.as_str()].concat(),
+                false => [other.insert.as_str(), self.insert.as_str()].concat(),
+            };
+            Splice { at: start, delete: end - start - other.delete + other.inserted(), insert }
+        }
+    }
+}
+
+/// Applies `splices` one after the other.
+pub fn apply(text: &str, splices: &[Splice]) -> Result<String, EditError> {
+    splices.iter().try_fold(text.to_owned(), |text, splice| splice.apply(&text))
+}
+
+/// `edit` rewritten to apply after `other`, when both were made against the same text.
+pub fn transform(edit: &[Splice], other: &[Splice], first: bool) -> Vec<Splice> {
+    let mut other = other.to_vec();
+    edit.iter()
+        .map(|splice| {
+            other.iter_mut().fold(splice.clone(), |splice, against| {
+                let transformed = splice.transform(against, first);
+                *against = against.transform(&splice, !first);
+                transformed
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn splice(at: usize, delete: usize, insert: &str) -> Splice {
+        Splice { at, delete, insert: insert.to_owned() }
+    }
+
+    /// Both orders of applying two concurrent edits must give the same text.
+    fn merge(text: &str, a: &[Splice], b: &[Splice]) -> String {
+        let a_then_b = apply(&apply(text, a).unwrap(), &transform(b, a, false)).unwrap();
+        let b_then_a = apply(&apply(text, b).unwrap(), &transform(a, b, true)).unwrap();
+        assert_eq!(a_then_b, b_then_a, "{:?} and {:?} diverge on {:?}", a, b, text);
+        a_then_b
+    }
+
+    #[test]
+    fn finds_the_changed_range() {
+        assert_eq!(Splice::between("status: ok", "status: degraded"), splice(8, 2, "degraded"));
+        assert_eq!(Splice::between("aaa", "aaaa"), splice(3, 0, "a"));
+        assert_eq!(Splice::between("héllo", "hello"), splice(1, 1, "e"));
+        assert!(Splice::between("same", "same").is_noop());
+    }
+
+    #[test]
+    fn applies_by_character() {
+        assert_eq!(splice(1, 1, "e").apply("héllo").unwrap(), "hello");
+        assert_eq!(splice(5, 0, "!").apply("héllo").unwrap(), "héllo!");
+        assert!(splice(4, 2, "").apply("héllo").is_err());
+        assert!(splice(usize::MAX, 1, "").apply("héllo").is_err());
+    }
+
+    #[test]
+    fn concurrent_edits_converge() {
+        let text = "db primary down; failing over";
+        assert_eq!(merge(text, &[splice(0, 0, "09:14 ")], &[splice(29, 0, " to replica")]), "09:14 db primary down; failing over to replica");
+        assert_eq!(merge(text, &[splice(3, 7, "replica")], &[splice(11, 4, "up")]), "db replica up; failing over");
+        // overlapping replacements keep both insertions
+        assert_eq!(merge(text, &[splice(3, 12, "cache")], &[splice(11, 4, "slow")]), "db cacheslow; failing over");
+        assert_eq!(merge("ab", &[splice(1, 0, "x")], &[splice(1, 0, "y")]), "axyb");
+        assert_eq!(merge("abcdef", &[splice(2, 0, "x")], &[splice(1, 3, "")]), "axef");
+
+        let typed = [splice(0, 0, "a"), splice(1, 0, "b"), splice(0, 1, "")];
+        let pasted = [splice(2, 4, "X"), splice(0, 0, "Y")];
+        assert_eq!(merge("012345", &typed, &pasted), "bY01X");
+    }
+}
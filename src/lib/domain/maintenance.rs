@@ -1,20 +1,77 @@
+use std::time::Duration;
 use tokio::runtime::Handle;
-use crate::data::DatabasePool;
+use tokio::task::JoinHandle;
+use crate::data::backup::BackupPolicy;
+use crate::data::AppDatabase;
+use crate::domain::webhook::ReceiverPolicy;
 use crate::service;
+use crate::service::live::Changes;
 
-pub struct Maintenance;
+/// Deliveries that get no answer within this time are retried.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Maintenance {
+    task: JoinHandle<()>,
+    backups: Option<JoinHandle<()>>,
+    webhooks: Option<JoinHandle<()>>,
+}
 
 impl Maintenance {
-    pub fn spawn(pool: DatabasePool, handle: Handle) -> Self {
-        handle.spawn(async move {
-           let mut interval = tokio::time::interval(std::time::Duration::from_secs(10));
+    /// Deletes expired clips and collections every `period`, publishing each expired clip to `changes`.
+    pub fn spawn(database: AppDatabase, changes: Changes, handle: Handle, period: Duration) -> Self {
+        let task = handle.spawn(async move {
+           let mut interval = tokio::time::interval(period);
            loop {
                interval.tick().await;
-               if let Err(e) = service::action::delete_expires(&pool).await {
+               if let Err(e) = service::action::delete_expires(&changes, database.repository()).await {
                    eprintln!("Error cleaning up expired clips: {}", e);
                }
+               if let Err(e) = service::action::delete_expired_collections(database.repository()).await {
+                   eprintln!("Error cleaning up expired collections: {}", e);
+               }
            }
         });
-        Self
+        Self { task, backups: None, webhooks: None }
+    }
+
+    /// Also snapshot the database every `period`, starting one period from now.
+    pub fn with_backups(mut self, database: AppDatabase, handle: Handle, policy: BackupPolicy, period: Duration) -> Self {
+        self.backups = Some(handle.spawn(async move {
+            let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+            loop {
+                interval.tick().await;
+                match service::action::backup(&policy, database.repository()).await {
+                    Ok(snapshot) => println!("Backed up database to {}", snapshot.path.display()),
+                    Err(e) => eprintln!("Error backing up database: {}", e),
+                }
+            }
+        }));
+        self
+    }
+
+    /// Also deliver queued webhook events every `period`, to the receivers `policy` permits.
+    pub fn with_webhooks(mut self, database: AppDatabase, handle: Handle, policy: ReceiverPolicy, period: Duration) -> Self {
+        self.webhooks = Some(handle.spawn(async move {
+            let client = policy.client_builder()
+                .timeout(WEBHOOK_TIMEOUT)
+                .user_agent(concat!("clipstash/", env!("CARGO_PKG_VERSION")))
+                .build()
+                .expect("failed to build the webhook HTTP client");
+            let mut interval = tokio::time::interval(period);
+            loop {
+                interval.tick().await;
+                if let Err(e) = service::action::deliver_webhooks(&client, &policy, database.repository()).await {
+                    eprintln!("Error delivering webhooks: {}", e);
+                }
+            }
+        }));
+        self
+    }
+
+    pub fn is_alive(&self) -> bool {
+        [Some(&self.task), self.backups.as_ref(), self.webhooks.as_ref()]
+            .into_iter()
+            .flatten()
+            .all(|task| !task.is_finished())
     }
 }
@@ -1,5 +1,9 @@
 pub mod clip;
+pub mod collection;
+pub mod crypto;
+pub mod edit;
 pub mod time;
 pub mod maintenance;
+pub mod webhook;
 
 pub use clip::Clip;
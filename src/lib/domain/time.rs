@@ -16,7 +16,7 @@ impl Time {
     }
 
     pub fn from_naive_utc(date_time: NaiveDateTime) -> Self {
-        Self(DateTime::from_utc(date_time, Utc))
+        Self(DateTime::from_naive_utc_and_offset(date_time, Utc))
     }
 }
 
@@ -0,0 +1,268 @@
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use reqwest::dns::{Addrs, Resolve, Resolving};
+use reqwest::Url;
+use sha2::Sha256;
+use crate::Clip;
+
+/// `sha256=<hex>`: the HMAC-SHA256 of the request body, keyed with the webhook's secret.
+pub const SIGNATURE_HEADER: &str = "X-Clipstash-Signature";
+pub const EVENT_HEADER: &str = "X-Clipstash-Event";
+/// The event id, the same on every attempt; deliveries are at least once, so receivers dedupe on it.
+pub const DELIVERY_HEADER: &str = "X-Clipstash-Delivery";
+
+/// A delivery is given up after this many failed attempts.
+pub const MAX_ATTEMPTS: u32 = 8;
+const FIRST_RETRY: Duration = Duration::from_secs(30);
+const MAX_RETRY: Duration = Duration::from_secs(60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, strum::Display, strum::EnumString, utoipa::ToSchema)]
+pub enum ClipEvent {
+    #[serde(rename = "clip.created")]
+    #[strum(serialize = "clip.created")]
+    Created,
+    #[serde(rename = "clip.updated")]
+    #[strum(serialize = "clip.updated")]
+    Updated,
+    #[serde(rename = "clip.viewed")]
+    #[strum(serialize = "clip.viewed")]
+    Viewed,
+    #[serde(rename = "clip.expired")]
+    #[strum(serialize = "clip.expired")]
+    Expired,
+    #[serde(rename = "clip.deleted")]
+    #[strum(serialize = "clip.deleted")]
+    Deleted,
+}
+
+/// A URL receiving events on the clips of the API key that registered it.
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<ClipEvent>,
+    pub created: DateTime<Utc>,
+    /// Signs every delivery; only returned when the webhook is registered.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+}
+
+impl Webhook {
+    pub fn new_secret() -> String {
+        (0..32).map(|_| format!("{:02x}", rand::random::<u8>())).collect()
+    }
+
+    pub fn subscribes_to(&self, event: ClipEvent) -> bool {
+        self.events.contains(&event)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    /// Given up after [`MAX_ATTEMPTS`].
+    Failed,
+}
+
+/// An entry of a webhook's delivery log.
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct Delivery {
+    /// The event id, sent as `X-Clipstash-Delivery`.
+    pub id: String,
+    pub event: ClipEvent,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    pub created: DateTime<Utc>,
+    pub next_attempt: Option<DateTime<Utc>>,
+    pub delivered: Option<DateTime<Utc>>,
+    /// HTTP status of the last attempt, if the receiver answered at all.
+    pub response_status: Option<u16>,
+    pub last_error: Option<String>,
+}
+
+/// What a clip event says about the clip. Content and title are left out, so the outbox never holds clip text.
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct ClipSummary {
+    pub shortcode: String,
+    pub revision: u64,
+    pub hits: u64,
+    pub posted: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+    pub protected: bool,
+}
+
+impl From<&Clip> for ClipSummary {
+    fn from(clip: &Clip) -> Self {
+        Self {
+            shortcode: clip.shortcode.as_str().to_owned(),
+            revision: clip.revision.into_inner(),
+            hits: clip.hits.clone().into_inner(),
+            posted: clip.posted.clone().into_inner().into_inner(),
+            updated: clip.updated.clone().into_inner().into_inner(),
+            expires: clip.expires.clone().into_inner().map(|time| time.into_inner()),
+            protected: clip.password.has_password(),
+        }
+    }
+}
+
+/// The JSON body POSTed to a webhook.
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct EventPayload {
+    pub id: String,
+    pub event: ClipEvent,
+    pub created: DateTime<Utc>,
+    pub clip: ClipSummary,
+}
+
+/// Which receivers webhooks may be registered for and delivered to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReceiverPolicy {
+    /// Also loopback, private and link-local addresses; only for receivers on a trusted network.
+    pub allow_private: bool,
+}
+
+impl ReceiverPolicy {
+    pub fn permits(&self, ip: IpAddr) -> bool {
+        self.allow_private || is_public(ip)
+    }
+
+    /// Rejects urls whose host is, or resolves to, an address the policy does not permit. A name
+    /// that does not resolve yet is accepted; deliveries check the addresses again.
+    pub async fn check(&self, url: &Url) -> Result<(), String> {
+        let port = url.port_or_known_default().unwrap_or_default();
+        let host = url.host_str().ok_or_else(|| "the url has no host".to_owned())?;
+        let addrs: Vec<IpAddr> = match host_ip(url) {
+            Some(ip) => vec![ip],
+            None => match tokio::net::lookup_host((host, port)).await {
+                Ok(addrs) => addrs.map(|addr| addr.ip()).collect(),
+                Err(_) => vec![],
+            },
+        };
+        match addrs.into_iter().find(|ip| !self.permits(*ip)) {
+            Some(ip) => Err(format!("{} is not a public address", ip)),
+            None => Ok(()),
+        }
+    }
+
+    /// A client for deliveries: it only connects to permitted addresses and does not follow redirects.
+    pub fn client_builder(&self) -> reqwest::ClientBuilder {
+        reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .no_proxy()
+            .dns_resolver(Arc::new(Resolver(*self)))
+    }
+}
+
+/// The host of `url` if it is an address rather than a name.
+pub fn host_ip(url: &Url) -> Option<IpAddr> {
+    url.host_str()?.trim_start_matches('[').trim_end_matches(']').parse().ok()
+}
+
+/// Resolves receiver names at delivery, so that a name cannot be pointed at a private address
+/// after the webhook was registered.
+struct Resolver(ReceiverPolicy);
+
+impl Resolve for Resolver {
+    fn resolve(&self, name: hyper::client::connect::dns::Name) -> Resolving {
+        let policy = self.0;
+        Box::pin(async move {
+            let resolved: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+            if let Some(addr) = resolved.iter().find(|addr| !policy.permits(addr.ip())) {
+                return Err(format!("{} resolves to {}, which is not a public address", name, addr.ip()).into());
+            }
+            let addrs: Addrs = Box::new(resolved.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// Whether `ip` is reachable from the internet at large, rather than on the server's own host or network.
+pub fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            let shared = a == 100 && (64..128).contains(&b);
+            let reserved = a == 0 || a >= 240 || (a == 192 && b == 0) || (a == 198 && (18..20).contains(&b));
+            !(ip.is_loopback() || ip.is_private() || ip.is_link_local() || ip.is_broadcast()
+                || ip.is_documentation() || ip.is_multicast() || shared || reserved)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(ip.into()),
+            None => {
+                let first = ip.segments()[0];
+                let unique_local = first & 0xfe00 == 0xfc00;
+                let link_local = first & 0xffc0 == 0xfe80;
+                let documentation = first == 0x2001 && ip.segments()[1] == 0x0db8;
+                !(ip.is_loopback() || ip.is_unspecified() || ip.is_multicast() || unique_local || link_local || documentation)
+            }
+        },
+    }
+}
+
+/// The value of [`SIGNATURE_HEADER`] for `body`.
+pub fn signature(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    let digest: String = mac.finalize().into_bytes().iter().map(|b| format!("{:02x}", b)).collect();
+    format!("sha256={}", digest)
+}
+
+/// How long to wait after the `attempts`th failed attempt: doubling from 30 seconds, up to an hour.
+pub fn retry_delay(attempts: u32) -> Duration {
+    let doublings = attempts.saturating_sub(1).min(16);
+    (FIRST_RETRY * 2u32.pow(doublings)).min(MAX_RETRY)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn signs_with_hmac_sha256() {
+        assert_eq!(
+            signature("key", b"The quick brown fox jumps over the lazy dog"),
+            "sha256=f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8"
+        );
+    }
+
+    #[test]
+    fn events_use_dotted_names() {
+        assert_eq!(ClipEvent::Expired.to_string(), "clip.expired");
+        assert_eq!(ClipEvent::from_str("clip.viewed").unwrap(), ClipEvent::Viewed);
+        assert_eq!(serde_json::to_string(&ClipEvent::Created).unwrap(), "\"clip.created\"");
+    }
+
+    #[test]
+    fn only_public_addresses_receive_webhooks() {
+        for ip in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.1", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(!is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["93.184.216.34", "1.1.1.1", "2606:4700::1111", "::ffff:8.8.8.8"] {
+            assert!(is_public(ip.parse().unwrap()), "{}", ip);
+        }
+        assert!(ReceiverPolicy { allow_private: true }.permits("127.0.0.1".parse().unwrap()));
+
+        let rt = crate::test::async_runtime();
+        let check = |url: &str| rt.block_on(ReceiverPolicy::default().check(&url.parse().unwrap()));
+        assert!(check("http://127.0.0.1:8000/hook").is_err());
+        assert!(check("http://[::1]/hook").is_err());
+        assert!(check("http://localhost/hook").is_err());
+        assert!(check("https://93.184.216.34/hook").is_ok());
+    }
+
+    #[test]
+    fn retries_back_off() {
+        assert_eq!(retry_delay(1), Duration::from_secs(30));
+        assert_eq!(retry_delay(3), Duration::from_secs(120));
+        assert_eq!(retry_delay(MAX_ATTEMPTS), Duration::from_secs(60 * 60));
+    }
+}
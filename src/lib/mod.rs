@@ -1,3 +1,5 @@
+pub mod client;
+pub mod config;
 pub mod data;
 pub mod domain;
 pub mod service;
@@ -9,31 +11,60 @@ pub use domain::time::Time;
 pub use data::DataError;
 pub use service::ServiceError;
 
+use std::path::PathBuf;
 use data::AppDatabase;
+use rocket::figment::Figment;
 use rocket::fs::FileServer;
 use rocket::{Build, Rocket};
 use web::renderer::Renderer;
+use crate::domain::clip::field::ShortCodePolicy;
 use crate::domain::maintenance::Maintenance;
+use crate::domain::webhook::ReceiverPolicy;
+use crate::service::collab::Sessions;
+use crate::service::live::Changes;
 use crate::web::hitcounter::HitCounter;
 
 pub fn rocket(config: RocketConfig) -> Rocket<Build> {
-    rocket::build()
+    let sessions = Sessions::new(config.database.clone(), config.changes.clone());
+    rocket::custom(config.figment)
         .manage::<Renderer>(config.renderer)
         .manage::<AppDatabase>(config.database)
         .manage::<HitCounter>(config.hit_counter)
         .manage::<Maintenance>(config.maintenance)
+        .manage::<ReceiverPolicy>(config.webhooks)
+        .manage::<ShortCodePolicy>(config.shortcode)
+        .manage::<Changes>(config.changes)
+        .manage::<Sessions>(sessions)
         .mount("/", web::http::routes())
+        .mount("/", web::health::routes())
+        .mount("/api/v1/clip", web::api::routes())
+        .mount("/api/v1", web::api::account_routes())
+        .mount("/api/v1", web::api::v1_routes())
+        .mount("/api/v1", web::webhook::routes())
+        .mount("/api/v1", web::collection::routes())
+        .mount("/api/v1/clip", web::collab::routes())
+        // deprecated aliases of the first, unversioned API
         .mount("/api/clip", web::api::routes())
-        .mount("/static", FileServer::from("static"))
+        .mount("/api", web::api::account_routes())
+        .mount("/api", web::openapi::routes())
+        .mount("/static", FileServer::from(config.static_dir))
         .register("/", web::http::catcher::catchers())
-        .register("/api/clip", web::api::catcher::catchers())
+        .register("/api", web::api::catcher::catchers())
+        .attach(web::api::Deprecation)
 }
 
 pub struct RocketConfig {
+    pub figment: Figment,
+    pub static_dir: PathBuf,
     pub renderer: Renderer<'static>,
     pub database: AppDatabase,
     pub hit_counter: HitCounter,
-    pub maintenance: Maintenance
+    pub maintenance: Maintenance,
+    pub webhooks: ReceiverPolicy,
+    /// How the clips and collections this instance creates get their shortcodes.
+    pub shortcode: ShortCodePolicy,
+    /// Shared with the workers that change clips, such as the hit counter.
+    pub changes: Changes,
 }
 
 #[cfg(test)]
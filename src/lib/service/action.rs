@@ -1,55 +1,1049 @@
-use crate::data::{query, DatabasePool, Transaction};
-use crate::{Clip, ShortCode, ServiceError};
-use crate::service::ask;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use crate::data::backup::{self, BackupPolicy, Snapshot};
+use crate::data::migrate::MigrationReport;
+use crate::data::{model, DbId};
+use crate::data::repository::{ApiKeyRepository, ClipRepository, CollectionRepository, Repository, RevocationStatus, WebhookRepository};
+use crate::domain::collection::Collection;
+use crate::domain::webhook::{self, ClipEvent, ClipSummary, Delivery, EventPayload, ReceiverPolicy, Webhook};
+use crate::{Clip, ClipError, DataError, ShortCode, ServiceError};
+use crate::service::archive::{ArchivedClip, ConflictMode, ExportFilter, ImportReport};
+use crate::service::live::{Change, Changes};
+use crate::service::{ask, Fork, ListedClip, Provenance, Stats};
+use crate::domain::clip::field::{self, ShortCodePolicy};
 use std::convert::TryInto;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncWrite, AsyncWriteExt};
 use crate::web::api::ApiKey;
+use chrono::Utc;
+use futures::StreamExt;
 
-pub async fn begin_transaction(pool: &DatabasePool) ->  Result<Transaction<'_>, ServiceError> {
-    Ok(pool.begin().await?)
+const EXPORT_BATCH_SIZE: u32 = 500;
+const DELIVERY_BATCH_SIZE: u32 = 100;
+/// Deliveries in flight at once, so that slow receivers do not hold up the others.
+const DELIVERY_CONCURRENCY: usize = 8;
+const MAX_COLLECTION_ITEMS: usize = 100;
+
+pub async fn increase_hit_count<R: ClipRepository + ?Sized>(shortcode: &ShortCode, hits: u32, changes: &Changes, repo: &R) -> Result<(), ServiceError> {
+    repo.increase_hit_count(shortcode, hits).await?;
+    changes.publish(shortcode, Change::Hits);
+    Ok(())
 }
 
-pub async fn end_transaction(transaction: Transaction<'_>) -> Result<(), ServiceError> {
-    Ok(transaction.commit().await?)
+/// Queues `event` for the webhooks of the clip's owner. The change itself is done, so a failure is only logged.
+async fn notify<R: WebhookRepository + ?Sized>(event: ClipEvent, clip: &Clip, repo: &R) {
+    let owner = match clip.owner.as_deref() {
+        Some(owner) => owner.to_owned(),
+        None => return,
+    };
+    let payload = EventPayload {
+        id: DbId::new().to_string(),
+        event,
+        created: Utc::now(),
+        clip: ClipSummary::from(clip),
+    };
+    let queued = match model::NewEvent::new(owner, &payload) {
+        Ok(model) => repo.enqueue_event(model).await.map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = queued {
+        eprintln!("Error queueing {} webhooks: {}", event, e);
+    }
 }
-pub async fn increase_hit_count(shortcode: &ShortCode, hits: u32, pool: &DatabasePool) -> Result<(), ServiceError> {
-    Ok(query::increase_hit_count(shortcode, hits, pool).await?)
+
+/// [`notify`] for a clip as it was stored, such as one that was just deleted.
+async fn notify_stored<R: WebhookRepository + ?Sized>(event: ClipEvent, clip: model::Clip, repo: &R) {
+    match Clip::try_from(clip) {
+        Ok(clip) => notify(event, &clip, repo).await,
+        Err(e) => eprintln!("Error queueing {} webhooks: {}", event, e),
+    }
 }
 
-pub async fn get_clip(req: ask::GetClip, pool: &DatabasePool) -> Result<Clip, ServiceError>{
-    let user_password = req.password.clone();
-    let clip: Clip = query::get_clip(req, pool).await?.try_into()?;
+/// Reads the tags of a clip, which are stored apart from it.
+async fn with_tags<R: ClipRepository + ?Sized>(mut clip: Clip, repo: &R) -> Result<Clip, ServiceError> {
+    let tags = repo.clip_tags(&clip.clip_id.clone().into_inner().to_string()).await?;
+    clip.tags = field::Tags::new(tags)?;
+    Ok(clip)
+}
 
-    if clip.password.has_password() {
-        if clip.password ==user_password {
+/// Reads a clip for `req.viewer`, who must own it when it is private.
+async fn get_visible_clip<R: ClipRepository + ?Sized>(req: ask::GetClip, repo: &R) -> Result<model::Clip, ServiceError> {
+    let viewer = req.viewer.clone();
+    let clip = repo.get_clip(req.into()).await?;
+    match clip.visible_to(viewer.as_deref()) {
+        true => Ok(clip),
+        false => Err(ServiceError::Forbidden("this clip is private".to_owned())),
+    }
+}
+
+/// Runs `f` on the blocking pool: Argon2 for protected clips takes long enough to stall the async workers.
+async fn blocking<T, F>(f: F) -> Result<T, ServiceError>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, ClipError> + Send + 'static,
+{
+    let result = tokio::task::spawn_blocking(f).await.map_err(|e| ClipError::Crypto(e.to_string()))?;
+    Ok(result?)
+}
+
+/// Checks the password of a stored clip and decrypts it. A clip stored before content encryption
+/// is sealed once its password is known to be right.
+async fn unlock<R: ClipRepository + ?Sized>(clip: model::Clip, password: field::Password, repo: &R) -> Result<model::Clip, ServiceError> {
+    let unlocked = blocking(move || {
+        Ok(clip.unlock(&password)?.map(|clip| {
+            let seal = clip.seal_content();
+            (clip, seal)
+        }))
+    });
+    match unlocked.await? {
+        Some((clip, seal)) => {
+            if let Some(seal) = seal {
+                // the read itself succeeded; the next one tries again
+                if let Err(e) = repo.seal_clip_content(seal).await {
+                    eprintln!("Error sealing clip {}: {}", clip.shortcode(), e);
+                }
+            }
             Ok(clip)
-        } else {
-            Err(ServiceError::PermissionError("Invalid password".to_owned() ))
         }
-    } else {
-        Ok(clip)
+        None => Err(ServiceError::PermissionError("Invalid password".to_owned())),
+    }
+}
+
+pub async fn get_clip<R: ClipRepository + ?Sized>(req: ask::GetClip, repo: &R) -> Result<Clip, ServiceError>{
+    let user_password = req.password.clone();
+    let clip = get_visible_clip(req, repo).await?;
+    with_tags(unlock(clip, user_password, repo).await?.try_into()?, repo).await
+}
+
+/// Queues `clip.viewed` for a read that sent the clip to the reader, not for a revalidated copy.
+pub async fn clip_viewed<R: WebhookRepository + ?Sized>(clip: &Clip, repo: &R) {
+    notify(ClipEvent::Viewed, clip, repo).await;
+}
+
+/// Reads a clip again for a viewer who already has it open: the password is checked, but it is not another view.
+pub async fn refresh_clip<R: ClipRepository + ?Sized>(req: ask::GetClip, repo: &R) -> Result<Clip, ServiceError> {
+    let user_password = req.password.clone();
+    let clip = get_visible_clip(req, repo).await?;
+    with_tags(unlock(clip, user_password, repo).await?.try_into()?, repo).await
+}
+
+pub async fn new_clip<R: ClipRepository + WebhookRepository + ?Sized>(req: ask::NewClip, policy: &ShortCodePolicy, repo: &R) -> Result<Clip, ServiceError>{
+    let tags = req.tags.clone();
+    let shortcode = ShortCode::with_policy(policy);
+    let model = blocking(move || Ok(model::NewClip::new(req, shortcode))).await?;
+    let mut clip: Clip = repo.new_clip(model).await?.try_into()?;
+    clip.tags = tags;
+    notify(ClipEvent::Created, &clip, repo).await;
+    Ok(clip)
+}
+/// Creates a clip from `source`, which is read, and so needs its password, first. Someone else's
+/// private clip is not found.
+pub async fn fork_clip<R: ClipRepository + WebhookRepository + ?Sized>(source: ask::GetClip, req: ask::ForkClip, policy: &ShortCodePolicy, repo: &R) -> Result<Clip, ServiceError> {
+    let source = match get_clip(source, repo).await {
+        Err(ServiceError::Forbidden(_)) => return Err(ServiceError::NotFound),
+        source => source?,
+    };
+    let (content, encrypted) = match req.content {
+        Some(content) => (content, req.encrypted),
+        None => (source.content, source.encrypted),
+    };
+    let req = ask::NewClip {
+        content,
+        title: req.title.unwrap_or(source.title),
+        expires: req.expires,
+        password: req.password,
+        encrypted,
+        owner: req.owner,
+        parent: field::Parent::new(source.clip_id),
+        visibility: req.visibility,
+        tags: req.tags.unwrap_or(source.tags),
+    };
+    new_clip(req, policy, repo).await
+}
+
+/// The clip `clip` was forked from and the forks of it, skipping expired clips. Only the forks
+/// `viewer` may find are listed: public ones, and their own.
+pub async fn provenance<R: ClipRepository + ?Sized>(clip: &Clip, viewer: &field::Owner, repo: &R) -> Result<Provenance, ServiceError> {
+    let now = Utc::now();
+    let current = |clip: &Clip| clip.expires.clone().into_inner().is_none_or(|expires| expires.into_inner() > now);
+    let own = |clip: &Clip| clip.owner.as_deref().is_some() && clip.owner.as_deref() == viewer.as_deref();
+    let forked_from = match clip.parent.as_ref() {
+        Some(parent) => match repo.get_clip_by_id(&parent.clone().into_inner().to_string()).await {
+            Ok(parent) => Some(Clip::try_from(parent)?)
+                .filter(|parent| current(parent) && (!parent.visibility.is_private() || own(parent)))
+                .map(|parent| parent.shortcode),
+            // the parent was deleted after the foreign key was read
+            Err(DataError::Database(sqlx::Error::RowNotFound)) => None,
+            Err(e) => return Err(e.into()),
+        },
+        None => None,
+    };
+    let mut forks = vec![];
+    for fork in repo.list_forks(&clip.clip_id.clone().into_inner().to_string()).await? {
+        let fork = Clip::try_from(fork)?;
+        if current(&fork) && (fork.visibility == field::Visibility::Public || own(&fork)) {
+            forks.push(Fork { shortcode: fork.shortcode, posted: fork.posted.into_inner() });
+        }
+    }
+    Ok(Provenance { forked_from, forks })
+}
+
+/// Updates a clip on behalf of `owner`, who may only write to a private clip they own.
+pub async fn update_clip<R: ClipRepository + WebhookRepository + ?Sized>(req: ask::UpdateClip, owner: &field::Owner, changes: &Changes, repo: &R) -> Result<Clip, ServiceError>{
+    let get = ask::GetClip { shortcode: req.shortcode.clone(), password: Default::default(), viewer: owner.clone() };
+    get_visible_clip(get, repo).await?;
+    let model = blocking(move || Ok(model::UpdateClip::from(req))).await?;
+    let clip = with_tags(repo.update_clip(model).await?.try_into()?, repo).await?;
+    changes.publish(&clip.shortcode, Change::Updated);
+    notify(ClipEvent::Updated, &clip, repo).await;
+    Ok(clip)
+}
+
+/// The unexpired clips of `owner`, newest first; with a tag, only the clips tagged with it.
+pub async fn find_clips<R: ClipRepository + ?Sized>(owner: &str, tag: Option<String>, limit: u32, repo: &R) -> Result<Vec<ListedClip>, ServiceError> {
+    listed(repo.find_clips(model::ClipFilter::owned_by(owner.to_owned(), tag, limit)).await?, repo).await
+}
+
+/// Unexpired clips tagged `tag` that anyone may read, newest first.
+pub async fn tagged_clips<R: ClipRepository + ?Sized>(tag: &str, limit: u32, repo: &R) -> Result<Vec<ListedClip>, ServiceError> {
+    listed(repo.find_clips(model::ClipFilter::public(Some(tag.to_owned()), limit)).await?, repo).await
+}
+
+/// The newest clips anyone may find and read: public, unexpired, unprotected and not end-to-end encrypted.
+pub async fn recent_clips<R: ClipRepository + ?Sized>(limit: u32, repo: &R) -> Result<Vec<Clip>, ServiceError> {
+    let mut clips = vec![];
+    for clip in repo.recent_clips(limit).await? {
+        clips.push(with_tags(clip.try_into()?, repo).await?);
+    }
+    Ok(clips)
+}
+
+async fn listed<R: ClipRepository + ?Sized>(clips: Vec<model::Clip>, repo: &R) -> Result<Vec<ListedClip>, ServiceError> {
+    let mut listed = Vec::with_capacity(clips.len());
+    for clip in clips {
+        listed.push(ListedClip::from(&with_tags(clip.try_into()?, repo).await?));
     }
+    Ok(listed)
+}
+
+pub async fn delete_clip<R: ClipRepository + WebhookRepository + ?Sized>(shortcode: &ShortCode, changes: &Changes, repo: &R) -> Result<(), ServiceError> {
+    let clip = repo.get_clip(shortcode.clone().into()).await?;
+    repo.delete_clip(shortcode).await?;
+    changes.publish(shortcode, Change::Deleted);
+    notify_stored(ClipEvent::Deleted, clip, repo).await;
+    Ok(())
 }
 
-pub async fn new_clip(req: ask::NewClip, pool: &DatabasePool) -> Result<Clip, ServiceError>{
-    Ok(query::new_clip(req, pool).await?.try_into()?)
+/// An existing clip going into a collection, which anyone with the collection's link may read.
+async fn collection_member<R: ClipRepository + ?Sized>(shortcode: &ShortCode, repo: &R) -> Result<Clip, ServiceError> {
+    let invalid = |reason: &str| ServiceError::Collection(format!("clip {} {}", shortcode.as_str(), reason));
+    let clip = match refresh_clip(shortcode.clone().into(), repo).await {
+        Ok(clip) => clip,
+        Err(ServiceError::NotFound) => return Err(invalid("does not exist")),
+        Err(ServiceError::PermissionError(_)) => return Err(invalid("is password protected")),
+        Err(ServiceError::Forbidden(_)) => return Err(invalid("is private")),
+        Err(e) => return Err(e),
+    };
+    match clip.encrypted.is_encrypted() {
+        true => Err(invalid("is end-to-end encrypted")),
+        false => Ok(clip),
+    }
 }
-pub async fn update_clip(req: ask::UpdateClip, pool: &DatabasePool) -> Result<Clip, ServiceError>{
-    Ok(query::update_clip(req, pool).await?.try_into()?)
+
+/// Creates a collection of existing clips, and of new clips made from files, in the order given.
+pub async fn new_collection<R>(req: ask::NewCollection, policy: &ShortCodePolicy, repo: &R) -> Result<Collection, ServiceError>
+where
+    R: ClipRepository + CollectionRepository + WebhookRepository + ?Sized,
+{
+    if req.items.is_empty() || req.items.len() > MAX_COLLECTION_ITEMS {
+        return Err(ServiceError::Collection(format!("a collection holds 1 to {} clips", MAX_COLLECTION_ITEMS)));
+    }
+    // every existing clip is checked before any new one is made
+    let mut clips = Vec::with_capacity(req.items.len());
+    for item in &req.items {
+        clips.push(match item {
+            ask::CollectionItem::Clip { shortcode } => Some(collection_member(shortcode, repo).await?),
+            ask::CollectionItem::File { .. } => None,
+        });
+    }
+    for (item, clip) in req.items.into_iter().zip(clips.iter_mut()) {
+        if let ask::CollectionItem::File { name, content } = item {
+            let file = ask::NewClip {
+                content,
+                title: field::Title::new(name),
+                expires: req.expires.clone(),
+                password: Default::default(),
+                encrypted: Default::default(),
+                owner: req.owner.clone(),
+                parent: Default::default(),
+                visibility: Default::default(),
+                tags: Default::default(),
+            };
+            *clip = Some(new_clip(file, policy, repo).await?);
+        }
+    }
+    let clips: Vec<Clip> = clips.into_iter().flatten().collect();
+    let model = model::NewCollection::new(ShortCode::with_policy(policy), req.title.into_inner(), req.expires.into_inner(), req.owner.into_inner(), &clips);
+    let mut collection = Collection::from(repo.new_collection(model).await?);
+    collection.clips = clips;
+    Ok(collection)
 }
 
-pub async fn generate_api_key(pool: &DatabasePool) -> Result<ApiKey, ServiceError> {
+/// A collection with the clips in it that anyone may still read. An expired collection is not
+/// found, even before maintenance deletes it, and expired clips are skipped.
+pub async fn get_collection<R: ClipRepository + CollectionRepository + ?Sized>(shortcode: &ShortCode, repo: &R) -> Result<Collection, ServiceError> {
+    let now = Utc::now();
+    let model = repo.get_collection(shortcode.as_str()).await?;
+    let clips = repo.collection_clips(model.collection_id()).await?;
+    let mut collection = Collection::from(model);
+    if collection.expires.is_some_and(|expires| expires <= now) {
+        return Err(ServiceError::NotFound);
+    }
+    for clip in clips {
+        let clip = Clip::try_from(clip)?;
+        let current = clip.expires.clone().into_inner().is_none_or(|expires| expires.into_inner() > now);
+        if current && !clip.password.has_password() && !clip.encrypted.is_encrypted() && !clip.visibility.is_private() {
+            collection.clips.push(with_tags(clip, repo).await?);
+        }
+    }
+    Ok(collection)
+}
+
+pub async fn delete_expired_collections<R: CollectionRepository + ?Sized>(repo: &R) -> Result<u64, ServiceError> {
+    Ok(repo.delete_expired_collections().await?)
+}
+
+/// Writes every clip matching `filter` to `out` as JSON Lines, returning how many were written.
+pub async fn export_clips<R, W>(filter: &ExportFilter, mut out: W, repo: &R) -> Result<u64, ServiceError>
+where
+    R: ClipRepository + ?Sized,
+    W: AsyncWrite + Unpin + Send,
+{
+    let io_error = |e: std::io::Error| ServiceError::Archive(e.to_string());
+    let mut exported = 0;
+    let mut after = None;
+    loop {
+        let batch = repo.list_clips(after.take(), EXPORT_BATCH_SIZE).await?;
+        let last = match batch.last() {
+            Some(clip) => clip.clip_id().to_owned(),
+            None => break,
+        };
+        for model in batch {
+            let clip_id = model.clip_id().to_owned();
+            let parent_clip_id = model.parent_clip_id().map(str::to_owned);
+            let mut clip = ArchivedClip::from(model);
+            if !filter.matches(&clip) {
+                continue;
+            }
+            clip.tags = repo.clip_tags(&clip_id).await?;
+            if let Some(parent_clip_id) = parent_clip_id {
+                clip.parent = match repo.get_clip_by_id(&parent_clip_id).await {
+                    Ok(parent) => Some(parent.shortcode().to_owned()),
+                    Err(DataError::Database(sqlx::Error::RowNotFound)) => None,
+                    Err(e) => return Err(e.into()),
+                };
+            }
+            let mut line = serde_json::to_vec(&clip).map_err(|e| ServiceError::Archive(e.to_string()))?;
+            line.push(b'\n');
+            out.write_all(&line).await.map_err(io_error)?;
+            exported += 1;
+        }
+        after = Some(last);
+    }
+    out.flush().await.map_err(io_error)?;
+    Ok(exported)
+}
+
+/// Encrypts the content of protected clips stored before content encryption, returning how many
+/// were sealed. Clips changed while this runs are left for the next run.
+pub async fn seal_protected_clips<R: ClipRepository + ?Sized>(repo: &R) -> Result<u64, ServiceError> {
+    let mut sealed = 0;
+    let mut after = None;
+    loop {
+        let batch = repo.list_clips(after.take(), EXPORT_BATCH_SIZE).await?;
+        let last = match batch.last() {
+            Some(clip) => clip.clip_id().to_owned(),
+            None => return Ok(sealed),
+        };
+        let seals = blocking(move || Ok(batch.iter().filter_map(model::Clip::seal_content).collect::<Vec<_>>())).await?;
+        for seal in seals {
+            if repo.seal_clip_content(seal).await? {
+                sealed += 1;
+            }
+        }
+        after = Some(last);
+    }
+}
+
+async fn shortcode_is_taken<R: ClipRepository + ?Sized>(shortcode: &str, repo: &R) -> Result<bool, ServiceError> {
+    match repo.get_clip(shortcode.to_owned().into()).await {
+        Ok(_) => Ok(true),
+        Err(DataError::Database(sqlx::Error::RowNotFound)) => Ok(false),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// A fork read from an archive, inserted once its parent is.
+struct PendingFork {
+    shortcode: String,
+    parent: String,
+    clip: model::NewClip,
+    overwrite: bool,
+}
+
+async fn clip_id_of<R: ClipRepository + ?Sized>(shortcode: &str, repo: &R) -> Result<Option<String>, ServiceError> {
+    match repo.get_clip(shortcode.to_owned().into()).await {
+        Ok(clip) => Ok(Some(clip.clip_id().to_owned())),
+        Err(DataError::Database(sqlx::Error::RowNotFound)) => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+async fn insert_archived<R: ClipRepository + ?Sized>(
+    clip: model::NewClip,
+    overwrite: bool,
+    report: &mut ImportReport,
+    repo: &R,
+) -> Result<(), ServiceError> {
+    if overwrite {
+        repo.replace_clip(clip).await?;
+        report.overwritten += 1;
+    } else {
+        repo.new_clip(clip).await?;
+    }
+    report.imported += 1;
+    Ok(())
+}
+
+/// Reads a JSON Lines archive written by [`export_clips`], stopping at the first invalid line
+/// and at the error record that ends an incomplete archive. Forks are inserted after the rest of
+/// the archive so they can be linked to their parents, which may be renamed on import.
+pub async fn import_clips<R, B>(input: B, mode: ConflictMode, policy: &ShortCodePolicy, repo: &R) -> Result<ImportReport, ServiceError>
+where
+    R: ClipRepository + ?Sized,
+    B: AsyncBufRead + Unpin + Send,
+{
+    let mut report = ImportReport::default();
+    let mut pending = vec![];
+    let mut lines = input.lines();
+    let mut line_number = 0;
+    while let Some(line) = lines.next_line().await.map_err(|e| ServiceError::Archive(e.to_string()))? {
+        line_number += 1;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let line_error = |e: &dyn std::fmt::Display| ServiceError::Archive(format!("line {}: {}", line_number, e));
+        let line: serde_json::Value = serde_json::from_str(&line).map_err(|e| line_error(&e))?;
+        // the error envelope the API ends a failed export with
+        if let Some(error) = line.get("error") {
+            let message = error.get("message").and_then(serde_json::Value::as_str).unwrap_or("unknown error");
+            return Err(line_error(&format!("the archive is incomplete: {}", message)));
+        }
+        let mut clip: ArchivedClip = serde_json::from_value(line).map_err(|e| line_error(&e))?;
+
+        let mut overwrite = false;
+        if shortcode_is_taken(&clip.shortcode, repo).await? {
+            match mode {
+                ConflictMode::Skip => {
+                    report.skipped += 1;
+                    continue;
+                }
+                ConflictMode::Overwrite => overwrite = true,
+                ConflictMode::NewShortcode => {
+                    let mut shortcode = ShortCode::with_policy(policy);
+                    while shortcode_is_taken(shortcode.as_str(), repo).await? {
+                        shortcode = ShortCode::with_policy(policy);
+                    }
+                    let original = std::mem::replace(&mut clip.shortcode, shortcode.into_inner());
+                    report.renamed.push((original, clip.shortcode.clone()));
+                }
+            }
+        }
+        let parent = clip.parent.take();
+        let shortcode = clip.shortcode.clone();
+        // validated before anything is replaced, so an invalid line leaves the stored clip alone
+        let new_clip = model::NewClip::try_from(clip).map_err(|e| line_error(&e))?;
+        match parent {
+            Some(parent) => pending.push(PendingFork { shortcode, parent, clip: new_clip, overwrite }),
+            None => insert_archived(new_clip, overwrite, &mut report, repo).await?,
+        }
+    }
+
+    let renamed: HashMap<_, _> = report.renamed.iter().cloned().collect();
+    let parent_of = |fork: &PendingFork| renamed.get(&fork.parent).unwrap_or(&fork.parent).clone();
+    while !pending.is_empty() {
+        let waiting: HashSet<_> = pending.iter().map(|fork| fork.shortcode.clone()).collect();
+        let (mut ready, rest): (Vec<_>, Vec<_>) =
+            pending.into_iter().partition(|fork| !waiting.contains(&parent_of(fork)));
+        if ready.is_empty() {
+            // forks of each other: each is linked only if its parent was inserted before it
+            ready = rest;
+            pending = vec![];
+        } else {
+            pending = rest;
+        }
+        for fork in ready {
+            let parent_clip_id = clip_id_of(&parent_of(&fork), repo).await?;
+            insert_archived(fork.clip.with_parent(parent_clip_id), fork.overwrite, &mut report, repo).await?;
+        }
+    }
+    Ok(report)
+}
+
+pub async fn generate_api_key<R: ApiKeyRepository + ?Sized>(repo: &R) -> Result<ApiKey, ServiceError> {
     let api_key = ApiKey::default();
-    Ok(query::save_api_key(api_key, pool).await?)
+    Ok(repo.save_api_key(api_key).await?)
+}
+
+pub async fn revoke_api_key<R: ApiKeyRepository + ?Sized>(api_key: ApiKey, repo: &R) -> Result<RevocationStatus, ServiceError> {
+    Ok(repo.revoke_api_key(api_key).await?)
 }
 
-pub async fn revoke_api_key(api_key: ApiKey, pool: &DatabasePool) -> Result<query::RevocationStatus, ServiceError> {
-    Ok(query::revoke_api_key(api_key, pool).await?)
+pub async fn list_api_keys<R: ApiKeyRepository + ?Sized>(repo: &R) -> Result<Vec<ApiKey>, ServiceError> {
+    Ok(repo.list_api_keys().await?)
 }
 
-pub async fn api_key_is_valid(api_key: ApiKey, pool: &DatabasePool) -> Result<bool, ServiceError> {
-    Ok(query::api_key_is_valid(api_key, pool).await?)
+pub async fn api_key_is_valid<R: ApiKeyRepository + ?Sized>(api_key: ApiKey, repo: &R) -> Result<bool, ServiceError> {
+    Ok(repo.api_key_is_valid(api_key).await?)
 }
 
-pub async fn delete_expires(pool: &DatabasePool) -> Result<u64, ServiceError> {
-    Ok(query::delete_expired(pool).await?)
-}
\ No newline at end of file
+pub async fn ping<R: Repository + ?Sized>(repo: &R) -> Result<(), ServiceError> {
+    Ok(repo.ping().await?)
+}
+
+pub async fn pending_migrations<R: Repository + ?Sized>(repo: &R) -> Result<Vec<i64>, ServiceError> {
+    Ok(repo.migration_status().await?.pending())
+}
+
+pub async fn migrate<R: Repository + ?Sized>(repo: &R) -> Result<MigrationReport, ServiceError> {
+    Ok(repo.migrate().await?)
+}
+
+pub async fn migration_status<R: Repository + ?Sized>(repo: &R) -> Result<MigrationReport, ServiceError> {
+    Ok(repo.migration_status().await?)
+}
+
+pub async fn backup<R: Repository + ?Sized>(policy: &BackupPolicy, repo: &R) -> Result<Snapshot, ServiceError> {
+    Ok(backup::snapshot(repo, policy).await?)
+}
+
+pub async fn verify_backup(snapshot: &Path) -> Result<MigrationReport, ServiceError> {
+    Ok(backup::verify(snapshot).await?)
+}
+
+/// Restores a SQLite database file from a snapshot; nothing may be using `target` meanwhile.
+pub async fn restore_backup(snapshot: &Path, target: &Path) -> Result<MigrationReport, ServiceError> {
+    Ok(backup::restore(snapshot, target).await?)
+}
+
+pub async fn delete_expires<R: ClipRepository + WebhookRepository + ?Sized>(changes: &Changes, repo: &R) -> Result<u64, ServiceError> {
+    let expired = repo.delete_expired().await?;
+    let count = expired.len() as u64;
+    for clip in expired {
+        changes.publish(&clip.shortcode().into(), Change::Expired);
+        notify_stored(ClipEvent::Expired, clip, repo).await;
+    }
+    Ok(count)
+}
+
+/// Registers a webhook for the clips of `owner`; the result is the only time its secret is shown.
+pub async fn new_webhook<R: WebhookRepository + ?Sized>(
+    req: ask::NewWebhook,
+    owner: &str,
+    policy: &ReceiverPolicy,
+    repo: &R,
+) -> Result<Webhook, ServiceError> {
+    let url = reqwest::Url::parse(&req.url).map_err(|e| ServiceError::Webhook(format!("invalid url: {}", e)))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(ServiceError::Webhook("the url must be http or https".to_owned()));
+    }
+    policy.check(&url).await.map_err(|e| ServiceError::Webhook(format!("invalid url: {}", e)))?;
+    let mut events = vec![];
+    for event in req.events {
+        if !events.contains(&event) {
+            events.push(event);
+        }
+    }
+    if events.is_empty() {
+        return Err(ServiceError::Webhook("subscribe to at least one event".to_owned()));
+    }
+    let secret = Webhook::new_secret();
+    let model = model::NewWebhook::new(owner.to_owned(), url.to_string(), &events, secret.clone());
+    let mut webhook = Webhook::from(repo.new_webhook(model).await?);
+    webhook.secret = Some(secret);
+    Ok(webhook)
+}
+
+pub async fn list_webhooks<R: WebhookRepository + ?Sized>(owner: &str, repo: &R) -> Result<Vec<Webhook>, ServiceError> {
+    Ok(repo.list_webhooks(owner).await?.into_iter().map(Webhook::from).collect())
+}
+
+pub async fn delete_webhook<R: WebhookRepository + ?Sized>(owner: &str, webhook_id: &str, repo: &R) -> Result<(), ServiceError> {
+    Ok(repo.delete_webhook(owner, webhook_id).await?)
+}
+
+/// The delivery log of a webhook of `owner`, newest first.
+pub async fn list_deliveries<R: WebhookRepository + ?Sized>(owner: &str, webhook_id: &str, limit: u32, repo: &R) -> Result<Vec<Delivery>, ServiceError> {
+    if !repo.list_webhooks(owner).await?.iter().any(|webhook| webhook.webhook_id() == webhook_id) {
+        return Err(ServiceError::NotFound);
+    }
+    repo.list_deliveries(owner, webhook_id, limit)
+        .await?
+        .into_iter()
+        .map(|delivery| Delivery::try_from(delivery).map_err(|e| ServiceError::Webhook(e.to_string())))
+        .collect()
+}
+
+/// Makes one attempt at every due delivery, returning how many succeeded. Failures are retried with backoff.
+///
+/// `client` should come from [`ReceiverPolicy::client_builder`], which checks the addresses receiver
+/// names resolve to; receivers given by address are checked here.
+pub async fn deliver_webhooks<R: WebhookRepository + ?Sized>(
+    client: &reqwest::Client,
+    policy: &ReceiverPolicy,
+    repo: &R,
+) -> Result<u64, ServiceError> {
+    let due = repo.due_deliveries(Utc::now().timestamp(), DELIVERY_BATCH_SIZE).await?;
+    let mut attempts = futures::stream::iter(due)
+        .map(|delivery| deliver(client, policy, delivery))
+        .buffer_unordered(DELIVERY_CONCURRENCY);
+    let mut delivered = 0;
+    while let Some((attempt, success)) = attempts.next().await {
+        delivered += u64::from(success);
+        repo.record_attempt(attempt).await?;
+    }
+    Ok(delivered)
+}
+
+async fn deliver(client: &reqwest::Client, policy: &ReceiverPolicy, delivery: model::PendingDelivery) -> (model::DeliveryAttempt, bool) {
+    if let Some(ip) = reqwest::Url::parse(&delivery.url).ok().as_ref().and_then(webhook::host_ip) {
+        if !policy.permits(ip) {
+            let error = format!("{} is not a public address", ip);
+            return (model::DeliveryAttempt::failed(&delivery, None, error), false);
+        }
+    }
+    let response = client
+        .post(delivery.url.as_str())
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(webhook::EVENT_HEADER, delivery.event.as_str())
+        .header(webhook::DELIVERY_HEADER, delivery.event_id.as_str())
+        .header(webhook::SIGNATURE_HEADER, webhook::signature(&delivery.secret, delivery.payload.as_bytes()))
+        .body(delivery.payload.clone())
+        .send()
+        .await;
+    match response {
+        Ok(response) if response.status().is_success() => {
+            (model::DeliveryAttempt::delivered(&delivery, response.status().as_u16()), true)
+        }
+        Ok(response) => {
+            let status = response.status();
+            (model::DeliveryAttempt::failed(&delivery, Some(status.as_u16()), format!("the receiver answered {}", status)), false)
+        }
+        Err(e) => (model::DeliveryAttempt::failed(&delivery, None, e.to_string()), false),
+    }
+}
+
+pub async fn stats<R: Repository + ?Sized>(top: u32, repo: &R) -> Result<Stats, ServiceError> {
+    let counts = repo.clip_stats().await?;
+    let top_clips = repo
+        .top_clips(top)
+        .await?
+        .into_iter()
+        .map(TryInto::try_into)
+        .collect::<Result<_, _>>()?;
+    Ok(Stats {
+        clips: u64::try_from(counts.clips).unwrap_or_default(),
+        protected: u64::try_from(counts.protected).unwrap_or_default(),
+        expired: u64::try_from(counts.expired).unwrap_or_default(),
+        total_bytes: u64::try_from(counts.total_bytes).unwrap_or_default(),
+        api_keys: repo.list_api_keys().await?.len() as u64,
+        top_clips,
+    })
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::data::memory::MemoryRepository;
+    use crate::domain::clip::field::{Content, Expires, Owner, Password, Tags, Title, Visibility};
+    use crate::service::{action, ask};
+    use crate::{ServiceError, ShortCode};
+    use crate::web::test::block_on;
+
+    fn new_clip(content: &str, password: Option<&str>) -> ask::NewClip {
+        ask::NewClip {
+            content: Content::new(content).unwrap(),
+            title: Title::default(),
+            expires: Expires::default(),
+            password: Password::new(password.map(str::to_owned)).unwrap(),
+            encrypted: Default::default(),
+            owner: Default::default(),
+            parent: Default::default(),
+            visibility: Default::default(),
+            tags: Default::default(),
+        }
+    }
+
+    #[test]
+    fn new_and_get_clip() {
+        let repo = MemoryRepository::new();
+        let clip = block_on(action::new_clip(new_clip("hello", None), &Default::default(), &repo)).unwrap();
+        let fetched = block_on(action::get_clip(clip.shortcode.clone().into(), &repo)).unwrap();
+        assert_eq!(fetched.content.as_str(), "hello");
+    }
+
+    #[test]
+    fn get_clip_checks_password() {
+        let repo = MemoryRepository::new();
+        let clip = block_on(action::new_clip(new_clip("secret", Some("hunter2")), &Default::default(), &repo)).unwrap();
+
+        let err = block_on(action::get_clip(clip.shortcode.clone().into(), &repo)).unwrap_err();
+        assert!(matches!(err, ServiceError::PermissionError(_)));
+
+        let req = ask::GetClip {
+            shortcode: clip.shortcode,
+            password: Password::new("hunter2".to_owned()).unwrap(),
+            viewer: Default::default(),
+        };
+        assert_eq!(block_on(action::get_clip(req, &repo)).unwrap().content.as_str(), "secret");
+    }
+
+    #[test]
+    fn legacy_clips_are_sealed_when_read() {
+        use crate::data::repository::{conformance::legacy_protected_clip, ClipRepository};
+
+        let repo = MemoryRepository::new();
+        let shortcode = crate::ShortCode::new();
+        block_on(repo.new_clip(legacy_protected_clip(&shortcode, "hunter2"))).unwrap();
+        let stored = || block_on(repo.get_clip(shortcode.clone().into())).unwrap();
+        let read = |password: &str| {
+            let req = ask::GetClip { password: Password::new(password.to_owned()).unwrap(), ..shortcode.clone().into() };
+            block_on(action::get_clip(req, &repo))
+        };
+
+        assert!(matches!(read("hunter3").unwrap_err(), ServiceError::PermissionError(_)));
+        assert!(stored().seal_content().is_some());
+        let content = read("hunter2").unwrap().content;
+        assert!(stored().seal_content().is_none());
+        assert_eq!(read("hunter2").unwrap().content.as_str(), content.as_str());
+        assert!(matches!(read("hunter3").unwrap_err(), ServiceError::PermissionError(_)));
+    }
+
+    #[test]
+    fn private_clips_are_read_only_by_their_owner() {
+        let repo = MemoryRepository::new();
+        let mut req = new_clip("roadmap", None);
+        req.owner = Owner::new("me".to_owned());
+        req.visibility = Visibility::Private;
+        let clip = block_on(action::new_clip(req, &Default::default(), &repo)).unwrap();
+
+        let read = |viewer: Option<&str>| {
+            let req = ask::GetClip { viewer: Owner::new(viewer.map(str::to_owned)), ..clip.shortcode.clone().into() };
+            block_on(action::get_clip(req, &repo))
+        };
+        assert!(matches!(read(None), Err(ServiceError::Forbidden(_))));
+        assert!(matches!(read(Some("someone else")), Err(ServiceError::Forbidden(_))));
+        assert_eq!(read(Some("me")).unwrap().visibility, Visibility::Private);
+        // nor can anyone else fork it
+        let source = ask::GetClip { viewer: Owner::new("someone else".to_owned()), ..clip.shortcode.clone().into() };
+        let err = block_on(action::fork_clip(source, ask::ForkClip::default(), &Default::default(), &repo)).unwrap_err();
+        assert!(matches!(err, ServiceError::NotFound));
+        // a private clip can never be put in a collection, even by its owner
+        let items = vec![ask::CollectionItem::Clip { shortcode: clip.shortcode.clone() }];
+        let collection = ask::NewCollection { title: Default::default(), expires: Default::default(), items, owner: Owner::new("me".to_owned()) };
+        assert!(matches!(block_on(action::new_collection(collection, &Default::default(), &repo)), Err(ServiceError::Collection(_))));
+    }
+
+    #[test]
+    fn expired_collections_and_clips_are_not_found() {
+        use crate::data::model;
+        use crate::data::repository::{conformance, ClipRepository, CollectionRepository};
+        use crate::domain::collection::Collection;
+        use chrono::{Duration, Utc};
+
+        let repo = MemoryRepository::new();
+        let past = Utc::now() - Duration::minutes(1);
+        let current = block_on(repo.new_clip(conformance::new_clip(&ShortCode::new(), None))).unwrap();
+        let expired = block_on(repo.new_clip(conformance::new_clip(&ShortCode::new(), Some(past.timestamp())))).unwrap();
+        let clips: Vec<crate::Clip> = [current, expired].into_iter().map(|clip| clip.try_into().unwrap()).collect();
+        let new_collection = |expires: Option<crate::Time>| {
+            let model = model::NewCollection::new(ShortCode::new(), None, expires, None, &clips);
+            Collection::from(block_on(repo.new_collection(model)).unwrap()).shortcode
+        };
+
+        let collection = block_on(action::get_collection(&new_collection(None), &repo)).unwrap();
+        let shown: Vec<_> = collection.clips.iter().map(|clip| clip.shortcode.clone()).collect();
+        assert_eq!(shown, [clips[0].shortcode.clone()]);
+        // expired but not yet deleted by maintenance
+        let err = block_on(action::get_collection(&new_collection(Some(past.into())), &repo)).unwrap_err();
+        assert!(matches!(err, ServiceError::NotFound));
+    }
+
+    #[test]
+    fn update_clip_keeps_shortcode() {
+        let repo = MemoryRepository::new();
+        let clip = block_on(action::new_clip(new_clip("draft", None), &Default::default(), &repo)).unwrap();
+        let req = ask::UpdateClip {
+            shortcode: clip.shortcode.clone(),
+            content: Content::new("final").unwrap(),
+            title: Title::default(),
+            expires: Expires::default(),
+            password: Password::default(),
+            encrypted: Default::default(),
+            revision: Some(clip.revision),
+            visibility: None,
+            tags: None,
+        };
+        let updated = block_on(action::update_clip(req, &Default::default(), &Default::default(), &repo)).unwrap();
+        assert_eq!(updated.shortcode, clip.shortcode);
+        assert_eq!(updated.content.as_str(), "final");
+        assert_eq!(updated.revision.into_inner(), 2);
+    }
+
+    #[test]
+    fn only_the_owner_updates_a_private_clip() {
+        let repo = MemoryRepository::new();
+        let me = Owner::new("me".to_owned());
+        let req = ask::NewClip { owner: me.clone(), visibility: Visibility::Private, ..new_clip("draft", None) };
+        let clip = block_on(action::new_clip(req, &Default::default(), &repo)).unwrap();
+        let update = |visibility| ask::UpdateClip {
+            shortcode: clip.shortcode.clone(),
+            content: Content::new("final").unwrap(),
+            title: Title::default(),
+            expires: Expires::default(),
+            password: Password::default(),
+            encrypted: Default::default(),
+            revision: None,
+            visibility: Some(visibility),
+            tags: None,
+        };
+
+        for owner in [Owner::default(), Owner::new("someone else".to_owned())] {
+            let err = block_on(action::update_clip(update(Visibility::Public), &owner, &Default::default(), &repo)).unwrap_err();
+            assert!(matches!(err, ServiceError::Forbidden(_)));
+        }
+        let get = ask::GetClip { shortcode: clip.shortcode.clone(), password: Password::default(), viewer: me.clone() };
+        let stored = block_on(action::get_clip(get, &repo)).unwrap();
+        assert_eq!((stored.content.as_str(), stored.visibility), ("draft", Visibility::Private));
+
+        let updated = block_on(action::update_clip(update(Visibility::Private), &me, &Default::default(), &repo)).unwrap();
+        assert_eq!(updated.content.as_str(), "final");
+    }
+
+    #[test]
+    fn forks_copy_the_source_and_link_back() {
+        let repo = MemoryRepository::new();
+        let source = block_on(action::new_clip(new_clip("v1", Some("hunter2")), &Default::default(), &repo)).unwrap();
+        let err = block_on(action::fork_clip(source.shortcode.clone().into(), ask::ForkClip::default(), &Default::default(), &repo)).unwrap_err();
+        assert!(matches!(err, ServiceError::PermissionError(_)));
+
+        let unlocked = ask::GetClip {
+            shortcode: source.shortcode.clone(),
+            password: Password::new("hunter2".to_owned()).unwrap(),
+            viewer: Default::default(),
+        };
+        let req = ask::ForkClip { visibility: Visibility::Public, ..Default::default() };
+        let copy = block_on(action::fork_clip(unlocked.clone(), req, &Default::default(), &repo)).unwrap();
+        // the fork does not inherit the password
+        assert_eq!(block_on(action::get_clip(copy.shortcode.clone().into(), &repo)).unwrap().content.as_str(), "v1");
+        let me = Owner::new("me".to_owned());
+        let req = ask::ForkClip { content: Some(Content::new("v2").unwrap()), owner: me.clone(), ..Default::default() };
+        let edited = block_on(action::fork_clip(unlocked.clone(), req, &Default::default(), &repo)).unwrap();
+        assert_eq!(edited.content.as_str(), "v2");
+        let req = ask::ForkClip { owner: Owner::new("someone else".to_owned()), visibility: Visibility::Private, ..Default::default() };
+        block_on(action::fork_clip(unlocked, req, &Default::default(), &repo)).unwrap();
+
+        let provenance = block_on(action::provenance(&edited, &me, &repo)).unwrap();
+        assert_eq!(provenance.forked_from, Some(source.shortcode.clone()));
+        let forks = |viewer: &Owner| -> Vec<_> {
+            let provenance = block_on(action::provenance(&source, viewer, &repo)).unwrap();
+            assert_eq!(provenance.forked_from, None);
+            provenance.forks.into_iter().map(|fork| fork.shortcode).collect()
+        };
+        // unlisted and private forks are only listed to their owner
+        assert_eq!(forks(&Owner::default()), vec![copy.shortcode.clone()]);
+        let mine = forks(&me);
+        assert_eq!(mine.len(), 2);
+        assert!(mine.contains(&copy.shortcode) && mine.contains(&edited.shortcode));
+
+        block_on(action::delete_clip(&source.shortcode, &Default::default(), &repo)).unwrap();
+        assert_eq!(block_on(action::provenance(&edited, &me, &repo)).unwrap().forked_from, None);
+    }
+
+    #[test]
+    fn stats_count_clips_and_keys() {
+        let repo = MemoryRepository::new();
+        block_on(action::new_clip(new_clip("one", None), &Default::default(), &repo)).unwrap();
+        let popular = block_on(action::new_clip(new_clip("two", Some("hunter2")), &Default::default(), &repo)).unwrap();
+        block_on(action::increase_hit_count(&popular.shortcode, 7, &Default::default(), &repo)).unwrap();
+        block_on(action::generate_api_key(&repo)).unwrap();
+
+        let stats = block_on(action::stats(1, &repo)).unwrap();
+        assert_eq!((stats.clips, stats.protected, stats.api_keys), (2, 1, 1));
+        assert_eq!(stats.top_clips.len(), 1);
+        assert_eq!(stats.top_clips[0].shortcode, popular.shortcode);
+    }
+
+    #[test]
+    fn export_and_import_round_trip() {
+        use crate::service::archive::{ConflictMode, ExportFilter};
+
+        let source = MemoryRepository::new();
+        let tags = Tags::new(vec!["rust".to_owned()]).unwrap();
+        let plain = block_on(action::new_clip(ask::NewClip { tags: tags.clone(), ..new_clip("plain", None) }, &Default::default(), &source)).unwrap();
+        let protected = block_on(action::new_clip(new_clip("secret", Some("hunter2")), &Default::default(), &source)).unwrap();
+        block_on(action::increase_hit_count(&plain.shortcode, 3, &Default::default(), &source)).unwrap();
+        let fork = block_on(action::fork_clip(plain.shortcode.clone().into(), ask::ForkClip::default(), &Default::default(), &source)).unwrap();
+
+        let mut archive = vec![];
+        assert_eq!(block_on(action::export_clips(&ExportFilter::default(), &mut archive, &source)).unwrap(), 3);
+
+        let target = MemoryRepository::new();
+        let report = block_on(action::import_clips(archive.as_slice(), ConflictMode::Skip, &Default::default(), &target)).unwrap();
+        assert_eq!(report.imported, 3);
+        let imported = block_on(action::get_clip(plain.shortcode.clone().into(), &target)).unwrap();
+        assert_eq!(imported.hits.into_inner(), 3);
+        assert_eq!(imported.posted.into_inner().timestamp(), plain.posted.into_inner().timestamp());
+        assert_eq!(imported.tags, tags);
+        let imported_fork = block_on(action::get_clip(fork.shortcode.clone().into(), &target)).unwrap();
+        assert_eq!(imported_fork.tags, tags);
+        let provenance = block_on(action::provenance(&imported_fork, &Owner::default(), &target)).unwrap();
+        assert_eq!(provenance.forked_from, Some(plain.shortcode.clone()));
+        let req = ask::GetClip {
+            shortcode: protected.shortcode.clone(),
+            password: Password::new("hunter2".to_owned()).unwrap(),
+            viewer: Default::default(),
+        };
+        assert_eq!(block_on(action::get_clip(req, &target)).unwrap().content.as_str(), "secret");
+
+        let report = block_on(action::import_clips(archive.as_slice(), ConflictMode::Skip, &Default::default(), &target)).unwrap();
+        assert_eq!((report.imported, report.skipped), (0, 3));
+        let report = block_on(action::import_clips(archive.as_slice(), ConflictMode::Overwrite, &Default::default(), &target)).unwrap();
+        assert_eq!((report.imported, report.overwritten), (3, 3));
+        let report = block_on(action::import_clips(archive.as_slice(), ConflictMode::NewShortcode, &Default::default(), &target)).unwrap();
+        assert_eq!(report.renamed.len(), 3);
+        assert!(block_on(action::stats(0, &target)).unwrap().clips == 6);
+        // a renamed fork links to its renamed parent
+        let renamed = |shortcode: &ShortCode| {
+            report.renamed.iter().find(|(from, _)| from == shortcode.as_str()).map(|(_, to)| to.clone()).unwrap()
+        };
+        let renamed_fork = block_on(action::get_clip(renamed(&fork.shortcode).as_str().into(), &target)).unwrap();
+        let provenance = block_on(action::provenance(&renamed_fork, &Owner::default(), &target)).unwrap();
+        assert_eq!(provenance.forked_from.map(ShortCode::into_inner), Some(renamed(&plain.shortcode)));
+
+        let err = block_on(action::import_clips(&b"{}\n"[..], ConflictMode::Skip, &Default::default(), &target)).unwrap_err();
+        assert!(matches!(err, ServiceError::Archive(msg) if msg.starts_with("line 1")));
+
+        // an invalid clip must not cost the stored clip it would have replaced
+        let invalid = format!(r#"{{"shortcode":"{}","content":"","posted":"2026-01-01T00:00:00Z"}}"#, plain.shortcode.as_str());
+        let err = block_on(action::import_clips(invalid.as_bytes(), ConflictMode::Overwrite, &Default::default(), &target)).unwrap_err();
+        assert!(matches!(err, ServiceError::Archive(msg) if msg.starts_with("line 1")));
+        assert_eq!(block_on(action::get_clip(plain.shortcode.clone().into(), &target)).unwrap().content.as_str(), "plain");
+    }
+
+    /// Accepts one request on a local port and answers it with `status`, handing back the raw request.
+    fn receiver(status: u16) -> (String, std::thread::JoinHandle<String>) {
+        use std::io::{BufRead, BufReader, Read, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = std::thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request = String::new();
+            while !request.ends_with("\r\n\r\n") {
+                reader.read_line(&mut request).unwrap();
+            }
+            let length: usize = request
+                .lines()
+                .find_map(|line| line.strip_prefix("content-length: "))
+                .map_or(0, |length| length.parse().unwrap());
+            let mut body = vec![0; length];
+            reader.read_exact(&mut body).unwrap();
+            write!(reader.get_mut(), "HTTP/1.1 {} X\r\ncontent-length: 0\r\n\r\n", status).unwrap();
+            request + &String::from_utf8(body).unwrap()
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn webhooks_receive_signed_events() {
+        use crate::domain::webhook::{signature, ClipEvent, DeliveryStatus, ReceiverPolicy};
+        use crate::data::repository::WebhookRepository;
+
+        let rt = crate::test::async_runtime();
+        let repo = MemoryRepository::new();
+        // the receivers listen on loopback addresses
+        let trusted = ReceiverPolicy { allow_private: true };
+        let client = trusted.client_builder().build().unwrap();
+        let (url, request) = receiver(204);
+        let hook = |url: &str, events: Vec<ClipEvent>| ask::NewWebhook { url: url.to_owned(), events };
+
+        let err = rt.block_on(action::new_webhook(hook("ftp://example.com", vec![ClipEvent::Created]), "me", &trusted, &repo)).unwrap_err();
+        assert!(matches!(err, ServiceError::Webhook(_)));
+        let err = rt.block_on(action::new_webhook(hook(&url, vec![ClipEvent::Created]), "me", &Default::default(), &repo)).unwrap_err();
+        assert!(matches!(err, ServiceError::Webhook(ref message) if message.contains("not a public address")));
+        let webhook = rt.block_on(action::new_webhook(hook(&url, vec![ClipEvent::Updated, ClipEvent::Deleted]), "me", &trusted, &repo)).unwrap();
+        let secret = webhook.secret.clone().unwrap();
+        assert!(block_on(action::list_webhooks("me", &repo)).unwrap()[0].secret.is_none());
+
+        // only events the webhook subscribed to, on clips of its owner, are queued
+        let mut req = new_clip("runbook", None);
+        req.owner = crate::domain::clip::field::Owner::new("me".to_owned());
+        let clip = block_on(action::new_clip(req, &Default::default(), &repo)).unwrap();
+        block_on(action::new_clip(new_clip("someone else's", None), &Default::default(), &repo)).unwrap();
+        let update = ask::UpdateClip {
+            shortcode: clip.shortcode.clone(),
+            content: Content::new("runbook v2").unwrap(),
+            title: Title::default(),
+            expires: Expires::default(),
+            password: Password::default(),
+            encrypted: Default::default(),
+            revision: None,
+            visibility: None,
+            tags: None,
+        };
+        block_on(action::update_clip(update, &Default::default(), &Default::default(), &repo)).unwrap();
+        assert_eq!(block_on(repo.due_deliveries(chrono::Utc::now().timestamp(), 10)).unwrap().len(), 1);
+
+        assert_eq!(rt.block_on(action::deliver_webhooks(&client, &trusted, &repo)).unwrap(), 1);
+        let request = request.join().unwrap();
+        let (headers, body) = request.split_once("\r\n\r\n").unwrap();
+        assert!(headers.contains("x-clipstash-event: clip.updated"));
+        let signed = format!("x-clipstash-signature: {}", signature(&secret, body.as_bytes()));
+        assert!(headers.contains(&signed));
+        let payload: serde_json::Value = serde_json::from_str(body).unwrap();
+        assert_eq!((payload["event"].as_str(), payload["clip"]["revision"].as_u64()), (Some("clip.updated"), Some(2)));
+        assert!(!body.contains("runbook"));
+
+        // a failing receiver gets the delivery again later
+        let (url, request) = receiver(500);
+        block_on(action::delete_webhook("me", &webhook.id, &repo)).unwrap();
+        let webhook = rt.block_on(action::new_webhook(hook(&url, vec![ClipEvent::Deleted]), "me", &trusted, &repo)).unwrap();
+        block_on(action::delete_clip(&clip.shortcode, &Default::default(), &repo)).unwrap();
+        assert_eq!(rt.block_on(action::deliver_webhooks(&client, &trusted, &repo)).unwrap(), 0);
+        request.join().unwrap();
+        let log = block_on(action::list_deliveries("me", &webhook.id, 10, &repo)).unwrap();
+        assert_eq!((log[0].event, log[0].status, log[0].attempts), (ClipEvent::Deleted, DeliveryStatus::Pending, 1));
+        assert_eq!(log[0].response_status, Some(500));
+        assert!(matches!(block_on(action::list_deliveries("you", &webhook.id, 10, &repo)), Err(ServiceError::NotFound)));
+
+        // nor are private receivers called under the default policy, even if registered before
+        let mut req = new_clip("scratch", None);
+        req.owner = crate::domain::clip::field::Owner::new("me".to_owned());
+        let scratch = block_on(action::new_clip(req, &Default::default(), &repo)).unwrap();
+        block_on(action::delete_clip(&scratch.shortcode, &Default::default(), &repo)).unwrap();
+        let public = ReceiverPolicy::default();
+        let client = public.client_builder().build().unwrap();
+        assert_eq!(rt.block_on(action::deliver_webhooks(&client, &public, &repo)).unwrap(), 0);
+        let log = block_on(action::list_deliveries("me", &webhook.id, 10, &repo)).unwrap();
+        let refused = log.iter().find(|delivery| delivery.response_status.is_none()).unwrap();
+        assert_eq!(refused.attempts, 1);
+        assert!(refused.last_error.as_deref().unwrap().contains("not a public address"));
+    }
+
+    #[test]
+    fn missing_clip_is_not_found() {
+        let repo = MemoryRepository::new();
+        let err = block_on(action::get_clip("nope".into(), &repo)).unwrap_err();
+        assert!(matches!(err, ServiceError::NotFound));
+    }
+}
@@ -0,0 +1,83 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::domain::clip::field::Visibility;
+use crate::Time;
+
+/// One line of a JSON Lines archive: a clip exactly as stored, so that password protected
+/// clips keep their hash and ciphertext. Only encryption at rest is undone on export.
+#[derive(Debug, Clone, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct ArchivedClip {
+    pub shortcode: String,
+    pub content: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    pub posted: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires: Option<DateTime<Utc>>,
+    /// Argon2 hash of the clip password.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    #[serde(default)]
+    pub hits: u64,
+    /// Base64 nonce and salt of password protected content.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_nonce: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_salt: Option<String>,
+    #[serde(default)]
+    pub encrypted: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub owner: Option<String>,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Shortcode of the clip this one was forked from, if that clip still existed on export.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+}
+
+/// Which clips to export; `since` is inclusive and `until` exclusive, both on the posted date.
+#[derive(Debug, Clone, Default)]
+pub struct ExportFilter {
+    pub owner: Option<String>,
+    pub since: Option<Time>,
+    pub until: Option<Time>,
+}
+
+impl ExportFilter {
+    pub fn matches(&self, clip: &ArchivedClip) -> bool {
+        self.owner.as_ref().is_none_or(|owner| clip.owner.as_ref() == Some(owner))
+            && self.since.as_ref().is_none_or(|since| clip.posted >= since.clone().into_inner())
+            && self.until.as_ref().is_none_or(|until| clip.posted < until.clone().into_inner())
+    }
+}
+
+/// What to do with an archived clip whose shortcode is already taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, strum::EnumString, strum::Display)]
+#[strum(serialize_all = "kebab-case")]
+pub enum ConflictMode {
+    #[default]
+    Skip,
+    Overwrite,
+    NewShortcode,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: u64,
+    pub skipped: u64,
+    pub overwritten: u64,
+    /// Original and new shortcode of clips imported with [`ConflictMode::NewShortcode`].
+    pub renamed: Vec<(String, String)>,
+}
+
+impl std::fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "imported {} clips ({} overwritten), skipped {}", self.imported, self.overwritten, self.skipped)?;
+        for (from, to) in &self.renamed {
+            writeln!(f, "  {} -> {}", from, to)?;
+        }
+        Ok(())
+    }
+}
@@ -1,28 +1,152 @@
 use serde::{Deserialize, Serialize};
 use crate::domain::clip::field;
+use crate::domain::webhook::ClipEvent;
 use crate::ShortCode;
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
 pub struct NewClip {
+    #[schema(value_type = String)]
     pub content: field::Content,
+    #[schema(value_type = Option<String>, required)]
     pub title: field::Title,
+    #[schema(value_type = Option<DateTime<Utc>>, required)]
     pub expires: field::Expires,
+    #[schema(value_type = Option<String>, required)]
     pub password: field::Password,
+    /// The content was end-to-end encrypted by the client.
+    #[serde(default)]
+    #[schema(value_type = bool)]
+    pub encrypted: field::Encrypted,
+    /// Set by the server from the API key making the request.
+    #[serde(skip)]
+    pub owner: field::Owner,
+    /// Set by the server when the clip is a fork.
+    #[serde(skip)]
+    pub parent: field::Parent,
+    #[serde(default)]
+    pub visibility: field::Visibility,
+    #[serde(default)]
+    #[schema(value_type = Vec<String>)]
+    pub tags: field::Tags,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+#[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
 pub struct UpdateClip {
+    #[schema(value_type = String)]
     pub content: field::Content,
+    #[schema(value_type = Option<String>, required)]
     pub title: field::Title,
+    #[schema(value_type = Option<DateTime<Utc>>, required)]
     pub expires: field::Expires,
+    /// The password protecting the clip after the update.
+    #[schema(value_type = Option<String>, required)]
     pub password: field::Password,
+    #[schema(value_type = String)]
     pub shortcode: field::ShortCode,
+    #[serde(default)]
+    #[schema(value_type = bool)]
+    pub encrypted: field::Encrypted,
+    /// Only update the clip if it is still at this revision; set by the server from `If-Match`.
+    #[serde(skip)]
+    pub revision: Option<field::Revision>,
+    /// Left out, the visibility is kept.
+    #[serde(default)]
+    pub visibility: Option<field::Visibility>,
+    /// Replaces the clip's tags; left out, the tags are kept.
+    #[serde(default)]
+    #[schema(value_type = Option<Vec<String>>)]
+    pub tags: Option<field::Tags>,
 }
 
-#[derive(Debug, Deserialize, Serialize)]
+/// A new clip copied from an existing one. Fields left out are copied from the clip being forked,
+/// except the expiry, password and visibility, which the fork only gets when given.
+#[derive(Debug, Default, Deserialize, Serialize, utoipa::ToSchema)]
+pub struct ForkClip {
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub content: Option<field::Content>,
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub title: Option<field::Title>,
+    #[serde(default)]
+    #[schema(value_type = Option<DateTime<Utc>>)]
+    pub expires: field::Expires,
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub password: field::Password,
+    /// Whether `content` was end-to-end encrypted by the client; without `content` the source's flag is kept.
+    #[serde(default)]
+    #[schema(value_type = bool)]
+    pub encrypted: field::Encrypted,
+    /// Set by the server from the API key making the request.
+    #[serde(skip)]
+    pub owner: field::Owner,
+    #[serde(default)]
+    pub visibility: field::Visibility,
+    /// Left out, the source's tags are copied.
+    #[serde(default)]
+    #[schema(value_type = Option<Vec<String>>)]
+    pub tags: Option<field::Tags>,
+}
+
+/// Joining the live editing session of a clip.
+#[derive(Debug, Clone)]
+pub struct JoinEdit {
+    pub shortcode: ShortCode,
+    pub password: field::Password,
+    /// Set from the API key making the request, if any.
+    pub owner: field::Owner,
+    /// Shown to the other editors.
+    pub name: String,
+}
+
+/// A collection of clips, in the order of `items`.
+#[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
+pub struct NewCollection {
+    #[serde(default)]
+    #[schema(value_type = Option<String>)]
+    pub title: field::Title,
+    /// When the collection expires; clips made from files expire with it.
+    #[serde(default)]
+    #[schema(value_type = Option<DateTime<Utc>>)]
+    pub expires: field::Expires,
+    pub items: Vec<CollectionItem>,
+    /// Set by the server from the API key making the request.
+    #[serde(skip)]
+    pub owner: field::Owner,
+}
+
+#[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
+#[serde(untagged)]
+pub enum CollectionItem {
+    /// An existing clip; it must not have a password or be end-to-end encrypted, since anyone
+    /// with the collection's link can read it.
+    Clip {
+        #[schema(value_type = String)]
+        shortcode: ShortCode,
+    },
+    /// A new clip, titled with the file name.
+    File {
+        name: String,
+        #[schema(value_type = String)]
+        content: field::Content,
+    },
+}
+
+#[derive(Debug, Deserialize, Serialize, utoipa::ToSchema)]
+pub struct NewWebhook {
+    /// An http or https URL that events are POSTed to.
+    pub url: String,
+    pub events: Vec<ClipEvent>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct GetClip {
     pub shortcode: ShortCode,
     pub password: field::Password,
+    /// The API key or web session reading the clip, if any; only a private clip's owner may read it.
+    #[serde(skip)]
+    pub viewer: field::Owner,
 }
 
 impl GetClip {
@@ -30,6 +154,7 @@ impl GetClip {
         Self {
             shortcode: ShortCode::from(shortcode),
             password: field::Password::default(),
+            viewer: field::Owner::default(),
         }
     }
 }
@@ -39,6 +164,7 @@ impl From<ShortCode> for GetClip {
         Self {
             shortcode,
             password: field::Password::default(),
+            viewer: field::Owner::default(),
         }
     }
 }
@@ -0,0 +1,365 @@
+//! Live collaborative editing: everyone editing a clip shares one document, and concurrent edits
+//! are merged with operational transformation.
+//!
+//! The server orders edits. Each is made against a document revision and transformed past the
+//! edits the editor had not seen yet, then broadcast to every editor, its author included as the
+//! acknowledgement. The document is saved to the clip shortly after editing pauses, and when the
+//! last editor leaves.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use crate::data::{AppDatabase, DataError};
+use crate::domain::clip::field::{self, Password};
+use crate::domain::edit::{self, Splice};
+use crate::service::live::Changes;
+use crate::service::{action, ask, ServiceError};
+use crate::{Clip, ShortCode};
+
+/// How long editing must pause before the document is saved.
+pub const SAVE_DELAY: Duration = Duration::from_secs(2);
+/// Documents may not grow past the default JSON request limit.
+pub const MAX_DOCUMENT_BYTES: usize = 1024 * 1024;
+const MAX_NAME_CHARS: usize = 40;
+const CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Editor {
+    pub id: u64,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CloseReason {
+    Deleted,
+    /// The clip's password was changed; editors must join again with the new one.
+    Locked,
+}
+
+/// Sent from a session to its editors.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Outgoing {
+    /// Sent only to an editor who just joined.
+    Init { editor: u64, revision: u64, content: String, editors: Vec<Editor> },
+    /// `splices` take the document to `revision`. `editor` is 0 for changes made outside the session.
+    Edit { revision: u64, editor: u64, splices: Vec<Splice> },
+    Presence { editors: Vec<Editor> },
+    /// The document was saved as this clip revision.
+    Saved { clip_revision: u64 },
+    Rejected { message: String },
+    Closed { reason: CloseReason },
+}
+
+/// Sent from an editor to its session.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Incoming {
+    /// `splices` were made against the document at `revision`.
+    Edit { revision: u64, splices: Vec<Splice> },
+}
+
+#[derive(Debug)]
+struct Document {
+    content: String,
+    /// `history[r]` takes the document from revision `r` to `r + 1`.
+    history: Vec<Vec<Splice>>,
+    editors: Vec<Editor>,
+    next_editor: u64,
+    /// The clip as last read or saved: the content, title and expiry an update must carry.
+    clip: Clip,
+    save_scheduled: bool,
+    closed: bool,
+}
+
+impl Document {
+    fn revision(&self) -> u64 {
+        self.history.len() as u64
+    }
+}
+
+pub struct Session {
+    shortcode: ShortCode,
+    /// The password the clip is saved with, proven by whoever opened the session.
+    password: Password,
+    /// The API key that opened the session, which a private clip is read again with.
+    viewer: field::Owner,
+    sessions: Sessions,
+    document: Mutex<Document>,
+    /// Saves run one at a time, each updating the clip revision the last one produced.
+    saving: tokio::sync::Mutex<()>,
+    sender: broadcast::Sender<Outgoing>,
+}
+
+/// An editor's place in a session, with everything broadcast to the session since it joined.
+pub struct Membership {
+    pub session: Arc<Session>,
+    pub editor: Editor,
+    pub init: Outgoing,
+    pub receiver: broadcast::Receiver<Outgoing>,
+}
+
+/// The open editing sessions, by clip. Cheap to clone; clones share the sessions.
+#[derive(Clone)]
+pub struct Sessions {
+    open: Arc<Mutex<HashMap<ShortCode, Arc<Session>>>>,
+    database: AppDatabase,
+    /// Where saves are published, for viewers of the clip.
+    changes: Changes,
+}
+
+impl Sessions {
+    pub fn new(database: AppDatabase, changes: Changes) -> Self {
+        Self { open: Default::default(), database, changes }
+    }
+
+    /// Joins the editing session of a clip, opening one if nobody is editing it yet.
+    ///
+    /// Editors need the password of a protected clip. An unprotected clip can only be edited with the
+    /// API key that created it, since anyone with the link could otherwise change it.
+    pub async fn join(&self, req: ask::JoinEdit) -> Result<Membership, ServiceError> {
+        let get = ask::GetClip { shortcode: req.shortcode.clone(), password: req.password.clone(), viewer: req.owner.clone() };
+        let clip = action::refresh_clip(get, self.database.repository()).await?;
+        if clip.encrypted.is_encrypted() {
+            return Err(ServiceError::PermissionError("end-to-end encrypted clips cannot be edited on the server".to_owned()));
+        }
+        let is_owner = clip.owner.as_deref().is_some() && clip.owner.as_deref() == req.owner.as_deref();
+        if !clip.password.has_password() && !is_owner {
+            return Err(ServiceError::PermissionError("only the API key that created this clip can edit it".to_owned()));
+        }
+
+        // sessions close under this lock, so the session cannot close before the editor is added
+        let mut open = self.open.lock();
+        let session = open.entry(req.shortcode.clone()).or_insert_with(|| Arc::new(Session::open(clip, req.password, req.owner, self.clone())));
+        session.add_editor(&req.name)
+    }
+
+    /// Forgets `session`, unless another session for its clip has taken its place.
+    fn remove(open: &mut HashMap<ShortCode, Arc<Session>>, session: &Arc<Session>) {
+        if open.get(&session.shortcode).is_some_and(|open| Arc::ptr_eq(open, session)) {
+            open.remove(&session.shortcode);
+        }
+    }
+}
+
+impl Session {
+    fn open(clip: Clip, password: Password, viewer: field::Owner, sessions: Sessions) -> Self {
+        let document = Document {
+            content: clip.content.as_str().to_owned(),
+            history: vec![],
+            editors: vec![],
+            next_editor: 1,
+            clip: clip.clone(),
+            save_scheduled: false,
+            closed: false,
+        };
+        Self {
+            shortcode: clip.shortcode,
+            password,
+            viewer,
+            sessions,
+            document: Mutex::new(document),
+            saving: Default::default(),
+            sender: broadcast::channel(CHANNEL_CAPACITY).0,
+        }
+    }
+
+    fn add_editor(self: &Arc<Self>, name: &str) -> Result<Membership, ServiceError> {
+        let mut document = self.document.lock();
+        if document.closed {
+            return Err(ServiceError::NotFound);
+        }
+        let id = document.next_editor;
+        document.next_editor += 1;
+        let name: String = name.trim().chars().take(MAX_NAME_CHARS).collect();
+        let editor = Editor { id, name: if name.is_empty() { format!("Editor {}", id) } else { name } };
+        document.editors.push(editor.clone());
+
+        let receiver = self.sender.subscribe();
+        let _ = self.sender.send(Outgoing::Presence { editors: document.editors.clone() });
+        let init = Outgoing::Init {
+            editor: id,
+            revision: document.revision(),
+            content: document.content.clone(),
+            editors: document.editors.clone(),
+        };
+        Ok(Membership { session: self.clone(), editor, init, receiver })
+    }
+
+    /// Applies an editor's edit and broadcasts it, transformed past the edits made since `revision`.
+    pub fn submit(self: &Arc<Self>, editor: u64, revision: u64, splices: Vec<Splice>) -> Result<(), ServiceError> {
+        let mut document = self.document.lock();
+        if document.closed {
+            return Err(ServiceError::NotFound);
+        }
+        if revision > document.revision() {
+            return Err(ServiceError::Edit(format!("revision {} is ahead of the document at {}", revision, document.revision())));
+        }
+        let splices = document.history[revision as usize..]
+            .iter()
+            .fold(splices, |splices, seen| edit::transform(&splices, seen, false));
+        let content = edit::apply(&document.content, &splices).map_err(|e| ServiceError::Edit(e.to_string()))?;
+        if content.len() > MAX_DOCUMENT_BYTES {
+            return Err(ServiceError::Edit(format!("the document may not exceed {} bytes", MAX_DOCUMENT_BYTES)));
+        }
+        document.content = content;
+        document.history.push(splices.clone());
+        let _ = self.sender.send(Outgoing::Edit { revision: document.revision(), editor, splices });
+
+        self.schedule_save(&mut document);
+        Ok(())
+    }
+
+    fn schedule_save(self: &Arc<Self>, document: &mut Document) {
+        if !document.save_scheduled {
+            document.save_scheduled = true;
+            let session = self.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(SAVE_DELAY).await;
+                session.save().await;
+            });
+        }
+    }
+
+    /// Leaves the session; the last editor out saves the document and closes it.
+    pub async fn leave(self: &Arc<Self>, editor: u64) {
+        let last = {
+            let mut document = self.document.lock();
+            document.editors.retain(|e| e.id != editor);
+            let _ = self.sender.send(Outgoing::Presence { editors: document.editors.clone() });
+            document.editors.is_empty()
+        };
+        if !last {
+            return;
+        }
+        self.save().await;
+        // someone may have joined while saving
+        let mut open = self.sessions.open.lock();
+        let mut document = self.document.lock();
+        if document.editors.is_empty() {
+            document.closed = true;
+            Sessions::remove(&mut open, self);
+        }
+    }
+
+    /// Saves the document to the clip. A change made to the clip outside the session is merged into
+    /// the document first, and the edits made since the last save are saved on top of it.
+    async fn save(self: &Arc<Self>) {
+        let _saving = self.saving.lock().await;
+        let req = {
+            let mut document = self.document.lock();
+            document.save_scheduled = false;
+            let content = match field::Content::new(&document.content) {
+                Ok(content) if document.content != document.clip.content.as_str() => content,
+                // unchanged, or empty and not a valid clip until someone types again
+                _ => return,
+            };
+            ask::UpdateClip {
+                content,
+                title: document.clip.title.clone(),
+                expires: document.clip.expires.clone(),
+                password: self.password.clone(),
+                shortcode: self.shortcode.clone(),
+                encrypted: Default::default(),
+                revision: Some(document.clip.revision),
+                visibility: None,
+                tags: None,
+            }
+        };
+
+        match action::update_clip(req, &self.viewer, &self.sessions.changes, self.sessions.database.repository()).await {
+            Ok(clip) => {
+                let _ = self.sender.send(Outgoing::Saved { clip_revision: clip.revision.into_inner() });
+                let mut document = self.document.lock();
+                document.clip = clip;
+            }
+            Err(ServiceError::Data(DataError::RevisionMismatch { .. })) => self.reload().await,
+            Err(ServiceError::NotFound) => self.close(CloseReason::Deleted),
+            Err(e) => eprintln!("failed to save edited clip {}: {}", self.shortcode.as_str(), e),
+        }
+    }
+
+    async fn reload(self: &Arc<Self>) {
+        let get = ask::GetClip { shortcode: self.shortcode.clone(), password: self.password.clone(), viewer: self.viewer.clone() };
+        match action::refresh_clip(get, self.sessions.database.repository()).await {
+            Ok(clip) => {
+                let mut document = self.document.lock();
+                // the outside change and the unsaved edits were both made to the clip as last saved
+                let saved = document.clip.content.as_str();
+                let unsaved = [Splice::between(saved, &document.content)];
+                let change = [Splice::between(saved, clip.content.as_str())];
+                let rebased = edit::transform(&change, &unsaved, true);
+                let (splices, content) = match edit::apply(&document.content, &rebased) {
+                    Ok(content) => (rebased, content),
+                    Err(_) => (vec![Splice::between(&document.content, clip.content.as_str())], clip.content.as_str().to_owned()),
+                };
+                document.content = content;
+                document.history.push(splices.clone());
+                document.clip = clip;
+                let _ = self.sender.send(Outgoing::Edit { revision: document.revision(), editor: 0, splices });
+                if document.content != document.clip.content.as_str() {
+                    self.schedule_save(&mut document);
+                }
+            }
+            Err(ServiceError::PermissionError(_) | ServiceError::Forbidden(_)) => self.close(CloseReason::Locked),
+            Err(ServiceError::NotFound) => self.close(CloseReason::Deleted),
+            Err(e) => eprintln!("failed to reload edited clip {}: {}", self.shortcode.as_str(), e),
+        }
+    }
+
+    fn close(self: &Arc<Self>, reason: CloseReason) {
+        let mut open = self.sessions.open.lock();
+        self.document.lock().closed = true;
+        Sessions::remove(&mut open, self);
+        let _ = self.sender.send(Outgoing::Closed { reason });
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+    use crate::domain::clip::field::{Content, Owner};
+
+    #[test]
+    fn unsaved_edits_survive_outside_changes() {
+        let rt = crate::test::async_runtime();
+        let database = crate::data::test::new_db(rt.handle());
+        let changes = Changes::default();
+        let sessions = Sessions::new(database.clone(), changes.clone());
+        rt.block_on(async {
+            let owner = Owner::new("me".to_owned());
+            let mut new: ask::NewClip = serde_json::from_str(r#"{"content":"status: investigating","title":null,"expires":null,"password":null}"#).unwrap();
+            new.owner = owner.clone();
+            let clip = action::new_clip(new, &Default::default(), database.repository()).await.unwrap();
+            let join = ask::JoinEdit { shortcode: clip.shortcode.clone(), password: Default::default(), owner: owner.clone(), name: "alice".to_owned() };
+            let Membership { session, editor, .. } = sessions.join(join).await.unwrap();
+            session.submit(editor.id, 0, vec![Splice { at: 0, delete: 0, insert: "09:14 ".to_owned() }]).unwrap();
+
+            let update = ask::UpdateClip {
+                shortcode: clip.shortcode.clone(),
+                content: Content::new("status: mitigated").unwrap(),
+                title: Default::default(),
+                expires: Default::default(),
+                password: Default::default(),
+                encrypted: Default::default(),
+                revision: None,
+                visibility: None,
+                tags: None,
+            };
+            action::update_clip(update, &owner, &changes, database.repository()).await.unwrap();
+
+            // the first save finds the clip changed and merges the change, the second saves the merge
+            session.save().await;
+            assert_eq!(session.document.lock().content, "09:14 status: mitigated");
+            session.save().await;
+            let get = ask::GetClip { shortcode: clip.shortcode.clone(), password: Default::default(), viewer: owner };
+            let saved = action::refresh_clip(get, database.repository()).await.unwrap();
+            assert_eq!(saved.content.as_str(), "09:14 status: mitigated");
+            session.leave(editor.id).await;
+        });
+    }
+}
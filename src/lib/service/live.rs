@@ -0,0 +1,52 @@
+//! Changes to clips, broadcast in-process to whoever has a clip open.
+
+use tokio::sync::broadcast::{self, Receiver, Sender};
+use crate::ShortCode;
+
+/// Subscribers further behind than this miss changes, and get [`broadcast::error::RecvError::Lagged`].
+const CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+#[strum(serialize_all = "snake_case")]
+pub enum Change {
+    Updated,
+    /// Views were counted; the hit count is higher.
+    Hits,
+    Deleted,
+    Expired,
+}
+
+impl Change {
+    /// The clip is gone and no further changes will follow.
+    pub fn is_final(self) -> bool {
+        matches!(self, Self::Deleted | Self::Expired)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipChange {
+    pub shortcode: ShortCode,
+    pub change: Change,
+}
+
+/// The channel changes are published on. Cheap to clone; clones share the channel.
+#[derive(Debug, Clone)]
+pub struct Changes(Sender<ClipChange>);
+
+impl Default for Changes {
+    fn default() -> Self {
+        Self(broadcast::channel(CAPACITY).0)
+    }
+}
+
+impl Changes {
+    pub fn publish(&self, shortcode: &ShortCode, change: Change) {
+        // an error only means nobody is listening
+        let _ = self.0.send(ClipChange { shortcode: shortcode.clone(), change });
+    }
+
+    /// Every change published from now on, to all clips.
+    pub fn subscribe(&self) -> Receiver<ClipChange> {
+        self.0.subscribe()
+    }
+}
@@ -1,7 +1,13 @@
 pub mod ask;
 pub mod action;
+pub mod archive;
+pub mod collab;
+pub mod live;
 
-use crate::{ClipError, DataError};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use crate::domain::clip::field::Visibility;
+use crate::{Clip, ClipError, DataError, ShortCode, Time};
 
 #[derive(Debug, thiserror::Error)]
 pub enum ServiceError {
@@ -12,16 +18,79 @@ pub enum ServiceError {
     #[error("not found")]
     NotFound,
     #[error("permissions not met: {0}")]
-    PermissionError(String)
+    PermissionError(String),
+    /// The clip is private, and the request is not from its owner.
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+    #[error("archive error: {0}")]
+    Archive(String),
+    #[error("invalid webhook: {0}")]
+    Webhook(String),
+    #[error("invalid edit: {0}")]
+    Edit(String),
+    #[error("invalid collection: {0}")]
+    Collection(String),
+}
+
+/// Usage of a clipstash database, as reported by `clipstash-admin stats`.
+#[derive(Debug, Serialize)]
+pub struct Stats {
+    pub clips: u64,
+    pub protected: u64,
+    pub expired: u64,
+    pub total_bytes: u64,
+    pub api_keys: u64,
+    pub top_clips: Vec<Clip>,
+}
+
+/// Where a clip was forked from, and the clips forked from it that have not expired and the viewer may find.
+#[derive(Debug, Default, Serialize)]
+pub struct Provenance {
+    pub forked_from: Option<ShortCode>,
+    pub forks: Vec<Fork>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Fork {
+    pub shortcode: ShortCode,
+    pub posted: Time,
+}
+
+/// A clip in a listing. Listings are read without passwords, so the content is left out.
+#[derive(Debug, Clone, Serialize, utoipa::ToSchema)]
+pub struct ListedClip {
+    pub shortcode: String,
+    pub title: Option<String>,
+    pub posted: DateTime<Utc>,
+    pub expires: Option<DateTime<Utc>>,
+    pub hits: u64,
+    pub protected: bool,
+    pub encrypted: bool,
+    pub visibility: Visibility,
+    pub tags: Vec<String>,
+}
+
+impl From<&Clip> for ListedClip {
+    fn from(clip: &Clip) -> Self {
+        Self {
+            shortcode: clip.shortcode.as_str().to_owned(),
+            title: clip.title.clone().into_inner(),
+            posted: clip.posted.clone().into_inner().into_inner(),
+            expires: clip.expires.clone().into_inner().map(|time| time.into_inner()),
+            hits: clip.hits.clone().into_inner(),
+            protected: clip.password.has_password(),
+            encrypted: clip.encrypted.is_encrypted(),
+            visibility: clip.visibility,
+            tags: clip.tags.clone().into_inner(),
+        }
+    }
 }
 
 impl From<DataError> for ServiceError {
     fn from(err: DataError) -> Self {
         match err {
-            DataError::Database(d) => match d {
-                sqlx::Error::RowNotFound => Self::NotFound,
-                other => Self::Data(DataError::Database(other)),
-            },
+            DataError::Database(d) => d.into(),
+            other => Self::Data(other),
         }
     }
 }
@@ -30,7 +99,15 @@ impl From<sqlx::Error> for ServiceError {
     fn from(err: sqlx::Error) -> Self {
         match err {
             sqlx::Error::RowNotFound => Self::NotFound,
+            sqlx::Error::Database(e) if is_unique_violation(e.code().as_deref()) => {
+                Self::Data(DataError::Conflict(e.message().to_owned()))
+            }
             other => Self::Data(DataError::Database(other)),
         }
     }
+}
+
+/// SQLite's primary key and unique constraint codes, and PostgreSQL's `unique_violation`.
+fn is_unique_violation(code: Option<&str>) -> bool {
+    matches!(code, Some("1555" | "2067" | "23505"))
 }
\ No newline at end of file
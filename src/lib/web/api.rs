@@ -1,37 +1,61 @@
 use std::str::FromStr;
 use rocket::request::{FromRequest, Outcome};
-use rocket::{Request, Responder, State};
-use rocket::http::{CookieJar, Status};
-use rocket::serde::json::Json;
-use serde::Serialize;
-use crate::data::AppDatabase;
+use rocket::fairing::{Fairing, Info, Kind};
+use rocket::response::Responder;
+use rocket::{Request, State};
+use rocket::http::{ContentType, CookieJar, Status};
+use rocket::response::stream::{Event, EventStream, One, ReaderStream};
+use rocket::Shutdown;
+use tokio::sync::broadcast::error::RecvError;
+use rocket::serde::json::{self, Json};
+use serde::{Deserialize, Serialize};
+use crate::data::{AppDatabase, DataError};
+use crate::domain::clip::field::{self, Owner, Password, ShortCodePolicy};
+use crate::service::archive::ExportFilter;
+use crate::service::ListedClip;
+use crate::Time;
+use tokio::io::{AsyncWriteExt, DuplexStream};
 use crate::service::action;
-use crate::{service, ServiceError, ShortCode};
+use crate::service::live::{Change, Changes};
+use crate::{service, ServiceError};
+use crate::web::conditional::{Conditional, Preconditions};
 use crate::web::hitcounter::HitCounter;
+use crate::web::session::WebSession;
 use crate::web::PASSWORD_COOKIE;
 
 pub const API_KEY_HEADER: &str = "x-api-key";
+const DEFAULT_LIST_LIMIT: u32 = 50;
+const MAX_LIST_LIMIT: u32 = 500;
 
 #[derive(Debug, Clone)]
 pub struct ApiKey(Vec<u8>);
 
-#[derive(Responder, Debug, thiserror::Error, Serialize)]
+#[derive(Debug, thiserror::Error)]
 pub enum ApiKeyError {
-    #[error("Invalid API key")]
-    #[response(status = 400, content_type = "json")]
+    #[error("Invalid API key: {0}")]
     DecodeError(String),
-    #[error("API key not found")]
-    #[response(status = 404, content_type = "json")]
-    NotFound(String),
 }
 
 impl ApiKey{
     pub fn to_base64(&self) -> String {
-        base64::encode(&self.0.as_slice())
+        base64::encode(self.0.as_slice())
     }
     pub fn into_inner(self) -> Vec<u8> {
         self.0
     }
+
+    /// A fingerprint identifying the key without revealing it, recorded as the owner of its clips.
+    pub fn id(&self) -> String {
+        use sha2::{Digest, Sha256};
+
+        Sha256::digest(&self.0)[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+impl From<Vec<u8>> for ApiKey {
+    fn from(key: Vec<u8>) -> Self {
+        Self(key)
+    }
 }
 
 impl Default for ApiKey {
@@ -49,32 +73,150 @@ impl FromStr for ApiKey{
     }
 }
 
-#[derive(Responder, Debug, thiserror::Error)]
-pub enum ApiError {
-    #[error("not found")]
-    #[response(status = 404, content_type = "json")]
-    NotFound(Json<String>),
-    #[error("server error")]
-    #[response(status = 500, content_type = "json")]
-    ServerError(Json<String>),
-    #[error("client error")]
-    #[response(status = 401, content_type = "json")]
-    User(Json<String>),
-    #[error("key error")]
-    #[response(status = 400, content_type = "json")]
-    KeyError(Json<ApiKeyError>),
+/// Machine-readable reason of an [`ApiError`]; each maps to one HTTP status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, strum::Display, utoipa::ToSchema)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    MissingApiKey,
+    InvalidApiKey,
+    InvalidPassword,
+    Forbidden,
+    NotFound,
+    Conflict,
+    PreconditionFailed,
+    PayloadTooLarge,
+    InvalidBody,
+    InvalidClip,
+    InvalidParameter,
+    InvalidWebhook,
+    InvalidCollection,
+    PreconditionRequired,
+    TooManyRequests,
+    ServerError,
+}
+
+impl ErrorCode {
+    pub fn status(self) -> Status {
+        match self {
+            Self::BadRequest => Status::BadRequest,
+            Self::MissingApiKey | Self::InvalidApiKey => Status::Unauthorized,
+            Self::InvalidPassword | Self::Forbidden => Status::Forbidden,
+            Self::NotFound => Status::NotFound,
+            Self::Conflict => Status::Conflict,
+            Self::PreconditionFailed => Status::PreconditionFailed,
+            Self::PayloadTooLarge => Status::PayloadTooLarge,
+            Self::InvalidBody | Self::InvalidClip | Self::InvalidParameter | Self::InvalidWebhook | Self::InvalidCollection => Status::UnprocessableEntity,
+            Self::PreconditionRequired => Status::PreconditionRequired,
+            Self::TooManyRequests => Status::TooManyRequests,
+            Self::ServerError => Status::InternalServerError,
+        }
+    }
+}
+
+/// Every API error is sent as `{"error": {"code", "message", "details"}}`.
+#[derive(Debug, Clone, Serialize, Deserialize, thiserror::Error, utoipa::ToSchema)]
+#[error("{message}")]
+pub struct ApiError {
+    pub code: ErrorCode,
+    pub message: String,
+    #[schema(value_type = Option<Object>)]
+    pub details: Option<serde_json::Value>,
+}
+
+/// The body of every error response.
+#[derive(Debug, Serialize, Deserialize, utoipa::ToSchema)]
+pub struct ErrorEnvelope {
+    pub error: ApiError,
+}
+
+impl ApiError {
+    pub fn new<M: Into<String>>(code: ErrorCode, message: M) -> Self {
+        Self { code, message: message.into(), details: None }
+    }
+
+    pub fn with_details(mut self, details: serde_json::Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    fn server_error() -> Self {
+        Self::new(ErrorCode::ServerError, "a server error occurred")
+    }
+
+    /// The error for a status no route handler produced, such as an unknown route or an oversized body.
+    pub fn from_status(status: Status) -> Self {
+        match status.code {
+            400 => Self::new(ErrorCode::BadRequest, "the request could not be read"),
+            401 => Self::new(ErrorCode::MissingApiKey, format!("the {} header is required", API_KEY_HEADER)),
+            404 => Self::new(ErrorCode::NotFound, "no such route"),
+            413 => Self::new(ErrorCode::PayloadTooLarge, "the request body is too large"),
+            422 => Self::new(ErrorCode::InvalidBody, "the request body is invalid"),
+            429 => Self::new(ErrorCode::TooManyRequests, "too many requests; try again later"),
+            code if code < 500 => Self::new(ErrorCode::BadRequest, status.reason_lossy()),
+            _ => Self::server_error(),
+        }
+    }
+
+    /// A failed request guard's error, kept for the catcher that builds the response.
+    pub(crate) fn into_outcome<S>(self, req: &Request<'_>) -> Outcome<S, Self> {
+        let status = self.code.status();
+        req.local_cache(|| CaughtError(Some(self.clone())));
+        Outcome::Error((status, self))
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, req: &'r Request<'_>) -> rocket::response::Result<'static> {
+        let status = self.code.status();
+        rocket::Response::build_from(Json(ErrorEnvelope { error: self }).respond_to(req)?)
+            .status(status)
+            .ok()
+    }
 }
 
+/// Rocket hands catchers only the status of a failed guard; the error itself travels in the request cache.
+struct CaughtError(Option<ApiError>);
+
 impl From<ServiceError> for ApiError {
     fn from(e: ServiceError) -> Self {
         match e {
-            ServiceError::Clip(c) => Self::User(Json(format!("clip parsing error: {}", c))),
-            ServiceError::NotFound => Self::NotFound(Json("entity not found".to_owned())),
-            ServiceError::Data(_) => Self::ServerError(Json("a server error occurred".to_owned())),
-            ServiceError::PermissionError(msg) => Self::User(Json(msg)),
+            ServiceError::Clip(c) => Self::new(ErrorCode::InvalidClip, format!("invalid clip: {}", c))
+                .with_details(serde_json::json!({ "reason": c.to_string() })),
+            ServiceError::NotFound => Self::new(ErrorCode::NotFound, "entity not found"),
+            ServiceError::PermissionError(msg) => Self::new(ErrorCode::InvalidPassword, msg),
+            ServiceError::Forbidden(msg) => Self::new(ErrorCode::Forbidden, msg),
+            ServiceError::Data(DataError::Conflict(msg)) => Self::new(ErrorCode::Conflict, msg),
+            ServiceError::Data(e @ DataError::RevisionMismatch { actual, .. }) => {
+                Self::new(ErrorCode::PreconditionFailed, format!("the clip was changed; {}", e))
+                    .with_details(serde_json::json!({ "etag": format!("\"{}\"", actual) }))
+            }
+            ServiceError::Webhook(msg) => Self::new(ErrorCode::InvalidWebhook, msg),
+            ServiceError::Collection(msg) => Self::new(ErrorCode::InvalidCollection, msg),
+            ServiceError::Edit(msg) => Self::new(ErrorCode::InvalidBody, msg),
+            ServiceError::Data(_) | ServiceError::Archive(_) => Self::server_error(),
         }
     }
+}
 
+impl<'a> From<json::Error<'a>> for ApiError {
+    fn from(e: json::Error<'a>) -> Self {
+        match e {
+            json::Error::Io(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                Self::new(ErrorCode::PayloadTooLarge, "the request body is too large")
+            }
+            json::Error::Io(e) => Self::new(ErrorCode::BadRequest, format!("unable to read the request body: {}", e)),
+            json::Error::Parse(_, e) => {
+                let code = match e.classify() {
+                    serde_json::error::Category::Data => ErrorCode::InvalidBody,
+                    _ => ErrorCode::BadRequest,
+                };
+                Self::new(code, "the request body could not be parsed")
+                    .with_details(serde_json::json!({ "reason": e.to_string() }))
+            }
+        }
+    }
 }
 
 #[rocket::async_trait]
@@ -82,134 +224,717 @@ impl<'r> FromRequest<'r> for ApiKey {
     type Error = ApiError;
 
     async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-        fn server_error() -> Outcome<ApiKey, ApiError> {
-            Outcome::Error((
-                Status::InternalServerError,
-                ApiError::ServerError(Json("a server error occurred".to_owned()))
-            ))
-        }
-
-        fn key_error(e: ApiKeyError) -> Outcome<ApiKey, ApiError> {
-            Outcome::Error((
-                Status::BadRequest,
-                ApiError::KeyError(Json(e))
-            ))
-        }
-        
-        match req.headers().get_one(API_KEY_HEADER) {
-            None => key_error(ApiKeyError::NotFound("API key not found".to_string())),
-            Some(key) => {
-                let db = match req.guard::<&State<AppDatabase>>().await {
-                    Outcome::Success(db) => db,
-                    _ => return server_error(),
-                };
-
-                let api_key = match ApiKey::from_str(key) {
-                    Ok(key) => key,
-                    Err(e) => return key_error(e),
-                };
-                
-                match action::api_key_is_valid(api_key.clone(), db.get_pool()).await {
-                    Ok(valid) if valid => {
-                        Outcome::Success(api_key)
-                    }
-                    Ok(valid) if !valid => {
-                        key_error(ApiKeyError::NotFound("API key not found".to_string()))
-                    }
-                    _ => server_error(),
-                }
-            }
+        let key = match req.headers().get_one(API_KEY_HEADER) {
+            Some(key) => key,
+            None => return ApiError::new(ErrorCode::MissingApiKey, format!("the {} header is required", API_KEY_HEADER))
+                .into_outcome(req),
+        };
+        let db = match req.guard::<&State<AppDatabase>>().await {
+            Outcome::Success(db) => db,
+            _ => return ApiError::server_error().into_outcome(req),
+        };
+        let api_key = match ApiKey::from_str(key) {
+            Ok(key) => key,
+            Err(e) => return ApiError::new(ErrorCode::InvalidApiKey, e.to_string()).into_outcome(req),
+        };
+        match action::api_key_is_valid(api_key.clone(), db.repository()).await {
+            Ok(true) => Outcome::Success(api_key),
+            Ok(false) => ApiError::new(ErrorCode::InvalidApiKey, "API key not found").into_outcome(req),
+            Err(_) => ApiError::server_error().into_outcome(req),
         }
     }
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/clip/key",
+    tag = "keys",
+    responses(
+        (status = 200, description = "A key was generated and written to the server log", body = String, content_type = "application/json"),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+)]
 #[rocket::get("/key")]
 pub async fn new_api_key(database: &State<AppDatabase>) -> Result<Json<&str>, ApiError> {
-    let key = action::generate_api_key(database.get_pool()).await?;
+    let key = action::generate_api_key(database.repository()).await?;
     println!("Generated API key: {}", key.to_base64());
     Ok(Json("API key generated"))
 }
 
+#[utoipa::path(
+    get,
+    path = "/api/v1/clip/{shortcode}",
+    tag = "clips",
+    params(
+        ("shortcode" = String, Path, description = "clip shortcode"),
+        ("password" = Option<String>, Cookie, description = "password of a protected clip"),
+        ("If-None-Match" = Option<String>, Header, description = "ETag of a copy the client has"),
+    ),
+    responses(
+        (status = 200, description = "The clip; its hit count is increased", body = Clip, headers(
+            ("ETag" = String, description = "the clip revision"),
+            ("Last-Modified" = String, description = "when the clip last changed"),
+        )),
+        (status = 304, description = "The clip is still at the revision in If-None-Match"),
+        (status = 401, description = "missing_api_key, invalid_api_key", body = ErrorEnvelope),
+        (status = 403, description = "invalid_password: wrong or missing password; forbidden: a private clip of another API key", body = ErrorEnvelope),
+        (status = 404, description = "not_found: no clip with this shortcode", body = ErrorEnvelope),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+    security(("api_key" = [])),
+)]
 #[rocket::get("/<shortcode>")]
 pub async fn get_clip(
     shortcode: &str,
     database: &State<AppDatabase>,
     cookie: &CookieJar<'_>,
     hit_counter: &State<HitCounter>,
-    _api_key: ApiKey
-) -> Result<Json<crate::Clip>, ApiError> {
-    use crate::domain::clip::field::Password;
-
-    let req = service::ask::GetClip {
-        shortcode: shortcode.into(),
-        password: cookie
-            .get(PASSWORD_COOKIE)
-            .map(|cookie| cookie.value())
-            .map(|raw_password| Password::new(raw_password.to_string()).ok())
-            .flatten()
-            .unwrap_or_else(Password::default),
-    };
-    let clip = action::get_clip(req, database.get_pool()).await?;
-    hit_counter.hit(shortcode.into(), 1);
-    Ok(Json(clip))
+    preconditions: Preconditions,
+    api_key: ApiKey
+) -> Result<Conditional<Json<crate::Clip>>, ApiError> {
+    let req = service::ask::GetClip { shortcode: shortcode.into(), password: cookie_password(cookie), viewer: Owner::new(api_key.id()) };
+    let clip = action::get_clip(req, database.repository()).await?;
+    // a client revalidating its copy is not another view
+    if !preconditions.is_fresh(clip.revision) {
+        hit_counter.hit(shortcode.into(), 1).await;
+        action::clip_viewed(&clip, database.repository()).await;
+    }
+    Ok(Conditional::with_clip(Json(clip.clone()), &clip).unless_fresh(&preconditions, &clip))
+}
+
+pub(crate) fn cookie_password(cookie: &CookieJar<'_>) -> Password {
+    cookie
+        .get(PASSWORD_COOKIE)
+        .map(|cookie| cookie.value())
+        .and_then(|raw_password| Password::new(raw_password.to_string()).ok())
+        .unwrap_or_default()
+}
+
+/// Who is reading a clip: the API key when one is sent, or else the browser's web session.
+pub(crate) fn clip_viewer(api_key: Result<ApiKey, ApiError>, session: Option<WebSession>) -> Result<Owner, ApiError> {
+    match api_key {
+        Ok(key) => Ok(Owner::new(key.id())),
+        Err(e) if e.code == ErrorCode::MissingApiKey => Ok(Owner::new(session.map(|session| session.id()))),
+        Err(e) => Err(e),
+    }
+}
+
+/// Server-sent events for a clip someone has open: `clip` with the clip whenever it or its hit count changes,
+/// then `deleted`, `expired` or `locked` (the password was changed, or the clip made private) once, ending the stream.
+#[utoipa::path(
+    get,
+    path = "/api/v1/clip/{shortcode}/events",
+    tag = "clips",
+    params(
+        ("shortcode" = String, Path, description = "clip shortcode"),
+        ("password" = Option<String>, Cookie, description = "password of a protected clip"),
+    ),
+    responses(
+        (status = 200, description = "A stream of clip events", content_type = "text/event-stream", body = String),
+        (status = 401, description = "invalid_api_key", body = ErrorEnvelope),
+        (status = 403, description = "invalid_password: wrong or missing password; forbidden: a private clip of another API key or session", body = ErrorEnvelope),
+        (status = 404, description = "not_found: no clip with this shortcode", body = ErrorEnvelope),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+    security((), ("api_key" = [])),
+)]
+#[rocket::get("/<shortcode>/events")]
+pub async fn clip_events(
+    shortcode: &str,
+    database: &State<AppDatabase>,
+    changes: &State<Changes>,
+    cookie: &CookieJar<'_>,
+    api_key: Result<ApiKey, ApiError>,
+    session: Option<WebSession>,
+    mut shutdown: Shutdown
+) -> Result<EventStream![], ApiError> {
+    let viewer = clip_viewer(api_key, session)?;
+    let req = service::ask::GetClip { shortcode: shortcode.into(), password: cookie_password(cookie), viewer };
+    // subscribe first, so nothing published between the check and the stream is missed
+    let mut changes = changes.subscribe();
+    action::refresh_clip(req.clone(), database.repository()).await?;
+    let database = database.inner().clone();
+
+    Ok(EventStream! {
+        loop {
+            let change = tokio::select! {
+                change = changes.recv() => match change {
+                    Ok(change) if change.shortcode == req.shortcode => change.change,
+                    Ok(_) => continue,
+                    // some changes were missed, so read the clip again
+                    Err(RecvError::Lagged(_)) => Change::Updated,
+                    Err(RecvError::Closed) => break,
+                },
+                _ = &mut shutdown => break,
+            };
+            if change.is_final() {
+                yield Event::empty().event(change.to_string());
+                break;
+            }
+            match action::refresh_clip(req.clone(), database.repository()).await {
+                Ok(clip) => yield Event::json(&clip).event("clip"),
+                Err(ServiceError::PermissionError(_) | ServiceError::Forbidden(_)) => {
+                    yield Event::empty().event("locked");
+                    break;
+                }
+                Err(ServiceError::NotFound) => {
+                    yield Event::empty().event(Change::Deleted.to_string());
+                    break;
+                }
+                Err(e) => eprintln!("clip events: {}", e),
+            }
+        }
+    })
 }
 
+#[utoipa::path(
+    post,
+    path = "/api/v1/clip",
+    tag = "clips",
+    request_body = NewClip,
+    responses(
+        (status = 200, description = "The new clip", body = Clip),
+        (status = 400, description = "bad_request: malformed JSON", body = ErrorEnvelope),
+        (status = 401, description = "missing_api_key, invalid_api_key", body = ErrorEnvelope),
+        (status = 409, description = "conflict: the shortcode is taken", body = ErrorEnvelope),
+        (status = 413, description = "payload_too_large", body = ErrorEnvelope),
+        (status = 422, description = "invalid_body, invalid_clip", body = ErrorEnvelope),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+    security(("api_key" = [])),
+)]
 #[rocket::post("/", data = "<req>")]
 pub async fn new_clip(
-    req: Json<service::ask::NewClip>,
+    req: Result<Json<service::ask::NewClip>, json::Error<'_>>,
     database: &State<AppDatabase>,
-    _api_key: ApiKey
+    policy: &State<ShortCodePolicy>,
+    api_key: ApiKey
 ) -> Result<Json<crate::Clip>, ApiError> {
-    let clip = action::new_clip(req.into_inner(), database.get_pool()).await?;
+    let mut req = req?.into_inner();
+    req.owner = Owner::new(api_key.id());
+    let clip = action::new_clip(req, policy, database.repository()).await?;
     Ok(Json(clip))
 }
-#[rocket::put("/", data = "<req>")]
-pub async fn update_clip(
-    req: Json<service::ask::UpdateClip>,
+
+/// Creates a clip from an existing one. Without a body the fork is an exact copy, minus the
+/// source's expiry and password.
+#[utoipa::path(
+    post,
+    path = "/api/v1/clip/{shortcode}/fork",
+    tag = "clips",
+    request_body(content = Option<ForkClip>, description = "fields that replace the copied ones"),
+    params(
+        ("shortcode" = String, Path, description = "shortcode of the clip to fork"),
+        ("password" = Option<String>, Cookie, description = "password of a protected source clip"),
+    ),
+    responses(
+        (status = 200, description = "The new clip", body = Clip),
+        (status = 400, description = "bad_request: malformed JSON", body = ErrorEnvelope),
+        (status = 401, description = "missing_api_key, invalid_api_key", body = ErrorEnvelope),
+        (status = 403, description = "invalid_password: wrong or missing password of the source clip", body = ErrorEnvelope),
+        (status = 404, description = "not_found: no clip with this shortcode, or one that is private to someone else", body = ErrorEnvelope),
+        (status = 413, description = "payload_too_large", body = ErrorEnvelope),
+        (status = 422, description = "invalid_body, invalid_clip", body = ErrorEnvelope),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+    security(("api_key" = [])),
+)]
+#[rocket::post("/<shortcode>/fork", data = "<req>")]
+pub async fn fork_clip(
+    shortcode: &str,
+    req: Result<Json<service::ask::ForkClip>, json::Error<'_>>,
     database: &State<AppDatabase>,
-    _api_key: ApiKey
+    policy: &State<ShortCodePolicy>,
+    cookie: &CookieJar<'_>,
+    api_key: ApiKey
 ) -> Result<Json<crate::Clip>, ApiError> {
-    let clip = action::update_clip(req.into_inner(), database.get_pool()).await?;
+    let mut req = match req {
+        Ok(req) => req.into_inner(),
+        Err(json::Error::Parse(body, _)) if body.trim().is_empty() => Default::default(),
+        Err(e) => return Err(e.into()),
+    };
+    req.owner = Owner::new(api_key.id());
+    let source = service::ask::GetClip { shortcode: shortcode.into(), password: cookie_password(cookie), viewer: req.owner.clone() };
+    let clip = action::fork_clip(source, req, policy, database.repository()).await?;
     Ok(Json(clip))
 }
+#[utoipa::path(
+    put,
+    path = "/api/v1/clip",
+    tag = "clips",
+    request_body = UpdateClip,
+    params(
+        ("If-Match" = String, Header, description = "ETag of the revision being replaced, or * to overwrite any"),
+    ),
+    responses(
+        (status = 200, description = "The updated clip", body = Clip, headers(
+            ("ETag" = String, description = "the new clip revision"),
+        )),
+        (status = 400, description = "bad_request: malformed JSON", body = ErrorEnvelope),
+        (status = 401, description = "missing_api_key, invalid_api_key", body = ErrorEnvelope),
+        (status = 403, description = "forbidden: the clip is private to another API key", body = ErrorEnvelope),
+        (status = 404, description = "not_found: no clip with this shortcode", body = ErrorEnvelope),
+        (status = 412, description = "precondition_failed: the clip changed since it was read; details.etag is current", body = ErrorEnvelope),
+        (status = 413, description = "payload_too_large", body = ErrorEnvelope),
+        (status = 422, description = "invalid_body, invalid_clip", body = ErrorEnvelope),
+        (status = 428, description = "precondition_required: If-Match is missing", body = ErrorEnvelope),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+    security(("api_key" = [])),
+)]
+#[rocket::put("/", data = "<req>")]
+pub async fn update_clip(
+    req: Result<Json<service::ask::UpdateClip>, json::Error<'_>>,
+    database: &State<AppDatabase>,
+    changes: &State<Changes>,
+    preconditions: Preconditions,
+    api_key: ApiKey
+) -> Result<Conditional<Json<crate::Clip>>, ApiError> {
+    let mut req = req?.into_inner();
+    req.revision = preconditions.expected_revision().ok_or_else(|| {
+        ApiError::new(ErrorCode::PreconditionRequired, "send If-Match with the ETag of the clip being updated, or *")
+    })?;
+    let clip = action::update_clip(req, &Owner::new(api_key.id()), changes, database.repository()).await?;
+    Ok(Conditional::with_clip(Json(clip.clone()), &clip))
+}
+
+/// Clips created with the calling API key as JSON Lines, optionally limited to a posted date range.
+#[utoipa::path(
+    get,
+    path = "/api/v1/export",
+    tag = "clips",
+    params(
+        ("since" = Option<String>, Query, description = "only clips posted on or after this date (YYYY-MM-DD)"),
+        ("until" = Option<String>, Query, description = "only clips posted before this date (YYYY-MM-DD)"),
+    ),
+    responses(
+        (status = 200, description = "One archived clip per line; if the export fails part way, the last line is an error envelope", body = ArchivedClip, content_type = "application/x-ndjson"),
+        (status = 401, description = "missing_api_key, invalid_api_key", body = ErrorEnvelope),
+        (status = 422, description = "invalid_parameter: a date is not YYYY-MM-DD", body = ErrorEnvelope),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+    security(("api_key" = [])),
+)]
+#[rocket::get("/export?<since>&<until>")]
+pub async fn export_clips(
+    since: Option<&str>,
+    until: Option<&str>,
+    database: &State<AppDatabase>,
+    api_key: ApiKey
+) -> Result<(ContentType, ReaderStream<One<DuplexStream>>), ApiError> {
+    let parse = |name: &str, date: Option<&str>| date
+        .map(Time::from_str)
+        .transpose()
+        .map_err(|e| ApiError::new(ErrorCode::InvalidParameter, format!("invalid date: {}", e))
+            .with_details(serde_json::json!({ "parameter": name })));
+    let filter = ExportFilter {
+        owner: Some(api_key.id()),
+        since: parse("since", since)?,
+        until: parse("until", until)?,
+    };
+    let (mut writer, reader) = tokio::io::duplex(64 * 1024);
+    let database = database.inner().clone();
+    tokio::spawn(async move {
+        if let Err(e) = action::export_clips(&filter, &mut writer, database.repository()).await {
+            eprintln!("export failed: {}", e);
+            // the status is long sent, so a last line tells clients the archive is incomplete
+            let error = ApiError::new(ErrorCode::ServerError, format!("export failed: {}", e));
+            let mut line = serde_json::to_vec(&ErrorEnvelope { error }).expect("errors serialize");
+            line.push(b'\n');
+            let _ = writer.write_all(&line).await;
+        }
+    });
+    Ok((ContentType::new("application", "x-ndjson"), ReaderStream::one(reader)))
+}
+
+/// Unexpired clips created with the calling API key, newest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/clips",
+    tag = "clips",
+    params(
+        ("tag" = Option<String>, Query, description = "only clips with this tag"),
+        ("limit" = Option<u32>, Query, description = "how many clips, at most 500 (default 50)"),
+    ),
+    responses(
+        (status = 200, description = "The clips, without their content", body = [ListedClip]),
+        (status = 401, description = "missing_api_key, invalid_api_key", body = ErrorEnvelope),
+        (status = 422, description = "invalid_parameter: an invalid tag, or limit is out of range", body = ErrorEnvelope),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+    security(("api_key" = [])),
+)]
+#[rocket::get("/clips?<tag>&<limit>")]
+pub async fn list_clips(
+    tag: Option<&str>,
+    limit: Option<u32>,
+    database: &State<AppDatabase>,
+    api_key: ApiKey
+) -> Result<Json<Vec<ListedClip>>, ApiError> {
+    let tag = tag.map(field::normalize_tag).transpose().map_err(|e| {
+        ApiError::new(ErrorCode::InvalidParameter, e.to_string()).with_details(serde_json::json!({ "parameter": "tag" }))
+    })?;
+    let limit = limit.unwrap_or(DEFAULT_LIST_LIMIT);
+    if limit == 0 || limit > MAX_LIST_LIMIT {
+        return Err(ApiError::new(ErrorCode::InvalidParameter, format!("limit must be between 1 and {}", MAX_LIST_LIMIT))
+            .with_details(serde_json::json!({ "parameter": "limit" })));
+    }
+    Ok(Json(action::find_clips(&api_key.id(), tag, limit, database.repository()).await?))
+}
 
 pub fn routes() -> Vec<rocket::Route> {
-    rocket::routes![get_clip, new_clip, update_clip, new_api_key]
+    rocket::routes![get_clip, clip_events, new_clip, fork_clip, update_clip, new_api_key]
+}
+
+/// Routes new in `/api/v1`, which have no unversioned alias.
+pub fn v1_routes() -> Vec<rocket::Route> {
+    rocket::routes![list_clips]
+}
+
+/// Routes mounted at `/api/v1` rather than under `/api/v1/clip`.
+pub fn account_routes() -> Vec<rocket::Route> {
+    rocket::routes![export_clips]
 }
 
+/// The first API routes, mounted without a version prefix.
+pub const DEPRECATED_PREFIXES: [&str; 2] = ["/api/clip", "/api/export"];
+
+/// Marks responses of the unversioned aliases with `Deprecation` and a `Link` to their `/api/v1` successor.
+pub struct Deprecation;
+
+#[rocket::async_trait]
+impl Fairing for Deprecation {
+    fn info(&self) -> Info {
+        Info { name: "API deprecation headers", kind: Kind::Response }
+    }
+
+    async fn on_response<'r>(&self, req: &'r Request<'_>, res: &mut rocket::Response<'r>) {
+        let path = req.uri().path();
+        if DEPRECATED_PREFIXES.iter().any(|prefix| path.starts_with(prefix)) {
+            let successor = format!("/api/v1{}", &path.as_str()["/api".len()..]);
+            res.set_raw_header("Deprecation", "true");
+            res.set_raw_header("Link", format!("<{}>; rel=\"successor-version\"", successor));
+        }
+    }
+}
 
 pub mod catcher {
-    use rocket::serde::json::Json;
+    use rocket::http::Status;
     use rocket::Request;
     use rocket::{catch, catchers, Catcher};
+    use super::{ApiError, CaughtError};
 
+    /// Every error under `/api` gets the JSON envelope, including those from request guards.
     #[catch(default)]
-    fn default(req: &Request) -> Json<&'static str>{
-        eprintln!("General error: {:?}", req);
-        Json("something went wrong...")
+    fn default(status: Status, req: &Request) -> ApiError {
+        if status.code >= 500 {
+            eprintln!("Internal error: {:?}", req);
+        }
+        req.local_cache(|| CaughtError(None))
+            .0
+            .clone()
+            .filter(|e| e.code.status() == status)
+            .unwrap_or_else(|| ApiError::from_status(status))
     }
 
-    #[catch(500)]
-    fn internal_error(req: &Request) -> Json<&'static str> {
-        eprintln!("Internal error: {:?}", req);
-        Json("internal server error...")
+    pub fn catchers() -> Vec<Catcher> {
+        catchers![default]
     }
+}
+#[cfg(test)]
+pub mod test {
+    use crate::service::action;
+    use crate::web::test::config;
+    use crate::web::test::block_on;
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+    use super::{ApiError, ErrorCode, ErrorEnvelope, API_KEY_HEADER};
+
+    #[test]
+    fn exports_only_own_clips() {
+        let config = config();
+        let database = config.database.clone();
+        let client = Client::tracked(crate::rocket(config)).expect("valid rocket instance");
+        let new_key = || block_on(action::generate_api_key(database.repository())).unwrap().to_base64();
+        let (mine, theirs) = (new_key(), new_key());
+
+        let body = r#"{"content":"exported","title":null,"expires":null,"password":null}"#;
+        for key in [&mine, &theirs] {
+            let response = client.post("/api/v1/clip").header(Header::new(API_KEY_HEADER, key.clone())).body(body).dispatch();
+            assert_eq!(response.status(), Status::Ok);
+        }
 
-    #[catch(404)]
-    fn not_found() -> Json<&'static str> {
-        Json("404")
+        let response = client.get("/api/v1/export").header(Header::new(API_KEY_HEADER, mine)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let archive = response.into_string().unwrap();
+        assert_eq!(archive.lines().count(), 1);
+        assert!(archive.contains("\"content\":\"exported\""));
+
+        assert_eq!(client.get("/api/v1/export").dispatch().status(), Status::Unauthorized);
     }
 
-    #[catch(400)]
-    fn request_error() -> Json<&'static str> {
-        Json("request error")
+    #[test]
+    fn each_instance_uses_its_own_shortcode_policy() {
+        use crate::domain::clip::field::ShortCodePolicy;
+
+        let policies = [ShortCodePolicy { length: 6, alphabet: "xyz".to_owned() }, ShortCodePolicy::default()];
+        let clients: Vec<_> = policies.iter().map(|policy| {
+            let mut config = config();
+            config.shortcode = policy.clone();
+            let database = config.database.clone();
+            let client = Client::tracked(crate::rocket(config)).expect("valid rocket instance");
+            let key = block_on(action::generate_api_key(database.repository())).unwrap().to_base64();
+            (client, key)
+        }).collect();
+
+        let body = r#"{"content":"hello","title":null,"expires":null,"password":null}"#;
+        for ((client, key), policy) in clients.iter().zip(&policies) {
+            let response = client.post("/api/v1/clip").header(Header::new(API_KEY_HEADER, key.clone())).body(body).dispatch();
+            let clip: serde_json::Value = response.into_json().unwrap();
+            let shortcode = clip["shortcode"].as_str().unwrap();
+            assert_eq!(shortcode.len(), policy.length);
+            assert!(shortcode.chars().all(|c| policy.alphabet.contains(c)), "{} is not from {}", shortcode, policy.alphabet);
+        }
     }
 
-    #[catch(401)]
-    fn missing_api_key() -> Json<&'static str> {
-        Json("missing API key")
+    #[test]
+    fn failed_exports_end_with_an_error() {
+        use crate::data::envelope::Keyring;
+        use crate::domain::clip::field::Owner;
+
+        let keyfile = || {
+            let path = std::env::temp_dir().join(format!("clipstash-test-{}.keys", crate::data::DbId::new()));
+            let mut keyring = Keyring::default();
+            keyring.rotate();
+            keyring.save(&path).unwrap();
+            path
+        };
+        let (server_keys, lost_keys) = (keyfile(), keyfile());
+        let mut config = config();
+        let raw = config.database.clone();
+        config.database = raw.clone().with_envelope(&server_keys).unwrap();
+        let client = Client::tracked(crate::rocket(config)).expect("valid rocket instance");
+        let api_key = block_on(action::generate_api_key(raw.repository())).unwrap();
+
+        // sealed under a master key the server does not have, so the export fails part way
+        let mut new: crate::service::ask::NewClip = serde_json::from_str(r#"{"content":"x","title":null,"expires":null,"password":null}"#).unwrap();
+        new.owner = Owner::new(api_key.id());
+        let elsewhere = raw.clone().with_envelope(&lost_keys).unwrap();
+        block_on(action::new_clip(new, &Default::default(), elsewhere.repository())).unwrap();
+
+        let response = client.get("/api/v1/export").header(Header::new(API_KEY_HEADER, api_key.to_base64())).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let archive = response.into_string().unwrap();
+        let trailer: ErrorEnvelope = serde_json::from_str(archive.lines().last().unwrap()).unwrap();
+        assert_eq!(trailer.error.code, ErrorCode::ServerError);
+        let err = block_on(action::import_clips(archive.as_bytes(), Default::default(), &Default::default(), raw.repository())).unwrap_err();
+        assert!(err.to_string().contains("incomplete"));
+        for path in [server_keys, lost_keys] {
+            let _ = std::fs::remove_file(path);
+        }
     }
-    pub fn catchers() -> Vec<Catcher> {
-        catchers![default, internal_error, not_found, missing_api_key, request_error]
+
+    #[test]
+    fn lists_own_clips_by_tag() {
+        let config = config();
+        let database = config.database.clone();
+        let client = Client::tracked(crate::rocket(config)).expect("valid rocket instance");
+        let new_key = || Header::new(API_KEY_HEADER, block_on(action::generate_api_key(database.repository())).unwrap().to_base64());
+        let (mine, theirs) = (new_key(), new_key());
+        let post = |key: &Header<'static>, title: &str, password: &str, tags: &str| {
+            let body = format!(r#"{{"content":"x","title":"{}","expires":null,"password":"{}","tags":{}}}"#, title, password, tags);
+            assert_eq!(client.post("/api/v1/clip").header(key.clone()).body(body).dispatch().status(), Status::Ok);
+        };
+        post(&mine, "failover", "", r#"["Ops","db"]"#);
+        post(&mine, "keys", "hunter2", r#"["ops"]"#);
+        post(&mine, "notes", "", "[]");
+        post(&theirs, "theirs", "", r#"["ops"]"#);
+        let response = client.post("/api/v1/clip").header(mine.clone())
+            .body(r#"{"content":"x","title":null,"expires":null,"password":null,"tags":["a/b"]}"#)
+            .dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let listed: serde_json::Value = client.get("/api/v1/clips?tag=OPS").header(mine.clone()).dispatch().into_json().unwrap();
+        let mut clips = listed.as_array().unwrap().clone();
+        // posted in the same second, so in no particular order
+        clips.sort_by_key(|clip| clip["title"].as_str().unwrap().to_owned());
+        assert_eq!((clips[0]["title"].as_str(), clips[1]["title"].as_str()), (Some("failover"), Some("keys")));
+        assert_eq!(clips[0]["tags"], serde_json::json!(["db", "ops"]));
+        assert!(clips[0].get("content").is_none());
+        let listed: serde_json::Value = client.get("/api/v1/clips").header(mine.clone()).dispatch().into_json().unwrap();
+        assert_eq!(listed.as_array().unwrap().len(), 3);
+
+        for query in ["tag=a%2Fb", "limit=0"] {
+            let response = client.get(format!("/api/v1/clips?{}", query)).header(mine.clone()).dispatch();
+            assert_eq!(response.into_json::<ErrorEnvelope>().unwrap().error.code, ErrorCode::InvalidParameter);
+        }
+        assert_eq!(client.get("/api/clips").header(mine).dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn private_clips_need_their_api_key() {
+        let config = config();
+        let database = config.database.clone();
+        let client = Client::tracked(crate::rocket(config)).expect("valid rocket instance");
+        let new_key = || Header::new(API_KEY_HEADER, block_on(action::generate_api_key(database.repository())).unwrap().to_base64());
+        let (mine, theirs) = (new_key(), new_key());
+        let clip: serde_json::Value = client.post("/api/v1/clip").header(mine.clone())
+            .body(r#"{"content":"x","title":null,"expires":null,"password":null,"visibility":"private"}"#)
+            .dispatch()
+            .into_json()
+            .unwrap();
+        assert_eq!(clip["visibility"], "private");
+        let url = format!("/api/v1/clip/{}", clip["shortcode"].as_str().unwrap());
+
+        assert_eq!(client.get(url.as_str()).header(mine).dispatch().status(), Status::Ok);
+        let response = client.get(url.as_str()).header(theirs).dispatch();
+        assert_eq!(response.status(), Status::Forbidden);
+        assert_eq!(response.into_json::<ErrorEnvelope>().unwrap().error.code, ErrorCode::Forbidden);
+        assert_eq!(client.get(format!("{}/events", url)).dispatch().status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn errors_use_the_envelope() {
+        let config = config();
+        let database = config.database.clone();
+        let client = Client::tracked(crate::rocket(config)).expect("valid rocket instance");
+        let key = Header::new(API_KEY_HEADER, block_on(action::generate_api_key(database.repository())).unwrap().to_base64());
+        let error = |response: rocket::local::blocking::LocalResponse| -> (Status, ApiError) {
+            (response.status(), response.into_json::<ErrorEnvelope>().expect("error envelope").error)
+        };
+
+        let (status, e) = error(client.get("/api/v1/clip/nothing").dispatch());
+        assert_eq!((status, e.code), (Status::Unauthorized, ErrorCode::MissingApiKey));
+        let (status, e) = error(client.get("/api/v1/clip/nothing").header(Header::new(API_KEY_HEADER, "%%%")).dispatch());
+        assert_eq!((status, e.code), (Status::Unauthorized, ErrorCode::InvalidApiKey));
+        let (status, e) = error(client.get("/api/v1/clip/nothing").header(key.clone()).dispatch());
+        assert_eq!((status, e.code), (Status::NotFound, ErrorCode::NotFound));
+        let (status, e) = error(client.get("/api/v1/no/such/route").dispatch());
+        assert_eq!((status, e.code), (Status::NotFound, ErrorCode::NotFound));
+
+        let post = |body: &str| client.post("/api/v1/clip").header(key.clone()).body(body).dispatch();
+        let (status, e) = error(post("{"));
+        assert_eq!((status, e.code), (Status::BadRequest, ErrorCode::BadRequest));
+        let (status, e) = error(post(r#"{"content":"no title"}"#));
+        assert_eq!((status, e.code), (Status::UnprocessableEntity, ErrorCode::InvalidBody));
+        assert!(e.details.unwrap()["reason"].as_str().unwrap().contains("title"));
+        let (status, e) = error(post(r#"{"content":"","title":null,"expires":null,"password":null}"#));
+        assert_eq!((status, e.code), (Status::UnprocessableEntity, ErrorCode::InvalidClip));
+
+        let response = post(r#"{"content":"secret","title":null,"expires":null,"password":"hunter2"}"#);
+        let clip: crate::Clip = response.into_json().unwrap();
+        let (status, e) = error(client.get(format!("/api/v1/clip/{}", clip.shortcode.as_str())).header(key.clone()).dispatch());
+        assert_eq!((status, e.code), (Status::Forbidden, ErrorCode::InvalidPassword));
+
+        // the unversioned alias answers the same, marked as deprecated
+        let response = client.get(format!("/api/clip/{}", clip.shortcode.as_str())).header(key).dispatch();
+        assert_eq!(response.headers().get_one("Deprecation"), Some("true"));
+        let successor = format!("</api/v1/clip/{}>; rel=\"successor-version\"", clip.shortcode.as_str());
+        assert_eq!(response.headers().get_one("Link"), Some(successor.as_str()));
+        assert_eq!(error(response).1.code, ErrorCode::InvalidPassword);
+    }
+
+    #[test]
+    fn conditional_reads_and_updates() {
+        let config = config();
+        let database = config.database.clone();
+        let client = Client::tracked(crate::rocket(config)).expect("valid rocket instance");
+        let key = Header::new(API_KEY_HEADER, block_on(action::generate_api_key(database.repository())).unwrap().to_base64());
+        let body = r#"{"content":"draft","title":null,"expires":null,"password":null}"#;
+        let clip: crate::Clip = client.post("/api/v1/clip").header(key.clone()).body(body).dispatch().into_json().unwrap();
+        let url = format!("/api/v1/clip/{}", clip.shortcode.as_str());
+
+        let response = client.get(url.as_str()).header(key.clone()).dispatch();
+        let etag = response.headers().get_one("ETag").unwrap().to_owned();
+        assert_eq!(etag, "\"1\"");
+        assert!(response.headers().get_one("Last-Modified").unwrap().ends_with(" GMT"));
+        let response = client.get(url.as_str()).header(key.clone()).header(Header::new("If-None-Match", etag.clone())).dispatch();
+        assert_eq!(response.status(), Status::NotModified);
+
+        let update = format!(r#"{{"shortcode":"{}","content":"final","title":null,"expires":null,"password":null}}"#, clip.shortcode.as_str());
+        let put = |if_match: Option<&str>| {
+            let request = client.put("/api/v1/clip").header(key.clone()).body(update.clone());
+            match if_match {
+                Some(tag) => request.header(Header::new("If-Match", tag.to_owned())).dispatch(),
+                None => request.dispatch(),
+            }
+        };
+        assert_eq!(put(None).status(), Status::PreconditionRequired);
+        let response = put(Some(&etag));
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.headers().get_one("ETag"), Some("\"2\""));
+
+        // a second writer still holding revision 1 must not overwrite revision 2
+        let response = put(Some(&etag));
+        assert_eq!(response.status(), Status::PreconditionFailed);
+        let error = response.into_json::<ErrorEnvelope>().unwrap().error;
+        assert_eq!(error.details.unwrap()["etag"], "\"2\"");
+        let response = client.get(url.as_str()).header(key).header(Header::new("If-None-Match", etag)).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+    }
+
+    #[test]
+    fn not_modified_reads_are_not_views() {
+        use crate::domain::webhook::ClipEvent;
+        use crate::web::hitcounter::HitCounter;
+
+        let mut config = config();
+        let database = config.database.clone();
+        config.hit_counter = HitCounter::new(
+            database.clone(), config.changes.clone(), crate::web::test::runtime().handle().clone(), std::time::Duration::from_millis(10));
+        let client = Client::tracked(crate::rocket(config)).expect("valid rocket instance");
+        let api_key = block_on(action::generate_api_key(database.repository())).unwrap();
+        let hook = crate::service::ask::NewWebhook { url: "http://93.184.216.34/hook".to_owned(), events: vec![ClipEvent::Viewed] };
+        block_on(action::new_webhook(hook, &api_key.id(), &Default::default(), database.repository())).unwrap();
+        let key = Header::new(API_KEY_HEADER, api_key.to_base64());
+        let body = r#"{"content":"draft","title":null,"expires":null,"password":null}"#;
+        let clip: crate::Clip = client.post("/api/v1/clip").header(key.clone()).body(body).dispatch().into_json().unwrap();
+        let url = format!("/api/v1/clip/{}", clip.shortcode.as_str());
+        let views = || {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            let clip = block_on(action::refresh_clip(clip.shortcode.clone().into(), database.repository())).unwrap();
+            let events = block_on(database.repository().due_deliveries(chrono::Utc::now().timestamp(), 10)).unwrap();
+            (clip.hits.into_inner(), events.len())
+        };
+
+        let etag = client.get(url.as_str()).header(key.clone()).dispatch().headers().get_one("ETag").unwrap().to_owned();
+        assert_eq!(views(), (1, 1));
+        let response = client.get(url.as_str()).header(key).header(Header::new("If-None-Match", etag)).dispatch();
+        assert_eq!(response.status(), Status::NotModified);
+        assert_eq!(views(), (1, 1));
+    }
+
+    #[test]
+    fn open_clips_receive_changes() {
+        use std::io::{BufRead, BufReader};
+
+        let config = config();
+        let (database, changes) = (config.database.clone(), config.changes.clone());
+        let client = Client::tracked(crate::rocket(config)).expect("valid rocket instance");
+        let new = serde_json::from_str(r#"{"content":"v1","title":null,"expires":null,"password":null}"#).unwrap();
+        let clip = block_on(action::new_clip(new, &Default::default(), database.repository())).unwrap();
+        let url = format!("/api/v1/clip/{}/events", clip.shortcode.as_str());
+
+        let response = client.get(url.as_str()).dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(rocket::http::ContentType::EventStream));
+        let mut events = BufReader::new(response).lines().map(Result::unwrap);
+        let mut next_event = || {
+            let event = events.by_ref().find(|line| line.starts_with("event:"))?;
+            let data = events.by_ref().find(|line| line.starts_with("data:")).unwrap_or_default();
+            Some((event, data))
+        };
+
+        let update = format!(r#"{{"shortcode":"{}","content":"v2","title":null,"expires":null,"password":null}}"#, clip.shortcode.as_str());
+        block_on(action::update_clip(serde_json::from_str(&update).unwrap(), &Default::default(), &changes, database.repository())).unwrap();
+        let (event, data) = next_event().unwrap();
+        assert_eq!(event, "event:clip");
+        assert!(data.contains(r#""content":"v2""#));
+
+        block_on(action::delete_clip(&clip.shortcode, &changes, database.repository())).unwrap();
+        assert_eq!(next_event().unwrap().0, "event:deleted");
+        assert_eq!(next_event(), None);
+
+        assert_eq!(client.get(url.as_str()).dispatch().status(), Status::NotFound);
     }
 }
\ No newline at end of file
@@ -0,0 +1,281 @@
+//! The WebSocket of a clip's live editing session, only under `/api/v1`.
+
+use std::io;
+use std::pin::Pin;
+use futures::{SinkExt, StreamExt};
+use rocket::data::{IoHandler, IoStream};
+use rocket::http::CookieJar;
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder};
+use rocket::{Request, Response, Shutdown, State};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Role, WebSocketConfig};
+use tokio_tungstenite::tungstenite::{self, Message};
+use tokio_tungstenite::WebSocketStream;
+use crate::domain::clip::field::Owner;
+use crate::service::collab::{self, Incoming, Membership, Outgoing, Sessions};
+use crate::service::ask;
+use crate::web::api::{cookie_password, ApiError, ApiKey, ErrorCode};
+
+/// A request to switch to the WebSocket protocol.
+pub struct WebSocketUpgrade {
+    accept: String,
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WebSocketUpgrade {
+    type Error = ApiError;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let headers = req.headers();
+        let upgrade = headers.get("Upgrade").any(|protocol| protocol.eq_ignore_ascii_case("websocket"));
+        match (upgrade, headers.get_one("Sec-WebSocket-Key"), headers.get_one("Sec-WebSocket-Version")) {
+            (true, Some(key), Some("13")) => Outcome::Success(Self { accept: derive_accept_key(key.as_bytes()) }),
+            _ => ApiError::new(ErrorCode::BadRequest, "this route only accepts WebSocket connections").into_outcome(req),
+        }
+    }
+}
+
+pub struct EditChannel {
+    accept: String,
+    membership: Membership,
+    shutdown: Shutdown,
+}
+
+impl<'r> Responder<'r, 'static> for EditChannel {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        Response::build()
+            .raw_header("Sec-WebSocket-Accept", self.accept.clone())
+            .upgrade("websocket", self)
+            .ok()
+    }
+}
+
+#[rocket::async_trait]
+impl IoHandler for EditChannel {
+    async fn io(self: Pin<Box<Self>>, io: IoStream) -> io::Result<()> {
+        let EditChannel { membership, shutdown, .. } = *Pin::into_inner(self);
+        let session = membership.session.clone();
+        let editor = membership.editor.id;
+        let config = WebSocketConfig { max_message_size: Some(2 * collab::MAX_DOCUMENT_BYTES), ..Default::default() };
+        let socket = WebSocketStream::from_raw_socket(io, Role::Server, Some(config)).await;
+        let result = relay(socket, membership, shutdown).await;
+        session.leave(editor).await;
+        result.map_err(io::Error::other)
+    }
+}
+
+fn text(message: &Outgoing) -> Message {
+    Message::Text(serde_json::to_string(message).expect("outgoing messages serialize"))
+}
+
+/// Passes edits from the editor to the session, and everything the session broadcasts back.
+async fn relay(mut socket: WebSocketStream<IoStream>, membership: Membership, mut shutdown: Shutdown) -> Result<(), tungstenite::Error> {
+    let Membership { session, editor, init, mut receiver } = membership;
+    socket.send(text(&init)).await?;
+    loop {
+        tokio::select! {
+            message = socket.next() => match message {
+                Some(Ok(Message::Text(message))) => {
+                    let result = match serde_json::from_str(&message) {
+                        Ok(Incoming::Edit { revision, splices }) => session.submit(editor.id, revision, splices).map_err(|e| e.to_string()),
+                        Err(e) => Err(format!("unreadable message: {}", e)),
+                    };
+                    if let Err(message) = result {
+                        socket.send(text(&Outgoing::Rejected { message })).await?;
+                    }
+                }
+                Some(Ok(Message::Close(_))) | None => return Ok(()),
+                // pings are answered by tungstenite
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return Err(e),
+            },
+            outgoing = receiver.recv() => match outgoing {
+                Ok(outgoing @ Outgoing::Closed { .. }) => {
+                    socket.send(text(&outgoing)).await?;
+                    return socket.close(None).await;
+                }
+                Ok(outgoing) => socket.send(text(&outgoing)).await?,
+                // with an edit missed the editor's copy has diverged, so it has to join again
+                Err(RecvError::Lagged(_)) => {
+                    let frame = CloseFrame { code: CloseCode::Again, reason: "fell behind the session".into() };
+                    return socket.close(Some(frame)).await;
+                }
+                Err(RecvError::Closed) => return Ok(()),
+            },
+            _ = &mut shutdown => return socket.close(Some(CloseFrame { code: CloseCode::Away, reason: "".into() })).await,
+        }
+    }
+}
+
+/// Joins the live editing session of a clip. Messages are JSON: the server sends `init`, `edit`,
+/// `presence`, `saved`, `rejected` and `closed`, and accepts `edit`.
+#[utoipa::path(
+    get,
+    path = "/api/v1/clip/{shortcode}/edit",
+    tag = "clips",
+    params(
+        ("shortcode" = String, Path, description = "clip shortcode"),
+        ("name" = Option<String>, Query, description = "the name shown to other editors"),
+        ("password" = Option<String>, Cookie, description = "password of a protected clip"),
+    ),
+    responses(
+        (status = 101, description = "Switched to the WebSocket of the editing session"),
+        (status = 400, description = "bad_request: not a WebSocket handshake", body = ErrorEnvelope),
+        (status = 401, description = "invalid_api_key", body = ErrorEnvelope),
+        (status = 403, description = "invalid_password: wrong password, end-to-end encrypted, or an unprotected clip edited without its API key", body = ErrorEnvelope),
+        (status = 404, description = "not_found: no clip with this shortcode", body = ErrorEnvelope),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+    security((), ("api_key" = [])),
+)]
+#[rocket::get("/<shortcode>/edit?<name>")]
+pub async fn edit_clip(
+    shortcode: &str,
+    name: Option<&str>,
+    upgrade: WebSocketUpgrade,
+    sessions: &State<Sessions>,
+    cookie: &CookieJar<'_>,
+    api_key: Result<ApiKey, ApiError>,
+    shutdown: Shutdown
+) -> Result<EditChannel, ApiError> {
+    let owner = match api_key {
+        Ok(key) => Some(key.id()),
+        Err(e) if e.code == ErrorCode::MissingApiKey => None,
+        Err(e) => return Err(e),
+    };
+    let req = ask::JoinEdit {
+        shortcode: shortcode.into(),
+        password: cookie_password(cookie),
+        owner: Owner::new(owner),
+        name: name.unwrap_or_default().to_owned(),
+    };
+    let membership = sessions.join(req).await?;
+    Ok(EditChannel { accept: upgrade.accept, membership, shutdown })
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![edit_clip]
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::domain::clip::field::Password;
+    use crate::service::{action, ask};
+    use crate::service::collab::Outgoing;
+    use crate::web::test::config;
+    use futures::{SinkExt, StreamExt};
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+    use std::time::Duration;
+    use tokio::net::TcpStream;
+    use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+    use tokio_tungstenite::tungstenite::Message;
+
+    fn handshake() -> [Header<'static>; 3] {
+        [
+            Header::new("Upgrade", "websocket"),
+            Header::new("Sec-WebSocket-Key", "dGhlIHNhbXBsZSBub25jZQ=="),
+            Header::new("Sec-WebSocket-Version", "13"),
+        ]
+    }
+
+    #[test]
+    fn only_authorized_editors_join() {
+        let config = config();
+        let database = config.database.clone();
+        let client = Client::tracked(crate::rocket(config)).expect("valid rocket instance");
+        let new = serde_json::from_str(r#"{"content":"notes","title":null,"expires":null,"password":null}"#).unwrap();
+        let clip = crate::web::test::block_on(action::new_clip(new, &Default::default(), database.repository())).unwrap();
+        let url = format!("/api/v1/clip/{}/edit", clip.shortcode.as_str());
+
+        assert_eq!(client.get(url.as_str()).dispatch().status(), Status::BadRequest);
+        let mut request = client.get(url.as_str());
+        for header in handshake() {
+            request = request.header(header);
+        }
+        // an unprotected clip without the API key that created it
+        assert_eq!(request.dispatch().status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn concurrent_edits_are_merged_and_saved() {
+        let mut config = config();
+        let database = config.database.clone();
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        config.figment = config.figment.merge(("port", port));
+        let runtime = crate::test::async_runtime();
+
+        runtime.block_on(async {
+            let rocket = crate::rocket(config).ignite().await.unwrap();
+            let shutdown = rocket.shutdown();
+            tokio::spawn(rocket.launch());
+
+            let new = ask::NewClip {
+                content: crate::domain::clip::field::Content::new("status: investigating").unwrap(),
+                title: Default::default(),
+                expires: Default::default(),
+                password: Password::new("incident".to_owned()).unwrap(),
+                encrypted: Default::default(),
+                owner: Default::default(),
+                parent: Default::default(),
+                visibility: Default::default(),
+                tags: Default::default(),
+            };
+            let clip = action::new_clip(new, &Default::default(), database.repository()).await.unwrap();
+            let connect = |name: &'static str| {
+                let url = format!("ws://127.0.0.1:{}/api/v1/clip/{}/edit?name={}", port, clip.shortcode.as_str(), name);
+                async move {
+                    let mut request = url.into_client_request().unwrap();
+                    request.headers_mut().insert("Cookie", "password=incident".parse().unwrap());
+                    for _ in 0..50 {
+                        if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)).await {
+                            return tokio_tungstenite::client_async(request, stream).await.unwrap().0;
+                        }
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                    }
+                    panic!("the server did not start");
+                }
+            };
+            let mut alice = connect("alice").await;
+            let mut bob = connect("bob").await;
+            let init = match bob.next().await {
+                Some(Ok(Message::Text(text))) => serde_json::from_str(&text).unwrap(),
+                other => panic!("unexpected message {:?}", other),
+            };
+            let Outgoing::Init { revision: 0, editors, .. } = init else { panic!("expected init, got {:?}", init) };
+            let names: Vec<_> = editors.iter().map(|editor| editor.name.as_str()).collect();
+            assert_eq!(names, ["alice", "bob"]);
+
+            // both edit revision 0 without having seen the other's edit
+            let edit = |revision: u64, splice: &str| Message::Text(format!(r#"{{"type":"edit","revision":{},"splices":[{}]}}"#, revision, splice));
+            alice.send(edit(0, r#"{"at":0,"insert":"09:14 "}"#)).await.unwrap();
+            bob.send(edit(0, r#"{"at":8,"delete":13,"insert":"mitigated"}"#)).await.unwrap();
+
+            let mut edits = 0;
+            while edits < 2 {
+                if let Some(Ok(Message::Text(text))) = bob.next().await {
+                    if let Outgoing::Edit { .. } = serde_json::from_str(&text).unwrap() {
+                        edits += 1;
+                    }
+                }
+            }
+            alice.close(None).await.unwrap();
+            bob.close(None).await.unwrap();
+
+            let get = ask::GetClip { shortcode: clip.shortcode.clone(), password: Password::new("incident".to_owned()).unwrap(), viewer: Default::default() };
+            let mut content = String::new();
+            for _ in 0..50 {
+                content = action::refresh_clip(get.clone(), database.repository()).await.unwrap().content.into_inner();
+                if content != "status: investigating" {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            assert_eq!(content, "09:14 status: mitigated");
+            shutdown.notify();
+        });
+    }
+}
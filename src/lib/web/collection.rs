@@ -0,0 +1,189 @@
+//! Collections of clips and their archives, only under `/api/v1`.
+
+use std::io::Cursor;
+use std::str::FromStr;
+use rocket::http::{ContentType, Status};
+use rocket::response::{self, status, Responder};
+use rocket::serde::json::{self, Json};
+use rocket::{Request, Response, State};
+use crate::data::AppDatabase;
+use crate::domain::clip::field::{Owner, ShortCodePolicy};
+use crate::domain::collection::{ArchiveFormat, Collection};
+use crate::service::{action, ask, ServiceError};
+use crate::web::api::{ApiError, ApiKey, ErrorCode};
+use crate::ShortCode;
+
+/// The clips of a collection as one tar or zip file.
+pub struct Archive {
+    filename: String,
+    format: ArchiveFormat,
+    body: Vec<u8>,
+}
+
+impl Archive {
+    pub fn new(collection: &Collection, format: ArchiveFormat) -> Result<Self, ServiceError> {
+        Ok(Self {
+            filename: format!("{}.{}", collection.shortcode.as_str(), format),
+            format,
+            body: collection.archive(format).map_err(|e| ServiceError::Archive(e.to_string()))?,
+        })
+    }
+}
+
+impl<'r> Responder<'r, 'static> for Archive {
+    fn respond_to(self, _: &'r Request<'_>) -> response::Result<'static> {
+        let content_type = ContentType::parse_flexible(self.format.media_type()).unwrap_or(ContentType::Binary);
+        Response::build()
+            .header(content_type)
+            .raw_header("Content-Disposition", format!("attachment; filename=\"{}\"", self.filename))
+            .sized_body(self.body.len(), Cursor::new(self.body))
+            .ok()
+    }
+}
+
+/// Reads the `format` query parameter; tar unless given.
+pub fn archive_format(format: Option<&str>) -> Result<ArchiveFormat, ApiError> {
+    format.map_or(Ok(ArchiveFormat::default()), |format| {
+        ArchiveFormat::from_str(format).map_err(|_| {
+            ApiError::new(ErrorCode::InvalidParameter, "format must be tar or zip")
+                .with_details(serde_json::json!({ "parameter": "format" }))
+        })
+    })
+}
+
+/// Groups existing clips, and new clips made from files, under one shortcode.
+#[utoipa::path(
+    post,
+    path = "/api/v1/collections",
+    tag = "collections",
+    request_body = NewCollection,
+    responses(
+        (status = 201, description = "The collection with its clips", body = Collection),
+        (status = 400, description = "bad_request: malformed JSON", body = ErrorEnvelope),
+        (status = 401, description = "missing_api_key, invalid_api_key", body = ErrorEnvelope),
+        (status = 413, description = "payload_too_large", body = ErrorEnvelope),
+        (status = 422, description = "invalid_body, invalid_clip, invalid_collection: no items, too many, or a clip that is missing or protected", body = ErrorEnvelope),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+    security(("api_key" = [])),
+)]
+#[rocket::post("/collections", data = "<req>")]
+pub async fn new_collection(
+    req: Result<Json<ask::NewCollection>, json::Error<'_>>,
+    database: &State<AppDatabase>,
+    policy: &State<ShortCodePolicy>,
+    api_key: ApiKey
+) -> Result<status::Custom<Json<Collection>>, ApiError> {
+    let mut req = req?.into_inner();
+    req.owner = Owner::new(api_key.id());
+    let collection = action::new_collection(req, policy, database.repository()).await?;
+    Ok(status::Custom(Status::Created, Json(collection)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/collections/{shortcode}",
+    tag = "collections",
+    params(("shortcode" = String, Path, description = "collection shortcode")),
+    responses(
+        (status = 200, description = "The collection with its clips in order", body = Collection),
+        (status = 401, description = "missing_api_key, invalid_api_key", body = ErrorEnvelope),
+        (status = 404, description = "not_found: no collection with this shortcode", body = ErrorEnvelope),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+    security(("api_key" = [])),
+)]
+#[rocket::get("/collections/<shortcode>")]
+pub async fn get_collection(shortcode: &str, database: &State<AppDatabase>, _api_key: ApiKey) -> Result<Json<Collection>, ApiError> {
+    Ok(Json(action::get_collection(&ShortCode::from(shortcode), database.repository()).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/collections/{shortcode}/archive",
+    tag = "collections",
+    params(
+        ("shortcode" = String, Path, description = "collection shortcode"),
+        ("format" = Option<String>, Query, description = "tar (the default) or zip"),
+    ),
+    responses(
+        (status = 200, description = "Every clip of the collection as a file named after its title", content_type = "application/x-tar", body = Vec<u8>),
+        (status = 401, description = "missing_api_key, invalid_api_key", body = ErrorEnvelope),
+        (status = 404, description = "not_found: no collection with this shortcode", body = ErrorEnvelope),
+        (status = 422, description = "invalid_parameter: an unknown format", body = ErrorEnvelope),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+    security(("api_key" = [])),
+)]
+#[rocket::get("/collections/<shortcode>/archive?<format>")]
+pub async fn collection_archive(
+    shortcode: &str,
+    format: Option<&str>,
+    database: &State<AppDatabase>,
+    _api_key: ApiKey
+) -> Result<Archive, ApiError> {
+    let format = archive_format(format)?;
+    let collection = action::get_collection(&ShortCode::from(shortcode), database.repository()).await?;
+    Ok(Archive::new(&collection, format)?)
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![new_collection, get_collection, collection_archive]
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::service::action;
+    use crate::web::api::{ErrorCode, ErrorEnvelope, API_KEY_HEADER};
+    use crate::web::test::config;
+    use crate::web::test::block_on;
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+    use serde_json::Value;
+
+    #[test]
+    fn collections_are_shared_and_downloaded() {
+        let config = config();
+        let database = config.database.clone();
+        let client = Client::tracked(crate::rocket(config)).expect("valid rocket instance");
+        let key = Header::new(API_KEY_HEADER, block_on(action::generate_api_key(database.repository())).unwrap().to_base64());
+        let new = serde_json::from_str(r#"{"content":"RUST_LOG=debug","title":".env","expires":null,"password":null}"#).unwrap();
+        let clip = block_on(action::new_clip(new, &Default::default(), database.repository())).unwrap();
+        let protected = serde_json::from_str(r#"{"content":"secret","title":null,"expires":null,"password":"pw"}"#).unwrap();
+        let protected = block_on(action::new_clip(protected, &Default::default(), database.repository())).unwrap();
+
+        let body = format!(r#"{{"items":[{{"shortcode":"{}"}}]}}"#, protected.shortcode.as_str());
+        let response = client.post("/api/v1/collections").header(key.clone()).body(body).dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert_eq!(response.into_json::<ErrorEnvelope>().unwrap().error.code, ErrorCode::InvalidCollection);
+
+        let body = format!(
+            r#"{{"title":"repro","items":[{{"shortcode":"{}"}},{{"name":"main.rs","content":"fn main() {{}}"}}]}}"#,
+            clip.shortcode.as_str()
+        );
+        assert_eq!(client.post("/api/v1/collections").body(body.as_str()).dispatch().status(), Status::Unauthorized);
+        let response = client.post("/api/v1/collections").header(key.clone()).body(body).dispatch();
+        assert_eq!(response.status(), Status::Created);
+        let created: Value = response.into_json().unwrap();
+        let shortcode = created["shortcode"].as_str().unwrap();
+        let titles: Vec<_> = created["clips"].as_array().unwrap().iter().map(|clip| clip["title"].clone()).collect();
+        assert_eq!(titles, [".env", "main.rs"]);
+
+        let fetched: Value = client.get(format!("/api/v1/collections/{}", shortcode)).header(key.clone()).dispatch().into_json().unwrap();
+        assert_eq!(fetched, created);
+        let response = client.get(format!("/api/v1/collections/{}/archive?format=rar", shortcode)).header(key).dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let page = client.get(format!("/collection/{}", shortcode)).dispatch().into_string().unwrap();
+        assert!(page.contains("repro") && page.contains(&format!(r#"href="/clip/{}""#, clip.shortcode.as_str())));
+        let response = client.get(format!("/collection/{}/download?format=zip", shortcode)).dispatch();
+        assert_eq!(response.headers().get_one("Content-Type"), Some("application/zip"));
+        assert_eq!(
+            response.headers().get_one("Content-Disposition").unwrap(),
+            format!(r#"attachment; filename="{}.zip""#, shortcode)
+        );
+        let zip = zip::ZipArchive::new(std::io::Cursor::new(response.into_bytes().unwrap())).unwrap();
+        assert_eq!(zip.file_names().collect::<std::collections::BTreeSet<_>>(), ["env", "main.rs"].into());
+        assert_eq!(client.get("/collection/missing").dispatch().status(), Status::NotFound);
+    }
+}
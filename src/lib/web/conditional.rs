@@ -0,0 +1,132 @@
+//! Conditional requests on clips: `ETag` and `Last-Modified` on reads, `If-None-Match` for
+//! revalidation and `If-Match` for updates that must not overwrite someone else's change.
+
+use rocket::http::{Header, Status};
+use rocket::request::{FromRequest, Outcome, Request};
+use rocket::response::{self, Responder, Response};
+use crate::domain::clip::field::Revision;
+use crate::Clip;
+
+/// The strong entity tag of a clip revision.
+pub fn etag(revision: Revision) -> String {
+    format!("\"{}\"", revision.into_inner())
+}
+
+/// Tags of an `If-Match` or `If-None-Match` header.
+fn tags(header: &str) -> impl Iterator<Item = &str> {
+    header.split(',').map(str::trim).filter(|tag| !tag.is_empty())
+}
+
+/// The conditional headers of a request; never fails.
+#[derive(Debug, Default)]
+pub struct Preconditions {
+    if_match: Option<String>,
+    if_none_match: Option<String>,
+}
+
+impl Preconditions {
+    /// Whether the client already has this revision, compared weakly as RFC 9110 asks for `If-None-Match`.
+    pub fn is_fresh(&self, revision: Revision) -> bool {
+        let current = etag(revision);
+        self.if_none_match.as_deref().is_some_and(|header| {
+            tags(header).any(|tag| tag == "*" || tag.trim_start_matches("W/") == current)
+        })
+    }
+
+    /// The revision an update expects from `If-Match`: `None` without the header, `Some(None)` for `*`.
+    ///
+    /// Weak or unknown tags yield revision 0, which no clip has, so the update fails its precondition.
+    pub fn expected_revision(&self) -> Option<Option<Revision>> {
+        let header = self.if_match.as_deref()?;
+        if tags(header).any(|tag| tag == "*") {
+            return Some(None);
+        }
+        let revision = tags(header)
+            .find_map(|tag| tag.strip_prefix('"')?.strip_suffix('"')?.parse().ok())
+            .unwrap_or(0);
+        Some(Some(Revision::new(revision)))
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Preconditions {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let header = |name| req.headers().get(name).collect::<Vec<_>>().join(", ");
+        let header = |name| Some(header(name)).filter(|value| !value.is_empty());
+        Outcome::Success(Self {
+            if_match: header("If-Match"),
+            if_none_match: header("If-None-Match"),
+        })
+    }
+}
+
+/// `body` sent with a clip's `ETag` and `Last-Modified`, or `304 Not Modified` in its place.
+pub struct Conditional<R> {
+    body: R,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    not_modified: bool,
+}
+
+impl<R> Conditional<R> {
+    pub fn new(body: R) -> Self {
+        Self { body, etag: None, last_modified: None, not_modified: false }
+    }
+
+    pub fn with_clip(body: R, clip: &Clip) -> Self {
+        let last_modified = clip.updated.clone().into_inner().into_inner();
+        Self {
+            body,
+            etag: Some(etag(clip.revision)),
+            last_modified: Some(last_modified.format("%a, %d %b %Y %H:%M:%S GMT").to_string()),
+            not_modified: false,
+        }
+    }
+
+    /// Replies `304 Not Modified` if the request's `If-None-Match` names the clip's revision.
+    pub fn unless_fresh(mut self, preconditions: &Preconditions, clip: &Clip) -> Self {
+        self.not_modified = preconditions.is_fresh(clip.revision);
+        self
+    }
+}
+
+impl<'r, 'o: 'r, R: Responder<'r, 'o>> Responder<'r, 'o> for Conditional<R> {
+    fn respond_to(self, req: &'r Request<'_>) -> response::Result<'o> {
+        let mut response = match self.not_modified {
+            true => Response::build().status(Status::NotModified).finalize(),
+            false => self.body.respond_to(req)?,
+        };
+        if let Some(etag) = self.etag {
+            response.set_header(Header::new("ETag", etag));
+        }
+        if let Some(last_modified) = self.last_modified {
+            response.set_header(Header::new("Last-Modified", last_modified));
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    fn preconditions(if_match: Option<&str>, if_none_match: Option<&str>) -> Preconditions {
+        Preconditions { if_match: if_match.map(str::to_owned), if_none_match: if_none_match.map(str::to_owned) }
+    }
+
+    #[test]
+    fn parses_conditional_headers() {
+        let rev = Revision::new;
+        assert!(preconditions(None, Some(r#""1", W/"3""#)).is_fresh(rev(3)));
+        assert!(preconditions(None, Some("*")).is_fresh(rev(7)));
+        assert!(!preconditions(None, Some(r#""2""#)).is_fresh(rev(3)));
+        assert!(!preconditions(None, None).is_fresh(rev(1)));
+
+        assert_eq!(preconditions(None, None).expected_revision(), None);
+        assert_eq!(preconditions(Some("*"), None).expected_revision(), Some(None));
+        assert_eq!(preconditions(Some(r#""4""#), None).expected_revision(), Some(Some(rev(4))));
+        assert_eq!(preconditions(Some(r#"W/"4""#), None).expected_revision(), Some(Some(rev(0))));
+    }
+}
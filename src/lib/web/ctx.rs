@@ -1,5 +1,7 @@
 use derive_more::Constructor;
 use serde::Serialize;
+use crate::domain::collection::Collection;
+use crate::service::{ListedClip, Provenance};
 
 pub trait PageContext {
     fn title(&self) -> &str;
@@ -7,18 +9,26 @@ pub trait PageContext {
     fn parent(&self) -> &str;
 }
 
-#[derive(Debug, Serialize)]
-pub struct Home {}
+#[derive(Debug, Serialize, Default)]
+pub struct Home {
+    /// The clip being forked, whose content the form starts with.
+    pub fork_of: Option<crate::ShortCode>,
+    /// The content being forked is end-to-end encrypted, and decrypted in the browser.
+    pub fork_encrypted: bool,
+}
 
-impl Default for Home {
-    fn default() -> Self {
-        Self {}
+impl Home {
+    pub fn fork(clip: &crate::Clip) -> Self {
+        Self { fork_of: Some(clip.shortcode.clone()), fork_encrypted: clip.encrypted.is_encrypted() }
     }
 }
 
 impl PageContext for Home {
     fn title(&self) -> &str {
-        "Home"
+        match self.fork_of {
+            Some(_) => "Fork clip",
+            None => "Home",
+        }
     }
 
     fn template_path(&self) -> &str {
@@ -30,9 +40,19 @@ impl PageContext for Home {
     }
 }
 
-#[derive(Debug, Serialize, Constructor)]
+#[derive(Debug, Serialize)]
 pub struct ViewClip {
     pub clip: crate::Clip,
+    /// The viewer gave the clip's password, which lets them edit it live.
+    pub editable: bool,
+    pub provenance: Provenance,
+}
+
+impl ViewClip {
+    pub fn new(clip: crate::Clip, provenance: Provenance) -> Self {
+        let editable = clip.password.has_password() && !clip.encrypted.is_encrypted();
+        Self { clip, editable, provenance }
+    }
 }
 
 impl PageContext for ViewClip {
@@ -49,6 +69,86 @@ impl PageContext for ViewClip {
     }
 }
 
+/// A clip of a collection, under the name it has in the collection's archive.
+#[derive(Debug, Serialize)]
+pub struct CollectionFile {
+    pub name: String,
+    pub clip: crate::Clip,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ViewCollection {
+    pub collection: Collection,
+    pub files: Vec<CollectionFile>,
+}
+
+impl ViewCollection {
+    pub fn new(mut collection: Collection) -> Self {
+        let names = collection.file_names();
+        let files = names
+            .into_iter()
+            .zip(std::mem::take(&mut collection.clips))
+            .map(|(name, clip)| CollectionFile { name, clip })
+            .collect();
+        Self { collection, files }
+    }
+}
+
+impl PageContext for ViewCollection {
+    fn title(&self) -> &str {
+        "Collection"
+    }
+
+    fn template_path(&self) -> &str {
+        "collection"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
+
+/// The clips anyone may read with one tag.
+#[derive(Debug, Serialize, Constructor)]
+pub struct Tagged {
+    pub tag: String,
+    pub clips: Vec<ListedClip>,
+}
+
+impl PageContext for Tagged {
+    fn title(&self) -> &str {
+        "Tagged clips"
+    }
+
+    fn template_path(&self) -> &str {
+        "tag"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
+
+/// The newest public clips, also published as feeds.
+#[derive(Debug, Serialize, Constructor)]
+pub struct Recent {
+    pub clips: Vec<ListedClip>,
+}
+
+impl PageContext for Recent {
+    fn title(&self) -> &str {
+        "Recent clips"
+    }
+
+    fn template_path(&self) -> &str {
+        "recent"
+    }
+
+    fn parent(&self) -> &str {
+        "base"
+    }
+}
+
 #[derive(Debug, Serialize, Constructor)]
 pub struct PasswordRequired {
     shortcode: crate::ShortCode,
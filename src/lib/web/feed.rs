@@ -0,0 +1,168 @@
+//! Feeds of the newest public clips: Atom at `/recent.atom` and JSON Feed at `/recent.json`.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use rocket::http::ContentType;
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+use serde::Serialize;
+use crate::Clip;
+
+const FEED_TITLE: &str = "clipstash: recent clips";
+
+/// The scheme and host a request was made to, for the absolute links feeds need.
+pub struct SiteUrl(String);
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for SiteUrl {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        // behind a reverse proxy terminating TLS
+        let scheme = match req.headers().get_one("X-Forwarded-Proto") {
+            Some("https") => "https",
+            _ => "http",
+        };
+        let host = req.host().map_or_else(|| "localhost".to_owned(), |host| host.to_string());
+        Outcome::Success(Self(format!("{}://{}", scheme, host)))
+    }
+}
+
+/// A clip as an item of either feed.
+struct Entry<'a> {
+    url: String,
+    title: &'a str,
+    posted: DateTime<Utc>,
+    updated: DateTime<Utc>,
+    content: &'a str,
+    tags: &'a [String],
+}
+
+impl<'a> Entry<'a> {
+    fn new(site: &SiteUrl, clip: &'a Clip) -> Self {
+        Self {
+            url: format!("{}/clip/{}", site.0, clip.shortcode.as_str()),
+            title: clip.title.as_deref().unwrap_or(clip.shortcode.as_str()),
+            posted: clip.posted.clone().into_inner().into_inner(),
+            updated: clip.updated.clone().into_inner().into_inner(),
+            content: clip.content.as_str(),
+            tags: clip.tags.as_slice(),
+        }
+    }
+}
+
+fn rfc3339(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+/// Escapes text for XML, dropping the control characters XML 1.0 cannot hold at all.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\t' | '\n' | '\r' => escaped.push(c),
+            c if c.is_control() && c < ' ' => {}
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// An Atom 1.0 feed of `clips`, newest first.
+pub fn atom(site: &SiteUrl, clips: &[Clip]) -> (ContentType, String) {
+    let entries: Vec<_> = clips.iter().map(|clip| Entry::new(site, clip)).collect();
+    let updated = entries.iter().map(|entry| entry.updated).max().unwrap_or_else(Utc::now);
+    let mut xml = format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n",
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\">\n",
+            "  <title>{title}</title>\n",
+            "  <id>{site}/recent</id>\n",
+            "  <link rel=\"self\" type=\"application/atom+xml\" href=\"{site}/recent.atom\"/>\n",
+            "  <link rel=\"alternate\" type=\"text/html\" href=\"{site}/recent\"/>\n",
+            "  <updated>{updated}</updated>\n",
+            "  <author><name>clipstash</name></author>\n",
+        ),
+        title = FEED_TITLE,
+        site = xml_escape(&site.0),
+        updated = rfc3339(updated),
+    );
+    for entry in entries {
+        let url = xml_escape(&entry.url);
+        xml.push_str("  <entry>\n");
+        xml.push_str(&format!("    <title>{}</title>\n", xml_escape(entry.title)));
+        xml.push_str(&format!("    <id>{}</id>\n", url));
+        xml.push_str(&format!("    <link href=\"{}\"/>\n", url));
+        xml.push_str(&format!("    <published>{}</published>\n", rfc3339(entry.posted)));
+        xml.push_str(&format!("    <updated>{}</updated>\n", rfc3339(entry.updated)));
+        for tag in entry.tags {
+            xml.push_str(&format!("    <category term=\"{}\"/>\n", xml_escape(tag)));
+        }
+        xml.push_str(&format!("    <content type=\"text\">{}</content>\n", xml_escape(entry.content)));
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    (ContentType::new("application", "atom+xml"), xml)
+}
+
+#[derive(Serialize)]
+struct JsonFeed<'a> {
+    version: &'static str,
+    title: &'static str,
+    home_page_url: String,
+    feed_url: String,
+    items: Vec<JsonFeedItem<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonFeedItem<'a> {
+    id: String,
+    url: String,
+    title: &'a str,
+    content_text: &'a str,
+    date_published: String,
+    date_modified: String,
+    tags: &'a [String],
+}
+
+/// A JSON Feed 1.1 of `clips`, newest first.
+pub fn json_feed(site: &SiteUrl, clips: &[Clip]) -> (ContentType, String) {
+    let feed = JsonFeed {
+        version: "https://jsonfeed.org/version/1.1",
+        title: FEED_TITLE,
+        home_page_url: format!("{}/recent", site.0),
+        feed_url: format!("{}/recent.json", site.0),
+        items: clips
+            .iter()
+            .map(|clip| {
+                let entry = Entry::new(site, clip);
+                JsonFeedItem {
+                    id: entry.url.clone(),
+                    url: entry.url,
+                    title: entry.title,
+                    content_text: entry.content,
+                    date_published: rfc3339(entry.posted),
+                    date_modified: rfc3339(entry.updated),
+                    tags: entry.tags,
+                }
+            })
+            .collect(),
+    };
+    let json = serde_json::to_string(&feed).expect("feeds serialize");
+    (ContentType::new("application", "feed+json"), json)
+}
+
+#[cfg(test)]
+pub mod test {
+    use super::*;
+
+    #[test]
+    fn escapes_xml() {
+        assert_eq!(xml_escape("<a href=\"x\">Tom & Jerry's</a>"), "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&apos;s&lt;/a&gt;");
+        assert_eq!(xml_escape("bell\u{7}\ttab\nline"), "bell\ttab\nline");
+    }
+}
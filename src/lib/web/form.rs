@@ -8,6 +8,9 @@ pub struct NewClip {
     pub content: field::Content,
     pub password: field::Password,
     pub expires: field::Expires,
+    pub encrypted: field::Encrypted,
+    pub visibility: field::Visibility,
+    pub tags: field::Tags,
 }
 
 #[derive(Debug, Serialize, FromForm)]
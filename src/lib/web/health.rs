@@ -0,0 +1,116 @@
+use std::collections::BTreeMap;
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::Json;
+use rocket::State;
+use serde::Serialize;
+use crate::data::AppDatabase;
+use crate::domain::maintenance::Maintenance;
+use crate::service::action;
+use crate::web::hitcounter::HitCounter;
+
+#[derive(Debug, Serialize)]
+pub struct Health {
+    pub status: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Check {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl Check {
+    fn ok() -> Self {
+        Self { ok: true, detail: None }
+    }
+
+    fn failed<D: Into<String>>(detail: D) -> Self {
+        Self { ok: false, detail: Some(detail.into()) }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct Readiness {
+    pub status: &'static str,
+    pub checks: BTreeMap<&'static str, Check>,
+}
+
+impl Readiness {
+    pub fn is_ready(&self) -> bool {
+        self.checks.values().all(|check| check.ok)
+    }
+}
+
+#[rocket::get("/healthz")]
+pub fn healthz() -> Json<Health> {
+    Json(Health { status: "ok" })
+}
+
+#[rocket::get("/readyz")]
+pub async fn readyz(
+    database: &State<AppDatabase>,
+    hit_counter: &State<HitCounter>,
+    maintenance: &State<Maintenance>,
+) -> status::Custom<Json<Readiness>> {
+    let repo = database.repository();
+    let mut checks = BTreeMap::new();
+
+    checks.insert("database", match action::ping(repo).await {
+        Ok(()) => Check::ok(),
+        Err(e) => Check::failed(e.to_string()),
+    });
+    checks.insert("migrations", match action::pending_migrations(repo).await {
+        Ok(pending) if pending.is_empty() => Check::ok(),
+        Ok(pending) => Check::failed(format!("pending migrations: {:?}", pending)),
+        Err(e) => Check::failed(e.to_string()),
+    });
+    checks.insert("hit_counter", match hit_counter.is_alive() {
+        true => Check::ok(),
+        false => Check::failed("hit counter worker has stopped"),
+    });
+    checks.insert("maintenance", match maintenance.is_alive() {
+        true => Check::ok(),
+        false => Check::failed("maintenance worker has stopped"),
+    });
+
+    let mut readiness = Readiness { status: "ready", checks };
+    if readiness.is_ready() {
+        status::Custom(Status::Ok, Json(readiness))
+    } else {
+        readiness.status = "unavailable";
+        status::Custom(Status::ServiceUnavailable, Json(readiness))
+    }
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![healthz, readyz]
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::web::test::client;
+    use rocket::http::{ContentType, Status};
+
+    #[test]
+    fn healthz_is_ok() {
+        let client = client();
+        let response = client.get("/healthz").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.content_type(), Some(ContentType::JSON));
+    }
+
+    #[test]
+    fn readyz_reports_all_checks() {
+        let client = client();
+        let response = client.get("/readyz").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+
+        let body: serde_json::Value = response.into_json().expect("readiness is json");
+        assert_eq!(body["status"], "ready");
+        for check in ["database", "migrations", "hit_counter", "maintenance"] {
+            assert_eq!(body["checks"][check]["ok"], true, "{} check failed", check);
+        }
+    }
+}
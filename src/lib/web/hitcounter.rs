@@ -1,10 +1,12 @@
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::runtime::Handle;
 use crate::{service, ServiceError, ShortCode};
-use crossbeam_channel::{unbounded, Sender, Receiver, TryRecvError};
+use crossbeam_channel::{unbounded, Sender, TryRecvError};
 use parking_lot::Mutex;
-use crate::data::DatabasePool;
+use crate::data::AppDatabase;
+use crate::service::live::Changes;
 
 type HitStore = Arc<Mutex<HashMap<ShortCode, u32>>>;
 
@@ -23,11 +25,12 @@ enum HitCountMsg {
 
 pub struct HitCounter {
     tx: Sender<HitCountMsg>,
+    worker: std::thread::JoinHandle<()>,
 }
 
 impl HitCounter {
 
-    fn commit_hits(hits: HitStore, handle: Handle, pool: DatabasePool) -> Result<(), HitCountError> {
+    fn commit_hits(hits: HitStore, handle: Handle, database: AppDatabase, changes: Changes) -> Result<(), HitCountError> {
         let hits = Arc::clone(&hits);
         let hits: Vec<(ShortCode, u32)> = {
             let mut hits = hits.lock();
@@ -37,19 +40,18 @@ impl HitCounter {
         };
 
         handle.block_on(async move {
-            let transaction = service::action::begin_transaction(&pool).await?;
             for (shortcode, hits) in hits {
-                if let Err(e) = service::action::increase_hit_count(&shortcode, hits, &pool).await {
+                if let Err(e) = service::action::increase_hit_count(&shortcode, hits, &changes, database.repository()).await {
                     eprintln!("Error updating hit count: {}", e);
                 }
             }
-            Ok(service::action::end_transaction(transaction).await?)
+            Ok(())
         })
     }
 
-    fn process_msg(msg: HitCountMsg, hits: HitStore, handle: Handle, pool: DatabasePool) -> Result<(), HitCountError> {
+    fn process_msg(msg: HitCountMsg, hits: HitStore, handle: Handle, database: AppDatabase, changes: Changes) -> Result<(), HitCountError> {
         match msg {
-            HitCountMsg::Commit => Self::commit_hits(hits.clone(), handle.clone(), pool.clone())?,
+            HitCountMsg::Commit => Self::commit_hits(hits.clone(), handle.clone(), database.clone(), changes.clone())?,
             HitCountMsg::Hit(shortcode, count) => {
                 let mut hitcount = hits.lock();
                 let hitcount = hitcount.entry(shortcode).or_insert(0);
@@ -58,23 +60,24 @@ impl HitCounter {
         }
         Ok(())
     }
-    pub fn new(pool: DatabasePool, handle: Handle) -> Self {
+    /// Counts hits in memory and adds them to the clips every `flush_interval`, publishing each to `changes`.
+    pub fn new(database: AppDatabase, changes: Changes, handle: Handle, flush_interval: Duration) -> Self {
        let (tx, rx) = unbounded();
        let tx_clone = tx.clone();
        let rx_clone = rx.clone();
 
-        let _ = std::thread::spawn(move || {
+        let worker = std::thread::spawn(move || {
            println!("Hit counter thread spawned");
            let store: HitStore = Arc::new(Mutex::new(HashMap::new()));
 
             loop {
                 match rx_clone.try_recv() {
-                    Ok(msg) => if let Err(e) = Self::process_msg(msg, store.clone(), handle.clone(), pool.clone()) {
+                    Ok(msg) => if let Err(e) = Self::process_msg(msg, store.clone(), handle.clone(), database.clone(), changes.clone()) {
                         eprintln!("Error processing hit count message: {}", e);
                     },
                     Err(e) => match e {
                         TryRecvError::Empty => {
-                            std::thread::sleep(std::time::Duration::from_secs(5));
+                            std::thread::sleep(flush_interval);
                             if let Err(e) = tx_clone.send(HitCountMsg::Commit) {
                                 eprintln!("Error sending commit message: {}", e);
                             }
@@ -85,7 +88,11 @@ impl HitCounter {
             }
         });
 
-        Self { tx }
+        Self { tx, worker }
+    }
+
+    pub fn is_alive(&self) -> bool {
+        !self.worker.is_finished()
     }
 
     pub async fn hit(&self, shortcode: ShortCode, hits: u32) {
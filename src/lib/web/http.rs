@@ -1,15 +1,23 @@
 use crate::data::AppDatabase;
-use crate::{service, Clip};
+use crate::service;
 use crate::service::action;
 use crate::web::{ctx, form, renderer::Renderer, PageError, PASSWORD_COOKIE};
-use crate::{ServiceError, ShortCode};
+use crate::web::api::cookie_password;
+use crate::web::collection::{archive_format, Archive};
+use crate::web::feed::{self, SiteUrl};
+use crate::web::session::WebSession;
+use crate::{Clip, ServiceError, ShortCode};
 use rocket::form::{Contextual, Form};
-use rocket::http::{Cookie, CookieJar, Status};
+use rocket::http::{ContentType, Cookie, CookieJar, Status};
 use rocket::response::content::RawHtml;
 use rocket::response::{status, Redirect};
 use rocket::{uri, State};
-use rocket::http::hyper::server::conn::Http;
+use crate::web::conditional::{Conditional, Preconditions};
 use crate::web::hitcounter::HitCounter;
+use crate::domain::clip::field::{self, ShortCodePolicy};
+
+const TAG_PAGE_LIMIT: u32 = 100;
+const RECENT_LIMIT: u32 = 50;
 
 #[rocket::get("/")]
 fn home(renderer: &State<Renderer<'_>>) -> RawHtml<String> {
@@ -19,8 +27,10 @@ fn home(renderer: &State<Renderer<'_>>) -> RawHtml<String> {
 
 #[rocket::post("/", data = "<form>")]
 pub async fn new_clip(
+    cookies: &CookieJar<'_>,
     form: Form<Contextual<'_, form::NewClip>>,
     database: &State<AppDatabase>,
+    policy: &State<ShortCodePolicy>,
     renderer: &State<Renderer<'_>>
 ) -> Result<Redirect, (Status, RawHtml<String>)> {
     let form = form.into_inner();
@@ -31,9 +41,14 @@ pub async fn new_clip(
             title: value.title,
             expires: value.expires,
             password: value.password,
+            encrypted: value.encrypted,
+            owner: field::Owner::new(WebSession::start(cookies).id()),
+            parent: Default::default(),
+            visibility: value.visibility,
+            tags: value.tags,
         };
 
-        match action::new_clip(req, database.get_pool()).await {
+        match action::new_clip(req, policy, database.repository()).await {
             Ok(clip) => {
                 Ok(Redirect::to(uri!(get_clip(shortcode = clip.shortcode))))
             }
@@ -65,9 +80,24 @@ pub async fn new_clip(
     }
 }
 
+/// The clip page, showing where the clip was forked from and the forks `viewer` may see.
+async fn view_clip(clip: Clip, viewer: &field::Owner, database: &AppDatabase) -> ctx::ViewClip {
+    let provenance = action::provenance(&clip, viewer, database.repository()).await.unwrap_or_else(|e| {
+        eprintln!("failed to read forks of clip {}: {}", clip.shortcode.as_str(), e);
+        Default::default()
+    });
+    ctx::ViewClip::new(clip, provenance)
+}
+
+/// The API key or web session reading a clip, as [`service::ask::GetClip::viewer`].
+fn viewer(session: Option<WebSession>) -> field::Owner {
+    field::Owner::new(session.map(|session| session.id()))
+}
+
 #[rocket::get("/clip/<shortcode>")]
 async fn get_clip(
     shortcode: ShortCode,
+    session: Option<WebSession>,
     database: &State<AppDatabase>,
     hit_counter: &State<HitCounter>,
     renderer: &State<Renderer<'_>>
@@ -80,14 +110,17 @@ async fn get_clip(
         Ok(status::Custom(status, RawHtml(renderer.render(context, &[]))))
     }
 
-    match action::get_clip(shortcode.clone().into(), database.get_pool()).await {
+    let req = service::ask::GetClip { shortcode: shortcode.clone(), password: Default::default(), viewer: viewer(session) };
+    match action::get_clip(req.clone(), database.repository()).await {
         Ok(clip) => {
-            hit_counter.hit(shortcode.clone(), 1);
-            let context = ctx::ViewClip::new(clip);
+            hit_counter.hit(shortcode.clone(), 1).await;
+            action::clip_viewed(&clip, database.repository()).await;
+            let context = view_clip(clip, &req.viewer, database).await;
             render_with_status(Status::Ok, context, renderer)
         }
         Err(e) => match e {
             ServiceError::NotFound => Err(PageError::NotFound("Clip not found".to_owned())),
+            ServiceError::Forbidden(_) => Err(PageError::Forbidden("This clip is private".to_owned())),
             ServiceError::PermissionError(_) => {
                 let context = ctx::PasswordRequired::new(shortcode);
                 render_with_status(Status::Unauthorized, context, renderer)
@@ -102,6 +135,7 @@ pub async fn submit_clip_password(
     cookies: &CookieJar<'_>,
     form: Form<Contextual<'_, form::GetPasswordProtectedClip>>,
     shortcode: ShortCode,
+    session: Option<WebSession>,
     hit_counter: &State<HitCounter>,
     database: &State<AppDatabase>,
     renderer: &State<Renderer<'_>>
@@ -110,12 +144,14 @@ pub async fn submit_clip_password(
         let req = service::ask::GetClip {
             shortcode: shortcode.clone(),
             password: form.password.clone(),
+            viewer: viewer(session),
         };
 
-        match action::get_clip(req, database.get_pool()).await {
+        match action::get_clip(req.clone(), database.repository()).await {
             Ok(clip) => {
-                hit_counter.hit(shortcode.clone(), 1);
-                let context = ctx::ViewClip::new(clip);
+                hit_counter.hit(shortcode.clone(), 1).await;
+                action::clip_viewed(&clip, database.repository()).await;
+                let context = view_clip(clip, &req.viewer, database).await;
                 cookies.add(Cookie::new(
                     PASSWORD_COOKIE,
                     form.password.clone().into_inner().unwrap_or_default()));
@@ -127,6 +163,7 @@ pub async fn submit_clip_password(
                     Ok(RawHtml(renderer.render(context, &[e.as_str()])))
                 }
                 ServiceError::NotFound => Err(PageError::NotFound("Clip not found".to_owned())),
+                ServiceError::Forbidden(_) => Err(PageError::Forbidden("This clip is private".to_owned())),
                 _ => Err(PageError::Internal("Internal error".to_owned())),
             }
         }
@@ -136,13 +173,97 @@ pub async fn submit_clip_password(
     }
 }
 
+/// The home form, starting with the content and title of the clip being forked.
+// ranked below `/clip/raw/<shortcode>`, which `/clip/raw/fork` also matches
+#[rocket::get("/clip/<shortcode>/fork", rank = 2)]
+async fn fork_form(
+    cookies: &CookieJar<'_>,
+    shortcode: ShortCode,
+    session: Option<WebSession>,
+    database: &State<AppDatabase>,
+    renderer: &State<Renderer<'_>>
+) -> Result<status::Custom<RawHtml<String>>, PageError> {
+    let req = service::ask::GetClip { shortcode: shortcode.clone(), password: cookie_password(cookies), viewer: viewer(session) };
+    match action::get_clip(req, database.repository()).await {
+        Ok(clip) => {
+            let values = serde_json::json!({ "values": {
+                "content": [clip.content.as_str()],
+                "title": [clip.title.clone().into_inner()],
+                "tags": [clip.tags.as_slice().join(", ")],
+                "visibility": [clip.visibility.to_string()],
+            }});
+            let page = renderer.render_with_data(ctx::Home::fork(&clip), ("clip", values), &[]);
+            Ok(status::Custom(Status::Ok, RawHtml(page)))
+        }
+        Err(ServiceError::PermissionError(_)) => {
+            let page = renderer.render(ctx::PasswordRequired::new(shortcode), &[]);
+            Ok(status::Custom(Status::Unauthorized, RawHtml(page)))
+        }
+        Err(ServiceError::NotFound) => Err(PageError::NotFound("Clip not found".to_owned())),
+        Err(ServiceError::Forbidden(_)) => Err(PageError::Forbidden("This clip is private".to_owned())),
+        Err(_) => Err(PageError::Internal("Internal error".to_owned())),
+    }
+}
+
+#[rocket::post("/clip/<shortcode>/fork", data = "<form>")]
+pub async fn fork_clip(
+    cookies: &CookieJar<'_>,
+    shortcode: ShortCode,
+    form: Form<Contextual<'_, form::NewClip>>,
+    database: &State<AppDatabase>,
+    policy: &State<ShortCodePolicy>,
+    renderer: &State<Renderer<'_>>
+) -> Result<Redirect, (Status, RawHtml<String>)> {
+    let form = form.into_inner();
+    let home = || ctx::Home { fork_of: Some(shortcode.clone()), fork_encrypted: false };
+    let value = match form.value {
+        Some(value) => value,
+        None => {
+            let errors = form.context.errors().map(|err| match &err.kind {
+                rocket::form::error::ErrorKind::Validation(msg) => msg.as_ref(),
+                _ => "An error occurred",
+            }).collect::<Vec<_>>();
+            return Err((Status::BadRequest, RawHtml(renderer.render_with_data(home(), ("clip", &form.context), &errors))));
+        }
+    };
+
+    let session = WebSession::start(cookies);
+    let source = service::ask::GetClip { shortcode: shortcode.clone(), password: cookie_password(cookies), viewer: field::Owner::new(session.id()) };
+    let req = service::ask::ForkClip {
+        content: Some(value.content),
+        title: Some(value.title),
+        expires: value.expires,
+        password: value.password,
+        encrypted: value.encrypted,
+        owner: field::Owner::new(session.id()),
+        visibility: value.visibility,
+        tags: Some(value.tags),
+    };
+    match action::fork_clip(source, req, policy, database.repository()).await {
+        Ok(clip) => Ok(Redirect::to(uri!(get_clip(shortcode = clip.shortcode)))),
+        Err(ServiceError::PermissionError(_)) => {
+            let page = renderer.render(ctx::PasswordRequired::new(shortcode.clone()), &[]);
+            Err((Status::Unauthorized, RawHtml(page)))
+        }
+        Err(ServiceError::NotFound) => {
+            Err((Status::NotFound, RawHtml(renderer.render(home(), &["The clip being forked no longer exists"]))))
+        }
+        Err(e) => {
+            eprintln!("internal error: {}", e);
+            Err((Status::InternalServerError, RawHtml(renderer.render(home(), &["A server error occurred"]))))
+        }
+    }
+}
+
 #[rocket::get("/clip/raw/<shortcode>")]
 pub async fn get_raw_clip(
     cookies: &CookieJar<'_>,
     shortcode: ShortCode,
+    session: Option<WebSession>,
     hit_counter: &State<HitCounter>,
     database: &State<AppDatabase>,
-) -> Result<status::Custom<String>, Status> {
+    preconditions: Preconditions,
+) -> Result<Conditional<status::Custom<String>>, Status> {
     use crate::domain::clip::field::Password;
 
     let req = service::ask::GetClip {
@@ -150,26 +271,105 @@ pub async fn get_raw_clip(
         password: cookies
             .get(PASSWORD_COOKIE)
             .map(|cookie| cookie.value())
-            .map(|raw_password| Password::new(raw_password.to_string()).ok())
-            .flatten()
-            .unwrap_or_else(|| Password::default()),
+            .and_then(|raw_password| Password::new(raw_password.to_string()).ok())
+            .unwrap_or_default(),
+        viewer: viewer(session),
     };
 
-    match action::get_clip(req, database.get_pool()).await {
+    match action::get_clip(req, database.repository()).await {
         Ok(clip) => {
-            hit_counter.hit(shortcode.into(), 1);
-            Ok(status::Custom(Status::Ok, clip.content.into_inner()))
+            if !preconditions.is_fresh(clip.revision) {
+                hit_counter.hit(shortcode, 1).await;
+                action::clip_viewed(&clip, database.repository()).await;
+            }
+            let body = status::Custom(Status::Ok, clip.content.clone().into_inner());
+            Ok(Conditional::with_clip(body, &clip).unless_fresh(&preconditions, &clip))
         },
         Err(e) => match e {
             ServiceError::NotFound => Err(Status::NotFound),
-            ServiceError::PermissionError(msg) => Ok(status::Custom(Status::Unauthorized, msg)),
+            ServiceError::PermissionError(msg) => Ok(Conditional::new(status::Custom(Status::Unauthorized, msg))),
+            ServiceError::Forbidden(msg) => Ok(Conditional::new(status::Custom(Status::Forbidden, msg))),
             _ => Err(Status::InternalServerError),
         }
     }
 
 }
+
+#[rocket::get("/collection/<shortcode>")]
+pub async fn get_collection(
+    shortcode: ShortCode,
+    database: &State<AppDatabase>,
+    renderer: &State<Renderer<'_>>
+) -> Result<RawHtml<String>, PageError> {
+    match action::get_collection(&shortcode, database.repository()).await {
+        Ok(collection) => Ok(RawHtml(renderer.render(ctx::ViewCollection::new(collection), &[]))),
+        Err(ServiceError::NotFound) => Err(PageError::NotFound("Collection not found".to_owned())),
+        Err(_) => Err(PageError::Internal("Internal error".to_owned())),
+    }
+}
+
+#[rocket::get("/collection/<shortcode>/download?<format>")]
+pub async fn download_collection(
+    shortcode: ShortCode,
+    format: Option<&str>,
+    database: &State<AppDatabase>,
+) -> Result<Archive, Status> {
+    let format = archive_format(format).map_err(|_| Status::UnprocessableEntity)?;
+    match action::get_collection(&shortcode, database.repository()).await {
+        Ok(collection) => Archive::new(&collection, format).map_err(|_| Status::InternalServerError),
+        Err(ServiceError::NotFound) => Err(Status::NotFound),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[rocket::get("/tag/<name>")]
+pub async fn tagged_clips(
+    name: &str,
+    database: &State<AppDatabase>,
+    renderer: &State<Renderer<'_>>
+) -> Result<RawHtml<String>, PageError> {
+    let tag = field::normalize_tag(name).map_err(|_| PageError::NotFound("No such tag".to_owned()))?;
+    match action::tagged_clips(&tag, TAG_PAGE_LIMIT, database.repository()).await {
+        Ok(clips) => Ok(RawHtml(renderer.render(ctx::Tagged::new(tag, clips), &[]))),
+        Err(_) => Err(PageError::Internal("Internal error".to_owned())),
+    }
+}
+
+#[rocket::get("/recent")]
+pub async fn recent_clips(
+    database: &State<AppDatabase>,
+    renderer: &State<Renderer<'_>>
+) -> Result<RawHtml<String>, PageError> {
+    match action::recent_clips(RECENT_LIMIT, database.repository()).await {
+        Ok(clips) => {
+            let clips = clips.iter().map(service::ListedClip::from).collect();
+            Ok(RawHtml(renderer.render(ctx::Recent::new(clips), &[])))
+        }
+        Err(_) => Err(PageError::Internal("Internal error".to_owned())),
+    }
+}
+
+#[rocket::get("/recent.atom")]
+pub async fn recent_atom(site: SiteUrl, database: &State<AppDatabase>) -> Result<(ContentType, String), Status> {
+    match action::recent_clips(RECENT_LIMIT, database.repository()).await {
+        Ok(clips) => Ok(feed::atom(&site, &clips)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
+#[rocket::get("/recent.json")]
+pub async fn recent_json(site: SiteUrl, database: &State<AppDatabase>) -> Result<(ContentType, String), Status> {
+    match action::recent_clips(RECENT_LIMIT, database.repository()).await {
+        Ok(clips) => Ok(feed::json_feed(&site, &clips)),
+        Err(_) => Err(Status::InternalServerError),
+    }
+}
+
 pub fn routes() -> Vec<rocket::Route> {
-    rocket::routes![home, get_clip, new_clip, submit_clip_password, get_raw_clip]
+    rocket::routes![
+        home, get_clip, new_clip, submit_clip_password, fork_form, fork_clip, get_raw_clip,
+        get_collection, download_collection, tagged_clips, recent_clips, recent_atom, recent_json,
+    ]
 }
 
 pub mod catcher {
@@ -200,10 +400,10 @@ pub mod catcher {
 
 #[cfg(test)]
 pub mod test {
-    use crate::data::AppDatabase;
-    use crate::test::async_runtime;
-    use crate::web::test::client;
-    use rocket::http::Status;
+    use crate::service::action;
+    use crate::web::test::{client, config};
+    use rocket::http::{ContentType, Status};
+    use rocket::local::blocking::Client;
 
     #[test]
     fn gets_home() {
@@ -218,4 +418,94 @@ pub mod test {
         let response = client.get("/clip/sdfa").dispatch();
         assert_eq!(response.status(), Status::NotFound);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn tag_pages_list_only_public_clips() {
+        let config = config();
+        let database = config.database.clone();
+        let client = Client::tracked(crate::rocket(config)).expect("valid rocket instance");
+        let form = |title: &str, password: &str, visibility: &str| {
+            format!("content=x&title={}&password={}&expires=&tags=deploy%2C+Runbook&visibility={}", title, password, visibility)
+        };
+        for (title, password, visibility) in [("rollback", "", "public"), ("credentials", "pw", "public"), ("draft", "", "unlisted")] {
+            let response = client.post("/").header(ContentType::Form).body(form(title, password, visibility)).dispatch();
+            assert_eq!(response.status(), Status::SeeOther);
+        }
+        let new = serde_json::from_str(r#"{"content":"x","title":"e2e","expires":null,"password":null,"encrypted":true,"visibility":"public","tags":["runbook"]}"#).unwrap();
+        crate::web::test::block_on(action::new_clip(new, &Default::default(), database.repository())).unwrap();
+
+        let page = client.get("/tag/runbook").dispatch().into_string().unwrap();
+        assert!(page.contains(">rollback</a>") && page.contains(r#"href="/tag/deploy""#));
+        assert!(!page.contains("credentials") && !page.contains(">e2e</a>") && !page.contains("draft"));
+        assert_eq!(client.get("/tag/no%2Fsuch").dispatch().status(), Status::NotFound);
+    }
+
+    #[test]
+    fn recent_clips_are_listed_and_published_as_feeds() {
+        let client = client();
+        for (title, password, visibility) in [("Q3 <plan>", "", "public"), ("secret", "pw", "public"), ("draft", "", "unlisted")] {
+            let body = format!("content=ship+it+%26+see&title={}&password={}&expires=&tags=ops&visibility={}", title, password, visibility);
+            let response = client.post("/").header(ContentType::Form).body(body).dispatch();
+            assert_eq!(response.status(), Status::SeeOther);
+        }
+
+        let page = client.get("/recent").dispatch().into_string().unwrap();
+        assert!(page.contains("Q3 &lt;plan&gt;") && page.contains(r#"href="/recent.atom""#));
+        assert!(!page.contains("secret") && !page.contains("draft"));
+
+        let response = client.get("/recent.atom").header(rocket::http::Header::new("X-Forwarded-Proto", "https")).dispatch();
+        assert_eq!(response.content_type(), Some(ContentType::new("application", "atom+xml")));
+        let atom = response.into_string().unwrap();
+        assert!(atom.contains("<title>Q3 &lt;plan&gt;</title>") && atom.contains("<content type=\"text\">ship it &amp; see</content>"));
+        assert!(atom.contains(r#"<link href="https://localhost/clip/"#) && atom.contains(r#"<category term="ops"/>"#));
+        assert!(!atom.contains("draft"));
+
+        let json: serde_json::Value = client.get("/recent.json").dispatch().into_json().unwrap();
+        let items = json["items"].as_array().unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!((items[0]["title"].as_str(), items[0]["content_text"].as_str()), (Some("Q3 <plan>"), Some("ship it & see")));
+    }
+
+    #[test]
+    fn private_clips_are_shown_only_to_their_session() {
+        let client = Client::untracked(crate::rocket(config())).expect("valid rocket instance");
+        let response = client.post("/").header(ContentType::Form)
+            .body("content=plans&title=&password=&expires=&visibility=private")
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        let url = response.headers().get_one("Location").unwrap().to_owned();
+        let session = response.cookies().get(crate::web::session::SESSION_COOKIE).unwrap().clone();
+        let page = client.get(url.as_str()).cookie(session).dispatch().into_string().unwrap();
+        assert!(page.contains("plans") && page.contains("private"));
+
+        assert_eq!(client.get(url.as_str()).dispatch().status(), Status::Forbidden);
+        assert_eq!(client.get(url.replace("/clip/", "/clip/raw/")).dispatch().status(), Status::Forbidden);
+    }
+
+    #[test]
+    fn forks_link_back_to_their_source() {
+        let config = config();
+        let database = config.database.clone();
+        let client = Client::tracked(crate::rocket(config)).expect("valid rocket instance");
+        let new = serde_json::from_str(r#"{"content":"SELECT 1;","title":"query","expires":null,"password":"pg"}"#).unwrap();
+        let source = crate::web::test::block_on(action::new_clip(new, &Default::default(), database.repository())).unwrap();
+        let source = source.shortcode.as_str();
+
+        let url = format!("/clip/{}/fork", source);
+        assert_eq!(client.get(url.as_str()).dispatch().status(), Status::Unauthorized);
+        let response = client.post(format!("/clip/{}", source)).header(ContentType::Form).body("password=pg").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let form = client.get(url.as_str()).dispatch().into_string().unwrap();
+        assert!(form.contains(">SELECT 1;</textarea>") && form.contains(&format!(r#"action="{}""#, url)));
+
+        let response = client.post(url.as_str()).header(ContentType::Form)
+            .body("content=SELECT+2%3B&title=query&password=&expires=")
+            .dispatch();
+        assert_eq!(response.status(), Status::SeeOther);
+        let fork = response.headers().get_one("Location").unwrap().to_owned();
+        let page = client.get(fork.as_str()).dispatch().into_string().unwrap();
+        assert!(page.contains("SELECT 2;") && page.contains(&format!(r#"Forked from <a href="/clip/{}">"#, source)));
+        let page = client.post(format!("/clip/{}", source)).header(ContentType::Form).body("password=pg").dispatch();
+        assert!(page.into_string().unwrap().contains(&format!(r#"href="{}""#, fork)));
+    }
+}
@@ -4,6 +4,14 @@ pub mod form;
 pub mod http;
 pub mod hitcounter;
 pub mod api;
+pub mod collab;
+pub mod conditional;
+pub mod health;
+pub mod openapi;
+pub mod webhook;
+pub mod collection;
+pub mod session;
+pub mod feed;
 
 pub const PASSWORD_COOKIE: &str = "password";
 
@@ -13,6 +21,8 @@ pub enum PageError {
     Serialization(String),
     #[response(status = 500)]
     Render(String),
+    #[response(status = 403)]
+    Forbidden(String),
     #[response(status = 404)]
     NotFound(String),
     #[response(status = 500)]
@@ -36,20 +46,42 @@ pub mod test{
     use crate::test::async_runtime;
     use crate::RocketConfig;
     use rocket::local::blocking::Client;
+    use std::sync::OnceLock;
+    use tokio::runtime::Runtime;
+
+    /// Background workers must outlive the config, so they share one runtime across tests.
+    pub fn runtime() -> &'static Runtime {
+        static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+        RUNTIME.get_or_init(async_runtime)
+    }
+
+    /// Runs `future` on the runtime the test database was created on.
+    pub fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        runtime().block_on(future)
+    }
 
     pub fn config() -> RocketConfig {
         use crate::web::{hitcounter::HitCounter, renderer::Renderer};
-        let rt = async_runtime();
-        let renderer = Renderer::new("templates/".into());
+        let app_config = crate::config::Config::default();
+        let rt = runtime();
+        let renderer = Renderer::new(app_config.paths.template_dir.clone());
         let database = crate::data::test::new_db(rt.handle());
-        let maintenance = crate::domain::maintenance::Maintenance::spawn(database.get_pool().clone(), rt.handle().clone());
-        let hit_counter = HitCounter::new(database.get_pool().clone(), rt.handle().clone());
+        let changes = crate::service::live::Changes::default();
+        let maintenance = crate::domain::maintenance::Maintenance::spawn(
+            database.clone(), changes.clone(), rt.handle().clone(), app_config.workers.maintenance_interval());
+        let hit_counter = HitCounter::new(
+            database.clone(), changes.clone(), rt.handle().clone(), app_config.workers.hit_flush_interval());
 
         RocketConfig {
+            figment: app_config.rocket_figment(),
+            static_dir: app_config.paths.static_dir,
             renderer,
             database,
             hit_counter,
-            maintenance
+            maintenance,
+            webhooks: app_config.webhooks,
+            shortcode: app_config.shortcode,
+            changes,
         }
     }
 
@@ -0,0 +1,107 @@
+//! The OpenAPI 3 description of the `/api` routes, served at `/api/openapi.json` and
+//! browsable with `/static/openapi.html`.
+
+use rocket::serde::json::Json;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use crate::web::api::{self, ApiError, ErrorCode, ErrorEnvelope, API_KEY_HEADER};
+use crate::web::{collab, collection, webhook};
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "clipstash", description = "Stash and share clips of text."),
+    paths(
+        api::get_clip, api::clip_events, api::new_clip, api::fork_clip, api::update_clip, api::new_api_key, api::export_clips, api::list_clips,
+        collab::edit_clip,
+        webhook::new_webhook, webhook::list_webhooks, webhook::delete_webhook, webhook::list_deliveries,
+        collection::new_collection, collection::get_collection, collection::collection_archive,
+    ),
+    components(schemas(
+        crate::Clip,
+        crate::service::ask::NewClip,
+        crate::service::ask::UpdateClip,
+        crate::service::ask::ForkClip,
+        crate::service::archive::ArchivedClip,
+        crate::service::ListedClip,
+        crate::domain::clip::field::Visibility,
+        crate::service::ask::NewWebhook,
+        crate::domain::webhook::Webhook,
+        crate::domain::webhook::Delivery,
+        crate::domain::webhook::DeliveryStatus,
+        crate::domain::webhook::ClipEvent,
+        crate::domain::webhook::EventPayload,
+        crate::domain::webhook::ClipSummary,
+        crate::service::ask::NewCollection,
+        crate::service::ask::CollectionItem,
+        crate::domain::collection::Collection,
+        ApiError,
+        ErrorCode,
+        ErrorEnvelope,
+    )),
+    modifiers(&ApiKeySecurity),
+    tags(
+        (name = "clips", description = "Create, read and export clips"),
+        (name = "keys", description = "API keys"),
+        (name = "collections", description = "Clips shared under one shortcode and downloaded as an archive"),
+        (name = "webhooks", description = "Notifications of clip events, POSTed as EventPayload"),
+    ),
+)]
+pub struct ApiDoc;
+
+/// Declares the `x-api-key` header referenced by the routes' `security` requirements.
+struct ApiKeySecurity;
+
+impl Modify for ApiKeySecurity {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::with_description(
+                API_KEY_HEADER,
+                "base64 API key; generate one with `clipstash-admin api-key create`",
+            ))),
+        );
+    }
+}
+
+/// The API description, without the empty license utoipa takes from `Cargo.toml`.
+pub fn document() -> utoipa::openapi::OpenApi {
+    let mut doc = ApiDoc::openapi();
+    doc.info.license = doc.info.license.filter(|license| !license.name.is_empty());
+    doc
+}
+
+#[rocket::get("/openapi.json")]
+pub fn openapi() -> Json<utoipa::openapi::OpenApi> {
+    Json(document())
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![openapi]
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::web::test::client;
+    use rocket::http::Status;
+
+    #[test]
+    fn documents_every_api_route() {
+        let client = client();
+        let response = client.get("/api/openapi.json").dispatch();
+        assert_eq!(response.status(), Status::Ok);
+        let doc: serde_json::Value = response.into_json().unwrap();
+
+        for route in client.rocket().routes().filter(|route| route.uri.path().starts_with("/api/v1")) {
+            // rocket's <param> segments are {param} in OpenAPI
+            let path = route.uri.path().replace('<', "{").replace('>', "}");
+            let method = route.method.as_str().to_lowercase();
+            assert!(doc["paths"][&path][&method].is_object(), "{} {} is not documented", method, path);
+        }
+        let new_clip = &doc["components"]["schemas"]["NewClip"];
+        assert!(new_clip["properties"]["content"].is_object());
+        // the server rejects a body without title, even though it may be null
+        assert!(new_clip["required"].as_array().unwrap().contains(&"title".into()));
+        assert_eq!(doc["components"]["securitySchemes"]["api_key"]["name"], "x-api-key");
+    }
+}
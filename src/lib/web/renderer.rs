@@ -17,7 +17,7 @@ impl<'a> Renderer<'a>{
     }
 
     fn convert_to_value<S: serde::Serialize+std::fmt::Debug>(serializable: &S) -> serde_json::Value {
-        serde_json::to_value(&serializable).expect("failed to convert to value")
+        serde_json::to_value(serializable).expect("failed to convert to value")
     }
 
     pub fn render<P>(&self, context: P, errors: &[&str]) -> String
@@ -0,0 +1,46 @@
+//! Web sessions: a private cookie naming a browser, which owns the clips posted from it.
+
+use rocket::http::{Cookie, CookieJar, SameSite, Status};
+use rocket::request::{FromRequest, Outcome};
+use rocket::Request;
+
+pub const SESSION_COOKIE: &str = "session";
+
+/// The session of a browser that has posted a clip. Requests without one are forwarded, so
+/// routes take an `Option<WebSession>`.
+pub struct WebSession(String);
+
+impl WebSession {
+    /// The browser's session, starting one if it has none yet.
+    pub fn start(cookies: &CookieJar<'_>) -> Self {
+        if let Some(cookie) = cookies.get_private(SESSION_COOKIE) {
+            return Self(cookie.value().to_owned());
+        }
+        let token = base64::encode((0..16).map(|_| rand::random::<u8>()).collect::<Vec<_>>());
+        let cookie = Cookie::build((SESSION_COOKIE, token.clone()))
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .permanent();
+        cookies.add_private(cookie);
+        Self(token)
+    }
+
+    /// A fingerprint of the session recorded as the owner of its clips, like [`crate::web::api::ApiKey::id`].
+    pub fn id(&self) -> String {
+        use sha2::{Digest, Sha256};
+
+        Sha256::digest(self.0.as_bytes())[..8].iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for WebSession {
+    type Error = std::convert::Infallible;
+
+    async fn from_request(req: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        match req.cookies().get_private(SESSION_COOKIE) {
+            Some(cookie) => Outcome::Success(Self(cookie.value().to_owned())),
+            None => Outcome::Forward(Status::Unauthorized),
+        }
+    }
+}
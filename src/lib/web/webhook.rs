@@ -0,0 +1,159 @@
+//! Webhook registration and delivery logs, only under `/api/v1`.
+
+use rocket::http::Status;
+use rocket::response::status;
+use rocket::serde::json::{self, Json};
+use rocket::State;
+use crate::data::AppDatabase;
+use crate::domain::webhook::{Delivery, ReceiverPolicy, Webhook};
+use crate::service::{action, ask};
+use crate::web::api::{ApiError, ApiKey, ErrorCode};
+
+const DEFAULT_DELIVERY_LIMIT: u32 = 50;
+const MAX_DELIVERY_LIMIT: u32 = 500;
+
+/// Events on clips created with the calling API key are POSTed to `url`, signed with the returned secret.
+#[utoipa::path(
+    post,
+    path = "/api/v1/webhooks",
+    tag = "webhooks",
+    request_body = NewWebhook,
+    responses(
+        (status = 201, description = "The webhook, with the secret that signs its deliveries", body = Webhook),
+        (status = 400, description = "bad_request: malformed JSON", body = ErrorEnvelope),
+        (status = 401, description = "missing_api_key, invalid_api_key", body = ErrorEnvelope),
+        (status = 422, description = "invalid_body, invalid_webhook: not an http(s) URL, not a public address, or no events", body = ErrorEnvelope),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+    security(("api_key" = [])),
+)]
+#[rocket::post("/webhooks", data = "<req>")]
+pub async fn new_webhook(
+    req: Result<Json<ask::NewWebhook>, json::Error<'_>>,
+    database: &State<AppDatabase>,
+    policy: &State<ReceiverPolicy>,
+    api_key: ApiKey
+) -> Result<status::Custom<Json<Webhook>>, ApiError> {
+    let webhook = action::new_webhook(req?.into_inner(), &api_key.id(), policy, database.repository()).await?;
+    Ok(status::Custom(Status::Created, Json(webhook)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks",
+    tag = "webhooks",
+    responses(
+        (status = 200, description = "The webhooks of the calling API key", body = [Webhook]),
+        (status = 401, description = "missing_api_key, invalid_api_key", body = ErrorEnvelope),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+    security(("api_key" = [])),
+)]
+#[rocket::get("/webhooks")]
+pub async fn list_webhooks(database: &State<AppDatabase>, api_key: ApiKey) -> Result<Json<Vec<Webhook>>, ApiError> {
+    Ok(Json(action::list_webhooks(&api_key.id(), database.repository()).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/v1/webhooks/{id}",
+    tag = "webhooks",
+    params(("id" = String, Path, description = "webhook id")),
+    responses(
+        (status = 204, description = "The webhook and its delivery log were deleted"),
+        (status = 401, description = "missing_api_key, invalid_api_key", body = ErrorEnvelope),
+        (status = 404, description = "not_found: the calling API key has no such webhook", body = ErrorEnvelope),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+    security(("api_key" = [])),
+)]
+#[rocket::delete("/webhooks/<id>")]
+pub async fn delete_webhook(id: &str, database: &State<AppDatabase>, api_key: ApiKey) -> Result<Status, ApiError> {
+    action::delete_webhook(&api_key.id(), id, database.repository()).await?;
+    Ok(Status::NoContent)
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/v1/webhooks/{id}/deliveries",
+    tag = "webhooks",
+    params(
+        ("id" = String, Path, description = "webhook id"),
+        ("limit" = Option<u32>, Query, description = "how many of the latest deliveries, at most 500 (default 50)"),
+    ),
+    responses(
+        (status = 200, description = "The latest deliveries, newest first", body = [Delivery]),
+        (status = 401, description = "missing_api_key, invalid_api_key", body = ErrorEnvelope),
+        (status = 404, description = "not_found: the calling API key has no such webhook", body = ErrorEnvelope),
+        (status = 422, description = "invalid_parameter: limit is out of range", body = ErrorEnvelope),
+        (status = 500, description = "server_error", body = ErrorEnvelope),
+    ),
+    security(("api_key" = [])),
+)]
+#[rocket::get("/webhooks/<id>/deliveries?<limit>")]
+pub async fn list_deliveries(
+    id: &str,
+    limit: Option<u32>,
+    database: &State<AppDatabase>,
+    api_key: ApiKey
+) -> Result<Json<Vec<Delivery>>, ApiError> {
+    let limit = limit.unwrap_or(DEFAULT_DELIVERY_LIMIT);
+    if limit == 0 || limit > MAX_DELIVERY_LIMIT {
+        return Err(ApiError::new(ErrorCode::InvalidParameter, format!("limit must be between 1 and {}", MAX_DELIVERY_LIMIT))
+            .with_details(serde_json::json!({ "parameter": "limit" })));
+    }
+    Ok(Json(action::list_deliveries(&api_key.id(), id, limit, database.repository()).await?))
+}
+
+pub fn routes() -> Vec<rocket::Route> {
+    rocket::routes![new_webhook, list_webhooks, delete_webhook, list_deliveries]
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::domain::webhook::{Delivery, Webhook};
+    use crate::service::action;
+    use crate::web::api::{ErrorCode, ErrorEnvelope, API_KEY_HEADER};
+    use crate::web::test::config;
+    use crate::web::test::block_on;
+    use rocket::http::{Header, Status};
+    use rocket::local::blocking::Client;
+
+    #[test]
+    fn webhooks_belong_to_their_api_key() {
+        let config = config();
+        let database = config.database.clone();
+        let client = Client::tracked(crate::rocket(config)).expect("valid rocket instance");
+        let new_key = || Header::new(API_KEY_HEADER, block_on(action::generate_api_key(database.repository())).unwrap().to_base64());
+        let (mine, theirs) = (new_key(), new_key());
+
+        let body = r#"{"url":"https://chat.example.com/hooks/runbook","events":["clip.updated","clip.deleted"]}"#;
+        let response = client.post("/api/v1/webhooks").header(mine.clone()).body(body).dispatch();
+        assert_eq!(response.status(), Status::Created);
+        let webhook: Webhook = response.into_json().unwrap();
+        assert_eq!(webhook.secret.as_ref().map(String::len), Some(64));
+
+        let response = client.post("/api/v1/webhooks").header(mine.clone()).body(r#"{"url":"chat","events":[]}"#).dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert_eq!(response.into_json::<ErrorEnvelope>().unwrap().error.code, ErrorCode::InvalidWebhook);
+
+        let listed: Vec<Webhook> = client.get("/api/v1/webhooks").header(mine.clone()).dispatch().into_json().unwrap();
+        assert_eq!((listed.len(), listed[0].secret.as_ref()), (1, None));
+        let listed: Vec<Webhook> = client.get("/api/v1/webhooks").header(theirs.clone()).dispatch().into_json().unwrap();
+        assert!(listed.is_empty());
+
+        let deliveries = format!("/api/v1/webhooks/{}/deliveries", webhook.id);
+        let log: Vec<Delivery> = client.get(deliveries.as_str()).header(mine.clone()).dispatch().into_json().unwrap();
+        assert!(log.is_empty());
+        assert_eq!(client.get(deliveries.as_str()).header(theirs.clone()).dispatch().status(), Status::NotFound);
+        let response = client.get(format!("{}?limit=0", deliveries)).header(mine.clone()).dispatch();
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let url = format!("/api/v1/webhooks/{}", webhook.id);
+        assert_eq!(client.delete(url.as_str()).header(theirs).dispatch().status(), Status::NotFound);
+        assert_eq!(client.delete(url.as_str()).header(mine.clone()).dispatch().status(), Status::NoContent);
+        assert_eq!(client.delete(url.as_str()).header(mine).dispatch().status(), Status::NotFound);
+        // webhooks are new in v1 and have no unversioned alias
+        assert_eq!(client.get("/api/webhooks").dispatch().status(), Status::NotFound);
+    }
+}
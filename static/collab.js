@@ -0,0 +1,191 @@
+// Live editing of a clip with other editors. Must stay compatible with `domain::edit`:
+// splices count Unicode code points, and an edit is a list of splices applied in order.
+// Edits are sent one at a time; later local edits wait in a buffer until the server
+// acknowledges the one in flight by broadcasting it back.
+var ClipstashCollab = (function () {
+  function chars(text) {
+    return Array.from(text);
+  }
+
+  function between(oldText, newText) {
+    var a = chars(oldText), b = chars(newText);
+    var prefix = 0;
+    while (prefix < a.length && prefix < b.length && a[prefix] === b[prefix]) {
+      prefix++;
+    }
+    var suffix = 0;
+    while (suffix < a.length - prefix && suffix < b.length - prefix &&
+      a[a.length - 1 - suffix] === b[b.length - 1 - suffix]) {
+      suffix++;
+    }
+    return { at: prefix, delete: a.length - prefix - suffix, insert: b.slice(prefix, b.length - suffix).join('') };
+  }
+
+  function isNoop(splice) {
+    return splice.delete === 0 && splice.insert === '';
+  }
+
+  function applySplice(text, splice) {
+    var c = chars(text);
+    return c.slice(0, splice.at).join('') + splice.insert + c.slice(splice.at + splice.delete).join('');
+  }
+
+  function apply(text, splices) {
+    return splices.reduce(applySplice, text);
+  }
+
+  // `a` rewritten to apply after `b`; the same rules as `Splice::transform`.
+  function transformSplice(a, b, first) {
+    var aEnd = a.at + a.delete, bEnd = b.at + b.delete;
+    var bothInserts = a.delete === 0 && b.delete === 0;
+    var bInserted = chars(b.insert).length;
+    if (aEnd < b.at || (aEnd === b.at && (!bothInserts || first))) {
+      return a;
+    }
+    if (a.at > bEnd || (a.at === bEnd && (!bothInserts || !first))) {
+      return { at: a.at - b.delete + bInserted, delete: a.delete, insert: a.insert };
+    }
+    var start = Math.min(a.at, b.at), end = Math.max(aEnd, bEnd);
+    return {
+      at: start,
+      delete: end - start - b.delete + bInserted,
+      insert: first ? a.insert + b.insert : b.insert + a.insert
+    };
+  }
+
+  // Both edits rewritten to apply after each other: [edit after other, other after edit].
+  function transform(edit, other, first) {
+    other = other.slice();
+    var out = edit.map(function (splice) {
+      for (var i = 0; i < other.length; i++) {
+        var transformed = transformSplice(splice, other[i], first);
+        other[i] = transformSplice(other[i], splice, !first);
+        splice = transformed;
+      }
+      return splice;
+    });
+    return [out, other];
+  }
+
+  // Where a position (in code points) ends up after a splice.
+  function movePosition(position, splice) {
+    if (position <= splice.at) {
+      return position;
+    }
+    if (position >= splice.at + splice.delete) {
+      return position - splice.delete + chars(splice.insert).length;
+    }
+    return splice.at + chars(splice.insert).length;
+  }
+
+  // Joins the session at `url`, editing `textarea`. `handlers` are called with
+  // presence(editors, me), status(text) and closed(reason), where a reason is
+  // 'deleted', 'locked', 'rejected', 'disconnected' or 'left'.
+  function join(url, textarea, handlers) {
+    var socket = new WebSocket(url);
+    var me = null, revision = 0, inflight = null, buffer = [];
+    var shadow = textarea.value;
+
+    function send() {
+      if (inflight === null && buffer.length) {
+        inflight = buffer;
+        buffer = [];
+        socket.send(JSON.stringify({ type: 'edit', revision: revision, splices: inflight }));
+        handlers.status('Editing…');
+      }
+    }
+
+    function onInput() {
+      var splice = between(shadow, textarea.value);
+      shadow = textarea.value;
+      if (!isNoop(splice)) {
+        buffer.push(splice);
+        send();
+      }
+    }
+
+    function applyRemote(splices) {
+      var value = textarea.value;
+      var start = chars(value.slice(0, textarea.selectionStart)).length;
+      var end = chars(value.slice(0, textarea.selectionEnd)).length;
+      splices.forEach(function (splice) {
+        start = movePosition(start, splice);
+        end = movePosition(end, splice);
+      });
+      value = apply(value, splices);
+      var focused = document.activeElement === textarea;
+      textarea.value = shadow = value;
+      if (focused) {
+        var c = chars(value);
+        textarea.setSelectionRange(c.slice(0, start).join('').length, c.slice(0, end).join('').length);
+      }
+    }
+
+    socket.onmessage = function (event) {
+      var message = JSON.parse(event.data);
+      switch (message.type) {
+        case 'init':
+          me = message.editor;
+          revision = message.revision;
+          textarea.value = shadow = message.content;
+          textarea.readOnly = false;
+          textarea.addEventListener('input', onInput);
+          handlers.presence(message.editors, me);
+          handlers.status('Connected');
+          break;
+        case 'edit':
+          revision = message.revision;
+          if (message.editor === me) {
+            inflight = null;
+            send();
+            return;
+          }
+          // the server ordered the remote edit first, so it wins ties
+          var remote = message.splices, pair;
+          if (inflight !== null) {
+            pair = transform(remote, inflight, true);
+            remote = pair[0];
+            inflight = pair[1];
+          }
+          pair = transform(remote, buffer, true);
+          buffer = pair[1];
+          applyRemote(pair[0]);
+          break;
+        case 'presence':
+          handlers.presence(message.editors, me);
+          break;
+        case 'saved':
+          if (inflight === null && !buffer.length) {
+            handlers.status('Saved');
+          }
+          break;
+        case 'rejected':
+          console.error('edit rejected:', message.message);
+          leave('rejected');
+          break;
+        case 'closed':
+          leave(message.reason);
+          break;
+      }
+    };
+
+    var left = false;
+    function leave(reason) {
+      if (left) {
+        return;
+      }
+      left = true;
+      textarea.removeEventListener('input', onInput);
+      textarea.readOnly = true;
+      socket.close();
+      handlers.closed(reason);
+    }
+    socket.onclose = function () {
+      leave('disconnected');
+    };
+
+    return { leave: function () { leave('left'); } };
+  }
+
+  return { between: between, apply: apply, transform: transform, join: join };
+})();
@@ -0,0 +1,62 @@
+// End-to-end clip encryption. Must stay compatible with `domain::crypto::e2e`:
+// AES-256-GCM, a base64url (unpadded) key kept in the link fragment, and content
+// stored as base64(iv || ciphertext).
+var ClipstashE2E = (function () {
+  var IV_LEN = 12;
+
+  function toBase64(bytes) {
+    var binary = '';
+    bytes.forEach(function (b) { binary += String.fromCharCode(b); });
+    return btoa(binary);
+  }
+
+  function fromBase64(text) {
+    var binary = atob(text);
+    var bytes = new Uint8Array(binary.length);
+    for (var i = 0; i < binary.length; i++) {
+      bytes[i] = binary.charCodeAt(i);
+    }
+    return bytes;
+  }
+
+  function toBase64Url(bytes) {
+    return toBase64(bytes).replace(/\+/g, '-').replace(/\//g, '_').replace(/=+$/, '');
+  }
+
+  function fromBase64Url(text) {
+    var padded = text.replace(/-/g, '+').replace(/_/g, '/');
+    while (padded.length % 4) {
+      padded += '=';
+    }
+    return fromBase64(padded);
+  }
+
+  function importKey(key) {
+    return crypto.subtle.importKey('raw', fromBase64Url(key), 'AES-GCM', false, ['encrypt', 'decrypt']);
+  }
+
+  return {
+    generateKey: function () {
+      return toBase64Url(crypto.getRandomValues(new Uint8Array(32)));
+    },
+
+    encrypt: async function (key, plaintext) {
+      var iv = crypto.getRandomValues(new Uint8Array(IV_LEN));
+      var cryptoKey = await importKey(key);
+      var ciphertext = await crypto.subtle.encrypt(
+        { name: 'AES-GCM', iv: iv }, cryptoKey, new TextEncoder().encode(plaintext));
+      var out = new Uint8Array(IV_LEN + ciphertext.byteLength);
+      out.set(iv, 0);
+      out.set(new Uint8Array(ciphertext), IV_LEN);
+      return toBase64(out);
+    },
+
+    decrypt: async function (key, content) {
+      var bytes = fromBase64(content.trim());
+      var cryptoKey = await importKey(key);
+      var plaintext = await crypto.subtle.decrypt(
+        { name: 'AES-GCM', iv: bytes.slice(0, IV_LEN) }, cryptoKey, bytes.slice(IV_LEN));
+      return new TextDecoder().decode(plaintext);
+    },
+  };
+})();
@@ -0,0 +1,191 @@
+// A small viewer for /api/openapi.json: lists operations and schemas, and sends requests
+// with the API key entered on the page.
+(function () {
+  var METHOD_COLORS = { get: 'is-info', post: 'is-success', put: 'is-warning', delete: 'is-danger' };
+  var doc;
+
+  function el(tag, attrs, children) {
+    var node = document.createElement(tag);
+    Object.keys(attrs || {}).forEach(function (name) {
+      if (name === 'text') {
+        node.textContent = attrs[name];
+      } else {
+        node.setAttribute(name, attrs[name]);
+      }
+    });
+    (children || []).forEach(function (child) { node.appendChild(child); });
+    return node;
+  }
+
+  function resolve(schema) {
+    if (schema && schema.$ref) {
+      return doc.components.schemas[schema.$ref.split('/').pop()];
+    }
+    return schema || {};
+  }
+
+  function typeName(schema) {
+    if (schema.$ref) {
+      return schema.$ref.split('/').pop();
+    }
+    if (schema.oneOf) {
+      return schema.oneOf.map(function (s) { return typeName(resolve(s)); }).join(' | ');
+    }
+    var name = schema.format ? schema.type + ' (' + schema.format + ')' : (schema.type || 'object');
+    return schema.nullable ? name + ' | null' : name;
+  }
+
+  function example(schema) {
+    schema = resolve(schema);
+    if (schema.nullable) {
+      return null;
+    }
+    switch (schema.type) {
+      case 'object':
+        var value = {};
+        Object.keys(schema.properties || {}).forEach(function (name) {
+          value[name] = example(schema.properties[name]);
+        });
+        return value;
+      case 'boolean': return false;
+      case 'integer': return 0;
+      case 'string': return schema.format === 'date-time' ? new Date().toISOString() : '';
+      default: return null;
+    }
+  }
+
+  function schemaTable(schema) {
+    schema = resolve(schema);
+    if (!schema.properties) {
+      return el('p', { text: typeName(schema) });
+    }
+    var required = schema.required || [];
+    var rows = Object.keys(schema.properties).map(function (name) {
+      var property = schema.properties[name];
+      return el('tr', {}, [
+        el('td', { text: name + (required.indexOf(name) >= 0 ? '' : '?') }),
+        el('td', { text: typeName(property) }),
+        el('td', { text: property.description || '' }),
+      ]);
+    });
+    return el('table', { class: 'table is-narrow is-fullwidth schema' }, [el('tbody', {}, rows)]);
+  }
+
+  function send(method, path, operation, form, output) {
+    var url = path;
+    var query = [];
+    var headers = { 'x-api-key': document.getElementById('api-key').value };
+    (operation.parameters || []).forEach(function (param) {
+      var value = form.querySelector('[name="' + param.name + '"]').value;
+      if (param.in === 'path') {
+        url = url.replace('{' + param.name + '}', encodeURIComponent(value));
+      } else if (param.in === 'query' && value) {
+        query.push(encodeURIComponent(param.name) + '=' + encodeURIComponent(value));
+      } else if (param.in === 'cookie' && value) {
+        document.cookie = param.name + '=' + encodeURIComponent(value) + '; path=/api';
+      }
+    });
+    var options = { method: method.toUpperCase(), headers: headers };
+    var body = form.querySelector('textarea');
+    if (body) {
+      headers['content-type'] = 'application/json';
+      options.body = body.value;
+    }
+    output.textContent = '...';
+    fetch(url + (query.length ? '?' + query.join('&') : ''), options)
+      .then(function (response) {
+        return response.text().then(function (text) {
+          try {
+            text = JSON.stringify(JSON.parse(text), null, 2);
+          } catch (e) { /* not a single JSON value, e.g. JSON Lines */ }
+          output.textContent = response.status + ' ' + response.statusText + '\n\n' + text;
+        });
+      })
+      .catch(function (e) { output.textContent = e.toString(); });
+  }
+
+  function renderOperation(method, path, operation) {
+    var form = el('form', { class: 'box' });
+    (operation.parameters || []).forEach(function (param) {
+      form.appendChild(el('div', { class: 'field' }, [
+        el('label', { class: 'label', text: param.name + ' (' + param.in + (param.required ? ', required' : '') + ')' }),
+        el('input', { class: 'input', type: 'text', name: param.name, placeholder: param.description || '' }),
+      ]));
+    });
+    if (operation.requestBody) {
+      var schema = operation.requestBody.content['application/json'].schema;
+      form.appendChild(el('p', { class: 'has-text-weight-bold', text: 'Body: ' + typeName(schema) }));
+      form.appendChild(schemaTable(schema));
+      var body = el('textarea', { class: 'textarea', rows: 8 });
+      body.value = JSON.stringify(example(schema), null, 2);
+      form.appendChild(body);
+    }
+    var responses = Object.keys(operation.responses).map(function (status) {
+      var response = operation.responses[status];
+      var content = response.content || {};
+      var type = Object.keys(content)[0];
+      var schema = type ? typeName(content[type].schema) + ' (' + type + ')' : '';
+      return el('tr', {}, [el('td', { text: status }), el('td', { text: response.description }), el('td', { text: schema })]);
+    });
+    form.appendChild(el('p', { class: 'has-text-weight-bold mt-3', text: 'Responses' }));
+    form.appendChild(el('table', { class: 'table is-narrow is-fullwidth' }, [el('tbody', {}, responses)]));
+    var output = el('pre', {});
+    form.appendChild(el('button', { class: 'button is-link', type: 'submit', text: 'Send' }));
+    form.appendChild(output);
+    form.addEventListener('submit', function (e) {
+      e.preventDefault();
+      send(method, path, operation, form, output);
+    });
+
+    var summary = el('summary', {}, [
+      el('span', { class: 'tag method ' + (METHOD_COLORS[method] || ''), text: method.toUpperCase() }),
+      el('code', { class: 'ml-2', text: path }),
+      el('span', { class: 'ml-2 has-text-grey', text: operation.operationId }),
+      el('span', { class: 'ml-2', text: operation.security ? '🔒' : '' }),
+    ]);
+    return el('details', { class: 'operation mb-2' }, [summary, form]);
+  }
+
+  function render() {
+    document.getElementById('api-title').textContent = doc.info.title + ' ' + doc.info.version;
+    document.getElementById('api-description').textContent = doc.info.description || '';
+    var operations = document.getElementById('operations');
+    (doc.tags || [{ name: 'default' }]).forEach(function (tag) {
+      operations.appendChild(el('h2', { class: 'title is-4 mt-5', text: tag.name }));
+      operations.appendChild(el('p', { class: 'mb-3', text: tag.description || '' }));
+      Object.keys(doc.paths).forEach(function (path) {
+        Object.keys(doc.paths[path]).forEach(function (method) {
+          var operation = doc.paths[path][method];
+          if ((operation.tags || ['default']).indexOf(tag.name) >= 0) {
+            operations.appendChild(renderOperation(method, path, operation));
+          }
+        });
+      });
+    });
+    var schemas = document.getElementById('schemas');
+    Object.keys(doc.components.schemas).forEach(function (name) {
+      var schema = doc.components.schemas[name];
+      schemas.appendChild(el('h3', { class: 'title is-5 mt-4', text: name }));
+      if (schema.description) {
+        schemas.appendChild(el('p', { class: 'mb-2', text: schema.description }));
+      }
+      schemas.appendChild(schemaTable(schema));
+    });
+  }
+
+  var keyInput = document.getElementById('api-key');
+  keyInput.value = localStorage.getItem('clipstash-api-key') || '';
+  keyInput.addEventListener('change', function () {
+    localStorage.setItem('clipstash-api-key', keyInput.value);
+  });
+
+  fetch('/api/openapi.json')
+    .then(function (response) { return response.json(); })
+    .then(function (json) {
+      doc = json;
+      render();
+    })
+    .catch(function (e) {
+      document.getElementById('operations').textContent = 'Unable to load the API description: ' + e;
+    });
+})();